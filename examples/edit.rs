@@ -1,9 +1,9 @@
-use hiex::{action::ActionError, EditAction, Hiex};
+use hiex::{EditAction, Hiex};
 use std::io::{Cursor, Seek, SeekFrom};
 
 fn print_bytes(data: &[u8]) {
     for c in data.iter().copied() {
-        if c >= 32 && c <= 127 {
+        if (32..=127).contains(&c) {
             print!("{}", c as char);
         } else {
             print!("\\u{{{:x}}}", c);
@@ -24,13 +24,14 @@ fn main() {
         .seek(SeekFrom::Start(0))
         .expect("Failed to reset seek position");
 
-    let mut hex = Hiex::from_reader(copy_cursor).expect("Failed to create hex editor instance.");
+    let mut hex: Hiex<_> =
+        Hiex::from_reader(copy_cursor).expect("Failed to create hex editor instance.");
     let data = hex.read_amount_at(0, 420).expect("Failed to read");
     assert_eq!(data.len(), 26);
     print!("Data: ");
     print_bytes(&data);
 
-    hex.add_action(EditAction::new(1, b"ZDX".to_vec()), ())
+    hex.add_action_simple(EditAction::new(1, b"ZDX".to_vec()))
         .expect("Failed to write");
     // hex.write_at(1, b"ZDX").expect("Failed to write data");
     let data = hex.read_amount_at(0, 10).expect("Failed to read");
@@ -41,10 +42,12 @@ fn main() {
 
     let length = hex.length().expect("Failed to get length");
     let _result = hex
-        .add_action(EditAction::new(length, b"0123".to_vec()), ())
+        .add_action_simple(EditAction::new(length, b"0123".to_vec()))
         .expect_err("Expected error when trying to edit past end of file.");
 
-    hex.save_to(&mut destination_cursor)
+    // Truncating afterwards means that nothing of the destination is left after the data, even
+    // if the data had shrunk.
+    hex.save_to_truncating(&mut destination_cursor)
         .expect("Failed to save to writer");
 
     let data = destination_cursor.into_inner();
@@ -52,7 +55,7 @@ fn main() {
     print!("Dest Data: ");
     print_bytes(data);
 
-    hex.add_action(EditAction::new(5, b"01".to_vec()), ())
+    hex.add_action_simple(EditAction::new(5, b"01".to_vec()))
         .expect("Failed to add action");
     let data = hex.read_amount_at(0, 26).expect("Failed to read");
     assert_eq!(data.len(), 26);
@@ -60,14 +63,14 @@ fn main() {
     print!("Data: ");
     print_bytes(&data);
 
-    hex.undo(()).expect("Failed to undo");
+    hex.undo_simple().expect("Failed to undo");
     let data = hex.read_amount_at(0, 10).expect("Failed to read");
     assert_eq!(data.len(), 10);
     assert_eq!(data, b"AZDXEFGHIJ");
     print!("Data: ");
     print_bytes(&data);
 
-    hex.undo(()).expect("Failed to undo");
+    hex.undo_simple().expect("Failed to undo");
     let data = hex.read_amount_at(0, 10).expect("Failed to read");
     assert_eq!(data.len(), 10);
     assert_eq!(data, b"ABCDEFGHIJ");
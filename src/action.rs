@@ -1,10 +1,38 @@
-use std::{
-    fmt::Debug,
-    io::{Read, Seek, Write},
+#[cfg(not(feature = "no_std_io"))]
+use crate::stream_len;
+use crate::{
+    buffer_pool::BufferPool,
+    clamp_usize,
+    io::{self, Read, Seek, SeekFrom, Write},
 };
+use alloc::collections::BTreeSet;
+#[cfg(feature = "no_std_io")]
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{any::Any, fmt::Debug, ops::Range};
+use usize_cast::FromUsize;
+
+/// When an entry in an [`ActionList`] was added or applied. Without `std` there is no clock, so
+/// this is empty.
+#[cfg(not(feature = "no_std_io"))]
+type Timestamp = std::time::SystemTime;
+#[cfg(feature = "no_std_io")]
+type Timestamp = ();
+
+#[cfg(not(feature = "no_std_io"))]
+fn now() -> Timestamp {
+    std::time::SystemTime::now()
+}
+#[cfg(feature = "no_std_io")]
+fn now() -> Timestamp {}
 
 // TODO: make this more generic
-pub trait Action<F, E>: MemoryUsage + Debug
+pub trait Action<F, E = ()>: MemoryUsage + Debug + AsAny
 where
     F: Read + Seek,
 {
@@ -15,38 +43,673 @@ where
     /// One can assume that the action has already been applied.
     fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError>;
 
+    /// Create a fresh copy of this action, which can be applied to other data without depending on
+    /// anything that was captured when this action was applied (such as previous data).
+    /// Returns `None` if this action can't be replayed, which is the default.
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        None
+    }
+
+    /// Move this action by `delta` bytes, so that it acts on the bytes at that distance from the
+    /// ones it did, such as when replaying a [`Macro`](crate::recorder::Macro) elsewhere. Only
+    /// called on fresh copies from [`Action::clone_for_replay`].
+    /// Returns `false` if it isn't supported, which is the default, or if an offset would
+    /// overflow, in which case the action is left unchanged.
+    fn translate(&mut self, _delta: i64) -> bool {
+        false
+    }
+
+    /// The range of bytes that this action may modify, if it is known before the action is applied.
+    /// Returns `None` by default, which is treated as possibly modifying anything.
+    fn affected_range(&self) -> Option<Range<u64>> {
+        None
+    }
+
+    /// If this action only overwrites bytes in place, the bytes before and after it was applied.
+    /// This lets derived data (such as checksums) be kept current without re-reading the data.
+    /// Only valid after the action has been applied. Returns `None` by default.
+    fn overwrite_delta(&self) -> Option<OverwriteDelta<'_>> {
+        None
+    }
+
+    /// If this action inserts or removes bytes, how it moves the bytes after that point.
+    /// This is used to keep offsets stored alongside the data (such as bookmarks) pointing at the
+    /// same bytes. Returns `None` by default, which means that no bytes are moved.
+    fn offset_shift(&self) -> Option<OffsetShift> {
+        None
+    }
+
+    /// Re-capture what this action restores when it is undone from the current contents of
+    /// `data`, for the bytes within `modified` (or all of its bytes, if `None`), after they were
+    /// modified by something other than the history. The modification is then kept by undoing.
+    /// Only called on applied actions. Returns `false` if it isn't supported, which is the default.
+    fn rebase(
+        &mut self,
+        _data: &mut F,
+        _modified: Option<Range<u64>>,
+    ) -> Result<bool, ActionError> {
+        Ok(false)
+    }
+
+    /// Take any buffers this action fills when it is applied from `pool`, before it is first
+    /// applied by [`ActionList::add`]. Does nothing by default.
+    fn take_buffers(&mut self, _pool: &mut BufferPool) {}
+
+    /// Give the buffers this action holds back to `pool`, as it is being discarded from the
+    /// history. Does nothing by default.
+    fn release_buffers(&mut self, _pool: &mut BufferPool) {}
+
+    /// Whether `next`, which was just applied directly after this action, can be merged into it
+    /// with [`Action::merge`], so that they are a single entry in the history, such as for
+    /// consecutive keystrokes. Actions which move bytes (see [`Action::offset_shift`]) shouldn't
+    /// be merged. Implementations find the concrete type of `next` with [`AsAny::as_any`].
+    /// Returns `false` by default.
+    fn can_merge(&self, _next: &dyn Action<F, E>) -> bool {
+        false
+    }
+
+    /// Absorb `next`, so that undoing this action also undoes it. Only called if
+    /// [`Action::can_merge`] returned `true`. Returns `false` if it couldn't be merged after all,
+    /// in which case `next` is added to the history on its own. Does nothing and returns `false`
+    /// by default.
+    fn merge(&mut self, _next: &dyn Action<F, E>) -> bool {
+        false
+    }
+
+    /// Whether applying this action left the data unchanged, such as an edit writing the bytes
+    /// that were already there. [`ActionList::add`] doesn't add these to the history (see
+    /// [`ActionList::set_skip_noops`]). Only valid after the action has been applied.
+    /// By default this is whether its [`Action::overwrite_delta`] has the same bytes before and
+    /// after.
+    fn is_noop(&self) -> bool {
+        self.overwrite_delta()
+            .is_some_and(|delta| delta.previous == delta.new)
+    }
+
+    /// A short description of what this action does, for labelling it to the user, such as in
+    /// "Undo Overwrite 3 bytes at 0x1F". By default this describes its affected range.
+    fn description(&self) -> String {
+        match self.affected_range() {
+            Some(range) if range.end == u64::MAX => format!("Modify from {:#X}", range.start),
+            Some(range) => describe_bytes("Modify", range.start, range.end - range.start),
+            None => "Modify data".to_string(),
+        }
+    }
+
     // TODO: can_undo / can_redo?
 }
 
-/// Used to measure how much memory something uses.
-pub trait MemoryUsage {
-    /// About how much memory this structure uses.
-    fn memory_usage(&self) -> usize;
+/// Gives access to the concrete type of an action, such as to inspect an action stored in an
+/// [`ActionList`] (see [`ActionList::get`]) by downcasting it:
+/// `list.get(0)?.as_any().downcast_ref::<EditAction>()`.
+/// This is implemented for every `'static` type.
+///
+/// NOTE: Call this on the action itself rather than on a `Box` of it, since the `Box` is also
+/// `'static`, and would be what is downcast.
+///
+/// [`EditAction`]: crate::EditAction
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// How an action moves bytes, see [`Action::offset_shift`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum OffsetShift {
+    /// `length` bytes were inserted at `position`, moving the bytes from there onwards forward.
+    Insert { position: u64, length: u64 },
+    /// The bytes within `range` were removed, moving the bytes after it back.
+    Delete { range: Range<u64> },
 }
+impl OffsetShift {
+    /// The shift from the `from` bytes at `position` becoming `to` bytes long, with bytes opened
+    /// or removed at the end of them. `None` if the length doesn't change.
+    #[cfg(not(feature = "no_std_io"))]
+    pub(crate) fn resize(position: u64, from: u64, to: u64) -> Option<OffsetShift> {
+        if to > from {
+            Some(OffsetShift::Insert {
+                position: position + from,
+                length: to - from,
+            })
+        } else if to < from {
+            Some(OffsetShift::Delete {
+                range: position + to..position + from,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Where the byte at `offset` is after the shift.
+    /// Offsets within a deleted range move to its start.
+    pub fn offset(&self, offset: u64) -> u64 {
+        match self {
+            OffsetShift::Insert { position, length } if offset >= *position => offset + length,
+            OffsetShift::Insert { .. } => offset,
+            OffsetShift::Delete { range } => {
+                if offset <= range.start {
+                    offset
+                } else if offset < range.end {
+                    range.start
+                } else {
+                    offset - (range.end - range.start)
+                }
+            }
+        }
+    }
+
+    /// Where the exclusive end of a range at `end` is after the shift.
+    /// This differs from [`OffsetShift::offset`] in that a range ending where bytes are inserted
+    /// does not grow to include them.
+    pub fn end(&self, end: u64) -> u64 {
+        match self {
+            OffsetShift::Insert { position, .. } if end == *position => end,
+            _ => self.offset(end),
+        }
+    }
+
+    /// The shift which undoes this one.
+    pub fn inverse(&self) -> OffsetShift {
+        match self {
+            OffsetShift::Insert { position, length } => OffsetShift::Delete {
+                range: *position..position + length,
+            },
+            OffsetShift::Delete { range } => OffsetShift::Insert {
+                position: range.start,
+                length: range.end - range.start,
+            },
+        }
+    }
+}
+
+/// The bytes overwritten in place by an action.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct OverwriteDelta<'a> {
+    pub position: u64,
+    /// The bytes before the action was applied.
+    pub previous: &'a [u8],
+    /// The bytes after the action was applied. Always the same length as `previous`.
+    pub new: &'a [u8],
+}
+
+pub use crate::memory_usage::MemoryUsage;
 
 #[derive(Debug)]
 pub enum ActionError {
     /// Unrecoverable. Action is removed from list.
-    IoError(std::io::Error),
+    IoError(io::Error),
+    #[cfg(not(feature = "no_std_io"))]
     Custom(Box<dyn std::error::Error>),
     // TODO: it would be good to provide a manner of specifying why it was invalid.
     /// The action was invalid in some way.
     Invalid,
+    /// The action was given ranges which overlap, where they are required not to.
+    OverlappingRanges,
+    /// The data can't be modified as a background save is reading from it.
+    SaveInProgress,
+    /// The action would modify bytes within the locked `range`.
+    RegionLocked { range: Range<u64> },
+    /// A verifying action found unexpected data at `expected_at`, such as bytes it wrote having
+    /// been modified by something else before it was undone.
+    VerificationFailed { expected_at: u64 },
+    /// The action was invalidated by a modification made outside of the history, so undoing it
+    /// would write stale data. See [`ActionList::mark_external_modification`].
+    Invalidated,
+    /// Writing failed with `error` after some of the bytes may have been written. The previous
+    /// bytes were then written back if `rolled_back`, otherwise the data may be left partially
+    /// modified (see [`ActionList::resync`]).
+    PartialWrite { error: io::Error, rolled_back: bool },
 }
-impl From<std::io::Error> for ActionError {
-    fn from(err: std::io::Error) -> Self {
+impl From<io::Error> for ActionError {
+    fn from(err: io::Error) -> Self {
         Self::IoError(err)
     }
 }
 
-pub struct ActionList<F, E>
+/// Whether an entry in the history is currently applied.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ActionStatus {
+    /// The action is applied, and can be undone.
+    Past,
+    /// The action has been undone, and can be redone.
+    Future,
+}
+
+/// The memory used by a single entry in an [`ActionList`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ActionMemory {
+    pub index: usize,
+    pub status: ActionStatus,
+    /// The memory reported by the action itself.
+    pub action: usize,
+    /// The memory used by the list to store the action, beyond the action itself.
+    pub overhead: usize,
+}
+impl ActionMemory {
+    pub fn total(&self) -> usize {
+        self.action + self.overhead
+    }
+}
+
+/// An entry in an [`ActionList`], see [`ActionList::history`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct HistoryEntry {
+    pub index: usize,
+    /// Unique within the list, and increasing in the order that entries were added, so it keeps
+    /// identifying the entry as others are added or discarded.
+    pub id: u64,
+    pub status: ActionStatus,
+    /// When the entry was added.
+    pub created: Timestamp,
+    /// When the entry was last applied, by being added, redone, or having an action merged into
+    /// it (see [`Action::can_merge`]).
+    pub applied: Timestamp,
+    /// See [`Action::description`].
+    pub description: String,
+}
+
+/// What an [`ActionList`] records alongside each action.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct EntryInfo {
+    id: u64,
+    created: Timestamp,
+    applied: Timestamp,
+}
+
+/// A branch of an [`ActionList`], see [`ActionList::branches`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BranchInfo {
+    /// The history index it follows on from.
+    pub start: usize,
+    /// The amount of entries in it.
+    pub len: usize,
+    /// The id of its first entry, see [`HistoryEntry::id`].
+    pub id: u64,
+    /// The description of its first entry.
+    pub description: String,
+}
+
+/// Undone actions which were kept when an action was added in their place, see
+/// [`ActionList::set_branching`].
+struct Branch<F, E> {
+    /// The history index it follows on from.
+    start: usize,
+    actions: Vec<Box<dyn Action<F, E>>>,
+    entries: Vec<EntryInfo>,
+    /// The branches which follow on from within this one, with their start relative to its start.
+    branches: Vec<Branch<F, E>>,
+}
+impl<F, E> Branch<F, E> {
+    fn memory_usage(&self) -> usize {
+        let overhead =
+            core::mem::size_of::<Box<dyn Action<F, E>>>() + core::mem::size_of::<EntryInfo>();
+        self.actions
+            .iter()
+            .map(|action| action.memory_usage() + overhead)
+            .chain(self.branches.iter().map(Branch::memory_usage))
+            .sum()
+    }
+}
+
+/// An error from [`ActionList::replay_onto`] or [`Macro::replay`](crate::recorder::Macro::replay).
+#[derive(Debug)]
+pub enum ReplayError {
+    /// The action at `index` does not support being replayed.
+    NotReplayable { index: usize },
+    /// The action at `index` failed to apply to the target.
+    Failed { index: usize, error: ActionError },
+}
+
+/// Which way an action was being moved through the history.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum HistoryDirection {
+    Undo,
+    Redo,
+}
+
+/// An error from undoing or redoing the action at `index` in the history.
+#[derive(Debug)]
+pub struct HistoryError {
+    pub index: usize,
+    pub direction: HistoryDirection,
+    /// What the action does, see [`Action::description`].
+    pub description: String,
+    pub affected_range: Option<Range<u64>>,
+    /// Whether the failed attempt modified the data, if that could be determined.
+    /// Only actions which report the bytes they overwrite (see [`Action::overwrite_delta`]) can
+    /// be checked. Errors from before the action was run are always `Some(false)`.
+    pub modified: Option<bool>,
+    pub error: ActionError,
+}
+
+/// An error from [`ActionList::jump_to`].
+#[derive(Debug)]
+pub struct JumpError {
+    /// The amount of applied actions where it stopped, see [`ActionList::past_len`].
+    pub reached: usize,
+    /// The amount of actions undone or redone before it stopped.
+    pub steps: usize,
+    pub error: HistoryError,
+}
+
+/// The result of [`ActionList::resync`]. Indices are into the history, most recent first.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ResyncReport {
+    /// The previous and new amount of applied actions, if the index was repaired.
+    pub moved_index: Option<(usize, usize)>,
+    /// Applied actions whose new bytes are present.
+    pub verified: Vec<usize>,
+    /// Applied actions whose new bytes are not present.
+    pub mismatched: Vec<usize>,
+    /// Applied actions which could not be checked.
+    pub unverifiable: Vec<usize>,
+}
+impl ResyncReport {
+    /// Whether every applied action that could be checked was present.
+    pub fn is_consistent(&self) -> bool {
+        self.mismatched.is_empty()
+    }
+}
+
+/// Whether an action with the affected range `range` may have touched `modified`, where `None` is
+/// anywhere for either.
+fn overlaps(range: Option<Range<u64>>, modified: &Option<Range<u64>>) -> bool {
+    match (range, modified) {
+        (Some(range), Some(modified)) => {
+            modified.start < modified.end
+                && range.start < modified.end
+                && modified.start < range.end
+        }
+        _ => true,
+    }
+}
+
+/// The parts of `range` which aren't within any of `covered`.
+fn uncovered(range: Range<u64>, covered: &[Range<u64>]) -> Vec<Range<u64>> {
+    let mut parts = vec![range];
+    for cover in covered {
+        parts = parts
+            .into_iter()
+            .flat_map(|part| {
+                let before = part.start..part.end.min(cover.start);
+                let after = part.start.max(cover.end)..part.end;
+                core::iter::once(before).chain(core::iter::once(after))
+            })
+            .filter(|part| part.start < part.end)
+            .collect();
+    }
+    parts
+}
+
+/// Checks that the `length` bytes starting at `position` are all within `data`.
+/// Returns `ActionError::Invalid` if they are not.
+#[cfg(not(feature = "no_std_io"))]
+pub(crate) fn check_range<F>(data: &mut F, position: u64, length: u64) -> Result<(), ActionError>
+where
+    F: Seek,
+{
+    let end = position.checked_add(length).ok_or(ActionError::Invalid)?;
+    if end > stream_len(data)? {
+        Err(ActionError::Invalid)
+    } else {
+        Ok(())
+    }
+}
+
+/// Moves each of `offsets` by `delta` for [`Action::translate`], only if none of them overflow.
+pub(crate) fn translate_offsets(offsets: &mut [&mut u64], delta: i64) -> bool {
+    if offsets
+        .iter()
+        .any(|offset| offset.checked_add_signed(delta).is_none())
+    {
+        return false;
+    }
+    for offset in offsets.iter_mut() {
+        **offset = offset.wrapping_add_signed(delta);
+    }
+    true
+}
+
+/// Describes `length` bytes at `position` being acted on, such as "Overwrite 3 bytes at 0x1F".
+pub(crate) fn describe_bytes(verb: &str, position: u64, length: u64) -> String {
+    format!("{} {} at {:#X}", verb, plural_bytes(length), position)
+}
+
+/// "1 byte", or "`n` bytes".
+pub(crate) fn plural_bytes(n: u64) -> String {
+    if n == 1 {
+        "1 byte".to_string()
+    } else {
+        format!("{} bytes", n)
+    }
+}
+
+/// An action made up of several child actions, which are applied in order and unapplied in
+/// reverse order, so that they act as a single entry in the history.
+/// If a child fails to apply, then the children which were already applied are unapplied.
+/// To group actions as they are added to the history, rather than building them up beforehand,
+/// see [`TransactionGuard`](crate::transaction::TransactionGuard).
+pub struct CompoundAction<F, E>
+where
+    F: Read + Seek,
+{
+    actions: Vec<Box<dyn Action<F, E>>>,
+    failed_at: Option<usize>,
+}
+impl<F, E> CompoundAction<F, E>
+where
+    F: Read + Seek,
+{
+    pub fn new() -> Self {
+        Self {
+            actions: Vec::new(),
+            failed_at: None,
+        }
+    }
+
+    pub fn push<A>(&mut self, action: A)
+    where
+        A: 'static + Action<F, E>,
+    {
+        self.actions.push(Box::new(action));
+    }
+
+    /// Like [`CompoundAction::push`], for an action which is already boxed.
+    pub fn push_boxed(&mut self, action: Box<dyn Action<F, E>>) {
+        self.actions.push(action);
+    }
+
+    pub fn len(&self) -> usize {
+        self.actions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// The child at `index`.
+    pub fn get(&self, index: usize) -> Option<&dyn Action<F, E>> {
+        self.actions.get(index).map(|action| &**action)
+    }
+
+    /// The index of the child which failed, if the last attempt to apply this failed.
+    pub fn failed_at(&self) -> Option<usize> {
+        self.failed_at
+    }
+}
+impl<F, E> Action<F, E> for CompoundAction<F, E>
+where
+    F: 'static + Read + Seek,
+    E: 'static + Clone,
+{
+    fn apply(&mut self, data: &mut F, other: E) -> Result<(), ActionError> {
+        self.failed_at = None;
+        for index in 0..self.actions.len() {
+            if let Err(err) = self.actions[index].apply(data, other.clone()) {
+                self.failed_at = Some(index);
+                // Best-effort rollback of the children that were applied.
+                for action in self.actions[..index].iter_mut().rev() {
+                    let _ = action.unapply(data, other.clone());
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, other: E) -> Result<(), ActionError> {
+        for action in self.actions.iter_mut().rev() {
+            action.unapply(data, other.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Covers the affected ranges of all of the children, and everything between them.
+    fn affected_range(&self) -> Option<Range<u64>> {
+        self.actions
+            .iter()
+            .map(|action| action.affected_range())
+            .try_fold(None, |acc: Option<Range<u64>>, range| {
+                let range = range?;
+                Some(Some(match acc {
+                    Some(acc) => acc.start.min(range.start)..acc.end.max(range.end),
+                    None => range,
+                }))
+            })
+            .flatten()
+    }
+
+    /// The child's description if there is only one.
+    fn description(&self) -> String {
+        match &self.actions[..] {
+            [action] => action.description(),
+            actions => format!("{} changes", actions.len()),
+        }
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        let actions = self
+            .actions
+            .iter()
+            .map(|action| action.clone_for_replay())
+            .collect::<Option<Vec<_>>>()?;
+        Some(Box::new(CompoundAction {
+            actions,
+            failed_at: None,
+        }))
+    }
+
+    /// Moves each of the children, succeeding only if all of them support it. Unlike the other
+    /// actions, some of them may have been moved when this fails.
+    fn translate(&mut self, delta: i64) -> bool {
+        self.actions
+            .iter_mut()
+            .all(|action| action.translate(delta))
+    }
+
+    /// Rebases each of the children, succeeding only if all of them support it.
+    fn rebase(&mut self, data: &mut F, modified: Option<Range<u64>>) -> Result<bool, ActionError> {
+        let mut rebased = true;
+        for action in self.actions.iter_mut() {
+            rebased &= action.rebase(data, modified.clone())?;
+        }
+        Ok(rebased)
+    }
+
+    fn take_buffers(&mut self, pool: &mut BufferPool) {
+        for action in self.actions.iter_mut() {
+            action.take_buffers(pool);
+        }
+    }
+
+    fn release_buffers(&mut self, pool: &mut BufferPool) {
+        for action in self.actions.iter_mut() {
+            action.release_buffers(pool);
+        }
+    }
+}
+impl<F, E> MemoryUsage for CompoundAction<F, E>
+where
+    F: Read + Seek,
+{
+    fn memory_usage(&self) -> usize {
+        self.actions
+            .iter()
+            .fold(core::mem::size_of::<Self>(), |acc, action| {
+                acc + action.memory_usage()
+            })
+    }
+}
+impl<F, E> Debug for CompoundAction<F, E>
+where
+    F: Read + Seek,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("CompoundAction")
+            .field("actions", &self.actions)
+            .finish()
+    }
+}
+impl<F, E> Default for CompoundAction<F, E>
+where
+    F: Read + Seek,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct ActionList<F, E = ()>
 where
     F: Read + Write + Seek,
 {
     actions: Vec<Box<dyn Action<F, E>>>,
+    /// The id and times of each action, by the same index.
+    entries: Vec<EntryInfo>,
+    /// The id of the next entry added.
+    next_id: u64,
     /// Index into actions.
     /// All values in positions < `index` are 'active' actions.
     index: usize,
+    /// The indices of the actions which can't be undone, due to modifications made outside of
+    /// the history.
+    invalidated: BTreeSet<usize>,
+    /// Buffers reused by actions and searches.
+    pool: BufferPool,
+    /// Whether added actions are merged into the latest one, see [`ActionList::set_coalescing`].
+    coalescing: bool,
+    /// The lowest index of an action which added actions may be merged into.
+    coalesce_from: usize,
+    /// Whether added actions which didn't change anything are discarded, see
+    /// [`ActionList::set_skip_noops`].
+    skip_noops: bool,
+    /// Whether the future is kept when an action is added, see [`ActionList::set_branching`].
+    branching: bool,
+    /// The branches which follow on from the history, in the order they were made.
+    branches: Vec<Branch<F, E>>,
+    /// See [`ActionList::set_memory_budget`].
+    memory_budget: Option<usize>,
+    /// The amount of actions discarded to fit the memory budget.
+    evicted: usize,
+}
+
+/// What [`ActionList::add_coalescing`] did with an action which it applied.
+pub(crate) enum Added<A> {
+    /// It was added to the history.
+    Pushed,
+    /// It was merged into the latest action.
+    Merged(A),
+    /// It didn't change anything, so it was discarded.
+    Skipped(A),
 }
 impl<F, E> ActionList<F, E>
 where
@@ -55,17 +718,59 @@ where
     pub fn new() -> Self {
         Self {
             actions: Vec::new(),
+            entries: Vec::new(),
+            next_id: 0,
             index: 0,
+            invalidated: BTreeSet::new(),
+            pool: BufferPool::new(),
+            coalescing: true,
+            coalesce_from: 0,
+            skip_noops: true,
+            branching: false,
+            branches: Vec::new(),
+            memory_budget: None,
+            evicted: 0,
         }
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             actions: Vec::with_capacity(capacity),
+            entries: Vec::with_capacity(capacity),
+            next_id: 0,
             index: 0,
+            invalidated: BTreeSet::new(),
+            pool: BufferPool::new(),
+            coalescing: true,
+            coalesce_from: 0,
+            skip_noops: true,
+            branching: false,
+            branches: Vec::new(),
+            memory_budget: None,
+            evicted: 0,
         }
     }
 
+    /// A list with `actions` as its history, of which the first `index` are applied. They are
+    /// given new ids, and are treated as having been added now.
+    #[cfg(all(feature = "serde", not(feature = "no_std_io")))]
+    pub(crate) fn from_actions(actions: Vec<Box<dyn Action<F, E>>>, index: usize) -> Self {
+        let now = now();
+        let mut list = Self::with_capacity(actions.len());
+        list.entries = (0..actions.len())
+            .map(|id| EntryInfo {
+                id: u64::from_usize(id),
+                created: now,
+                applied: now,
+            })
+            .collect();
+        list.next_id = u64::from_usize(actions.len());
+        list.actions = actions;
+        list.index = index.min(list.actions.len());
+        list.coalesce_from = list.index;
+        list
+    }
+
     pub fn len(&self) -> usize {
         self.actions.len()
     }
@@ -74,6 +779,199 @@ where
         self.actions.is_empty()
     }
 
+    /// Sets whether [`ActionList::add`] merges an action into the latest one when the latest
+    /// one allows it (see [`Action::can_merge`]), such as consecutive single byte
+    /// [`EditAction`]s from typing. This is on by default.
+    ///
+    /// [`EditAction`]: crate::EditAction
+    pub fn set_coalescing(&mut self, coalescing: bool) {
+        self.coalescing = coalescing;
+    }
+
+    pub fn coalescing(&self) -> bool {
+        self.coalescing
+    }
+
+    /// Sets whether [`ActionList::add`] discards actions which didn't change anything (see
+    /// [`Action::is_noop`]) rather than adding them to the history, such as pasting the same bytes
+    /// that were already there. The future is kept when one is discarded. This is on by default.
+    pub fn set_skip_noops(&mut self, skip_noops: bool) {
+        self.skip_noops = skip_noops;
+    }
+
+    pub fn skip_noops(&self) -> bool {
+        self.skip_noops
+    }
+
+    /// Sets the most memory (see [`MemoryUsage::memory_usage`]) the list may use, or `None` for
+    /// no limit, which is the default. Past this, adding an action discards the oldest applied
+    /// actions until the list fits, after which they can no longer be undone. The future is never
+    /// discarded, so the list may still use more. See [`ActionList::enforce_memory_budget`].
+    pub fn set_memory_budget(&mut self, budget: Option<usize>) {
+        self.memory_budget = budget;
+    }
+
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.memory_budget
+    }
+
+    /// The amount of actions discarded to fit the memory budget since the list was created.
+    pub fn evicted(&self) -> usize {
+        self.evicted
+    }
+
+    /// Discards the oldest applied actions until the list fits in its memory budget (see
+    /// [`ActionList::set_memory_budget`]), along with the branches which follow on from before
+    /// the last of them. This is done after adding an action.
+    /// Returns the amount of actions discarded.
+    pub fn enforce_memory_budget(&mut self) -> usize {
+        let budget = match self.memory_budget {
+            Some(budget) => budget,
+            None => return 0,
+        };
+        let overhead =
+            core::mem::size_of::<Box<dyn Action<F, E>>>() + core::mem::size_of::<EntryInfo>();
+        let mut usage = self.memory_usage();
+        let mut count = 0;
+        while usage > budget && count < self.index {
+            usage -= self.actions[count].memory_usage() + overhead;
+            count += 1;
+        }
+        if count == 0 {
+            return 0;
+        }
+
+        for mut action in self.actions.drain(..count) {
+            action.release_buffers(&mut self.pool);
+        }
+        self.entries.drain(..count);
+        self.index -= count;
+        self.coalesce_from = self.coalesce_from.saturating_sub(count);
+        self.invalidated = self
+            .invalidated
+            .split_off(&count)
+            .into_iter()
+            .map(|index| index - count)
+            .collect();
+        self.branches.retain(|branch| branch.start >= count);
+        for branch in &mut self.branches {
+            branch.start -= count;
+        }
+        self.evicted += count;
+        trace!(evicted = count; "discarded actions over the memory budget");
+        count
+    }
+
+    /// Sets whether adding an action while there are undone actions keeps them as a branch of the
+    /// history, rather than discarding them, so that they can be returned to with
+    /// [`ActionList::switch_branch`]. This is off by default, and turning it off discards the
+    /// branches.
+    pub fn set_branching(&mut self, branching: bool) {
+        self.branching = branching;
+        if !branching {
+            self.branches.clear();
+        }
+    }
+
+    pub fn branching(&self) -> bool {
+        self.branching
+    }
+
+    /// The branches which follow on from the history, in the order they were made. The branches
+    /// which follow on from within one of them are listed once it is switched to.
+    pub fn branches(&self) -> Vec<BranchInfo> {
+        self.branches
+            .iter()
+            .map(|branch| BranchInfo {
+                start: branch.start,
+                len: branch.actions.len(),
+                id: branch.entries[0].id,
+                description: branch.actions[0].description(),
+            })
+            .collect()
+    }
+
+    /// Undoes or redoes to where the branch at `branch` in [`ActionList::branches`] follows on
+    /// from the history, then makes it the future, so that its actions can be redone. The actions
+    /// which were after that point become a branch in its place.
+    /// Returns `Ok(None)` if there is no such branch. If undoing or redoing fails, the history is
+    /// left where it got to, without switching.
+    pub fn switch_branch(
+        &mut self,
+        branch: usize,
+        reader: &mut F,
+        other: E,
+    ) -> Result<Option<()>, HistoryError>
+    where
+        E: Clone,
+    {
+        let start = match self.branch_start(branch) {
+            Some(start) => start,
+            None => return Ok(None),
+        };
+        self.jump_to(reader, other, start)
+            .map_err(|err| err.error)?;
+        self.swap_branch(branch);
+        Ok(Some(()))
+    }
+
+    /// The history index which the branch at `branch` follows on from.
+    pub(crate) fn branch_start(&self, branch: usize) -> Option<usize> {
+        self.branches.get(branch).map(|branch| branch.start)
+    }
+
+    /// Replaces the future with the branch at `branch`, which has to follow on from the applied
+    /// actions, keeping the future as a branch.
+    pub(crate) fn swap_branch(&mut self, branch: usize) {
+        let branch = self.branches.remove(branch);
+        debug_assert_eq!(branch.start, self.index);
+        self.stash_future();
+        self.actions.extend(branch.actions);
+        self.entries.extend(branch.entries);
+        let start = self.index;
+        self.branches
+            .extend(branch.branches.into_iter().map(|mut nested| {
+                nested.start += start;
+                nested
+            }));
+        trace!(start = start; "switched branch");
+    }
+
+    /// Moves the future into a new branch, along with the branches which follow on from within it.
+    fn stash_future(&mut self) {
+        let start = self.index;
+        if self.is_future_empty() {
+            return;
+        }
+        let (nested, kept): (Vec<_>, Vec<_>) = self
+            .branches
+            .drain(..)
+            .partition(|branch| branch.start > start);
+        self.branches = kept;
+        self.invalidated.split_off(&start);
+        self.branches.push(Branch {
+            start,
+            actions: self.actions.split_off(start),
+            entries: self.entries.split_off(start),
+            branches: nested
+                .into_iter()
+                .map(|mut nested| {
+                    nested.start -= start;
+                    nested
+                })
+                .collect(),
+        });
+        // The branch depends on the actions before it, so they can't be merged into.
+        self.seal();
+        trace!(start = start; "kept future as a branch");
+    }
+
+    /// Stops the next action added from being merged into the latest one, such as to keep a
+    /// point in the history that something refers to. Undoing and redoing do this too.
+    pub fn seal(&mut self) {
+        self.coalesce_from = self.index;
+    }
+
     /// The amount of entries that are 'active'.
     pub fn past_len(&self) -> usize {
         self.index
@@ -93,15 +991,56 @@ where
     }
 
     pub fn clear_future(&mut self) {
+        if !self.is_future_empty() {
+            trace!(discarded = self.future_len(); "cleared future actions");
+        }
         let mut length = self.actions.len();
         while length > self.index {
-            self.actions.pop();
+            if let Some(mut action) = self.actions.pop() {
+                action.release_buffers(&mut self.pool);
+            }
             length = self.actions.len()
         }
+        self.entries.truncate(self.index);
+        self.invalidated.split_off(&self.index);
+        let index = self.index;
+        self.branches.retain(|branch| branch.start <= index);
 
         debug_assert!(self.is_future_empty());
     }
 
+    /// Replaces the applied actions from `start` onwards with a single [`CompoundAction`] of them,
+    /// discarding the future, and the branches which follow on from within them. It is
+    /// invalidated if any of them were. It keeps the id and creation time of the first of them.
+    #[cfg(not(feature = "no_std_io"))]
+    pub(crate) fn collapse_from(&mut self, start: usize)
+    where
+        F: 'static,
+        E: 'static + Clone,
+    {
+        self.clear_future();
+        self.branches.retain(|branch| branch.start <= start);
+        let invalidated = self.invalidated.split_off(&start);
+        let actions = self.actions.split_off(start);
+        self.actions.push(Box::new(CompoundAction {
+            actions,
+            failed_at: None,
+        }));
+        let entries = self.entries.split_off(start);
+        if let Some(first) = entries.first() {
+            let applied = entries.iter().map(|entry| entry.applied).max();
+            self.entries.push(EntryInfo {
+                applied: applied.unwrap_or(first.applied),
+                ..*first
+            });
+        }
+        self.index = start + 1;
+        if !invalidated.is_empty() {
+            self.invalidated.insert(start);
+        }
+        trace!(start = start; "collapsed actions");
+    }
+
     /// Get the mots recently performed action's index, if one exists
     fn latest_action_index(&self) -> Option<usize> {
         if self.index == 0 {
@@ -114,6 +1053,23 @@ where
         }
     }
 
+    /// The buffers reused by actions and searches.
+    pub fn pool(&self) -> &BufferPool {
+        &self.pool
+    }
+
+    /// The buffers reused by actions and searches, such as for taking a buffer to build the data
+    /// of a new action in, or for replacing it with one with different limits.
+    pub fn pool_mut(&mut self) -> &mut BufferPool {
+        &mut self.pool
+    }
+
+    /// Get the action at `index` in the history, such as to inspect it after it was added, by
+    /// downcasting it with [`AsAny::as_any`].
+    pub fn get(&self, index: usize) -> Option<&dyn Action<F, E>> {
+        self.actions.get(index).map(|action| &**action)
+    }
+
     /// Get the most recently performed action, if one exists.
     fn latest_action_mut(&mut self) -> Option<&mut Box<dyn Action<F, E>>> {
         let index = self.latest_action_index();
@@ -124,11 +1080,35 @@ where
         }
     }
 
+    /// The description of the action that would be undone next, such as for an "Undo ..." label.
+    /// See [`Action::description`].
+    pub fn undo_description(&self) -> Option<String> {
+        let index = self.latest_action_index()?;
+        Some(self.actions[index].description())
+    }
+
+    /// The description of the action that would be redone next, such as for a "Redo ..." label.
+    pub fn redo_description(&self) -> Option<String> {
+        self.actions
+            .get(self.index)
+            .map(|action| action.description())
+    }
+
     /// Returns `Ok(None)` if there was no actions to undo.
-    pub fn undo(&mut self, reader: &mut F, other: E) -> Result<Option<()>, ActionError> {
+    /// If the action fails to unapply it stays in the past, and the error describes it along with
+    /// whether the data was modified (see [`ActionList::resync`]).
+    pub fn undo(&mut self, reader: &mut F, other: E) -> Result<Option<()>, HistoryError> {
         if self.is_past_empty() {
             // No actions to undo
             Ok(None)
+        } else if self.invalidated.contains(&(self.index - 1)) {
+            let index = self.index - 1;
+            Err(self.history_error(
+                index,
+                HistoryDirection::Undo,
+                ActionError::Invalidated,
+                Some(false),
+            ))
         } else {
             debug_assert!(self.index > 0);
             if let Err(err) = self
@@ -137,50 +1117,411 @@ where
                 .unapply(reader, other)
             {
                 // Failure. Editor is in a somewhat indeterminate state now.
-                Err(err)
+                let index = self.index - 1;
+                let modified = self.check_modified(index, HistoryDirection::Undo, reader);
+                Err(self.history_error(index, HistoryDirection::Undo, err, modified))
             } else {
                 // Move back a space
                 // We do this here rather than before the action, because repeated undoes have a
                 // slightly higher chance of fixing reality...somewhat.
                 self.index -= 1;
+                self.seal();
+                trace!(index = self.index; "undid action");
                 // We succeeded
                 Ok(Some(()))
             }
         }
     }
 
-    pub fn redo(&mut self, reader: &mut F, other: E) -> Result<Option<()>, ActionError> {
+    /// Returns `Ok(None)` if there was no actions to redo.
+    /// If the action fails to apply it stays in the future, and the error describes it along with
+    /// whether the data was modified (see [`ActionList::resync`]).
+    pub fn redo(&mut self, reader: &mut F, other: E) -> Result<Option<()>, HistoryError> {
         if self.is_future_empty() {
             // No actions to redo
             Ok(None)
+        } else if self.invalidated.contains(&self.index) {
+            Err(self.history_error(
+                self.index,
+                HistoryDirection::Redo,
+                ActionError::Invalidated,
+                Some(false),
+            ))
         } else if let Err(err) = self.actions[self.index].apply(reader, other) {
             // Failure. Editor is in a somewhat indeterminate state now.
-            Err(err)
+            let index = self.index;
+            let modified = self.check_modified(index, HistoryDirection::Redo, reader);
+            Err(self.history_error(index, HistoryDirection::Redo, err, modified))
         } else {
+            self.entries[self.index].applied = now();
             // Move forward a space
             self.index = self.index.checked_add(1).expect("Failed to do next action, as there was too many actions (which should probably be impossible)!");
+            self.seal();
+            trace!(index = self.index - 1; "redid action");
             Ok(Some(()))
         }
     }
 
-    pub fn add<A>(
+    /// Undoes or redoes until `index` actions are applied (see [`ActionList::past_len`]), such as
+    /// for going back to an entry picked from a list of the history. `index` is limited to
+    /// [`ActionList::len`]. Returns the amount of actions undone or redone.
+    /// Stops at the first action which fails to move, reporting where the history got to.
+    pub fn jump_to(&mut self, reader: &mut F, other: E, index: usize) -> Result<usize, JumpError>
+    where
+        E: Clone,
+    {
+        let target = index.min(self.len());
+        let mut steps = 0;
+        while self.index != target {
+            let result = if self.index > target {
+                self.undo(reader, other.clone())
+            } else {
+                self.redo(reader, other.clone())
+            };
+            if let Err(error) = result {
+                return Err(JumpError {
+                    reached: self.index,
+                    steps,
+                    error,
+                });
+            }
+            steps += 1;
+        }
+        Ok(steps)
+    }
+
+    /// Describe the failure to undo or redo the action at `index`.
+    pub(crate) fn history_error(
+        &self,
+        index: usize,
+        direction: HistoryDirection,
+        error: ActionError,
+        modified: Option<bool>,
+    ) -> HistoryError {
+        let action = &self.actions[index];
+        HistoryError {
+            index,
+            direction,
+            description: action.description(),
+            affected_range: action.affected_range(),
+            modified,
+            error,
+        }
+    }
+
+    /// Whether the data differs from what it was before the action at `index` failed to move in
+    /// `direction`, if the action reports the bytes it overwrites.
+    fn check_modified(
+        &self,
+        index: usize,
+        direction: HistoryDirection,
+        reader: &mut F,
+    ) -> Option<bool> {
+        let delta = self.actions[index].overwrite_delta()?;
+        let expected = match direction {
+            HistoryDirection::Undo => delta.new,
+            HistoryDirection::Redo => delta.previous,
+        };
+        let mut current = vec![0; expected.len()];
+        reader.seek(SeekFrom::Start(delta.position)).ok()?;
+        reader.read_exact(&mut current).ok()?;
+        Some(current != expected)
+    }
+
+    /// Re-validates the history against the current contents of `reader`, such as after an undo or
+    /// redo failed.
+    ///
+    /// First the index is repaired: if the most recent action's previous bytes are present instead
+    /// of its new bytes then it is treated as undone, and if the next action's new bytes are present
+    /// instead of its previous bytes then it is treated as redone.
+    /// Then each applied action which reports the bytes it overwrites (see
+    /// [`Action::overwrite_delta`]) is checked for its new bytes still being present, ignoring
+    /// bytes that later actions modify. Actions before one with an unknown affected range can't be
+    /// checked.
+    ///
+    /// The history is otherwise left as is, so that the caller can decide whether to retry, or to
+    /// discard it.
+    pub fn resync(&mut self, reader: &mut F) -> io::Result<ResyncReport> {
+        let previous_index = self.index;
+        loop {
+            if self.index > 0 && self.shows(self.index - 1, reader, false)? {
+                self.index -= 1;
+            } else if self.index < self.actions.len() && self.shows(self.index, reader, true)? {
+                self.index += 1;
+            } else {
+                break;
+            }
+        }
+
+        let mut report = ResyncReport {
+            moved_index: if self.index == previous_index {
+                None
+            } else {
+                Some((previous_index, self.index))
+            },
+            verified: Vec::new(),
+            mismatched: Vec::new(),
+            unverifiable: Vec::new(),
+        };
+
+        // Ranges modified by actions after the one being checked.
+        let mut covered: Vec<Range<u64>> = Vec::new();
+        for index in (0..self.index).rev() {
+            let action = &self.actions[index];
+            let range = match action.affected_range() {
+                Some(range) => range,
+                None => {
+                    report.unverifiable.extend((0..=index).rev());
+                    break;
+                }
+            };
+
+            match action.overwrite_delta() {
+                Some(delta) => {
+                    let mut matches = true;
+                    let end = delta.position + u64::from_usize(delta.new.len());
+                    for part in uncovered(delta.position..end, &covered) {
+                        let length = clamp_usize(part.end - part.start, delta.new.len());
+                        let mut current = vec![0; length];
+                        reader.seek(SeekFrom::Start(part.start))?;
+                        reader.read_exact(&mut current)?;
+                        let start = clamp_usize(part.start - delta.position, delta.new.len());
+                        if current[..] != delta.new[start..start + current.len()] {
+                            matches = false;
+                            break;
+                        }
+                    }
+                    if matches {
+                        report.verified.push(index);
+                    } else {
+                        report.mismatched.push(index);
+                    }
+                }
+                None => report.unverifiable.push(index),
+            }
+            covered.push(range);
+        }
+
+        Ok(report)
+    }
+
+    /// Whether the data holds the new bytes of the action at `index` rather than its previous
+    /// bytes (if `new`), or the previous bytes rather than the new bytes (if not `new`).
+    fn shows(&self, index: usize, reader: &mut F, new: bool) -> io::Result<bool> {
+        let delta = match self.actions[index].overwrite_delta() {
+            Some(delta)
+                if delta.new != delta.previous && delta.new.len() == delta.previous.len() =>
+            {
+                delta
+            }
+            _ => return Ok(false),
+        };
+        let expected = if new { delta.new } else { delta.previous };
+        let mut current = vec![0; expected.len()];
+        reader.seek(SeekFrom::Start(delta.position))?;
+        match reader.read_exact(&mut current) {
+            Ok(()) => Ok(current == expected),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Records that something other than this list modified the data within `affected` (or
+    /// anywhere, if `None`), such as another program writing to the same file.
+    /// The applied actions whose affected ranges overlap it are invalidated: undoing them (and so
+    /// anything before them) fails with `ActionError::Invalidated`, rather than writing bytes
+    /// which no longer match the data. See [`ActionList::rebase_external_modification`] to keep
+    /// them usable instead.
+    /// Returns the indices of the newly invalidated actions.
+    pub fn mark_external_modification(&mut self, affected: Option<Range<u64>>) -> Vec<usize> {
+        let indices: Vec<usize> = (0..self.index)
+            .filter(|index| !self.invalidated.contains(index))
+            .filter(|&index| overlaps(self.actions[index].affected_range(), &affected))
+            .collect();
+        self.invalidated.extend(indices.iter().copied());
+        indices
+    }
+
+    /// Like [`ActionList::mark_external_modification`], but the overlapping actions are rebased
+    /// onto the current contents of `reader` (see [`Action::rebase`]), so that they can still be
+    /// undone, keeping the modification. Only actions which don't support rebasing are
+    /// invalidated.
+    /// Returns the indices of the newly invalidated actions.
+    pub fn rebase_external_modification(
+        &mut self,
+        affected: Option<Range<u64>>,
+        reader: &mut F,
+    ) -> Result<Vec<usize>, ActionError> {
+        let mut indices = Vec::new();
+        for index in 0..self.index {
+            let action = &mut self.actions[index];
+            if self.invalidated.contains(&index) || !overlaps(action.affected_range(), &affected) {
+                continue;
+            }
+            if !action.rebase(reader, affected.clone())? {
+                self.invalidated.insert(index);
+                indices.push(index);
+            }
+        }
+        Ok(indices)
+    }
+
+    /// Whether the action at `index` was invalidated by a modification made outside of the
+    /// history.
+    pub fn is_invalidated(&self, index: usize) -> bool {
+        self.invalidated.contains(&index)
+    }
+
+    /// The memory used by each entry, in history order.
+    /// The totals of the entries, along with the memory used by the branches (see
+    /// [`ActionList::set_branching`]), sum to [`MemoryUsage::memory_usage`] of the list.
+    pub fn memory_usage_per_action(&self) -> Vec<ActionMemory> {
+        self.actions
+            .iter()
+            .enumerate()
+            .map(|(index, action)| ActionMemory {
+                index,
+                status: if index < self.index {
+                    ActionStatus::Past
+                } else {
+                    ActionStatus::Future
+                },
+                action: action.memory_usage(),
+                overhead: core::mem::size_of::<Box<dyn Action<F, E>>>()
+                    + core::mem::size_of::<EntryInfo>(),
+            })
+            .collect()
+    }
+
+    /// The entry at `index` in the history, with when it was added and last applied, such as for
+    /// showing how long ago an edit was made.
+    pub fn entry(&self, index: usize) -> Option<HistoryEntry> {
+        let action = self.actions.get(index)?;
+        let info = self.entries[index];
+        Some(HistoryEntry {
+            index,
+            id: info.id,
+            status: if index < self.index {
+                ActionStatus::Past
+            } else {
+                ActionStatus::Future
+            },
+            created: info.created,
+            applied: info.applied,
+            description: action.description(),
+        })
+    }
+
+    /// Every entry in the history, in history order. See [`ActionList::entry`].
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        (0..self.actions.len())
+            .filter_map(|index| self.entry(index))
+            .collect()
+    }
+
+    /// The index of the entry with `id`, if it is still in the history.
+    pub fn index_of_id(&self, id: u64) -> Option<usize> {
+        // Ids increase with the index.
+        self.entries
+            .binary_search_by_key(&id, |entry| entry.id)
+            .ok()
+    }
+
+    /// The indices of the `n` entries which use the most memory, heaviest first.
+    pub fn heaviest(&self, n: usize) -> Vec<usize> {
+        let mut usage = self.memory_usage_per_action();
+        // Stable, so ties stay in history order.
+        usage.sort_by_key(|memory| core::cmp::Reverse(memory.total()));
+        usage
+            .into_iter()
+            .take(n)
+            .map(|memory| memory.index)
+            .collect()
+    }
+
+    /// Applies fresh copies of each of the 'active' actions, in order, to `target`.
+    /// This is useful for recording edits against one reader and applying the same sequence to
+    /// another. The history of `self` is not modified, and the replayed actions are not recorded.
+    /// Stops at the first action that can't be replayed or fails to apply, reporting its index.
+    /// Returns the amount of actions replayed.
+    pub fn replay_onto(&self, target: &mut F, other: E) -> Result<usize, ReplayError>
+    where
+        E: Clone,
+    {
+        for (index, action) in self.actions[..self.index].iter().enumerate() {
+            let mut action = action
+                .clone_for_replay()
+                .ok_or(ReplayError::NotReplayable { index })?;
+            action
+                .apply(target, other.clone())
+                .map_err(|error| ReplayError::Failed { index, error })?;
+        }
+        Ok(self.index)
+    }
+
+    pub fn add<A>(&mut self, action: A, reader: &mut F, other: E) -> Result<(), (A, ActionError)>
+    where
+        A: 'static + Action<F, E>,
+    {
+        match self.add_coalescing(action, reader, other)? {
+            Added::Pushed => {}
+            Added::Merged(mut action) | Added::Skipped(mut action) => {
+                action.release_buffers(&mut self.pool)
+            }
+        }
+        self.enforce_memory_budget();
+        Ok(())
+    }
+
+    /// [`ActionList::add`], returning the action if it was merged into the latest one or discarded
+    /// rather than added, so that anything derived from the data can be updated from it.
+    pub(crate) fn add_coalescing<A>(
         &mut self,
         mut action: A,
         reader: &mut F,
         other: E,
-    ) -> Result<(), (A, ActionError)>
+    ) -> Result<Added<A>, (A, ActionError)>
     where
         A: 'static + Action<F, E>,
     {
+        action.take_buffers(&mut self.pool);
         if let Err(err) = action.apply(reader, other) {
-            Err((action, err))
-        } else {
-            self.clear_future();
-            // We've applied the action correctly, so add it to the vector.
-            self.actions.push(Box::new(action));
-            self.index += 1;
-            Ok(())
+            return Err((action, err));
         }
+        if self.skip_noops && action.is_noop() {
+            trace!("skipped no-op action");
+            return Ok(Added::Skipped(action));
+        }
+        if self.branching {
+            self.stash_future();
+        }
+        self.clear_future();
+
+        let mergeable = self.coalescing
+            && self.index > self.coalesce_from
+            && !self.invalidated.contains(&(self.index - 1));
+        if mergeable {
+            let latest = &mut self.actions[self.index - 1];
+            if latest.can_merge(&action) && latest.merge(&action) {
+                self.entries[self.index - 1].applied = now();
+                trace!(index = self.index - 1; "merged action");
+                return Ok(Added::Merged(action));
+            }
+        }
+
+        // We've applied the action correctly, so add it to the vector.
+        self.actions.push(Box::new(action));
+        #[cfg_attr(feature = "no_std_io", allow(clippy::let_unit_value))]
+        let now = now();
+        self.entries.push(EntryInfo {
+            id: self.next_id,
+            created: now,
+            applied: now,
+        });
+        self.next_id += 1;
+        self.index += 1;
+        trace!(index = self.index - 1; "added action");
+        Ok(Added::Pushed)
     }
 }
 impl<F, E> MemoryUsage for ActionList<F, E>
@@ -188,9 +1529,14 @@ where
     F: Read + Write + Seek,
 {
     fn memory_usage(&self) -> usize {
-        self.actions
+        self.memory_usage_per_action()
             .iter()
-            .fold(0usize, |acc, action| acc + action.memory_usage())
+            .fold(0usize, |acc, memory| acc + memory.total())
+            + self
+                .branches
+                .iter()
+                .map(Branch::memory_usage)
+                .sum::<usize>()
     }
 }
 impl<F, E> Default for ActionList<F, E>
@@ -201,3 +1547,667 @@ where
         Self::new()
     }
 }
+
+#[cfg(all(test, not(feature = "no_std_io")))]
+mod tests {
+    use super::{
+        Action, ActionError, ActionList, ActionStatus, CompoundAction, HistoryDirection,
+        HistoryError, MemoryUsage, ReplayError,
+    };
+    use crate::{
+        checksum::{crc32, ChecksumAlgorithm, ChecksumState},
+        transform::TransformAction,
+        EditAction, Hiex,
+    };
+    use std::{
+        io::{Cursor, Read, Seek, SeekFrom, Write},
+        time::SystemTime,
+    };
+
+    /// Fails writes once `budget` bytes have been written, if it is set. If `recover` is set, the
+    /// budget is lifted after a write fails.
+    struct FailingWriter {
+        inner: Cursor<Vec<u8>>,
+        budget: Option<usize>,
+        recover: bool,
+    }
+    impl Read for FailingWriter {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let amount = match &mut self.budget {
+                Some(0) => {
+                    if self.recover {
+                        self.budget = None;
+                    }
+                    return Err(std::io::Error::other("out of budget"));
+                }
+                Some(budget) => {
+                    let amount = buf.len().min(*budget);
+                    *budget -= amount;
+                    amount
+                }
+                None => buf.len(),
+            };
+            self.inner.write(&buf[..amount])
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    impl Seek for FailingWriter {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_replay_onto() {
+        let mut hex = Hiex::from_reader(Cursor::new(b"0123456789".to_vec())).unwrap();
+        hex.add_action(EditAction::new(1, b"ab".to_vec()), ())
+            .unwrap();
+        hex.add_action(EditAction::new(8, b"yz".to_vec()), ())
+            .unwrap();
+        // Undone actions aren't replayed.
+        hex.add_action(EditAction::new(0, b"!".to_vec()), ())
+            .unwrap();
+        hex.undo(()).unwrap();
+
+        let mut target = Cursor::new(b"0123456789ABCDEF".to_vec());
+        assert_eq!(hex.actions.replay_onto(&mut target, ()).unwrap(), 2);
+        assert_eq!(target.get_ref(), b"0ab34567yzABCDEF");
+
+        // The target is too short for the second edit.
+        let mut target = Cursor::new(b"012345".to_vec());
+        let err = hex.actions.replay_onto(&mut target, ()).unwrap_err();
+        assert!(matches!(
+            err,
+            ReplayError::Failed {
+                index: 1,
+                error: ActionError::Invalid
+            }
+        ));
+        assert_eq!(target.get_ref(), b"0ab345");
+
+        // Closures can't be replayed.
+        let mut hex = Hiex::from_reader(Cursor::new(b"0123".to_vec())).unwrap();
+        hex.add_action(TransformAction::new(0, 1, Box::new(|_| ())), ())
+            .unwrap();
+        let mut target = Cursor::new(b"0123".to_vec());
+        let err = hex.actions.replay_onto(&mut target, ()).unwrap_err();
+        assert!(matches!(err, ReplayError::NotReplayable { index: 0 }));
+    }
+
+    #[test]
+    fn test_memory_usage_per_action() {
+        let mut hex = Hiex::from_reader(Cursor::new(vec![0u8; 64])).unwrap();
+        hex.add_action(EditAction::new(0, vec![1; 2]), ()).unwrap();
+        hex.add_action(EditAction::new(0, vec![1; 20]), ()).unwrap();
+        hex.add_action(EditAction::new(0, vec![2; 8]), ()).unwrap();
+        hex.undo(()).unwrap();
+
+        let usage = hex.memory_usage_per_action();
+        assert_eq!(usage.len(), 3);
+        assert_eq!(
+            usage.iter().map(|memory| memory.status).collect::<Vec<_>>(),
+            [ActionStatus::Past, ActionStatus::Past, ActionStatus::Future]
+        );
+        assert_eq!(usage[1].action, 8 + 20 + 20);
+        assert_eq!(
+            usage.iter().map(|memory| memory.total()).sum::<usize>(),
+            hex.actions.memory_usage()
+        );
+
+        assert_eq!(hex.actions.heaviest(2), [1, 2]);
+        assert_eq!(hex.actions.heaviest(10), [1, 2, 0]);
+    }
+
+    #[test]
+    fn test_compound_action() {
+        let mut data = Cursor::new(b"0123456789".to_vec());
+        let mut list = ActionList::new();
+        let mut compound: CompoundAction<Cursor<Vec<u8>>, ()> = CompoundAction::new();
+        compound.push(EditAction::new(0, b"ab".to_vec()));
+        compound.push(EditAction::new(1, b"XY".to_vec()));
+        compound.push(EditAction::new(8, b"!".to_vec()));
+        assert_eq!(compound.affected_range(), Some(0..9));
+        list.add(compound, &mut data, ()).unwrap();
+        assert_eq!(data.get_ref(), b"aXY34567!9");
+
+        // Undone and redone as a single entry, in reverse order.
+        assert_eq!(list.len(), 1);
+        list.undo(&mut data, ()).unwrap();
+        assert_eq!(data.get_ref(), b"0123456789");
+        list.redo(&mut data, ()).unwrap();
+        assert_eq!(data.get_ref(), b"aXY34567!9");
+
+        // A failing child rolls back the ones before it.
+        let mut compound = CompoundAction::new();
+        compound.push(EditAction::new(3, b"cd".to_vec()));
+        compound.push(EditAction::new(5, b"ef".to_vec()));
+        compound.push(EditAction::new(9, b"too long".to_vec()));
+        compound.push(EditAction::new(0, b"never".to_vec()));
+        let (compound, err) = list.add(compound, &mut data, ()).unwrap_err();
+        assert!(matches!(err, ActionError::Invalid));
+        assert_eq!(compound.failed_at(), Some(2));
+        assert_eq!(data.get_ref(), b"aXY34567!9");
+        assert_eq!(list.len(), 1);
+    }
+
+    #[test]
+    fn test_history_error() {
+        let mut data = FailingWriter {
+            inner: Cursor::new(b"0123456789".to_vec()),
+            budget: None,
+            recover: false,
+        };
+        let mut list = ActionList::new();
+        for (position, bytes) in [(0, b"abc"), (4, b"def"), (6, b"ghi")] {
+            list.add(EditAction::new(position, bytes.to_vec()), &mut data, ())
+                .unwrap();
+        }
+        assert_eq!(data.inner.get_ref(), b"abc3deghi9");
+
+        // Fails before writing anything.
+        data.budget = Some(0);
+        let err = list.undo(&mut data, ()).unwrap_err();
+        assert_eq!(err.index, 2);
+        assert_eq!(err.direction, HistoryDirection::Undo);
+        assert_eq!(err.affected_range, Some(6..9));
+        assert_eq!(err.description, "Overwrite 3 bytes at 0x6");
+        assert_eq!(err.modified, Some(false));
+        assert!(matches!(err.error, ActionError::IoError(_)));
+        assert_eq!(list.past_len(), 3);
+
+        // Fails partway through writing.
+        data.budget = Some(1);
+        let err = list.undo(&mut data, ()).unwrap_err();
+        assert!(matches!(
+            err,
+            HistoryError {
+                index: 2,
+                modified: Some(true),
+                ..
+            }
+        ));
+        assert_eq!(data.inner.get_ref(), b"abc3defhi9");
+
+        let report = list.resync(&mut data).unwrap();
+        assert_eq!(report.moved_index, None);
+        assert_eq!(report.mismatched, [2]);
+        // The second edit's `f` is overwritten by the third, so only `de` is checked.
+        assert_eq!(report.verified, [1, 0]);
+        assert!(!report.is_consistent());
+
+        // Retrying succeeds.
+        data.budget = None;
+        list.undo(&mut data, ()).unwrap();
+        assert_eq!(data.inner.get_ref(), b"abc3def789");
+        assert!(list.resync(&mut data).unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_resync_index() {
+        let mut data = Cursor::new(b"0123".to_vec());
+        let mut list = ActionList::new();
+        list.add(EditAction::new(0, b"ab".to_vec()), &mut data, ())
+            .unwrap();
+        list.add(EditAction::new(2, b"cd".to_vec()), &mut data, ())
+            .unwrap();
+
+        // The second edit was undone without the history knowing.
+        data.get_mut()[2..].copy_from_slice(b"23");
+        let report = list.resync(&mut data).unwrap();
+        assert_eq!(report.moved_index, Some((2, 1)));
+        assert_eq!(report.verified, [0]);
+        assert!(report.is_consistent());
+        assert_eq!(list.future_len(), 1);
+
+        // And then redone.
+        data.get_mut()[2..].copy_from_slice(b"cd");
+        let report = list.resync(&mut data).unwrap();
+        assert_eq!(report.moved_index, Some((1, 2)));
+        assert_eq!(report.verified, [1, 0]);
+    }
+
+    #[test]
+    fn test_external_modification() {
+        let mut data = Cursor::new(b"0123456789".to_vec());
+        let mut list = ActionList::new();
+        list.add(EditAction::new(0, b"ab".to_vec()), &mut data, ())
+            .unwrap();
+        list.add(EditAction::new(4, b"cde".to_vec()), &mut data, ())
+            .unwrap();
+        list.add(TransformAction::new(8, 1, Box::new(|_| ())), &mut data, ())
+            .unwrap();
+
+        data.get_mut()[5] = b'!';
+        // Outside of every action.
+        assert!(list.mark_external_modification(Some(7..8)).is_empty());
+        assert_eq!(list.mark_external_modification(Some(5..6)), [1]);
+        assert!(list.is_invalidated(1));
+        list.undo(&mut data, ()).unwrap();
+        let err = list.undo(&mut data, ()).unwrap_err();
+        assert!(matches!(
+            err,
+            HistoryError {
+                index: 1,
+                modified: Some(false),
+                error: ActionError::Invalidated,
+                ..
+            }
+        ));
+        assert_eq!(data.get_ref(), b"ab23c!e789");
+
+        // Rebasing keeps the modification when undoing.
+        let mut data = Cursor::new(b"0123456789".to_vec());
+        let mut list = ActionList::new();
+        list.add(EditAction::new(0, b"ab".to_vec()), &mut data, ())
+            .unwrap();
+        list.add(EditAction::new(4, b"cde".to_vec()), &mut data, ())
+            .unwrap();
+        list.add(TransformAction::new(8, 1, Box::new(|_| ())), &mut data, ())
+            .unwrap();
+        data.get_mut()[5] = b'!';
+        data.get_mut()[8] = b'?';
+        let invalidated = list
+            .rebase_external_modification(Some(5..9), &mut data)
+            .unwrap();
+        // The transform doesn't support rebasing.
+        assert_eq!(invalidated, [2]);
+        assert!(list.undo(&mut data, ()).is_err());
+
+        let mut data = Cursor::new(b"0123456789".to_vec());
+        let mut list = ActionList::new();
+        list.add(EditAction::new(0, b"ab".to_vec()), &mut data, ())
+            .unwrap();
+        list.add(EditAction::new(4, b"cde".to_vec()), &mut data, ())
+            .unwrap();
+        data.get_mut()[5] = b'!';
+        assert!(list
+            .rebase_external_modification(Some(5..6), &mut data)
+            .unwrap()
+            .is_empty());
+        list.undo(&mut data, ()).unwrap();
+        list.undo(&mut data, ()).unwrap();
+        assert_eq!(data.get_ref(), b"01234!6789");
+        list.redo(&mut data, ()).unwrap();
+        list.redo(&mut data, ()).unwrap();
+        assert_eq!(data.get_ref(), b"ab23cde789");
+    }
+
+    #[test]
+    fn test_verified_edit() {
+        let mut data = Cursor::new(b"0123456789".to_vec());
+        let mut list = ActionList::new();
+        list.add(
+            EditAction::new(2, b"abc".to_vec()).with_verify(true),
+            &mut data,
+            (),
+        )
+        .unwrap();
+        list.undo(&mut data, ()).unwrap();
+        list.redo(&mut data, ()).unwrap();
+        assert_eq!(data.get_ref(), b"01abc56789");
+
+        // Something else modified the edited bytes, so undoing would lose that.
+        data.get_mut()[3] = b'!';
+        let err = list.undo(&mut data, ()).unwrap_err();
+        assert!(matches!(
+            err.error,
+            ActionError::VerificationFailed { expected_at: 3 }
+        ));
+        assert_eq!(data.get_ref(), b"01a!c56789");
+        assert_eq!(list.past_len(), 1);
+    }
+
+    #[test]
+    fn test_coalescing() {
+        let original = b"0123456789".to_vec();
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(original.clone())).unwrap();
+        hex.track_checksum(ChecksumAlgorithm::Crc32).unwrap();
+        // Typing two hex digits into each byte.
+        for &(position, byte) in &[(2, 0xA0), (2, 0xAB), (3, 0xC0), (3, 0xCD), (4, 0xE0)] {
+            hex.add_action_simple(EditAction::new(position, vec![byte]))
+                .unwrap();
+        }
+        assert_eq!(hex.actions.len(), 1);
+        // The merged edits' buffers went back to the pool.
+        assert!(!hex.actions.pool().is_empty());
+        assert_eq!(hex.read_amount_at(0, 10).unwrap(), b"01\xAB\xCD\xE056789");
+        let current = hex.read_amount_at(0, 10).unwrap();
+        assert_eq!(
+            hex.current_checksum(),
+            Some(ChecksumState::Fresh(crc32(&current)))
+        );
+
+        // Not adjacent, or not a single byte.
+        hex.add_action_simple(EditAction::new(6, b"x".to_vec()))
+            .unwrap();
+        hex.add_action_simple(EditAction::new(7, b"yz".to_vec()))
+            .unwrap();
+        assert_eq!(hex.actions.len(), 3);
+        hex.undo_simple().unwrap();
+        hex.undo_simple().unwrap();
+        hex.undo_simple().unwrap();
+        assert_eq!(hex.read_amount_at(0, 10).unwrap(), original);
+        assert_eq!(
+            hex.current_checksum(),
+            Some(ChecksumState::Fresh(crc32(&original)))
+        );
+
+        // Savepoints, and undoing or redoing, keep the latest action separate.
+        hex.redo_simple().unwrap();
+        hex.add_action_simple(EditAction::new(5, b"a".to_vec()))
+            .unwrap();
+        hex.create_savepoint("typed").unwrap();
+        hex.add_action_simple(EditAction::new(6, b"b".to_vec()))
+            .unwrap();
+        assert_eq!(hex.actions.past_len(), 3);
+
+        hex.actions.set_coalescing(false);
+        hex.add_action_simple(EditAction::new(7, b"c".to_vec()))
+            .unwrap();
+        assert_eq!(hex.actions.past_len(), 4);
+    }
+    #[test]
+    fn test_description() {
+        let mut data = Cursor::new(b"0123456789".to_vec());
+        let mut list = ActionList::new();
+        assert_eq!(list.undo_description(), None);
+        list.add(EditAction::new(7, b"abc".to_vec()), &mut data, ())
+            .unwrap();
+        list.add(
+            TransformAction::new(0, 1, Box::new(|_| ())).with_label("Scramble"),
+            &mut data,
+            (),
+        )
+        .unwrap();
+        assert_eq!(
+            list.undo_description().as_deref(),
+            Some("Scramble 1 byte at 0x0")
+        );
+        assert_eq!(list.redo_description(), None);
+
+        list.undo(&mut data, ()).unwrap();
+        assert_eq!(
+            list.undo_description().as_deref(),
+            Some("Overwrite 3 bytes at 0x7")
+        );
+        assert_eq!(
+            list.redo_description().as_deref(),
+            Some("Scramble 1 byte at 0x0")
+        );
+
+        let mut compound = CompoundAction::new();
+        compound.push(EditAction::new(1, b"x".to_vec()));
+        assert_eq!(
+            Action::<Cursor<Vec<u8>>>::description(&compound),
+            "Overwrite 1 byte at 0x1"
+        );
+        compound.push(EditAction::new(2, b"y".to_vec()));
+        assert_eq!(
+            Action::<Cursor<Vec<u8>>>::description(&compound),
+            "2 changes"
+        );
+    }
+    #[test]
+    fn test_history_entries() {
+        let mut data = Cursor::new(b"0123456789".to_vec());
+        let mut list = ActionList::new();
+        let before = SystemTime::now();
+        list.add(EditAction::new(0, b"ab".to_vec()), &mut data, ())
+            .unwrap();
+        list.add(EditAction::new(4, b"cd".to_vec()), &mut data, ())
+            .unwrap();
+        list.add(EditAction::new(8, b"ef".to_vec()), &mut data, ())
+            .unwrap();
+        list.undo(&mut data, ()).unwrap();
+
+        let history = list.history();
+        assert_eq!(
+            history.iter().map(|entry| entry.id).collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+        assert_eq!(history[1].status, ActionStatus::Past);
+        assert_eq!(history[2].status, ActionStatus::Future);
+        assert_eq!(history[1].description, "Overwrite 2 bytes at 0x4");
+        assert!(history[0].created >= before);
+        assert!(history[1].created >= history[0].created);
+        assert_eq!(history[0].applied, history[0].created);
+
+        list.redo(&mut data, ()).unwrap();
+        let redone = list.entry(2).unwrap();
+        assert_eq!(redone.created, history[2].created);
+        assert!(redone.applied >= history[2].applied);
+
+        // Ids aren't reused once the future is discarded.
+        list.undo(&mut data, ()).unwrap();
+        list.add(EditAction::new(6, b"gh".to_vec()), &mut data, ())
+            .unwrap();
+        assert_eq!(list.entry(2).unwrap().id, 3);
+        assert_eq!(list.index_of_id(3), Some(2));
+        assert_eq!(list.index_of_id(2), None);
+
+        // Collapsing keeps the first id.
+        list.collapse_from(1);
+        assert_eq!(list.history().len(), 2);
+        assert_eq!(list.entry(1).unwrap().id, 1);
+        assert_eq!(list.entry(1).unwrap().created, history[1].created);
+        assert_eq!(list.index_of_id(1), Some(1));
+    }
+    #[test]
+    fn test_get_as_any() {
+        let mut data = Cursor::new(b"0123456789".to_vec());
+        let mut list = ActionList::new();
+        list.add(EditAction::new(2, b"ab".to_vec()), &mut data, ())
+            .unwrap();
+        let mut compound = CompoundAction::new();
+        compound.push(EditAction::new(6, b"c".to_vec()));
+        list.add(compound, &mut data, ()).unwrap();
+
+        let edit = list
+            .get(0)
+            .and_then(|action| action.as_any().downcast_ref::<EditAction>())
+            .unwrap();
+        assert_eq!(edit.position, 2);
+        assert_eq!(edit.new_data, b"ab");
+        assert!(list
+            .get(1)
+            .unwrap()
+            .as_any()
+            .downcast_ref::<EditAction>()
+            .is_none());
+
+        let compound = list
+            .get(1)
+            .unwrap()
+            .as_any()
+            .downcast_ref::<CompoundAction<Cursor<Vec<u8>>, ()>>()
+            .unwrap();
+        let child = compound.get(0).unwrap().as_any();
+        assert_eq!(child.downcast_ref::<EditAction>().unwrap().position, 6);
+        assert!(list.get(2).is_none());
+    }
+    #[test]
+    fn test_partial_write_rollback() {
+        let mut data = FailingWriter {
+            inner: Cursor::new(b"0123456789".to_vec()),
+            budget: Some(1),
+            recover: true,
+        };
+        let mut list = ActionList::new();
+        let (_, err) = list
+            .add(EditAction::new(2, b"abc".to_vec()), &mut data, ())
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ActionError::PartialWrite {
+                rolled_back: true,
+                ..
+            }
+        ));
+        assert_eq!(data.inner.get_ref(), b"0123456789");
+
+        // The rollback fails too.
+        data.budget = Some(1);
+        data.recover = false;
+        let (_, err) = list
+            .add(EditAction::new(2, b"abc".to_vec()), &mut data, ())
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ActionError::PartialWrite {
+                rolled_back: false,
+                ..
+            }
+        ));
+        assert_eq!(data.inner.get_ref(), b"01a3456789");
+        assert!(list.is_empty());
+    }
+    #[test]
+    fn test_skip_noops() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"0123456789".to_vec())).unwrap();
+        hex.add_action_simple(EditAction::new(0, b"ab".to_vec()))
+            .unwrap();
+        hex.add_action_simple(EditAction::new(4, b"cd".to_vec()))
+            .unwrap();
+        hex.undo_simple().unwrap();
+
+        // Re-pasting the same bytes adds nothing, and keeps the future.
+        hex.add_action_simple(EditAction::new(0, b"ab2".to_vec()))
+            .unwrap();
+        assert_eq!(hex.actions.past_len(), 1);
+        assert_eq!(hex.actions.future_len(), 1);
+        hex.redo_simple().unwrap();
+        assert_eq!(hex.read_amount_at(0, 10).unwrap(), b"ab23cd6789");
+
+        let mut list = ActionList::new();
+        let mut data = Cursor::new(b"0123".to_vec());
+        list.add(EditAction::new(0, b"01".to_vec()), &mut data, ())
+            .unwrap();
+        assert!(list.is_empty());
+        list.set_skip_noops(false);
+        list.add(EditAction::new(0, b"01".to_vec()), &mut data, ())
+            .unwrap();
+        assert_eq!(list.len(), 1);
+    }
+
+    #[test]
+    fn test_branching() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"0000".to_vec())).unwrap();
+        hex.actions.set_branching(true);
+        hex.add_action_simple(EditAction::new(0, b"aa".to_vec()))
+            .unwrap();
+        hex.add_action_simple(EditAction::new(2, b"bb".to_vec()))
+            .unwrap();
+        hex.undo_simple().unwrap();
+        hex.add_action_simple(EditAction::new(2, b"cc".to_vec()))
+            .unwrap();
+        let branches = hex.actions.branches();
+        assert_eq!(branches.len(), 1);
+        assert_eq!(branches[0].start, 1);
+        assert_eq!(branches[0].len, 1);
+        assert_eq!(branches[0].id, 1);
+        assert_eq!(branches[0].description, "Overwrite 2 bytes at 0x2");
+        let per_action: usize = hex
+            .actions
+            .memory_usage_per_action()
+            .iter()
+            .map(|memory| memory.total())
+            .sum();
+        assert!(hex.actions.memory_usage() > per_action);
+
+        assert_eq!(hex.switch_branch(1, ()).unwrap(), None);
+        hex.switch_branch(0, ()).unwrap().unwrap();
+        assert_eq!(hex.read_amount_at(0, 4).unwrap(), b"aa00");
+        assert_eq!(hex.actions.future_len(), 1);
+        hex.redo_simple().unwrap();
+        assert_eq!(hex.read_amount_at(0, 4).unwrap(), b"aabb");
+
+        // Replacing everything keeps the other branch within the new one.
+        hex.undo_simple().unwrap();
+        hex.undo_simple().unwrap();
+        hex.add_action_simple(EditAction::new(0, b"dd".to_vec()))
+            .unwrap();
+        assert_eq!(hex.actions.branches().len(), 1);
+        hex.switch_branch(0, ()).unwrap().unwrap();
+        assert_eq!(hex.read_amount_at(0, 4).unwrap(), b"0000");
+        assert_eq!(hex.actions.branches().len(), 2);
+        hex.redo_simple().unwrap();
+        let to_c = hex
+            .actions
+            .branches()
+            .iter()
+            .position(|branch| branch.start == 1)
+            .unwrap();
+        hex.switch_branch(to_c, ()).unwrap().unwrap();
+        hex.redo_simple().unwrap();
+        assert_eq!(hex.read_amount_at(0, 4).unwrap(), b"aacc");
+        assert_eq!(hex.actions.index_of_id(2), Some(1));
+
+        hex.actions.set_branching(false);
+        assert!(hex.actions.branches().is_empty());
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(vec![0u8; 400])).unwrap();
+        for i in 0..4u8 {
+            hex.add_action_simple(EditAction::new(u64::from(i) * 100, vec![i + 1; 100]))
+                .unwrap();
+        }
+        let per_action = hex.actions.memory_usage() / 4;
+        assert_eq!(hex.set_memory_budget(Some(per_action * 2)), 2);
+        assert_eq!(hex.actions.len(), 2);
+        assert_eq!(hex.actions.evicted(), 2);
+        assert!(hex.actions.memory_usage() <= per_action * 2);
+
+        // Actions discarded during a transaction don't stop it from being rolled back.
+        let mut guard = hex.transaction_guard(|| ());
+        guard.add_action(EditAction::new(0, vec![7; 100])).unwrap();
+        guard
+            .add_action(EditAction::new(100, vec![8; 100]))
+            .unwrap();
+        drop(guard);
+        assert_eq!(hex.actions.evicted(), 4);
+        assert_eq!(hex.actions.past_len(), 0);
+        let data = hex.read_amount_at(0, 400).unwrap();
+        assert_eq!([data[0], data[100], data[200], data[300]], [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_jump_to() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"00000000".to_vec())).unwrap();
+        for (i, bytes) in [b"aa", b"bb", b"cc", b"dd"].iter().enumerate() {
+            hex.add_action_simple(EditAction::new(i as u64 * 2, bytes.to_vec()))
+                .unwrap();
+        }
+        assert_eq!(hex.jump_to_history(1, ()).unwrap(), 3);
+        assert_eq!(hex.read_amount_at(0, 8).unwrap(), b"aa000000");
+        assert_eq!(hex.jump_to_history(1, ()).unwrap(), 0);
+        assert_eq!(hex.jump_to_history(10, ()).unwrap(), 3);
+        assert_eq!(hex.read_amount_at(0, 8).unwrap(), b"aabbccdd");
+
+        // Stops at the action which can't be undone.
+        let lock = hex.lock_region(2..4);
+        let err = hex.jump_to_history(0, ()).unwrap_err();
+        assert_eq!(err.reached, 2);
+        assert_eq!(err.steps, 2);
+        assert_eq!(err.error.index, 1);
+        assert_eq!(err.error.direction, HistoryDirection::Undo);
+        assert_eq!(hex.read_amount_at(0, 8).unwrap(), b"aabb0000");
+        hex.unlock_region(lock);
+
+        let mut data = Cursor::new(b"00".to_vec());
+        let mut list = ActionList::new();
+        list.add(EditAction::new(0, b"ab".to_vec()), &mut data, ())
+            .unwrap();
+        assert_eq!(list.jump_to(&mut data, (), 0).unwrap(), 1);
+        assert_eq!(data.get_ref(), b"00");
+    }
+}
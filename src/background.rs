@@ -0,0 +1,293 @@
+//! Saving on a background thread.
+use crate::{Hiex, CHUNK_SIZE};
+use std::{
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+};
+use usize_cast::FromUsize;
+
+/// State shared between a [`SaveHandle`], its thread, and the editor.
+#[derive(Debug)]
+pub(crate) struct SaveState {
+    done: AtomicU64,
+    total: u64,
+    cancelled: AtomicBool,
+    finished: AtomicBool,
+    /// Whether all of the data was written.
+    saved: AtomicBool,
+    /// The amount of applied actions when the save started, which can't change while it runs.
+    index: usize,
+}
+impl SaveState {
+    pub(crate) fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Acquire)
+    }
+
+    /// The amount of applied actions which were saved, if the save has finished successfully.
+    fn saved_index(&self) -> Option<usize> {
+        let saved = self.is_finished() && self.saved.load(Ordering::Acquire);
+        saved.then_some(self.index)
+    }
+}
+
+/// Marks the save as finished when dropped, so that the editor is unblocked even if the thread
+/// panics.
+struct FinishGuard(Arc<SaveState>);
+impl Drop for FinishGuard {
+    fn drop(&mut self) {
+        self.0.finished.store(true, Ordering::Release);
+    }
+}
+
+/// A handle to a save running on a background thread.
+#[derive(Debug)]
+pub struct SaveHandle {
+    state: Arc<SaveState>,
+    thread: JoinHandle<std::io::Result<()>>,
+}
+impl SaveHandle {
+    /// The amount of bytes written so far, and the total amount that will be written.
+    pub fn progress(&self) -> (u64, u64) {
+        (self.state.done.load(Ordering::Acquire), self.state.total)
+    }
+
+    /// Whether the save has finished, whether it succeeded or not.
+    pub fn is_finished(&self) -> bool {
+        self.state.is_finished()
+    }
+
+    /// Ask the save to stop. It stops after the chunk it is currently writing, and `join` then
+    /// returns an error of `ErrorKind::Interrupted`.
+    /// The destination will have been partially written.
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Wait for the save to finish.
+    pub fn join(self) -> std::io::Result<()> {
+        self.thread
+            .join()
+            .unwrap_or_else(|_| Err(std::io::Error::other("background save panicked")))
+    }
+}
+
+fn copy_in_background<R, W>(mut reader: R, mut dest: W, state: &SaveState) -> std::io::Result<()>
+where
+    R: Read + Seek,
+    W: Write,
+{
+    reader.seek(SeekFrom::Start(0))?;
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    loop {
+        if state.cancelled.load(Ordering::Acquire) {
+            return Err(std::io::Error::new(
+                ErrorKind::Interrupted,
+                "background save cancelled",
+            ));
+        }
+
+        let amount = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(amount) => amount,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        dest.write_all(&buffer[..amount])?;
+        state
+            .done
+            .fetch_add(u64::from_usize(amount), Ordering::AcqRel);
+    }
+    dest.flush()
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Whether a background save is currently running.
+    /// While it is, actions can't be added, undone, or redone, and fail with
+    /// `ActionError::SaveInProgress`.
+    pub fn is_saving(&self) -> bool {
+        self.background_save
+            .as_ref()
+            .is_some_and(|state| !state.is_finished())
+    }
+
+    /// The amount of applied actions which a background save which has finished successfully
+    /// saved, if it hasn't been settled yet (see [`Hiex::settle_background_save`]).
+    pub(crate) fn background_saved_index(&self) -> Option<usize> {
+        self.background_save
+            .as_ref()
+            .and_then(|state| state.saved_index())
+    }
+
+    /// Marks the data as saved, as [`Hiex::save_to`] does, if a background save has finished
+    /// successfully since this was last called. This is done before anything which changes the
+    /// history, so that edits made after the save aren't merged into ones made before it.
+    pub(crate) fn settle_background_save(&mut self) {
+        if let Some(index) = self.background_saved_index() {
+            self.background_save = None;
+            self.last_saved = Some(index);
+            self.clean_index = Some(index);
+            self.actions.seal();
+        }
+    }
+
+    /// Copies the data to `dest` on a background thread.
+    /// `reader` is called (on the current thread) to produce an independent reader of the same
+    /// data, such as by `File::try_clone` or by opening the file again, which is read from its start.
+    ///
+    /// NOTE: `File::try_clone` shares the file's position with the original, so reading through
+    /// the editor during the save would disturb it. Opening the file again avoids this.
+    ///
+    /// Edits are blocked until the save has finished (see [`Hiex::is_saving`]), so that the saved
+    /// data is a consistent snapshot. Once it has finished successfully, the data is marked as
+    /// saved (see [`Hiex::is_dirty`]), as with [`Hiex::save_to`].
+    pub fn save_to_background<R, W, O>(&mut self, dest: W, reader: O) -> std::io::Result<SaveHandle>
+    where
+        R: Read + Seek + Send + 'static,
+        W: Write + Send + 'static,
+        O: Fn() -> std::io::Result<R>,
+    {
+        if self.is_saving() {
+            return Err(std::io::Error::other(
+                "a background save is already running",
+            ));
+        }
+        self.settle_background_save();
+
+        let total = self.length()?;
+        let reader = reader()?;
+        let state = Arc::new(SaveState {
+            done: AtomicU64::new(0),
+            total,
+            cancelled: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+            saved: AtomicBool::new(false),
+            index: self.actions.past_len(),
+        });
+
+        let guard = FinishGuard(state.clone());
+        let thread = std::thread::spawn(move || {
+            let guard = guard;
+            let result = copy_in_background(reader, dest, &guard.0);
+            if result.is_ok() {
+                guard.0.saved.store(true, Ordering::Release);
+            }
+            result
+        });
+        self.background_save = Some(state.clone());
+
+        Ok(SaveHandle { state, thread })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{action::ActionError, EditAction, Hiex, CHUNK_SIZE};
+    use std::{
+        io::{Cursor, ErrorKind, Write},
+        sync::{
+            mpsc::{channel, Receiver},
+            Arc, Mutex,
+        },
+    };
+    use usize_cast::FromUsize;
+
+    /// A writer which waits for permission before each write.
+    struct GatedWriter {
+        permits: Receiver<()>,
+        data: Arc<Mutex<Vec<u8>>>,
+    }
+    impl Write for GatedWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            // Once the sender is dropped, writes are no longer gated.
+            let _ = self.permits.recv();
+            self.data.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn wait_for_progress(handle: &crate::background::SaveHandle, done: u64) {
+        while handle.progress().0 < done {
+            std::thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn test_save_to_background() {
+        let mut source: Vec<u8> = (0..CHUNK_SIZE * 3).map(|i| (i % 251) as u8).collect();
+        let mut hex = Hiex::from_reader(Cursor::new(source.clone())).unwrap();
+        hex.add_action(EditAction::new(0, vec![9]), ()).unwrap();
+        source[0] = 9;
+        assert!(hex.is_dirty());
+        let (permits, receiver) = channel();
+        let data = Arc::new(Mutex::new(Vec::new()));
+        let dest = GatedWriter {
+            permits: receiver,
+            data: data.clone(),
+        };
+
+        let snapshot = source.clone();
+        let handle = hex
+            .save_to_background(dest, move || Ok(Cursor::new(snapshot.clone())))
+            .unwrap();
+        permits.send(()).unwrap();
+        wait_for_progress(&handle, u64::from_usize(CHUNK_SIZE));
+        assert_eq!(
+            handle.progress(),
+            (u64::from_usize(CHUNK_SIZE), 3 * u64::from_usize(CHUNK_SIZE))
+        );
+        assert!(hex.is_saving());
+
+        // Edits are blocked during the save.
+        let (_, err) = hex.add_action(EditAction::new(0, vec![1]), ()).unwrap_err();
+        assert!(matches!(err, ActionError::SaveInProgress));
+
+        drop(permits);
+        handle.join().unwrap();
+        assert_eq!(*data.lock().unwrap(), source);
+        assert!(!hex.is_saving());
+        assert!(!hex.is_dirty());
+        assert_eq!(hex.last_saved(), Some(1));
+        // Edits after the save aren't merged into the one before it.
+        hex.add_action(EditAction::new(1, vec![0xFF]), ()).unwrap();
+        assert_eq!(hex.actions.past_len(), 2);
+        assert!(hex.is_dirty());
+    }
+
+    #[test]
+    fn test_save_to_background_cancel() {
+        let source = vec![7u8; CHUNK_SIZE * 4];
+        let mut hex = Hiex::from_reader(Cursor::new(source.clone())).unwrap();
+        let (permits, receiver) = channel();
+        let data = Arc::new(Mutex::new(Vec::new()));
+        let dest = GatedWriter {
+            permits: receiver,
+            data: data.clone(),
+        };
+
+        let handle = hex
+            .save_to_background(dest, move || Ok(Cursor::new(source.clone())))
+            .unwrap();
+        permits.send(()).unwrap();
+        wait_for_progress(&handle, u64::from_usize(CHUNK_SIZE));
+        handle.cancel();
+        drop(permits);
+
+        let err = handle.join().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Interrupted);
+        assert!(data.lock().unwrap().len() < CHUNK_SIZE * 4);
+        assert!(!hex.is_saving());
+        assert_eq!(hex.last_saved(), None);
+        hex.add_action(EditAction::new(0, vec![1]), ()).unwrap();
+    }
+}
@@ -0,0 +1,443 @@
+//! Hashes of the data in fixed-size blocks, so that repeated comparisons can skip unchanged
+//! blocks instead of re-reading everything.
+use crate::{checked_usize, clamp_usize, stream_len, Hiex, CHUNK_SIZE};
+use std::{
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+use usize_cast::FromUsize;
+
+/// Identifies an exported [`BlockIndex`].
+const MAGIC: &[u8; 8] = b"HIEXBIDX";
+
+/// The 64-bit FNV-1a hash of `bytes`. This is fast and stable across versions and platforms,
+/// which matters for indices that are exported and compared later, but isn't cryptographic.
+fn hash_block(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    bytes.iter().fold(OFFSET_BASIS, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
+
+/// Hashes of the data in blocks of `block_size` bytes, with the last block possibly being
+/// shorter. See [`Hiex::build_block_index`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockIndex {
+    block_size: u64,
+    /// The length of the data when it was last hashed.
+    length: u64,
+    /// The hash of each block, or `None` if it was modified since being hashed.
+    hashes: Vec<Option<u64>>,
+}
+impl BlockIndex {
+    /// Hashes all of `stream`. Fails with `ErrorKind::InvalidInput` if `block_size` is 0.
+    pub fn build<S>(stream: &mut S, block_size: u64) -> std::io::Result<Self>
+    where
+        S: Read + Seek,
+    {
+        if block_size == 0 {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "block size must be at least 1",
+            ));
+        }
+        let mut index = Self {
+            block_size,
+            length: 0,
+            hashes: Vec::new(),
+        };
+        index.refresh(stream)?;
+        Ok(index)
+    }
+
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    /// The length of the data when it was last hashed.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// The hash of the block at `block`, or `None` if it has been modified since it was hashed or
+    /// is past the end.
+    pub fn hash(&self, block: usize) -> Option<u64> {
+        self.hashes.get(block).copied().flatten()
+    }
+
+    /// The blocks which were modified since they were hashed.
+    pub fn dirty_blocks(&self) -> Vec<usize> {
+        (0..self.hashes.len())
+            .filter(|&block| self.hashes[block].is_none())
+            .collect()
+    }
+
+    /// The range of bytes covered by the block at `block`.
+    pub fn block_range(&self, block: usize) -> Range<u64> {
+        let start = u64::from_usize(block).saturating_mul(self.block_size);
+        start.min(self.length)..start.saturating_add(self.block_size).min(self.length)
+    }
+
+    /// Marks the blocks overlapping `range` as modified, or all of them if `None`.
+    pub fn invalidate(&mut self, range: Option<Range<u64>>) {
+        let blocks = match range {
+            Some(range) if range.start >= range.end => return,
+            Some(range) => {
+                let last = clamp_usize((range.end - 1) / self.block_size, usize::MAX - 1);
+                clamp_usize(range.start / self.block_size, usize::MAX)..last + 1
+            }
+            None => 0..self.hashes.len(),
+        };
+        let end = blocks.end.min(self.hashes.len());
+        for hash in self.hashes.iter_mut().take(end).skip(blocks.start) {
+            *hash = None;
+        }
+    }
+
+    /// Re-hashes the blocks which were modified, and the blocks affected by the length of
+    /// `stream` having changed.
+    pub fn refresh<S>(&mut self, stream: &mut S) -> std::io::Result<()>
+    where
+        S: Read + Seek,
+    {
+        let length = stream_len(stream)?;
+        if length != self.length {
+            // The block which held the old end may have grown or shrunk.
+            if self.length % self.block_size != 0 {
+                self.invalidate(Some(self.length - 1..self.length));
+            }
+            self.length = length;
+            let count = checked_usize(length.div_ceil(self.block_size))?;
+            self.hashes.resize(count, None);
+        }
+
+        let mut buffer = Vec::new();
+        for block in 0..self.hashes.len() {
+            if self.hashes[block].is_some() {
+                continue;
+            }
+            let range = self.block_range(block);
+            buffer.resize(clamp_usize(range.end - range.start, usize::MAX), 0);
+            stream.seek(SeekFrom::Start(range.start))?;
+            stream.read_exact(&mut buffer)?;
+            self.hashes[block] = Some(hash_block(&buffer));
+        }
+        Ok(())
+    }
+
+    /// The ranges of the blocks whose hashes differ from those of `other`, or that only one of
+    /// them covers. Blocks that are dirty in either are treated as differing.
+    /// Fails with `ErrorKind::InvalidInput` if the block sizes differ.
+    pub fn differing_ranges(&self, other: &BlockIndex) -> std::io::Result<Vec<Range<u64>>> {
+        check_block_sizes(self, other)?;
+        let length = self.length.max(other.length);
+        let mut ranges: Vec<Range<u64>> = Vec::new();
+        for block in 0..self.hashes.len().max(other.hashes.len()) {
+            let same = match (self.hash(block), other.hash(block)) {
+                (Some(a), Some(b)) => a == b && self.block_range(block) == other.block_range(block),
+                _ => false,
+            };
+            if same {
+                continue;
+            }
+            let start = u64::from_usize(block) * self.block_size;
+            let end = start.saturating_add(self.block_size).min(length);
+            match ranges.last_mut() {
+                Some(last) if last.end == start => last.end = end,
+                _ => ranges.push(start..end),
+            }
+        }
+        Ok(ranges)
+    }
+
+    /// Writes the index, for comparing against later with [`BlockIndex::read_from`].
+    /// Dirty blocks should be refreshed first, as they are written as differing from everything.
+    pub fn write_to<W>(&self, writer: &mut W) -> std::io::Result<()>
+    where
+        W: Write,
+    {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&self.block_size.to_le_bytes())?;
+        writer.write_all(&self.length.to_le_bytes())?;
+        for hash in &self.hashes {
+            // A dirty block is marked by a leading 0, and a hashed block by a leading 1.
+            let (present, hash) = match hash {
+                Some(hash) => (1, *hash),
+                None => (0, 0),
+            };
+            writer.write_all(&[present])?;
+            writer.write_all(&hash.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Reads an index written by [`BlockIndex::write_to`].
+    /// Fails with `ErrorKind::InvalidData` if it isn't one.
+    pub fn read_from<R>(reader: &mut R) -> std::io::Result<Self>
+    where
+        R: Read,
+    {
+        let invalid = |message| std::io::Error::new(ErrorKind::InvalidData, message);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a block index"));
+        }
+        let mut word = [0; 8];
+        reader.read_exact(&mut word)?;
+        let block_size = u64::from_le_bytes(word);
+        reader.read_exact(&mut word)?;
+        let length = u64::from_le_bytes(word);
+        if block_size == 0 {
+            return Err(invalid("block index has a block size of 0"));
+        }
+
+        let count = checked_usize(length.div_ceil(block_size))?;
+        let mut hashes = Vec::new();
+        for _ in 0..count {
+            let mut entry = [0; 9];
+            reader.read_exact(&mut entry)?;
+            let mut hash = [0; 8];
+            hash.copy_from_slice(&entry[1..]);
+            hashes.push(match entry[0] {
+                0 => None,
+                1 => Some(u64::from_le_bytes(hash)),
+                _ => return Err(invalid("block index has an invalid entry")),
+            });
+        }
+        Ok(Self {
+            block_size,
+            length,
+            hashes,
+        })
+    }
+}
+
+fn check_block_sizes(a: &BlockIndex, b: &BlockIndex) -> std::io::Result<()> {
+    if a.block_size != b.block_size {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            "block indices have different block sizes",
+        ));
+    }
+    Ok(())
+}
+
+/// Pushes the runs of differing bytes between `a` and `b` within `range` onto `ranges`, merging
+/// with the last range if they touch. Bytes past the end of only one of them differ.
+fn push_differences<A, B>(
+    a: &mut A,
+    b: &mut B,
+    range: Range<u64>,
+    ranges: &mut Vec<Range<u64>>,
+) -> std::io::Result<()>
+where
+    A: Read + Seek,
+    B: Read + Seek,
+{
+    let (length_a, length_b) = (stream_len(a)?, stream_len(b)?);
+    let shared = length_a.min(length_b);
+    let mut push = |start: u64, end: u64| match ranges.last_mut() {
+        Some(last) if last.end == start => last.end = end,
+        _ => ranges.push(start..end),
+    };
+
+    let mut chunk_a = vec![0; CHUNK_SIZE];
+    let mut chunk_b = vec![0; CHUNK_SIZE];
+    let mut position = range.start;
+    while position < range.end.min(shared) {
+        let amount = clamp_usize(range.end.min(shared) - position, CHUNK_SIZE);
+        a.seek(SeekFrom::Start(position))?;
+        a.read_exact(&mut chunk_a[..amount])?;
+        b.seek(SeekFrom::Start(position))?;
+        b.read_exact(&mut chunk_b[..amount])?;
+        for (offset, (x, y)) in chunk_a[..amount].iter().zip(&chunk_b[..amount]).enumerate() {
+            if x != y {
+                let offset = position + u64::from_usize(offset);
+                push(offset, offset + 1);
+            }
+        }
+        position += u64::from_usize(amount);
+    }
+
+    let tail_start = range.start.max(shared);
+    let tail_end = range.end.min(length_a.max(length_b));
+    if tail_start < tail_end {
+        push(tail_start, tail_end);
+    }
+    Ok(())
+}
+
+/// The runs of bytes which differ between `a` and `b`, comparing every byte.
+pub fn diff_ranges<A, B>(a: &mut A, b: &mut B) -> std::io::Result<Vec<Range<u64>>>
+where
+    A: Read + Seek,
+    B: Read + Seek,
+{
+    let mut ranges = Vec::new();
+    push_differences(a, b, 0..u64::MAX, &mut ranges)?;
+    Ok(ranges)
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Hashes the data in blocks of `block_size` bytes and keeps the index on the editor.
+    /// Applying and undoing actions marks the blocks they affected as dirty, and those are
+    /// re-hashed by [`Hiex::refresh_block_index`].
+    /// Fails with `ErrorKind::InvalidInput` if `block_size` is 0.
+    pub fn build_block_index(&mut self, block_size: u64) -> std::io::Result<&BlockIndex> {
+        let index = BlockIndex::build(self, block_size)?;
+        Ok(self.block_index.insert(index))
+    }
+
+    /// The block index, if one was built. Blocks modified since it was last refreshed are dirty.
+    pub fn block_index(&self) -> Option<&BlockIndex> {
+        self.block_index.as_ref()
+    }
+
+    /// Re-hashes the dirty blocks of the block index, returning it if one was built.
+    pub fn refresh_block_index(&mut self) -> std::io::Result<Option<&BlockIndex>> {
+        let mut index = match self.block_index.take() {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        let result = index.refresh(self);
+        self.block_index = Some(index);
+        result?;
+        Ok(self.block_index.as_ref())
+    }
+
+    /// Stop keeping a block index.
+    pub fn drop_block_index(&mut self) {
+        self.block_index = None;
+    }
+
+    /// Like [`diff_ranges`] between the data and `other`, but blocks whose hashes in the editor's
+    /// block index match those in `other_index` are skipped without being read. `other_index`
+    /// must have been built from `other` as it is now.
+    /// Without a block index on the editor, every byte is compared.
+    /// Fails with `ErrorKind::InvalidInput` if the block sizes differ.
+    pub fn diff_against_indexed<R>(
+        &mut self,
+        other: &mut R,
+        other_index: &BlockIndex,
+    ) -> std::io::Result<Vec<Range<u64>>>
+    where
+        R: Read + Seek,
+    {
+        let candidates = match self.refresh_block_index()? {
+            Some(index) => index.differing_ranges(other_index)?,
+            None => return diff_ranges(self, other),
+        };
+        let mut ranges = Vec::new();
+        for range in candidates {
+            push_differences(self, other, range, &mut ranges)?;
+        }
+        Ok(ranges)
+    }
+
+    /// The ranges of the blocks which differ from `recorded`, such as an index of the data that
+    /// was exported earlier or built from a saved copy, comparing only the hashes.
+    /// Fails with `ErrorKind::InvalidInput` if there is no block index on the editor, or the block
+    /// sizes differ.
+    pub fn verify_against_index(
+        &mut self,
+        recorded: &BlockIndex,
+    ) -> std::io::Result<Vec<Range<u64>>> {
+        match self.refresh_block_index()? {
+            Some(index) => index.differing_ranges(recorded),
+            None => Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "no block index was built",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_ranges, BlockIndex};
+    use crate::{EditAction, Hiex};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_block_invalidation() {
+        let data: Vec<u8> = (0..100).collect();
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(data.clone())).unwrap();
+        let original = hex.build_block_index(16).unwrap().clone();
+        assert_eq!(original.block_count(), 7);
+        assert!(original.dirty_blocks().is_empty());
+
+        hex.add_action_simple(EditAction::new(40, vec![0xff]))
+            .unwrap();
+        assert_eq!(hex.block_index().unwrap().dirty_blocks(), [2]);
+        hex.refresh_block_index().unwrap();
+        let changed = hex.verify_against_index(&original).unwrap();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0], 32..48);
+
+        // Undoing dirties it again, and brings back the original hash.
+        hex.undo_simple().unwrap();
+        assert_eq!(hex.block_index().unwrap().dirty_blocks(), [2]);
+        assert!(hex.verify_against_index(&original).unwrap().is_empty());
+
+        // An edit spanning a block boundary.
+        hex.add_action_simple(EditAction::new(47, vec![0, 0]))
+            .unwrap();
+        assert_eq!(hex.block_index().unwrap().dirty_blocks(), [2, 3]);
+
+        let fresh =
+            BlockIndex::build(&mut Cursor::new(hex.read_amount_at(0, 100).unwrap()), 16).unwrap();
+        assert_eq!(hex.refresh_block_index().unwrap().unwrap(), &fresh);
+        assert!(BlockIndex::build(&mut Cursor::new(data), 0).is_err());
+    }
+
+    #[test]
+    fn test_indexed_diff() {
+        let original: Vec<u8> = (0..200).map(|value| (value * 7) as u8).collect();
+        let mut other = Cursor::new(original.clone());
+        let other_index = BlockIndex::build(&mut other, 32).unwrap();
+
+        // Exported and imported, as though it was hashed yesterday.
+        let mut exported = Vec::new();
+        other_index.write_to(&mut exported).unwrap();
+        let other_index = BlockIndex::read_from(&mut exported.as_slice()).unwrap();
+        assert!(BlockIndex::read_from(&mut &exported[1..]).is_err());
+
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(original.clone())).unwrap();
+        hex.build_block_index(32).unwrap();
+        assert!(hex
+            .diff_against_indexed(&mut other, &other_index)
+            .unwrap()
+            .is_empty());
+
+        hex.add_action_simple(EditAction::new(5, vec![0, 0, 0]))
+            .unwrap();
+        hex.add_action_simple(EditAction::new(63, vec![1, 2]))
+            .unwrap();
+        hex.add_action_simple(EditAction::new(150, original[150..152].to_vec()))
+            .unwrap();
+        let indexed = hex.diff_against_indexed(&mut other, &other_index).unwrap();
+        let naive = diff_ranges(&mut hex, &mut other).unwrap();
+        assert_eq!(indexed, naive);
+        assert!(!naive.is_empty());
+
+        // Different lengths.
+        let mut shorter = Cursor::new(original[..150].to_vec());
+        let shorter_index = BlockIndex::build(&mut shorter, 32).unwrap();
+        let indexed = hex
+            .diff_against_indexed(&mut shorter, &shorter_index)
+            .unwrap();
+        assert_eq!(indexed, diff_ranges(&mut hex, &mut shorter).unwrap());
+        assert_eq!(indexed.last(), Some(&(150..200)));
+        let mismatched = BlockIndex::build(&mut shorter, 16).unwrap();
+        assert!(hex.diff_against_indexed(&mut shorter, &mismatched).is_err());
+    }
+}
@@ -0,0 +1,233 @@
+//! Named offsets into the data, which follow the bytes they point at as bytes are inserted and
+//! removed.
+use crate::{
+    action::OffsetShift,
+    region::{RegionTags, TagId},
+    Hiex,
+};
+use std::io::{Read, Seek, Write};
+
+/// Identifies a bookmark, see [`Hiex::add_bookmark`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct BookmarkId(u64);
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Bookmark {
+    pub id: BookmarkId,
+    pub name: String,
+    pub offset: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Bookmarks {
+    bookmarks: Vec<Bookmark>,
+    next_id: u64,
+}
+
+/// An offset stored alongside the data.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum Anchor {
+    Bookmark(BookmarkId),
+    TagStart(TagId),
+    TagEnd(TagId),
+}
+
+/// Moves the bookmarks and tags as the bytes they point at are moved by `shift`.
+/// Returns the anchors which were moved to the start of a deleted range, with their offsets from
+/// before the shift, so that undoing the shift can restore them.
+pub(crate) fn shift_anchors(
+    bookmarks: &mut Bookmarks,
+    tags: &mut RegionTags,
+    shift: &OffsetShift,
+) -> Vec<(Anchor, u64)> {
+    let mut collapsed = Vec::new();
+    if let OffsetShift::Delete { range } = shift {
+        let mut check = |anchor, offset: u64| {
+            if offset > range.start && offset <= range.end {
+                collapsed.push((anchor, offset));
+            }
+        };
+        for bookmark in &bookmarks.bookmarks {
+            check(Anchor::Bookmark(bookmark.id), bookmark.offset);
+        }
+        for tag in tags.iter() {
+            check(Anchor::TagStart(tag.id), tag.range.start);
+            check(Anchor::TagEnd(tag.id), tag.range.end);
+        }
+    }
+
+    for bookmark in &mut bookmarks.bookmarks {
+        bookmark.offset = shift.offset(bookmark.offset);
+    }
+    tags.remap(|tag| {
+        let start = shift.offset(tag.range.start);
+        tag.range = start..shift.end(tag.range.end).max(start);
+    });
+    collapsed
+}
+
+/// Undoes [`shift_anchors`], given the anchors that it collapsed.
+/// Anchors at the point where the bytes are restored stay there.
+pub(crate) fn unshift_anchors(
+    bookmarks: &mut Bookmarks,
+    tags: &mut RegionTags,
+    shift: &OffsetShift,
+    collapsed: &[(Anchor, u64)],
+) {
+    let inverse = shift.inverse();
+    let restored = |anchor: Anchor| {
+        collapsed
+            .iter()
+            .find(|(collapsed, _)| *collapsed == anchor)
+            .map(|(_, offset)| *offset)
+    };
+
+    for bookmark in &mut bookmarks.bookmarks {
+        bookmark.offset =
+            restored(Anchor::Bookmark(bookmark.id)).unwrap_or_else(|| inverse.end(bookmark.offset));
+    }
+    tags.remap(|tag| {
+        let start =
+            restored(Anchor::TagStart(tag.id)).unwrap_or_else(|| inverse.end(tag.range.start));
+        let end = restored(Anchor::TagEnd(tag.id)).unwrap_or_else(|| inverse.end(tag.range.end));
+        tag.range = start..end.max(start);
+    });
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Mark `offset`. The bookmark moves along with the byte at `offset` as bytes are inserted or
+    /// removed before it, including when those actions are undone or redone.
+    pub fn add_bookmark(&mut self, name: impl Into<String>, offset: u64) -> BookmarkId {
+        let id = BookmarkId(self.bookmarks.next_id);
+        self.bookmarks.next_id += 1;
+        self.bookmarks.bookmarks.push(Bookmark {
+            id,
+            name: name.into(),
+            offset,
+        });
+        id
+    }
+
+    /// Remove a bookmark, returning it if it existed.
+    pub fn remove_bookmark(&mut self, id: BookmarkId) -> Option<Bookmark> {
+        let index = self
+            .bookmarks
+            .bookmarks
+            .iter()
+            .position(|bookmark| bookmark.id == id)?;
+        Some(self.bookmarks.bookmarks.remove(index))
+    }
+
+    pub fn bookmark(&self, id: BookmarkId) -> Option<&Bookmark> {
+        self.bookmarks
+            .bookmarks
+            .iter()
+            .find(|bookmark| bookmark.id == id)
+    }
+
+    /// Iterate over the bookmarks in the order they were added.
+    pub fn bookmarks(&self) -> impl Iterator<Item = &Bookmark> {
+        self.bookmarks.bookmarks.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{contents, region::TagInfo, Hiex};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_insert_delete_bytes() {
+        let mut hex = Hiex::from_reader(Cursor::new(b"0123456789".to_vec())).unwrap();
+        hex.insert_bytes(4, b"ab".to_vec(), ()).unwrap();
+        assert_eq!(contents(&mut hex), b"0123ab456789");
+        hex.insert_bytes(12, b"!".to_vec(), ()).unwrap();
+        assert_eq!(contents(&mut hex), b"0123ab456789!");
+        hex.delete_bytes(2..7, ()).unwrap();
+        assert_eq!(contents(&mut hex), b"0156789!");
+        assert!(hex.insert_bytes(9, b"x".to_vec(), ()).is_err());
+        assert!(hex.delete_bytes(7..9, ()).is_err());
+
+        // Empty edits aren't recorded.
+        hex.insert_bytes(3, Vec::new(), ()).unwrap();
+        hex.delete_bytes(3..3, ()).unwrap();
+        assert_eq!(hex.actions.past_len(), 3);
+
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), b"0123ab456789!");
+        hex.undo(()).unwrap();
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), b"0123456789");
+        hex.redo(()).unwrap();
+        assert_eq!(contents(&mut hex), b"0123ab456789");
+
+        // Overlapping moves in both directions, across several chunks.
+        let mut data = Cursor::new(b"0123456789".to_vec());
+        crate::move_bytes(&mut data, 1..8, 3, 3).unwrap();
+        assert_eq!(data.get_ref(), b"0121234567");
+        crate::move_bytes(&mut data, 3..10, 1, 3).unwrap();
+        assert_eq!(data.get_ref(), b"0123456767");
+    }
+
+    #[test]
+    fn test_bookmarks_follow_bytes() {
+        let mut hex = Hiex::from_reader(Cursor::new(b"0123456789".to_vec())).unwrap();
+        let bookmarks = [0, 4, 6, 8].map(|offset| hex.add_bookmark(offset.to_string(), offset));
+        let offsets = |hex: &Hiex<_, _>| bookmarks.map(|id| hex.bookmark(id).unwrap().offset);
+
+        hex.insert_bytes(4, b"ab".to_vec(), ()).unwrap();
+        assert_eq!(offsets(&hex), [0, 6, 8, 10]);
+        // `4` is the start of the deleted range, `6` is within it and `8` is its end.
+        hex.delete_bytes(4..8, ()).unwrap();
+        assert_eq!(contents(&mut hex), b"01236789");
+        assert_eq!(offsets(&hex), [0, 4, 4, 6]);
+
+        hex.undo(()).unwrap();
+        assert_eq!(offsets(&hex), [0, 6, 8, 10]);
+        hex.undo(()).unwrap();
+        assert_eq!(offsets(&hex), [0, 4, 6, 8]);
+        hex.redo(()).unwrap();
+        hex.redo(()).unwrap();
+        assert_eq!(offsets(&hex), [0, 4, 4, 6]);
+
+        // A new action discards the restore information of the undone delete.
+        hex.undo(()).unwrap();
+        hex.insert_bytes(0, b"x".to_vec(), ()).unwrap();
+        assert_eq!(offsets(&hex), [1, 7, 9, 11]);
+        hex.undo(()).unwrap();
+        hex.undo(()).unwrap();
+        assert_eq!(offsets(&hex), [0, 4, 6, 8]);
+    }
+
+    #[test]
+    fn test_tags_follow_bytes() {
+        let mut hex = Hiex::from_reader(Cursor::new(b"0123456789".to_vec())).unwrap();
+        let before = hex.tags_mut().add_tag(0..4, TagInfo::new("before"));
+        let around = hex.tags_mut().add_tag(3..6, TagInfo::new("around"));
+        let after = hex.tags_mut().add_tag(6..9, TagInfo::new("after"));
+        let range = |hex: &Hiex<_, _>, id| hex.tags().get(id).unwrap().range.clone();
+
+        // A tag ending at the insertion point doesn't grow.
+        hex.insert_bytes(4, b"ab".to_vec(), ()).unwrap();
+        assert_eq!(range(&hex, before), 0..4);
+        assert_eq!(range(&hex, around), 3..8);
+        assert_eq!(range(&hex, after), 8..11);
+
+        hex.delete_bytes(2..9, ()).unwrap();
+        assert_eq!(range(&hex, before), 0..2);
+        assert_eq!(range(&hex, around), 2..2);
+        assert_eq!(range(&hex, after), 2..4);
+        assert_eq!(hex.tags().tags_at(2).len(), 1);
+
+        hex.undo(()).unwrap();
+        assert_eq!(range(&hex, before), 0..4);
+        assert_eq!(range(&hex, around), 3..8);
+        assert_eq!(range(&hex, after), 8..11);
+        hex.undo(()).unwrap();
+        assert_eq!(range(&hex, around), 3..6);
+        assert_eq!(range(&hex, after), 6..9);
+    }
+}
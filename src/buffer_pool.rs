@@ -0,0 +1,206 @@
+//! Reusing byte buffers, so that frequent small actions and searches don't each allocate.
+use crate::{memory_usage::MemoryUsage, CHUNK_SIZE};
+#[cfg(feature = "no_std_io")]
+use alloc::vec::Vec;
+
+/// Counts of how [`BufferPool`] was used.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct PoolStats {
+    /// Buffers handed out from the pool.
+    pub hits: u64,
+    /// Buffers which had to be allocated, as none in the pool were large enough.
+    pub misses: u64,
+    /// Buffers given back and kept.
+    pub returned: u64,
+    /// Buffers given back but dropped, as the pool was full or they were too large.
+    pub dropped: u64,
+}
+
+/// A pool of byte buffers, kept by the [`ActionList`](crate::action::ActionList).
+/// [`EditAction`](crate::EditAction)s take the buffer for the bytes they overwrite from it, and
+/// give back both of their buffers when they are discarded from the history. Searches use it for
+/// their scratch space. Buffers can also be taken for building the data of new actions with
+/// [`BufferPool::take`].
+#[derive(Debug, Clone)]
+pub struct BufferPool {
+    buffers: Vec<Vec<u8>>,
+    max_buffers: usize,
+    max_capacity: usize,
+    stats: PoolStats,
+}
+impl BufferPool {
+    /// The default for [`BufferPool::with_max_buffers`].
+    pub const DEFAULT_MAX_BUFFERS: usize = 32;
+    /// The default for [`BufferPool::with_max_capacity`], which fits the scratch space of a
+    /// search for a needle of up to a chunk long.
+    pub const DEFAULT_MAX_CAPACITY: usize = 2 * CHUNK_SIZE;
+
+    pub fn new() -> Self {
+        Self {
+            buffers: Vec::new(),
+            max_buffers: Self::DEFAULT_MAX_BUFFERS,
+            max_capacity: Self::DEFAULT_MAX_CAPACITY,
+            stats: PoolStats::default(),
+        }
+    }
+
+    /// Keep at most `max_buffers` buffers. A limit of 0 disables pooling.
+    pub fn with_max_buffers(mut self, max_buffers: usize) -> Self {
+        self.max_buffers = max_buffers;
+        self.buffers.truncate(max_buffers);
+        self
+    }
+
+    /// Don't keep buffers with a capacity of more than `max_capacity` bytes.
+    pub fn with_max_capacity(mut self, max_capacity: usize) -> Self {
+        self.max_capacity = max_capacity;
+        self.buffers
+            .retain(|buffer| buffer.capacity() <= max_capacity);
+        self
+    }
+
+    /// A buffer of `length` zeroes, reusing a pooled buffer which can hold them if there is one.
+    pub fn take(&mut self, length: usize) -> Vec<u8> {
+        let mut buffer = self.take_empty(length);
+        buffer.resize(length, 0);
+        buffer
+    }
+
+    /// An empty buffer with a capacity of at least `capacity`, reusing a pooled buffer if there
+    /// is one large enough. A capacity of 0 doesn't allocate, so it is never taken from the pool.
+    pub fn take_empty(&mut self, capacity: usize) -> Vec<u8> {
+        if capacity == 0 {
+            return Vec::new();
+        }
+        match self
+            .buffers
+            .iter()
+            .position(|buffer| buffer.capacity() >= capacity)
+        {
+            Some(index) => {
+                self.stats.hits += 1;
+                self.buffers.swap_remove(index)
+            }
+            None => {
+                self.stats.misses += 1;
+                Vec::with_capacity(capacity)
+            }
+        }
+    }
+
+    /// Gives back a buffer which is no longer needed.
+    pub fn give(&mut self, mut buffer: Vec<u8>) {
+        if buffer.capacity() == 0 {
+            return;
+        }
+        if self.buffers.len() >= self.max_buffers || buffer.capacity() > self.max_capacity {
+            self.stats.dropped += 1;
+            return;
+        }
+        buffer.clear();
+        self.buffers.push(buffer);
+        self.stats.returned += 1;
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.stats
+    }
+
+    /// The amount of buffers in the pool.
+    pub fn len(&self) -> usize {
+        self.buffers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+
+    /// Drops the pooled buffers, freeing their memory.
+    pub fn clear(&mut self) {
+        self.buffers.clear();
+    }
+}
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl MemoryUsage for BufferPool {
+    fn memory_usage(&self) -> usize {
+        self.buffers.iter().map(Vec::capacity).sum()
+    }
+}
+
+#[cfg(all(test, not(feature = "no_std_io")))]
+mod tests {
+    use super::{BufferPool, PoolStats};
+    use crate::{memory_usage::MemoryUsage, EditAction, Hiex};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_buffer_pool() {
+        let mut pool = BufferPool::new().with_max_buffers(2).with_max_capacity(8);
+        assert!(pool.take(0).is_empty());
+        let a = pool.take(4);
+        assert_eq!(a, [0; 4]);
+        let mut b = pool.take(16);
+        b[0] = 1;
+        pool.give(a);
+        pool.give(b);
+        assert_eq!(pool.len(), 1);
+
+        // Reused buffers are cleared.
+        let mut a = pool.take(2);
+        assert_eq!(a, [0; 2]);
+        a[1] = 5;
+        pool.give(a);
+        assert_eq!(pool.take(3), [0; 3]);
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                hits: 2,
+                misses: 2,
+                returned: 2,
+                dropped: 1
+            }
+        );
+        assert_eq!(pool.memory_usage(), 0);
+    }
+
+    #[test]
+    fn test_edit_reuse() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(vec![0; 64])).unwrap();
+        // Each edit is kept as its own entry.
+        hex.actions.set_coalescing(false);
+        // A burst of 1-byte edits, like typing.
+        let burst = |hex: &mut Hiex<_>, byte: u8| {
+            for position in 0..16 {
+                let mut data = hex.actions.pool_mut().take(1);
+                data[0] = byte;
+                hex.add_action_simple(EditAction::new(position, data))
+                    .unwrap();
+            }
+        };
+
+        burst(&mut hex, b'a');
+        let first = hex.actions.pool().stats();
+        assert_eq!(first.hits, 0);
+        assert_eq!(first.misses, 32);
+
+        // Undoing and typing over the undone edits reuses their buffers, except for the first
+        // edit, whose buffers are taken before adding it discards the undone edits.
+        for _ in 0..16 {
+            hex.undo_simple().unwrap();
+        }
+        burst(&mut hex, b'b');
+        let second = hex.actions.pool().stats();
+        assert_eq!(second.hits, 30);
+        assert_eq!(second.misses, first.misses + 2);
+        assert_eq!(hex.read_amount_at(0, 17).unwrap(), b"bbbbbbbbbbbbbbbb\0");
+
+        // Searching reuses its scratch space.
+        assert_eq!(hex.find_all(b"bb", 0..64).unwrap().len(), 15);
+        assert_eq!(hex.find_next(b"b\0", 0..64).unwrap(), Some(15));
+        assert_eq!(hex.actions.pool().stats().misses, second.misses + 1);
+    }
+}
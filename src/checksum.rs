@@ -0,0 +1,576 @@
+//! Checksums over the data, including ones kept current as actions are applied.
+use crate::{
+    action::{check_range, translate_offsets, Action, ActionError, MemoryUsage, OverwriteDelta},
+    clamp_usize, read_exact_at, write_at, Endian, CHUNK_SIZE,
+};
+use std::{
+    io::{Read, Seek, Write},
+    ops::Range,
+};
+use usize_cast::FromUsize;
+
+/// The reversed polynomial of CRC-32 (as used by zlib, PNG, etc.)
+const CRC32_POLY: u32 = 0xEDB8_8320;
+/// The reversed polynomial of CRC-16/ARC (as used by LHA, ARJ, etc.)
+const CRC16_POLY: u32 = 0xA001;
+
+const fn crc_table(poly: u32) -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut value = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            value = if value & 1 != 0 {
+                (value >> 1) ^ poly
+            } else {
+                value >> 1
+            };
+            bit += 1;
+        }
+        table[i] = value;
+        i += 1;
+    }
+    table
+}
+static CRC32_TABLE: [u32; 256] = crc_table(CRC32_POLY);
+static CRC16_TABLE: [u32; 256] = crc_table(CRC16_POLY);
+
+/// Feed `data` through a reflected CRC register, without any initial value or final xor.
+fn crc_update(table: &[u32; 256], mut state: u32, data: &[u8]) -> u32 {
+    for byte in data {
+        state = table[((state ^ u32::from(*byte)) & 0xFF) as usize] ^ (state >> 8);
+    }
+    state
+}
+
+/// Multiply `a` and `b` modulo `poly`, of `width` bits, in the reflected representation.
+fn multiply_mod_poly(a: u32, mut b: u32, poly: u32, width: u32) -> u32 {
+    let mut m: u32 = 1 << (width - 1);
+    let mut product = 0;
+    loop {
+        if a & m != 0 {
+            product ^= b;
+            if a & (m - 1) == 0 {
+                break;
+            }
+        }
+        m >>= 1;
+        b = if b & 1 != 0 { (b >> 1) ^ poly } else { b >> 1 };
+    }
+    product
+}
+
+/// `x^(8 * n)` modulo `poly`, of `width` bits, which is the effect of feeding `n` zero bytes
+/// through the register.
+fn zero_bytes_operator(mut n: u64, poly: u32, width: u32) -> u32 {
+    // `x^(2^k)`, starting at `x^8`.
+    let mut power: u32 = 1 << (width - 2);
+    for _ in 0..3 {
+        power = multiply_mod_poly(power, power, poly, width);
+    }
+
+    // `x^0`
+    let mut operator: u32 = 1 << (width - 1);
+    while n != 0 {
+        if n & 1 != 0 {
+            operator = multiply_mod_poly(power, operator, poly, width);
+        }
+        power = multiply_mod_poly(power, power, poly, width);
+        n >>= 1;
+    }
+    operator
+}
+
+/// The change to a reflected CRC from the bytes at `position` being replaced, in data of `length`
+/// bytes. Only the difference (`previous ^ new`) and the amount of bytes after it matter, since
+/// the CRC is linear and any initial value or final xor cancels out.
+fn crc_patch_delta(
+    table: &[u32; 256],
+    poly: u32,
+    width: u32,
+    length: u64,
+    position: u64,
+    previous: &[u8],
+    new: &[u8],
+) -> u32 {
+    debug_assert_eq!(previous.len(), new.len());
+    let difference: Vec<u8> = previous.iter().zip(new).map(|(a, b)| a ^ b).collect();
+    let trailing = length - position - u64::from_usize(difference.len());
+    let delta = crc_update(table, 0, &difference);
+    multiply_mod_poly(
+        zero_bytes_operator(trailing, poly, width),
+        delta,
+        poly,
+        width,
+    )
+}
+
+/// An incremental CRC-32 hasher.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Crc32 {
+    state: u32,
+}
+impl Crc32 {
+    pub fn new() -> Self {
+        Self { state: !0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.state = crc_update(&CRC32_TABLE, self.state, data);
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.state
+    }
+}
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The CRC-32 of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut hasher = Crc32::new();
+    hasher.update(data);
+    hasher.finish()
+}
+
+/// Patch the CRC-32 `crc` of some data of `length` bytes for the bytes at `position` being
+/// replaced, without needing to read the rest of the data.
+pub fn crc32_patch(crc: u32, length: u64, position: u64, previous: &[u8], new: &[u8]) -> u32 {
+    crc ^ crc_patch_delta(
+        &CRC32_TABLE,
+        CRC32_POLY,
+        32,
+        length,
+        position,
+        previous,
+        new,
+    )
+}
+
+/// An incremental CRC-16/ARC hasher.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Crc16 {
+    state: u16,
+}
+impl Crc16 {
+    pub fn new() -> Self {
+        Self { state: 0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.state = crc_update(&CRC16_TABLE, u32::from(self.state), data) as u16;
+    }
+
+    pub fn finish(&self) -> u16 {
+        self.state
+    }
+}
+impl Default for Crc16 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The CRC-16/ARC of `data`.
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut hasher = Crc16::new();
+    hasher.update(data);
+    hasher.finish()
+}
+
+/// Patch the CRC-16/ARC `crc` of some data of `length` bytes, see [`crc32_patch`].
+pub fn crc16_patch(crc: u16, length: u64, position: u64, previous: &[u8], new: &[u8]) -> u16 {
+    crc ^ crc_patch_delta(
+        &CRC16_TABLE,
+        CRC16_POLY,
+        16,
+        length,
+        position,
+        previous,
+        new,
+    ) as u16
+}
+
+/// The checksum algorithms which can be kept current as actions are applied.
+/// Only actions which report an [`OverwriteDelta`] can be tracked incrementally; any other action
+/// makes the checksum stale.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChecksumAlgorithm {
+    /// CRC-16/ARC
+    Crc16,
+    Crc32,
+}
+impl ChecksumAlgorithm {
+    /// The size of the checksum in bytes.
+    pub fn width(self) -> usize {
+        match self {
+            ChecksumAlgorithm::Crc16 => 2,
+            ChecksumAlgorithm::Crc32 => 4,
+        }
+    }
+
+    pub(crate) fn hasher(self) -> ChecksumHasher {
+        match self {
+            ChecksumAlgorithm::Crc16 => ChecksumHasher::Crc16(Crc16::new()),
+            ChecksumAlgorithm::Crc32 => ChecksumHasher::Crc32(Crc32::new()),
+        }
+    }
+}
+
+/// An incremental hasher for any [`ChecksumAlgorithm`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum ChecksumHasher {
+    Crc16(Crc16),
+    Crc32(Crc32),
+}
+impl ChecksumHasher {
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            ChecksumHasher::Crc16(hasher) => hasher.update(data),
+            ChecksumHasher::Crc32(hasher) => hasher.update(data),
+        }
+    }
+
+    pub(crate) fn finish(&self) -> u32 {
+        match self {
+            ChecksumHasher::Crc16(hasher) => u32::from(hasher.finish()),
+            ChecksumHasher::Crc32(hasher) => hasher.finish(),
+        }
+    }
+}
+
+/// An action which computes the checksum of `data_range` and writes it at `field_offset`, such as
+/// for the checksum field in a firmware header.
+/// If the field is within `data_range`, its bytes are skipped when computing the checksum, so that
+/// the result doesn't depend on the previous value of the field.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChecksumFieldAction {
+    pub data_range: Range<u64>,
+    pub field_offset: u64,
+    pub algorithm: ChecksumAlgorithm,
+    pub endian: Endian,
+    previous_data: Vec<u8>,
+    new_data: Vec<u8>,
+}
+impl ChecksumFieldAction {
+    pub fn new(
+        data_range: Range<u64>,
+        field_offset: u64,
+        algorithm: ChecksumAlgorithm,
+        endian: Endian,
+    ) -> Self {
+        Self {
+            data_range,
+            field_offset,
+            algorithm,
+            endian,
+            previous_data: Vec::new(),
+            new_data: Vec::new(),
+        }
+    }
+
+    fn field_range(&self) -> Range<u64> {
+        let width = u64::from_usize(self.algorithm.width());
+        self.field_offset..self.field_offset.saturating_add(width)
+    }
+
+    /// Compute the checksum of `data_range`, skipping the field.
+    fn compute<F>(&self, data: &mut F) -> Result<u32, ActionError>
+    where
+        F: Read + Seek,
+    {
+        let field = self.field_range();
+        // The parts of the data range before and after the field.
+        let parts = [
+            self.data_range.start..self.data_range.end.min(field.start),
+            self.data_range.start.max(field.end)..self.data_range.end,
+        ];
+
+        let mut hasher = self.algorithm.hasher();
+        let mut chunk = vec![0u8; CHUNK_SIZE];
+        for part in parts.iter().filter(|part| part.start < part.end) {
+            let mut position = part.start;
+            while position < part.end {
+                let amount = clamp_usize(part.end - position, CHUNK_SIZE);
+                read_exact_at(data, position, &mut chunk[..amount])?;
+                hasher.update(&chunk[..amount]);
+                position += u64::from_usize(amount);
+            }
+        }
+        Ok(hasher.finish())
+    }
+}
+impl<F, E> Action<F, E> for ChecksumFieldAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        if self.data_range.start > self.data_range.end {
+            return Err(ActionError::Invalid);
+        }
+        check_range(
+            data,
+            self.data_range.start,
+            self.data_range.end - self.data_range.start,
+        )?;
+        let width = self.algorithm.width();
+        check_range(data, self.field_offset, u64::from_usize(width))?;
+
+        let checksum = self.compute(data)?;
+        let mut new_data = vec![0u8; width];
+        self.endian.write_uint(u64::from(checksum), &mut new_data);
+
+        // Read in the field to store it for if the action is undone.
+        self.previous_data.resize(width, 0);
+        read_exact_at(data, self.field_offset, &mut self.previous_data)?;
+        write_at(data, self.field_offset, &new_data)?;
+        self.new_data = new_data;
+
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        write_at(data, self.field_offset, &self.previous_data)?;
+        Ok(())
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.field_range())
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Update {:?} checksum at {:#X}",
+            self.algorithm, self.field_offset
+        )
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(ChecksumFieldAction::new(
+            self.data_range.clone(),
+            self.field_offset,
+            self.algorithm,
+            self.endian,
+        )))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(
+            &mut [
+                &mut self.data_range.start,
+                &mut self.data_range.end,
+                &mut self.field_offset,
+            ],
+            delta,
+        )
+    }
+
+    fn overwrite_delta(&self) -> Option<OverwriteDelta<'_>> {
+        Some(OverwriteDelta {
+            position: self.field_offset,
+            previous: &self.previous_data,
+            new: &self.new_data,
+        })
+    }
+}
+impl MemoryUsage for ChecksumFieldAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.previous_data.len() + self.new_data.len()
+    }
+}
+
+/// The state of a checksum kept current by the editor.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ChecksumState {
+    /// The checksum of the current data.
+    Fresh(u32),
+    /// An action was applied which couldn't be incrementally tracked, so the checksum needs to be
+    /// recomputed.
+    Stale,
+}
+
+/// A checksum which is kept current as actions are applied and unapplied.
+#[derive(Debug, Clone)]
+pub(crate) struct LiveChecksum {
+    pub(crate) algorithm: ChecksumAlgorithm,
+    /// `None` when stale.
+    value: Option<u32>,
+    /// The length of the data the checksum is over.
+    length: u64,
+}
+impl LiveChecksum {
+    pub(crate) fn new(algorithm: ChecksumAlgorithm, value: u32, length: u64) -> Self {
+        Self {
+            algorithm,
+            value: Some(value),
+            length,
+        }
+    }
+
+    pub(crate) fn state(&self) -> ChecksumState {
+        self.value
+            .map_or(ChecksumState::Stale, ChecksumState::Fresh)
+    }
+
+    /// Update for an action having been applied or unapplied.
+    /// Since only the difference between the bytes matters, the same delta works for both.
+    pub(crate) fn update(&mut self, delta: Option<OverwriteDelta<'_>>) {
+        self.value = match (self.value, delta) {
+            (Some(value), Some(delta)) => match self.algorithm {
+                ChecksumAlgorithm::Crc16 => Some(u32::from(crc16_patch(
+                    value as u16,
+                    self.length,
+                    delta.position,
+                    delta.previous,
+                    delta.new,
+                ))),
+                ChecksumAlgorithm::Crc32 => Some(crc32_patch(
+                    value,
+                    self.length,
+                    delta.position,
+                    delta.previous,
+                    delta.new,
+                )),
+            },
+            _ => None,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc16, crc16_patch, crc32, crc32_patch, ChecksumAlgorithm, ChecksumState};
+    use crate::{action::ActionError, transform::ReverseAction, EditAction, Endian, Hiex};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(
+            crc32(b"The quick brown fox jumps over the lazy dog"),
+            0x414F_A339
+        );
+
+        let original = b"The quick brown fox jumps over the lazy dog";
+        let mut edited = original.to_vec();
+        edited[10..15].copy_from_slice(b"BROWN");
+        assert_eq!(
+            crc32_patch(crc32(original), 43, 10, &original[10..15], b"BROWN"),
+            crc32(&edited)
+        );
+    }
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(crc16(b""), 0);
+        assert_eq!(crc16(b"123456789"), 0xBB3D);
+
+        let original = b"The quick brown fox jumps over the lazy dog";
+        let mut edited = original.to_vec();
+        edited[40..43].copy_from_slice(b"cat");
+        assert_eq!(
+            crc16_patch(crc16(original), 43, 40, &original[40..43], b"cat"),
+            crc16(&edited)
+        );
+
+        let mut hex: Hiex<_> =
+            Hiex::from_reader(Cursor::new(b"\x00\x00123456789".to_vec())).unwrap();
+        hex.track_checksum(ChecksumAlgorithm::Crc16).unwrap();
+        hex.fixup_checksum(2..11, 0, ChecksumAlgorithm::Crc16, Endian::Big, ())
+            .unwrap();
+        assert_eq!(hex.read_amount_at(0, 2).unwrap(), [0xBB, 0x3D]);
+        let current = hex.read_amount_at(0, 11).unwrap();
+        assert_eq!(
+            hex.current_checksum(),
+            Some(ChecksumState::Fresh(u32::from(crc16(&current))))
+        );
+        hex.undo_simple().unwrap();
+        assert_eq!(hex.read_amount_at(0, 2).unwrap(), [0, 0]);
+        assert_eq!(
+            hex.current_checksum(),
+            Some(ChecksumState::Fresh(u32::from(crc16(b"\x00\x00123456789"))))
+        );
+    }
+
+    #[test]
+    fn test_live_checksum() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 7 % 256) as u8).collect();
+        let mut hex = Hiex::from_reader(Cursor::new(data.clone())).unwrap();
+        assert_eq!(hex.current_checksum(), None);
+        assert_eq!(
+            hex.track_checksum(ChecksumAlgorithm::Crc32).unwrap(),
+            crc32(&data)
+        );
+
+        hex.add_action(EditAction::new(500, b"edit".to_vec()), ())
+            .unwrap();
+        hex.add_action(EditAction::new(996, b"tail".to_vec()), ())
+            .unwrap();
+        let current = hex.read_amount_at(0, 1000).unwrap();
+        assert_eq!(
+            hex.current_checksum(),
+            Some(ChecksumState::Fresh(crc32(&current)))
+        );
+
+        hex.undo(()).unwrap();
+        let current = hex.read_amount_at(0, 1000).unwrap();
+        assert_eq!(
+            hex.current_checksum(),
+            Some(ChecksumState::Fresh(crc32(&current)))
+        );
+
+        // Actions which don't report their changed bytes make it stale.
+        hex.add_action(ReverseAction::new(0, 10), ()).unwrap();
+        assert_eq!(hex.current_checksum(), Some(ChecksumState::Stale));
+        let current = hex.read_amount_at(0, 1000).unwrap();
+        assert_eq!(hex.recompute_checksum().unwrap(), Some(crc32(&current)));
+        assert_eq!(
+            hex.current_checksum(),
+            Some(ChecksumState::Fresh(crc32(&current)))
+        );
+    }
+
+    #[test]
+    fn test_checksum_field() {
+        let mut hex: Hiex<_> =
+            Hiex::from_reader(Cursor::new(b"123456789\0\0\0\0".to_vec())).unwrap();
+        hex.fixup_checksum(0..9, 9, ChecksumAlgorithm::Crc32, Endian::Little, ())
+            .unwrap();
+        assert_eq!(
+            hex.read_amount_at(9, 4).unwrap(),
+            0xCBF4_3926u32.to_le_bytes()
+        );
+        hex.undo_simple().unwrap();
+        assert_eq!(hex.read_amount_at(9, 4).unwrap(), [0; 4]);
+
+        // The field is within the range, so its previous value doesn't affect the checksum.
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"1234abcd56789".to_vec())).unwrap();
+        hex.fixup_checksum(0..13, 4, ChecksumAlgorithm::Crc32, Endian::Big, ())
+            .unwrap();
+        assert_eq!(
+            hex.read_amount_at(0, 13).unwrap(),
+            [&b"1234"[..], &0xCBF4_3926u32.to_be_bytes(), b"56789"].concat()
+        );
+        hex.fixup_checksum(0..13, 4, ChecksumAlgorithm::Crc32, Endian::Big, ())
+            .unwrap();
+        assert_eq!(
+            hex.read_amount_at(4, 4).unwrap(),
+            0xCBF4_3926u32.to_be_bytes()
+        );
+        hex.undo_simple().unwrap();
+        hex.undo_simple().unwrap();
+        assert_eq!(hex.read_amount_at(0, 13).unwrap(), b"1234abcd56789");
+
+        let (_, err) = hex
+            .fixup_checksum(0..13, 10, ChecksumAlgorithm::Crc32, Endian::Big, ())
+            .unwrap_err();
+        assert!(matches!(err, ActionError::Invalid));
+    }
+}
@@ -0,0 +1,182 @@
+//! Closing the editor, without silently losing unsaved changes.
+use crate::Hiex;
+use std::io::{Read, Seek, Write};
+
+/// The changes which haven't been saved, see [`Hiex::unsaved_summary`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct UnsavedSummary {
+    /// The amount of actions applied or undone since the data was last saved (or opened).
+    pub actions: usize,
+    /// The sum of the lengths of the ranges those actions affected, which may count bytes more
+    /// than once. `None` if any of them doesn't report its range.
+    pub affected_bytes: Option<u64>,
+}
+
+/// Why [`Hiex::close`] didn't close the editor. Either way, the editor is returned intact.
+pub enum CloseError<F, E = ()>
+where
+    F: Read + Seek + Write,
+{
+    /// There are unsaved changes.
+    Unsaved {
+        hex: Box<Hiex<F, E>>,
+        summary: UnsavedSummary,
+    },
+    /// Flushing the data failed.
+    Io {
+        hex: Box<Hiex<F, E>>,
+        error: std::io::Error,
+    },
+}
+impl<F, E> CloseError<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Get back the editor, to save it or to use [`Hiex::close_discarding`].
+    pub fn into_hex(self) -> Hiex<F, E> {
+        match self {
+            CloseError::Unsaved { hex, .. } | CloseError::Io { hex, .. } => *hex,
+        }
+    }
+}
+impl<F, E> std::fmt::Debug for CloseError<F, E>
+where
+    F: Read + Seek + Write,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CloseError::Unsaved { summary, .. } => f
+                .debug_struct("Unsaved")
+                .field("summary", summary)
+                .finish_non_exhaustive(),
+            CloseError::Io { error, .. } => f
+                .debug_struct("Io")
+                .field("error", error)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Whether the data differs from when it was opened or last saved with [`Hiex::save_to`] or
+    /// [`Hiex::save_to_no_trunc`]. Undoing back to that point makes it clean again, unless that
+    /// point was discarded from the history by adding an action after undoing past it.
+    pub fn is_dirty(&self) -> bool {
+        self.background_saved_index().or(self.clean_index) != Some(self.actions.past_len())
+    }
+
+    /// Summarizes the unsaved changes, or returns `None` if there aren't any.
+    pub fn unsaved_summary(&self) -> Option<UnsavedSummary> {
+        if !self.is_dirty() {
+            return None;
+        }
+
+        let current = self.actions.past_len();
+        // If the clean point was discarded, every applied action is suspect.
+        let clean = self
+            .background_saved_index()
+            .or(self.clean_index)
+            .unwrap_or(0);
+        let changed = clean.min(current)..clean.max(current);
+        let affected_bytes = changed
+            .clone()
+            .map(|index| {
+                let range = self.actions.get(index)?.affected_range()?;
+                Some(range.end.saturating_sub(range.start))
+            })
+            .sum();
+        Some(UnsavedSummary {
+            actions: changed.len(),
+            affected_bytes,
+        })
+    }
+
+    /// Flushes the data and returns the inner reader, if there are no unsaved changes (see
+    /// [`Hiex::is_dirty`]). Otherwise the editor is returned in the error, along with a summary of
+    /// the changes, so that it can be saved or closed with [`Hiex::close_discarding`].
+    pub fn close(mut self) -> Result<F, CloseError<F, E>> {
+        if let Some(summary) = self.unsaved_summary() {
+            return Err(CloseError::Unsaved {
+                hex: Box::new(self),
+                summary,
+            });
+        }
+        if let Err(error) = self.flush_inner() {
+            return Err(CloseError::Io {
+                hex: Box::new(self),
+                error,
+            });
+        }
+        Ok(self.into_inner())
+    }
+
+    /// Returns the inner reader, discarding the history even if there are unsaved changes.
+    pub fn close_discarding(self) -> F {
+        self.into_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CloseError;
+    use crate::{EditAction, Hiex};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_close_clean() {
+        let hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"0123".to_vec())).unwrap();
+        assert!(!hex.is_dirty());
+        assert_eq!(hex.close().unwrap().into_inner(), b"0123");
+
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"0123".to_vec())).unwrap();
+        hex.add_action_simple(EditAction::new(0, b"a".to_vec()))
+            .unwrap();
+        let mut saved = Cursor::new(Vec::new());
+        hex.save_to(&mut saved).unwrap();
+        assert!(!hex.is_dirty());
+        // Undoing past the save and redoing back to it.
+        hex.undo_simple().unwrap();
+        assert!(hex.is_dirty());
+        hex.redo_simple().unwrap();
+        assert_eq!(hex.close().unwrap().into_inner(), b"a123");
+    }
+
+    #[test]
+    fn test_close_dirty() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"0123".to_vec())).unwrap();
+        hex.add_action_simple(EditAction::new(0, b"ab".to_vec()))
+            .unwrap();
+        hex.add_action_simple(EditAction::new(3, b"c".to_vec()))
+            .unwrap();
+
+        let err = hex.close().unwrap_err();
+        let mut hex = match err {
+            CloseError::Unsaved { hex, summary } => {
+                assert_eq!(summary.actions, 2);
+                assert_eq!(summary.affected_bytes, Some(3));
+                *hex
+            }
+            CloseError::Io { .. } => panic!("expected unsaved changes"),
+        };
+        // The editor still works.
+        hex.undo_simple().unwrap();
+        assert_eq!(hex.read_amount_at(0, 4).unwrap(), b"ab23");
+
+        // Saved after the first edit, then diverging from it means it can't become clean again.
+        hex.save_to(&mut Cursor::new(Vec::new())).unwrap();
+        assert!(!hex.is_dirty());
+        hex.undo_simple().unwrap();
+        assert!(hex.is_dirty());
+        hex.add_action_simple(EditAction::new(1, b"y".to_vec()))
+            .unwrap();
+        hex.undo_simple().unwrap();
+        assert!(hex.is_dirty());
+        hex.redo_simple().unwrap();
+        assert_eq!(hex.unsaved_summary().unwrap().actions, 1);
+        let hex = hex.close().unwrap_err().into_hex();
+        assert_eq!(hex.close_discarding().into_inner(), b"0y23");
+    }
+}
@@ -0,0 +1,295 @@
+//! Measures of how similar two sequences of bytes are.
+use crate::{clamp_usize, Hiex, CHUNK_SIZE};
+use std::{
+    collections::{hash_map::DefaultHasher, HashSet},
+    hash::{Hash, Hasher},
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+use usize_cast::FromUsize;
+
+/// The result of comparing two sequences of bytes position by position.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Similarity {
+    pub length_a: u64,
+    pub length_b: u64,
+    /// The amount of bytes compared, which is the length of the shorter sequence.
+    pub compared: u64,
+    /// The amount of compared bytes which differ, the Hamming distance of the overlapping prefix.
+    pub differing: u64,
+}
+impl Similarity {
+    /// How much longer `b` is than `a`.
+    pub fn length_delta(&self) -> i128 {
+        i128::from(self.length_b) - i128::from(self.length_a)
+    }
+
+    /// The percentage of bytes which are the same, out of the length of the longer sequence, so
+    /// that bytes only in one of them count as differing.
+    /// Two empty sequences are 100% similar.
+    pub fn match_percentage(&self) -> f64 {
+        let total = self.length_a.max(self.length_b);
+        if total == 0 {
+            return 100.0;
+        }
+        (self.compared - self.differing) as f64 / total as f64 * 100.0
+    }
+
+    fn add(&mut self, a: &[u8], b: &[u8]) {
+        self.length_a += u64::from_usize(a.len());
+        self.length_b += u64::from_usize(b.len());
+        let compared = a.len().min(b.len());
+        self.compared += u64::from_usize(compared);
+        self.differing += u64::from_usize(
+            a[..compared]
+                .iter()
+                .zip(&b[..compared])
+                .filter(|(x, y)| x != y)
+                .count(),
+        );
+    }
+}
+
+/// Fills `buf` from `reader` until it is full or the reader ends, returning how much was read.
+fn read_full<R>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<usize>
+where
+    R: Read,
+{
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(amount) => read += amount,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(read)
+}
+
+/// Compares `a` and `b` position by position, reading each in chunks until its end.
+pub fn similarity<A, B>(mut a: A, mut b: B) -> std::io::Result<Similarity>
+where
+    A: Read,
+    B: Read,
+{
+    let mut similarity = Similarity {
+        length_a: 0,
+        length_b: 0,
+        compared: 0,
+        differing: 0,
+    };
+    let mut chunk_a = vec![0u8; CHUNK_SIZE];
+    let mut chunk_b = vec![0u8; CHUNK_SIZE];
+    loop {
+        let amount_a = read_full(&mut a, &mut chunk_a)?;
+        let amount_b = read_full(&mut b, &mut chunk_b)?;
+        if amount_a == 0 && amount_b == 0 {
+            break;
+        }
+        similarity.add(&chunk_a[..amount_a], &chunk_b[..amount_b]);
+    }
+    Ok(similarity)
+}
+
+/// The result of [`block_similarity`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct BlockSimilarity {
+    /// The amount of blocks in `a`, including a final partial block.
+    pub blocks: u64,
+    /// The amount of blocks in `a` which are also blocks of `b`.
+    pub matched: u64,
+}
+impl BlockSimilarity {
+    /// The fraction of the blocks of `a` which are also in `b`, from `0.0` to `1.0`.
+    /// No blocks at all is treated as fully similar.
+    pub fn fraction(&self) -> f64 {
+        if self.blocks == 0 {
+            return 1.0;
+        }
+        self.matched as f64 / self.blocks as f64
+    }
+}
+
+fn block_hash(block: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    block.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Splits both `a` and `b` into blocks of `block_size` bytes, and counts how many of the blocks of
+/// `a` are anywhere in `b`. Unlike [`similarity`], content which has moved by a multiple of
+/// `block_size` still matches.
+/// Blocks are compared by a hash of them, so only the hashes of `b` are held in memory, and a hash
+/// collision could count a block as matching when it isn't.
+/// Fails with `ErrorKind::InvalidInput` if `block_size` is `0`.
+pub fn block_similarity<A, B>(
+    mut a: A,
+    mut b: B,
+    block_size: usize,
+) -> std::io::Result<BlockSimilarity>
+where
+    A: Read,
+    B: Read,
+{
+    if block_size == 0 {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            "block size is zero",
+        ));
+    }
+
+    let mut block = vec![0u8; block_size];
+    let mut hashes = HashSet::new();
+    loop {
+        let amount = read_full(&mut b, &mut block)?;
+        if amount == 0 {
+            break;
+        }
+        hashes.insert(block_hash(&block[..amount]));
+    }
+
+    let mut result = BlockSimilarity {
+        blocks: 0,
+        matched: 0,
+    };
+    loop {
+        let amount = read_full(&mut a, &mut block)?;
+        if amount == 0 {
+            break;
+        }
+        result.blocks += 1;
+        if hashes.contains(&block_hash(&block[..amount])) {
+            result.matched += 1;
+        }
+    }
+    Ok(result)
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Compares the bytes within `range_a` with those within `range_b`, position by position, as
+    /// [`similarity`] does. The ranges may be different lengths, and may overlap.
+    /// Fails with `ErrorKind::InvalidInput` if either runs past the end of the data.
+    pub fn range_similarity(
+        &mut self,
+        range_a: Range<u64>,
+        range_b: Range<u64>,
+    ) -> std::io::Result<Similarity> {
+        let length = self.length()?;
+        if range_a.end > length || range_b.end > length {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "compared range runs past the end of the data",
+            ));
+        }
+
+        let length_a = range_a.end.saturating_sub(range_a.start);
+        let length_b = range_b.end.saturating_sub(range_b.start);
+        let mut similarity = Similarity {
+            length_a: 0,
+            length_b: 0,
+            compared: 0,
+            differing: 0,
+        };
+        let mut chunk_a = vec![0u8; CHUNK_SIZE];
+        let mut chunk_b = vec![0u8; CHUNK_SIZE];
+        let mut offset = 0;
+        while offset < length_a.max(length_b) {
+            let amount = |total: u64| clamp_usize(total.saturating_sub(offset), CHUNK_SIZE);
+            let (amount_a, amount_b) = (amount(length_a), amount(length_b));
+            self.seek(SeekFrom::Start(range_a.start + offset))?;
+            self.read_exact(&mut chunk_a[..amount_a])?;
+            self.seek(SeekFrom::Start(range_b.start + offset))?;
+            self.read_exact(&mut chunk_b[..amount_b])?;
+            similarity.add(&chunk_a[..amount_a], &chunk_b[..amount_b]);
+            offset += u64::from_usize(amount_a.max(amount_b));
+        }
+        Ok(similarity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{block_similarity, similarity};
+    use crate::Hiex;
+    use std::io::Cursor;
+
+    fn data(length: usize) -> Vec<u8> {
+        (0..length).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_similarity() {
+        let a = data(100_000);
+        let same = similarity(&a[..], &a[..]).unwrap();
+        assert_eq!(same.compared, 100_000);
+        assert_eq!(same.differing, 0);
+        assert_eq!(same.length_delta(), 0);
+        assert_eq!(same.match_percentage(), 100.0);
+
+        let inverted: Vec<u8> = a.iter().map(|byte| !byte).collect();
+        let different = similarity(&a[..], &inverted[..]).unwrap();
+        assert_eq!(different.differing, 100_000);
+        assert_eq!(different.match_percentage(), 0.0);
+
+        // Every hundredth byte changed.
+        let mut changed = a.clone();
+        for byte in changed.iter_mut().step_by(100) {
+            *byte = byte.wrapping_add(1);
+        }
+        let one_percent = similarity(&a[..], &changed[..]).unwrap();
+        assert_eq!(one_percent.differing, 1000);
+        assert!((one_percent.match_percentage() - 99.0).abs() < 1e-9);
+
+        // The extra bytes of the longer one count against the percentage.
+        let longer = similarity(&a[..50_000], &a[..]).unwrap();
+        assert_eq!(longer.compared, 50_000);
+        assert_eq!(longer.differing, 0);
+        assert_eq!(longer.length_delta(), 50_000);
+        assert_eq!(longer.match_percentage(), 50.0);
+        assert_eq!(
+            similarity(&b""[..], &b""[..]).unwrap().match_percentage(),
+            100.0
+        );
+    }
+
+    #[test]
+    fn test_block_similarity() {
+        let a = data(1000);
+        // Shifted by a whole block, which `similarity` would see as almost entirely different.
+        let mut shifted = vec![0u8; 100];
+        shifted.extend_from_slice(&a);
+        let blocks = block_similarity(&a[..], &shifted[..], 100).unwrap();
+        assert_eq!(blocks.blocks, 10);
+        assert_eq!(blocks.matched, 10);
+        assert_eq!(blocks.fraction(), 1.0);
+        assert!(similarity(&a[..], &shifted[..]).unwrap().match_percentage() < 10.0);
+
+        let mut changed = a.clone();
+        changed[250] ^= 1;
+        let blocks = block_similarity(&changed[..], &a[..], 100).unwrap();
+        assert_eq!(blocks.matched, 9);
+        assert!(block_similarity(&a[..], &a[..], 0).is_err());
+    }
+
+    #[test]
+    fn test_range_similarity() {
+        let mut data = data(200_000);
+        data[150_000] = !data[150_000];
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(data)).unwrap();
+        let result = hex.range_similarity(0..100_000, 100_000..200_000).unwrap();
+        // 100_000 isn't a multiple of 251, so the halves are offset from each other.
+        assert_eq!(result.compared, 100_000);
+        assert!(result.differing > 90_000);
+
+        let result = hex
+            .range_similarity(100_000..200_000, 100_000..190_000)
+            .unwrap();
+        assert_eq!(result.differing, 0);
+        assert_eq!(result.length_delta(), -10_000);
+        assert!(hex.range_similarity(0..10, 199_995..200_005).is_err());
+    }
+}
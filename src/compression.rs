@@ -0,0 +1,354 @@
+//! Replacing ranges of the data with their compressed or decompressed form.
+use crate::{
+    action::{
+        check_range, describe_bytes, translate_offsets, Action, ActionError, MemoryUsage,
+        OffsetShift,
+    },
+    checked_usize, resize_at,
+    truncate::Truncate,
+    write_at, Hiex,
+};
+use flate2::{
+    bufread::{DeflateDecoder, GzDecoder, ZlibDecoder},
+    write::{DeflateEncoder, GzEncoder, ZlibEncoder},
+    Compression,
+};
+use std::{
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+use usize_cast::FromUsize;
+
+/// The container around the deflate stream.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CompressionFormat {
+    /// RFC 1950
+    Zlib,
+    /// RFC 1952
+    Gzip,
+    /// Raw deflate (RFC 1951), without any header or checksum.
+    Deflate,
+}
+impl CompressionFormat {
+    /// Compress `data` at `level`, from 0 (none) to 9 (best).
+    pub fn compress(self, data: &[u8], level: u32) -> std::io::Result<Vec<u8>> {
+        let level = Compression::new(level);
+        match self {
+            CompressionFormat::Zlib => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), level);
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            CompressionFormat::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), level);
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            CompressionFormat::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), level);
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+
+    /// Decompress the stream at the start of `data`. Anything after the end of the stream is
+    /// ignored.
+    pub fn decompress(self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        self.decompress_prefix(data, None)
+            .map(|(decompressed, _)| decompressed)
+    }
+
+    /// Decompress the stream at the start of `data`, returning it along with the amount of bytes
+    /// of `data` the stream took up. Fails with `ErrorKind::InvalidData` if it decompresses to
+    /// more than `limit` bytes, if given.
+    pub fn decompress_prefix(
+        self,
+        data: &[u8],
+        limit: Option<u64>,
+    ) -> std::io::Result<(Vec<u8>, usize)> {
+        let limit = limit.unwrap_or(u64::MAX);
+        let mut decompressed = Vec::new();
+        let rest = match self {
+            CompressionFormat::Zlib => {
+                let mut decoder = ZlibDecoder::new(data);
+                read_limited(&mut decoder, limit, &mut decompressed)?;
+                decoder.into_inner()
+            }
+            CompressionFormat::Gzip => {
+                let mut decoder = GzDecoder::new(data);
+                read_limited(&mut decoder, limit, &mut decompressed)?;
+                decoder.into_inner()
+            }
+            CompressionFormat::Deflate => {
+                let mut decoder = DeflateDecoder::new(data);
+                read_limited(&mut decoder, limit, &mut decompressed)?;
+                decoder.into_inner()
+            }
+        };
+        Ok((decompressed, data.len() - rest.len()))
+    }
+}
+
+/// Reads all of `reader` into `buffer`, failing with `ErrorKind::InvalidData` if there are more
+/// than `limit` bytes.
+fn read_limited<R>(reader: &mut R, limit: u64, buffer: &mut Vec<u8>) -> std::io::Result<()>
+where
+    R: Read,
+{
+    reader.take(limit.saturating_add(1)).read_to_end(buffer)?;
+    if u64::from_usize(buffer.len()) > limit {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            "decompressed data is larger than the limit",
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CompressionMode {
+    Compress,
+    Decompress,
+}
+
+/// An action which replaces the bytes within a range with their compressed or decompressed form,
+/// growing or shrinking the data to fit it and moving the bytes after the range.
+/// Decompressing fails with `ActionError::Invalid` unless the range holds exactly one valid
+/// stream, with nothing after it, and if it decompresses to more than the limit given with
+/// [`CompressRangeAction::with_limit`].
+/// The range is read into memory, and its previous bytes are stored for if the action is undone.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CompressRangeAction {
+    pub position: u64,
+    pub length: u64,
+    pub format: CompressionFormat,
+    pub mode: CompressionMode,
+    /// Only used when compressing, see [`CompressRangeAction::with_level`].
+    pub level: u32,
+    /// Only used when decompressing, see [`CompressRangeAction::with_limit`].
+    pub limit: Option<u64>,
+    previous_data: Vec<u8>,
+    new_length: u64,
+}
+impl CompressRangeAction {
+    pub fn new(
+        position: u64,
+        length: u64,
+        format: CompressionFormat,
+        mode: CompressionMode,
+    ) -> Self {
+        Self {
+            position,
+            length,
+            format,
+            mode,
+            level: Compression::default().level(),
+            limit: None,
+            previous_data: Vec::new(),
+            new_length: 0,
+        }
+    }
+
+    /// The compression level, from 0 (none) to 9 (best). Defaults to 6.
+    pub fn with_level(mut self, level: u32) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// The most bytes decompressing may produce, such as to guard against data which decompresses
+    /// to far more than fits in memory. Unlimited by default.
+    pub fn with_limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+impl<F, E> Action<F, E> for CompressRangeAction
+where
+    F: Read + Seek + Write + Truncate,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        check_range(data, self.position, self.length)?;
+
+        let mut previous = vec![0u8; checked_usize(self.length)?];
+        data.seek(SeekFrom::Start(self.position))?;
+        data.read_exact(&mut previous)?;
+        let new_data = match self.mode {
+            CompressionMode::Compress => self.format.compress(&previous, self.level)?,
+            CompressionMode::Decompress => match self
+                .format
+                .decompress_prefix(&previous, self.limit)
+            {
+                Ok((decompressed, consumed)) if consumed == previous.len() => decompressed,
+                // The stream ended before the range did.
+                Ok(_) => return Err(ActionError::Invalid),
+                Err(err)
+                    if matches!(err.kind(), ErrorKind::InvalidInput | ErrorKind::InvalidData) =>
+                {
+                    return Err(ActionError::Invalid)
+                }
+                Err(err) => return Err(err.into()),
+            },
+        };
+
+        let new_length = u64::from_usize(new_data.len());
+        resize_at(data, self.position, self.length, new_length)?;
+        write_at(data, self.position, &new_data)?;
+        self.previous_data = previous;
+        self.new_length = new_length;
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        resize_at(data, self.position, self.new_length, self.length)?;
+        write_at(data, self.position, &self.previous_data)?;
+        Ok(())
+    }
+
+    /// Everything from the position onwards is moved.
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.position..u64::MAX)
+    }
+
+    fn description(&self) -> String {
+        let verb = match self.mode {
+            CompressionMode::Compress => "Compress",
+            CompressionMode::Decompress => "Decompress",
+        };
+        describe_bytes(verb, self.position, self.length)
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        let mut action =
+            CompressRangeAction::new(self.position, self.length, self.format, self.mode)
+                .with_level(self.level);
+        action.limit = self.limit;
+        Some(Box::new(action))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.position], delta)
+    }
+
+    fn offset_shift(&self) -> Option<OffsetShift> {
+        OffsetShift::resize(self.position, self.length, self.new_length)
+    }
+}
+impl MemoryUsage for CompressRangeAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.previous_data.len()
+    }
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write + Truncate,
+{
+    /// Replaces the bytes within `range` with their compressed form, as a single action.
+    /// See [`CompressRangeAction`].
+    pub fn compress_range(
+        &mut self,
+        range: Range<u64>,
+        format: CompressionFormat,
+        other: E,
+    ) -> Result<(), (CompressRangeAction, ActionError)> {
+        let length = range.end.saturating_sub(range.start);
+        let action =
+            CompressRangeAction::new(range.start, length, format, CompressionMode::Compress);
+        self.add_action(action, other)
+    }
+
+    /// Replaces the compressed stream within `range` with its decompressed form, as a single
+    /// action. See [`CompressRangeAction`].
+    pub fn decompress_range(
+        &mut self,
+        range: Range<u64>,
+        format: CompressionFormat,
+        other: E,
+    ) -> Result<(), (CompressRangeAction, ActionError)> {
+        let length = range.end.saturating_sub(range.start);
+        let action =
+            CompressRangeAction::new(range.start, length, format, CompressionMode::Decompress);
+        self.add_action(action, other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CompressRangeAction, CompressionFormat, CompressionMode};
+    use crate::{action::ActionError, Hiex};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_compress_range() {
+        let text = b"abcabcabcabcabcabcabcabcabcabcabcabcabcabcabcabcabcabc".repeat(4);
+        let formats = [
+            CompressionFormat::Zlib,
+            CompressionFormat::Gzip,
+            CompressionFormat::Deflate,
+        ];
+        for &format in &formats {
+            let mut data = b"<".to_vec();
+            data.extend_from_slice(&text);
+            data.push(b'>');
+            let mut hex = Hiex::from_reader(Cursor::new(data.clone())).unwrap();
+            let end = 1 + text.len() as u64;
+
+            hex.compress_range(1..end, format, ()).unwrap();
+            let length = hex.length().unwrap();
+            assert!(length < data.len() as u64);
+            let compressed = hex.read_amount_at(1, length as usize - 2).unwrap();
+            assert_eq!(format.decompress(&compressed).unwrap(), text);
+            assert_eq!(hex.read_amount_at(length - 1, 1).unwrap(), b">");
+
+            // Decompressing it again, then undoing both.
+            hex.decompress_range(1..length - 1, format, ()).unwrap();
+            assert_eq!(hex.read_amount_at(0, data.len()).unwrap(), data);
+            hex.undo(()).unwrap();
+            assert_eq!(hex.length().unwrap(), length);
+            hex.undo(()).unwrap();
+            assert_eq!(hex.length().unwrap(), data.len() as u64);
+            assert_eq!(hex.read_amount_at(0, data.len()).unwrap(), data);
+
+            let (_, err) = hex.decompress_range(0..end, format, ()).unwrap_err();
+            assert!(matches!(err, ActionError::Invalid));
+            assert_eq!(hex.read_amount_at(0, data.len()).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_decompress_range_checks() {
+        let text = b"abcabcabcabcabcabcabcabcabcabcabcabcabcabcabcabcabcabc".repeat(4);
+        for &format in &[
+            CompressionFormat::Zlib,
+            CompressionFormat::Gzip,
+            CompressionFormat::Deflate,
+        ] {
+            let mut data = format.compress(&text, 6).unwrap();
+            let length = data.len() as u64;
+            data.extend_from_slice(b"trailing");
+            let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(data.clone())).unwrap();
+            let (decompressed, consumed) = format.decompress_prefix(&data, None).unwrap();
+            assert_eq!(decompressed, text);
+            assert_eq!(consumed as u64, length);
+
+            // Including the bytes after the stream isn't allowed, rather than losing them.
+            let (_, err) = hex.decompress_range(0..length + 2, format, ()).unwrap_err();
+            assert!(matches!(err, ActionError::Invalid));
+
+            let limited = |limit| {
+                CompressRangeAction::new(0, length, format, CompressionMode::Decompress)
+                    .with_limit(limit)
+            };
+            let (_, err) = hex
+                .add_action_simple(limited(text.len() as u64 - 1))
+                .unwrap_err();
+            assert!(matches!(err, ActionError::Invalid));
+            assert_eq!(hex.read_amount_at(0, data.len()).unwrap(), data);
+
+            hex.add_action_simple(limited(text.len() as u64)).unwrap();
+            assert_eq!(hex.read_amount_at(0, text.len()).unwrap(), text);
+            assert_eq!(hex.length().unwrap(), text.len() as u64 + 8);
+        }
+    }
+}
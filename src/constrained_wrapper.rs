@@ -1,11 +1,15 @@
-use std::{
-    convert::TryInto,
-    io::{ErrorKind, Read, Seek, SeekFrom, Write},
-    ops::Range,
+#[cfg(feature = "no_std_io")]
+use alloc::vec::Vec;
+use core::ops::Range;
+use usize_cast::FromUsize;
+
+use crate::{
+    clamp_usize,
+    io::{self, ErrorKind, Read, Seek, SeekFrom, Write},
+    stream_len, stream_position,
 };
-use usize_cast::IntoUsize;
-
-use crate::{stream_len, stream_position};
+#[cfg(not(feature = "no_std_io"))]
+use crate::{known_len, known_length::KnownLength, positioned_io::PositionedIo};
 
 pub type ViewRange<T> = Range<T>;
 
@@ -30,22 +34,46 @@ pub enum IntoOffsetError {
     /// `offset > range.end`
     OutOfUpperBounds,
 }
-impl From<IntoOffsetError> for std::io::Error {
+impl From<IntoOffsetError> for io::Error {
     fn from(err: IntoOffsetError) -> Self {
-        match err {
-            IntoOffsetError::OutOfLowerBounds | IntoOffsetError::OutOfUpperBounds => {
-                ErrorKind::InvalidInput.into()
-            }
-        }
+        let message = match err {
+            IntoOffsetError::OutOfLowerBounds => "position is before the start of the range",
+            IntoOffsetError::OutOfUpperBounds => "position is past the end of the range",
+        };
+        io::Error::new(ErrorKind::InvalidInput, message)
     }
 }
 
+/// The range given to [`ConstrainedWrapper::new`] ends past the end of the reader.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct RangePastEnd {
+    /// The end of the range.
+    pub end: u64,
+    /// The length of the reader.
+    pub length: u64,
+}
+impl core::fmt::Display for RangePastEnd {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "range ends at {} but the reader is only {} bytes long",
+            self.end, self.length
+        )
+    }
+}
+#[cfg(not(feature = "no_std_io"))]
+impl std::error::Error for RangePastEnd {}
+
 /// A wrapper around a Reader+Seeker (and potentially Writer!) that stops reading/writing/seeking
 /// past/before a certain point
 /// Position can
 pub struct ConstrainedWrapper<R: Read + Seek> {
     reader: R,
     range: ViewRange<u64>,
+    /// The end of the range as it was requested, which `range.end` may have been clamped from.
+    requested_end: u64,
+    /// Finds the length of the reader, see [`ConstrainedWrapper::with_known_length`].
+    length: fn(&mut R) -> io::Result<u64>,
 }
 impl<R> ConstrainedWrapper<R>
 where
@@ -53,8 +81,41 @@ where
 {
     /// Creates a `ConstrainedWrapper` that makes sure that the reader is within range.
     /// If it is _not_ in range, then it seeks to `range.start`, otherwise it does not modify it.
-    pub fn new(mut reader: R, range: ViewRange<u64>) -> std::io::Result<Self> {
+    /// Fails with `ErrorKind::InvalidInput` carrying a [`RangePastEnd`] if the range ends past the
+    /// end of the reader. See [`ConstrainedWrapper::new_clamped`] to allow that.
+    pub fn new(mut reader: R, range: ViewRange<u64>) -> io::Result<Self> {
+        let range = sort_range(range);
+        let length = stream_len(&mut reader)?;
+        if range.end > length {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                RangePastEnd {
+                    end: range.end,
+                    length,
+                },
+            ));
+        }
+        Self::new_within(reader, range)
+    }
+
+    /// Creates a `ConstrainedWrapper` like [`ConstrainedWrapper::new`], but if the range ends past
+    /// the end of the reader then its end is clamped to the end of the reader, as [`range`] will
+    /// show. If the reader grows, [`refresh_range_end`] extends it again, up to the end given here.
+    ///
+    /// [`range`]: ConstrainedWrapper::range
+    /// [`refresh_range_end`]: ConstrainedWrapper::refresh_range_end
+    pub fn new_clamped(mut reader: R, range: ViewRange<u64>) -> io::Result<Self> {
         let range = sort_range(range);
+        let requested_end = range.end;
+        let length = stream_len(&mut reader)?;
+        let end = range.end.min(length).max(range.start);
+        let mut wrapper = Self::new_within(reader, range.start..end)?;
+        wrapper.requested_end = requested_end;
+        Ok(wrapper)
+    }
+
+    /// Seeks to `range.start` if the reader isn't within `range`.
+    fn new_within(mut reader: R, range: ViewRange<u64>) -> io::Result<Self> {
         let position = stream_position(&mut reader)?;
         if position < range.start || position > range.end {
             reader.seek(SeekFrom::Start(range.start))?;
@@ -70,7 +131,23 @@ where
             let position = stream_position(&mut reader).unwrap();
             position >= range.start && position <= range.end
         });
-        Self { reader, range }
+        Self {
+            requested_end: range.end,
+            reader,
+            range,
+            length: stream_len::<R>,
+        }
+    }
+
+    /// Uses [`KnownLength`] to find the length of the reader when seeking, rather than seeking to
+    /// its end.
+    #[cfg(not(feature = "no_std_io"))]
+    pub fn with_known_length(mut self) -> Self
+    where
+        R: KnownLength,
+    {
+        self.length = known_len::<R>;
+        self
     }
 
     /// Consume self and return inner reader.
@@ -86,6 +163,26 @@ where
         &self.range
     }
 
+    /// Gets a mutable reference to the inner reader.
+    /// Its position should be kept within the range. If it grows or shrinks, call
+    /// [`ConstrainedWrapper::refresh_range_end`] afterwards.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Probes the length of the reader again, after it has grown or shrunk, and moves the end of
+    /// the range to match it, though never past the end the range was created with. If the
+    /// position is now past the end, it is moved to the end.
+    /// Returns the new [`limit`](ConstrainedWrapper::limit).
+    pub fn refresh_range_end(&mut self) -> io::Result<u64> {
+        let length = (self.length)(&mut self.reader)?;
+        self.range.end = self.requested_end.min(length).max(self.range.start);
+        if stream_position(&mut self.reader)? > self.range.end {
+            self.reader.seek(SeekFrom::Start(self.range.end))?;
+        }
+        Ok(self.limit())
+    }
+
     /// Converts an offset into the `reader` into an absolute position into the reader.
     /// If offset is past end, it is clamped to the last position
     pub fn position_from_offset(&self, offset: u64) -> u64 {
@@ -103,13 +200,11 @@ where
     }
 
     /// Get the amount of bytes left to consume.
-    fn remaining_bytes(&mut self) -> std::io::Result<u64> {
+    fn remaining_bytes(&mut self) -> io::Result<u64> {
         // The current position in the wrapper. Can't pass `self` to `stream_position`..
-        let current_offset: u64 = self.seek(SeekFrom::Current(0))?;
-        dbg!(current_offset);
+        let current_offset: u64 = self.stream_position()?;
         // The last point
         let offset_end: u64 = self.position_into_offset(self.range.end)?;
-        dbg!(offset_end);
         // The maximum amount of bytes that can be used.
         Ok(offset_end.checked_sub(current_offset).unwrap())
     }
@@ -119,22 +214,28 @@ impl<R> Write for ConstrainedWrapper<R>
 where
     R: Read + Seek + Write,
 {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         // The starting position
         let absolute_position = stream_position(&mut self.reader)?;
-        dbg!(absolute_position);
         if absolute_position >= self.range.end {
             // If we're at the end, we can just early exit with (essentially) EOF
             Ok(0)
         } else {
             // The max length that we can write at our current position.
-            let max_length = self.remaining_bytes()?.into_usize().min(buf.len());
-            dbg!(max_length);
+            let max_length = clamp_usize(self.remaining_bytes()?, buf.len());
+            if max_length < buf.len() {
+                trace!(
+                    position = absolute_position,
+                    requested = buf.len(),
+                    allowed = max_length;
+                    "clamped write to the end of the range"
+                );
+            }
             self.reader.write(&buf[..max_length])
         }
     }
 
-    fn flush(&mut self) -> std::io::Result<()> {
+    fn flush(&mut self) -> io::Result<()> {
         self.reader.flush()
     }
 }
@@ -142,9 +243,9 @@ impl<R> Read for ConstrainedWrapper<R>
 where
     R: Read + Seek,
 {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         // Get the max length of the data we can stuff in a buffer.
-        let max_length = self.remaining_bytes()?.into_usize().min(buf.len());
+        let max_length = clamp_usize(self.remaining_bytes()?, buf.len());
         if max_length == 0 {
             // EOF. There are no more bytes to read.
             Ok(0)
@@ -155,39 +256,51 @@ where
         }
     }
 }
+#[cfg(not(feature = "no_std_io"))]
+impl<R> KnownLength for ConstrainedWrapper<R>
+where
+    R: Read + Seek + KnownLength,
+{
+    fn known_length(&self) -> Option<u64> {
+        let length = self.reader.known_length()?;
+        Some(length.min(self.range.end).saturating_sub(self.range.start))
+    }
+}
+#[cfg(not(feature = "no_std_io"))]
+impl<R> PositionedIo for ConstrainedWrapper<R> where R: Read + Seek {}
 impl<R> Seek for ConstrainedWrapper<R>
 where
     R: Read + Seek,
 {
     /// Seek to position.
     /// The position that is returned is relative to `self.range.start`.
-    /// If values would overflow/underflow, it returns `ErrorKind::InvalidInput` as its error
-    fn seek(&mut self, seek_from: SeekFrom) -> std::io::Result<u64> {
+    /// As with `Cursor`, seeking to before the start or to a position which overflows a `u64` is
+    /// an `ErrorKind::InvalidInput` error, and leaves the position unchanged. Unlike `Cursor`,
+    /// seeking past the end puts you at the end.
+    fn seek(&mut self, seek_from: SeekFrom) -> io::Result<u64> {
+        // We do not allow seeking past the end _at all_, which is the end of the range or the end
+        // of the data, whichever comes first.
+        let end = (self.length)(&mut self.reader)?.clamp(self.range.start, self.range.end);
+        // The position and offset within the view.
         let (position, offset) = match seek_from {
-            SeekFrom::Current(offset) => (stream_position(&mut self.reader)?, offset),
-            // We do not allow seeking past the end _at all_. Seeking past the end just puts you at
-            // the last value in our range, and doesn't allow you further.
-            // TODO: verify that this is correct and not off by one
-            SeekFrom::End(offset) => (self.range.end, offset),
-            // The start is offset from `self.range.start`
-            // so we add it on, but if it overflows, we return that it was invalid input.
-            SeekFrom::Start(position) => (
-                self.range
-                    .start
-                    .checked_add(position)
-                    .ok_or(ErrorKind::InvalidInput)?,
-                0,
-            ),
+            SeekFrom::Current(offset) => {
+                let position = stream_position(&mut self.reader)?;
+                (self.position_into_offset(position)?, offset)
+            }
+            SeekFrom::End(offset) => (end - self.range.start, offset),
+            SeekFrom::Start(position) => (position, 0),
         };
-
-        // Apply the offset to the position, getting the full destination.
-        // We turn any errors of resulting negative values into invalid input errors.
-        let destination_position = apply_offset(position, offset).map_err(|err| match err {
-            // If the result after applying th eoffset was negative, then that is an invalid input.
-            OffsetError::Negative => ErrorKind::InvalidInput,
-        })?;
-        // Clamp the position down to the end position
-        let destination_position = destination_position.min(stream_len(&mut self.reader)?);
+        let destination = apply_offset(position, offset)?;
+
+        let destination_position = self.range.start.saturating_add(destination);
+        if destination_position > end {
+            trace!(
+                requested = destination_position,
+                end = end;
+                "clamped seek to the end of the data"
+            );
+        }
+        let destination_position = destination_position.min(end);
         // Finally go to the actual position that we desire.
         // We store the resulting position that we are now at, because Read can be crazy :]
         // (also it lets us avoid checking immediately again..)
@@ -198,45 +311,196 @@ where
     }
 }
 
+/// A view over several disjoint ranges of a reader, as if they were one contiguous stream.
+/// Offset `0` of the view is the start of the first range, and the offset just past the end of
+/// one range is the start of the next. Reads and writes which span ranges are split between them.
+/// Like [`ConstrainedWrapper`], seeking past the end puts you at the end, and writing there
+/// writes nothing.
+pub struct MultiRangeWrapper<R: Read + Seek> {
+    reader: R,
+    ranges: Vec<ViewRange<u64>>,
+    /// The offset in the view that each range starts at, followed by the total length.
+    starts: Vec<u64>,
+    position: u64,
+}
+impl<R> MultiRangeWrapper<R>
+where
+    R: Read + Seek,
+{
+    /// Creates a `MultiRangeWrapper` over `ranges`, which must be sorted and must not overlap
+    /// (though they may touch). Otherwise this fails with `ErrorKind::InvalidInput`, rather than
+    /// sorting or merging them, since the order of the ranges is the order of the view.
+    /// Empty ranges are dropped.
+    pub fn new(reader: R, ranges: Vec<ViewRange<u64>>) -> io::Result<Self> {
+        let ranges = ranges
+            .into_iter()
+            .filter(|range| range.start < range.end)
+            .collect::<Vec<_>>();
+        if ranges.windows(2).any(|pair| pair[0].end > pair[1].start) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "ranges must be sorted and not overlap",
+            ));
+        }
+
+        let mut starts = Vec::with_capacity(ranges.len() + 1);
+        let mut total = 0;
+        starts.push(total);
+        for range in &ranges {
+            total += range.end - range.start;
+            starts.push(total);
+        }
+        Ok(Self {
+            reader,
+            ranges,
+            starts,
+            position: 0,
+        })
+    }
+
+    /// Consume self and return inner reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    pub fn ranges(&self) -> &[ViewRange<u64>] {
+        &self.ranges
+    }
+
+    /// The total length of the ranges.
+    pub fn limit(&self) -> u64 {
+        *self.starts.last().unwrap_or(&0)
+    }
+
+    /// Converts an offset into the view into an absolute position into the reader, along with
+    /// the amount of bytes left in its range. `None` if the offset is at or past the end.
+    pub fn position_from_offset(&self, offset: u64) -> Option<(u64, u64)> {
+        if offset >= self.limit() {
+            return None;
+        }
+        // The last range which starts at or before the offset.
+        let index = self.starts.partition_point(|start| *start <= offset) - 1;
+        let within = offset - self.starts[index];
+        let range = &self.ranges[index];
+        Some((range.start + within, range.end - range.start - within))
+    }
+}
+impl<R> Read for MultiRangeWrapper<R>
+where
+    R: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        while read < buf.len() {
+            let (position, remaining) = match self.position_from_offset(self.position) {
+                Some(found) => found,
+                None => break,
+            };
+            let amount = clamp_usize(remaining, buf.len() - read);
+            self.reader.seek(SeekFrom::Start(position))?;
+            let amount = self.reader.read(&mut buf[read..read + amount])?;
+            if amount == 0 {
+                // The reader ended before the range did.
+                break;
+            }
+            read += amount;
+            self.position += u64::from_usize(amount);
+        }
+        Ok(read)
+    }
+}
+impl<R> Write for MultiRangeWrapper<R>
+where
+    R: Read + Seek + Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut written = 0;
+        while written < buf.len() {
+            let (position, remaining) = match self.position_from_offset(self.position) {
+                Some(found) => found,
+                None => break,
+            };
+            let amount = clamp_usize(remaining, buf.len() - written);
+            self.reader.seek(SeekFrom::Start(position))?;
+            let amount = self.reader.write(&buf[written..written + amount])?;
+            if amount == 0 {
+                break;
+            }
+            written += amount;
+            self.position += u64::from_usize(amount);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.reader.flush()
+    }
+}
+impl<R> Seek for MultiRangeWrapper<R>
+where
+    R: Read + Seek,
+{
+    /// Seek to position in the view, clamping to the end.
+    /// Seeking to before the start or to a position which overflows a `u64` is an
+    /// `ErrorKind::InvalidInput` error, as with `Cursor`.
+    fn seek(&mut self, seek_from: SeekFrom) -> io::Result<u64> {
+        let (position, offset) = match seek_from {
+            SeekFrom::Current(offset) => (self.position, offset),
+            SeekFrom::End(offset) => (self.limit(), offset),
+            SeekFrom::Start(position) => (position, 0),
+        };
+        let destination = apply_offset(position, offset)?;
+        self.position = destination.min(self.limit());
+        Ok(self.position)
+    }
+}
+#[cfg(not(feature = "no_std_io"))]
+impl<R> PositionedIo for MultiRangeWrapper<R> where R: Read + Seek {}
+#[cfg(not(feature = "no_std_io"))]
+impl<R> KnownLength for MultiRangeWrapper<R>
+where
+    R: Read + Seek,
+{
+    fn known_length(&self) -> Option<u64> {
+        Some(self.limit())
+    }
+}
+
+/// Why a seek offset couldn't be applied to a position.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
-enum OffsetError {
+pub(crate) enum OffsetError {
     /// The offset would result in a negative number.
     Negative,
+    /// The offset would result in a number past `u64::MAX`.
+    Overflow,
 }
-
-/// SOUNDNESS: `offset` should be negative.
-fn negative_i64_into_u64_offset(offset: i64) -> Result<u64, OffsetError> {
-    debug_assert!(offset.is_negative());
-
-    // Negate the negative value to positive.
-    // will be `None` if it is `i64::MIN`, since negated that is 1 higher than `i64::MAX`
-    let offset: Option<i64> = offset.checked_neg();
-    if let Some(offset) = offset {
-        // Since it's a negated negative number we should always be able to transform it into a u64
-        // without issue.
-        let offset: u64 = offset.try_into().unwrap();
-        Ok(offset)
-    } else {
-        Err(OffsetError::Negative)
+impl From<OffsetError> for io::Error {
+    fn from(err: OffsetError) -> Self {
+        let message = match err {
+            OffsetError::Negative => "invalid seek to a negative position",
+            OffsetError::Overflow => "invalid seek to a position past u64::MAX",
+        };
+        io::Error::new(ErrorKind::InvalidInput, message)
     }
 }
 
-fn apply_offset(position: u64, offset: i64) -> Result<u64, OffsetError> {
-    if offset.is_negative() {
-        let offset = negative_i64_into_u64_offset(offset)?;
-        position.checked_sub(offset).ok_or(OffsetError::Negative)
+/// Applies a seek `offset` to `position`, with the same bounds as `Cursor`.
+pub(crate) fn apply_offset(position: u64, offset: i64) -> Result<u64, OffsetError> {
+    position.checked_add_signed(offset).ok_or(if offset < 0 {
+        OffsetError::Negative
     } else {
-        // Offset is not negative, so that means it must be within u64
-        let offset: u64 = offset.try_into().unwrap();
-        // FIXME: check what the std library does on overflows with offsets.
-        position.checked_add(offset).ok_or(OffsetError::Negative)
-    }
+        OffsetError::Overflow
+    })
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(feature = "no_std_io")))]
 mod tests {
-    use super::{sort_range, stream_len, stream_position, ConstrainedWrapper, ViewRange};
-    use std::io::{Read, Seek, SeekFrom, Write};
+    use super::{
+        apply_offset, sort_range, stream_position, ConstrainedWrapper, MultiRangeWrapper,
+        OffsetError, RangePastEnd, ViewRange,
+    };
+    use crate::stream_len;
+    use std::io::{Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
 
     #[test]
     fn test_sort_range() {
@@ -360,4 +624,146 @@ mod tests {
         let buf = [9, 4, 5, 6, 8];
         assert!(cons.write_all(&buf).is_err());
     }
+
+    #[test]
+    fn test_clamped_range() {
+        let data: Vec<u8> = (0..10).collect();
+        let err = ConstrainedWrapper::new(Cursor::new(data.clone()), 0..1_000_000)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(
+            err.get_ref().unwrap().downcast_ref::<RangePastEnd>(),
+            Some(&RangePastEnd {
+                end: 1_000_000,
+                length: 10
+            })
+        );
+
+        let mut cons = ConstrainedWrapper::new_clamped(Cursor::new(data), 4..1_000_000).unwrap();
+        assert_eq!(cons.range(), &(4..10));
+        assert_eq!(cons.limit(), 6);
+        assert_eq!(stream_len(&mut cons).unwrap(), 6);
+        assert_eq!(cons.seek(SeekFrom::End(0)).unwrap(), 6);
+        assert_eq!(cons.seek(SeekFrom::Start(1_000)).unwrap(), 6);
+        let mut buf = [0u8; 4];
+        assert_eq!(cons.read(&mut buf).unwrap(), 0);
+        cons.seek(SeekFrom::End(-2)).unwrap();
+        assert_eq!(cons.read(&mut buf).unwrap(), 2);
+        assert_eq!(buf[..2], [8, 9]);
+
+        // The inner vector grows, so more of the requested range exists.
+        cons.get_mut().get_mut().extend_from_slice(&[10, 11, 12]);
+        assert_eq!(cons.limit(), 6);
+        assert_eq!(cons.refresh_range_end().unwrap(), 9);
+        assert_eq!(cons.range(), &(4..13));
+        assert_eq!(cons.seek(SeekFrom::End(-1)).unwrap(), 8);
+        assert_eq!(cons.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], 12);
+
+        // And shrinks, moving the position back to the end.
+        cons.get_mut().get_mut().truncate(5);
+        assert_eq!(cons.refresh_range_end().unwrap(), 1);
+        assert_eq!(stream_position(&mut cons).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_apply_offset() {
+        assert_eq!(apply_offset(5, -5), Ok(0));
+        assert_eq!(apply_offset(5, -6), Err(OffsetError::Negative));
+        assert_eq!(apply_offset(0, i64::MIN), Err(OffsetError::Negative));
+        assert_eq!(apply_offset(u64::MAX, i64::MIN), Ok(u64::MAX - (1 << 63)));
+        assert_eq!(apply_offset(u64::MAX, 0), Ok(u64::MAX));
+        assert_eq!(apply_offset(u64::MAX, 1), Err(OffsetError::Overflow));
+        assert_eq!(apply_offset(1 << 63, i64::MAX), Ok(u64::MAX));
+        assert_eq!(
+            apply_offset((1 << 63) + 1, i64::MAX),
+            Err(OffsetError::Overflow)
+        );
+    }
+
+    /// Seeking matches `Cursor`, other than being clamped to the end.
+    #[test]
+    fn test_seek_matches_cursor() {
+        let data: Vec<u8> = (0..10).collect();
+        let cases = [
+            SeekFrom::Start(0),
+            SeekFrom::Start(10),
+            SeekFrom::Start(11),
+            SeekFrom::Start(u64::MAX),
+            SeekFrom::Current(0),
+            SeekFrom::Current(-4),
+            SeekFrom::Current(-5),
+            SeekFrom::Current(i64::MIN),
+            SeekFrom::Current(i64::MAX),
+            SeekFrom::End(0),
+            SeekFrom::End(1),
+            SeekFrom::End(-10),
+            SeekFrom::End(-11),
+            SeekFrom::End(i64::MIN),
+            SeekFrom::End(i64::MAX),
+        ];
+        for case in cases {
+            let mut cursor = Cursor::new(data.clone());
+            cursor.set_position(4);
+            let mut wrapper = ConstrainedWrapper::new(Cursor::new(data.clone()), 0..10).unwrap();
+            wrapper.seek(SeekFrom::Start(4)).unwrap();
+
+            match (cursor.seek(case), wrapper.seek(case)) {
+                (Ok(expected), Ok(actual)) => assert_eq!(actual, expected.min(10), "{:?}", case),
+                (Err(expected), Err(actual)) => {
+                    assert_eq!(actual.kind(), expected.kind(), "{:?}", case);
+                    assert_eq!(stream_position(&mut wrapper).unwrap(), 4, "{:?}", case);
+                }
+                (expected, actual) => panic!("{:?}: {:?} != {:?}", case, actual, expected),
+            }
+        }
+
+        // Before the start of the range, though not the data, is also an error.
+        let mut wrapper = ConstrainedWrapper::new(Cursor::new(data), 3..7).unwrap();
+        let err = wrapper.seek(SeekFrom::Current(-1)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(stream_position(&mut wrapper).unwrap(), 0);
+        assert_eq!(wrapper.seek(SeekFrom::Start(u64::MAX)).unwrap(), 4);
+        assert_eq!(wrapper.seek(SeekFrom::End(-4)).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_multi_range() {
+        let data = b"0123456789abcdefghij".to_vec();
+        assert!(
+            MultiRangeWrapper::new(std::io::Cursor::new(data.clone()), vec![5..8, 2..4]).is_err()
+        );
+        assert!(
+            MultiRangeWrapper::new(std::io::Cursor::new(data.clone()), vec![2..6, 5..8]).is_err()
+        );
+
+        let mut view =
+            MultiRangeWrapper::new(std::io::Cursor::new(data), vec![2..4, 8..8, 10..13, 16..20])
+                .unwrap();
+        assert_eq!(view.limit(), 9);
+
+        // Across every range.
+        let mut contents = Vec::new();
+        view.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, b"23abcghij");
+
+        // Into the middle range.
+        assert_eq!(view.seek(SeekFrom::Start(3)).unwrap(), 3);
+        let mut buf = [0u8; 2];
+        view.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"bc");
+
+        // Writing across the boundary between the first and second range.
+        view.seek(SeekFrom::Start(1)).unwrap();
+        view.write_all(b"XYZ").unwrap();
+        assert_eq!(stream_position(&mut view).unwrap(), 4);
+
+        // Past the end is clamped, and nothing is written there.
+        assert_eq!(view.seek(SeekFrom::End(5)).unwrap(), 9);
+        assert_eq!(view.write(b"!").unwrap(), 0);
+        assert!(view.seek(SeekFrom::Current(-10)).is_err());
+
+        assert_eq!(view.into_inner().into_inner(), b"012X456789YZcdefghij");
+    }
 }
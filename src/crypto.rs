@@ -0,0 +1,173 @@
+//! Encrypting and decrypting ranges of the data in place with a stream cipher.
+use crate::{
+    action::{check_range, describe_bytes, translate_offsets, Action, ActionError, MemoryUsage},
+    transform_range, Hiex, CHUNK_SIZE,
+};
+use chacha20::{
+    cipher::{KeyIvInit, StreamCipher, StreamCipherSeek},
+    ChaCha20,
+};
+use std::{
+    io::{Read, Seek, Write},
+    ops::Range,
+};
+
+/// An action which XORs a range with the ChaCha20 (RFC 8439) keystream for a key and nonce,
+/// starting from the keystream's block `counter` at the start of the range.
+/// Since encrypting and decrypting are the same operation, this is its own inverse, so no previous
+/// data is stored.
+///
+/// NOTE: The key is kept for as long as the action is in the history. It isn't shown by the
+/// `Debug` implementation.
+#[derive(Clone, Eq, PartialEq)]
+pub struct ChaCha20Action {
+    pub position: u64,
+    pub length: u64,
+    key: [u8; 32],
+    nonce: [u8; 12],
+    pub counter: u32,
+}
+impl ChaCha20Action {
+    pub fn new(position: u64, length: u64, key: [u8; 32], nonce: [u8; 12]) -> Self {
+        Self {
+            position,
+            length,
+            key,
+            nonce,
+            counter: 0,
+        }
+    }
+
+    /// Start from block `counter` of the keystream, which is 64 bytes per block. Some formats
+    /// start at 1, such as when block 0 is used to derive a Poly1305 key.
+    pub fn with_counter(mut self, counter: u32) -> Self {
+        self.counter = counter;
+        self
+    }
+}
+impl std::fmt::Debug for ChaCha20Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChaCha20Action")
+            .field("position", &self.position)
+            .field("length", &self.length)
+            .field("counter", &self.counter)
+            .finish_non_exhaustive()
+    }
+}
+impl<F, E> Action<F, E> for ChaCha20Action
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        check_range(data, self.position, self.length)?;
+        // The keystream can't run past the last block.
+        let keystream_end = (u64::from(self.counter) * 64).checked_add(self.length);
+        if keystream_end.map_or(true, |end| end > (1 << 32) * 64) {
+            return Err(ActionError::Invalid);
+        }
+
+        let mut cipher = ChaCha20::new(&self.key.into(), &self.nonce.into());
+        cipher.seek(u64::from(self.counter) * 64);
+        transform_range(data, self.position, self.length, CHUNK_SIZE, |_, chunk| {
+            cipher.apply_keystream(chunk)
+        })?;
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, other: E) -> Result<(), ActionError> {
+        self.apply(data, other)
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.position..self.position.saturating_add(self.length))
+    }
+
+    fn description(&self) -> String {
+        describe_bytes("ChaCha20", self.position, self.length)
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(self.clone()))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.position], delta)
+    }
+}
+impl MemoryUsage for ChaCha20Action {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+    }
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Encrypts or decrypts the bytes within `range` with ChaCha20, as a single action.
+    /// See [`ChaCha20Action`].
+    pub fn apply_chacha20(
+        &mut self,
+        range: Range<u64>,
+        key: [u8; 32],
+        nonce: [u8; 12],
+        other: E,
+    ) -> Result<(), (ChaCha20Action, ActionError)> {
+        let length = range.end.saturating_sub(range.start);
+        self.add_action(ChaCha20Action::new(range.start, length, key, nonce), other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChaCha20Action;
+    use crate::{action::ActionError, Hiex};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_chacha20() {
+        // RFC 8439, section 2.4.2.
+        let mut key = [0u8; 32];
+        key.iter_mut().zip(0..).for_each(|(byte, i)| *byte = i);
+        let nonce = [0, 0, 0, 0, 0, 0, 0, 0x4a, 0, 0, 0, 0];
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one \
+            tip for the future, sunscreen would be it.";
+        let mut data = b"header".to_vec();
+        data.extend_from_slice(plaintext);
+        let length = plaintext.len() as u64;
+
+        let mut hex = Hiex::from_reader(Cursor::new(data.clone())).unwrap();
+        hex.add_action(
+            ChaCha20Action::new(6, length, key, nonce).with_counter(1),
+            (),
+        )
+        .unwrap();
+        let encrypted = hex.read_amount_at(0, data.len()).unwrap();
+        assert_eq!(&encrypted[..6], b"header");
+        assert_eq!(
+            encrypted[6..22],
+            [
+                0x6e, 0x2e, 0x35, 0x9a, 0x25, 0x68, 0xf9, 0x80, 0x41, 0xba, 0x07, 0x28, 0xdd, 0x0d,
+                0x69, 0x81
+            ]
+        );
+        assert_eq!(encrypted[data.len() - 2..], [0x87, 0x4d]);
+        assert!(!format!("{:?}", ChaCha20Action::new(0, 0, key, nonce)).contains("key"));
+
+        hex.undo(()).unwrap();
+        assert_eq!(hex.read_amount_at(0, data.len()).unwrap(), data);
+        hex.redo(()).unwrap();
+        // Decrypting is the same.
+        hex.add_action(
+            ChaCha20Action::new(6, length, key, nonce).with_counter(1),
+            (),
+        )
+        .unwrap();
+        assert_eq!(hex.read_amount_at(0, data.len()).unwrap(), data);
+
+        let (_, err) = hex
+            .apply_chacha20(0..data.len() as u64 + 1, key, nonce, ())
+            .unwrap_err();
+        assert!(matches!(err, ActionError::Invalid));
+    }
+}
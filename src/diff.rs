@@ -0,0 +1,321 @@
+//! Readable hex dump diffs between some original data and the current data.
+use crate::{hex_dump::format_dump_line, Hiex, CHUNK_SIZE};
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    io::{BufReader, ErrorKind, Read, Seek, SeekFrom, Write},
+};
+use usize_cast::FromUsize;
+
+/// Options for [`Hiex::format_diff_against`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DiffOptions {
+    /// How many bytes are shown on each line. Treated as `1` if it is `0`.
+    pub bytes_per_line: usize,
+    /// How many unchanged lines are shown before and after each change.
+    pub context_lines: usize,
+    /// How many changed lines a hunk shows before the rest of its changes are elided.
+    pub max_hunk_lines: usize,
+}
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            bytes_per_line: 16,
+            context_lines: 3,
+            max_hunk_lines: 256,
+        }
+    }
+}
+
+/// Fills `buf` from `reader` until it is full or the reader ends, returning how much was read.
+fn read_line<R>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<usize>
+where
+    R: Read,
+{
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(amount) => read += amount,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(read)
+}
+
+/// Formats one line of the dump, marked with `marker`, see [`format_dump_line`].
+fn format_line(
+    output: &mut String,
+    marker: char,
+    offset: u64,
+    bytes: &[u8],
+    bytes_per_line: usize,
+) {
+    output.push(marker);
+    format_dump_line(output, offset, bytes, bytes_per_line);
+}
+
+/// A hunk which is being built up.
+struct Hunk {
+    start: u64,
+    old_length: u64,
+    new_length: u64,
+    body: String,
+    changed: usize,
+    elided: usize,
+}
+impl Hunk {
+    fn context(&mut self, offset: u64, bytes: &[u8], bytes_per_line: usize) {
+        let length = u64::from_usize(bytes.len());
+        self.old_length += length;
+        self.new_length += length;
+        if self.elided == 0 {
+            format_line(&mut self.body, ' ', offset, bytes, bytes_per_line);
+        }
+    }
+
+    fn finish(mut self, output: &mut String, trailing: &[(u64, Vec<u8>)], bytes_per_line: usize) {
+        if self.elided != 0 {
+            let _ = writeln!(self.body, "... {} changed lines elided ...", self.elided);
+        }
+        for (offset, bytes) in trailing {
+            let length = u64::from_usize(bytes.len());
+            self.old_length += length;
+            self.new_length += length;
+            format_line(&mut self.body, ' ', *offset, bytes, bytes_per_line);
+        }
+        let _ = writeln!(
+            output,
+            "@@ -{:08x},{} +{:08x},{} @@",
+            self.start, self.old_length, self.start, self.new_length
+        );
+        output.push_str(&self.body);
+    }
+}
+
+/// Diffs `original` against `current` line by line. See [`Hiex::format_diff_against`].
+pub(crate) fn format_diff<A, B>(
+    original: A,
+    current: B,
+    options: &DiffOptions,
+) -> std::io::Result<String>
+where
+    A: Read,
+    B: Read,
+{
+    let bytes_per_line = options.bytes_per_line.max(1);
+    let context = options.context_lines;
+    let mut original = BufReader::with_capacity(CHUNK_SIZE, original);
+    let mut current = BufReader::with_capacity(CHUNK_SIZE, current);
+
+    let mut output = String::new();
+    // Unchanged lines which may become the leading context of the next hunk.
+    let mut before: VecDeque<(u64, Vec<u8>)> = VecDeque::with_capacity(context + 1);
+    // Unchanged lines since the last change in the current hunk.
+    let mut pending: Vec<(u64, Vec<u8>)> = Vec::new();
+    let mut hunk: Option<Hunk> = None;
+
+    let mut old_line = vec![0u8; bytes_per_line];
+    let mut new_line = vec![0u8; bytes_per_line];
+    let mut offset = 0;
+    loop {
+        let old_length = read_line(&mut original, &mut old_line)?;
+        let new_length = read_line(&mut current, &mut new_line)?;
+        if old_length == 0 && new_length == 0 {
+            break;
+        }
+        let (old, new) = (&old_line[..old_length], &new_line[..new_length]);
+
+        if old == new {
+            if hunk.is_some() {
+                pending.push((offset, new.to_vec()));
+                // Far enough from the last change that the next one gets its own hunk.
+                if pending.len() > context * 2 {
+                    if let Some(hunk) = hunk.take() {
+                        hunk.finish(&mut output, &pending[..context], bytes_per_line);
+                    }
+                    before.extend(pending.drain(..).skip(context + 1));
+                }
+            } else if context != 0 {
+                if before.len() == context {
+                    before.pop_front();
+                }
+                before.push_back((offset, new.to_vec()));
+            }
+        } else {
+            let hunk = hunk.get_or_insert_with(|| Hunk {
+                start: before.front().map_or(offset, |(start, _)| *start),
+                old_length: 0,
+                new_length: 0,
+                body: String::new(),
+                changed: 0,
+                elided: 0,
+            });
+            for (offset, bytes) in before.drain(..).chain(pending.drain(..)) {
+                hunk.context(offset, &bytes, bytes_per_line);
+            }
+
+            hunk.old_length += u64::from_usize(old.len());
+            hunk.new_length += u64::from_usize(new.len());
+            if hunk.changed < options.max_hunk_lines {
+                hunk.changed += 1;
+                if !old.is_empty() {
+                    format_line(&mut hunk.body, '-', offset, old, bytes_per_line);
+                }
+                if !new.is_empty() {
+                    format_line(&mut hunk.body, '+', offset, new, bytes_per_line);
+                }
+            } else {
+                hunk.elided += 1;
+            }
+        }
+
+        offset += u64::from_usize(old_length.max(new_length));
+    }
+
+    if let Some(hunk) = hunk {
+        let trailing = pending.len().min(context);
+        hunk.finish(&mut output, &pending[..trailing], bytes_per_line);
+    }
+
+    if output.is_empty() {
+        Ok(output)
+    } else {
+        Ok(format!("--- original\n+++ current\n{}", output))
+    }
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Formats the differences between `original` (such as the file as it was last saved) and the
+    /// current data as a unified diff of hex dump lines.
+    /// Each line shows `bytes_per_line` bytes, so a change to any byte on a line shows the whole
+    /// old line (`-`) and new line (`+`), with unchanged lines around them as context. Hunk headers
+    /// give the hex offset of the hunk and its length in bytes on each side.
+    /// Both are read in chunks, and hunks with more than `max_hunk_lines` changed lines have the
+    /// rest elided, so this doesn't need to hold large files in memory.
+    /// Identical data gives an empty string. The position is restored afterwards.
+    pub fn format_diff_against<R>(
+        &mut self,
+        mut original: R,
+        options: &DiffOptions,
+    ) -> std::io::Result<String>
+    where
+        R: Read + Seek,
+    {
+        let position = self.position()?;
+        original.seek(SeekFrom::Start(0))?;
+        self.seek(SeekFrom::Start(0))?;
+        let result = format_diff(original, &mut *self, options);
+        self.seek(SeekFrom::Start(position))?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DiffOptions;
+    use crate::{EditAction, Hiex};
+    use std::io::Cursor;
+
+    fn data() -> Vec<u8> {
+        (0..100u8).map(|i| b'a' + i % 26).collect()
+    }
+
+    #[test]
+    fn test_diff_identical() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(data())).unwrap();
+        let diff = hex
+            .format_diff_against(Cursor::new(data()), &DiffOptions::default())
+            .unwrap();
+        assert_eq!(diff, "");
+    }
+
+    #[test]
+    fn test_diff_middle() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(data())).unwrap();
+        hex.add_action_simple(EditAction::new(50, vec![0xff]))
+            .unwrap();
+        let options = DiffOptions {
+            context_lines: 1,
+            ..DiffOptions::default()
+        };
+        let diff = hex
+            .format_diff_against(Cursor::new(data()), &options)
+            .unwrap();
+        assert_eq!(
+            diff,
+            "--- original\n\
+             +++ current\n\
+             @@ -00000020,48 +00000020,48 @@\n \
+             00000020  67 68 69 6a 6b 6c 6d 6e 6f 70 71 72 73 74 75 76  |ghijklmnopqrstuv|\n\
+             -00000030  77 78 79 7a 61 62 63 64 65 66 67 68 69 6a 6b 6c  |wxyzabcdefghijkl|\n\
+             +00000030  77 78 ff 7a 61 62 63 64 65 66 67 68 69 6a 6b 6c  |wx.zabcdefghijkl|\n \
+             00000040  6d 6e 6f 70 71 72 73 74 75 76 77 78 79 7a 61 62  |mnopqrstuvwxyzab|\n"
+        );
+    }
+
+    #[test]
+    fn test_diff_final_line() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(data())).unwrap();
+        hex.add_action_simple(EditAction::new(97, b"XY".to_vec()))
+            .unwrap();
+        let options = DiffOptions {
+            context_lines: 1,
+            ..DiffOptions::default()
+        };
+        let diff = hex
+            .format_diff_against(Cursor::new(data()), &options)
+            .unwrap();
+        assert_eq!(
+            diff,
+            "--- original\n\
+             +++ current\n\
+             @@ -00000050,20 +00000050,20 @@\n \
+             00000050  63 64 65 66 67 68 69 6a 6b 6c 6d 6e 6f 70 71 72  |cdefghijklmnopqr|\n\
+             -00000060  73 74 75 76                                      |stuv|\n\
+             +00000060  73 58 59 76                                      |sXYv|\n"
+        );
+    }
+
+    #[test]
+    fn test_diff_hunks() {
+        let original = vec![0u8; 160];
+        let mut current = original.clone();
+        // Far enough apart for separate hunks, and a length change at the end.
+        current[0] = 1;
+        current[80] = 1;
+        current.extend_from_slice(&[2; 3]);
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(current)).unwrap();
+        let options = DiffOptions {
+            bytes_per_line: 8,
+            context_lines: 1,
+            max_hunk_lines: 1,
+        };
+        let diff = hex
+            .format_diff_against(Cursor::new(original), &options)
+            .unwrap();
+        let headers: Vec<&str> = diff.lines().filter(|line| line.starts_with("@@")).collect();
+        assert_eq!(
+            headers,
+            [
+                "@@ -00000000,16 +00000000,16 @@",
+                "@@ -00000048,24 +00000048,24 @@",
+                "@@ -00000098,8 +00000098,11 @@",
+            ]
+        );
+        assert!(diff.contains("+000000a0  02 02 02"));
+        assert!(!diff.contains("-000000a0"));
+
+        // Every line is changed, so all but the first are elided.
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(vec![1u8; 32])).unwrap();
+        let diff = hex
+            .format_diff_against(Cursor::new(vec![0u8; 32]), &options)
+            .unwrap();
+        assert!(diff.contains("@@ -00000000,32 +00000000,32 @@\n"));
+        assert!(diff.ends_with("... 3 changed lines elided ...\n"));
+    }
+}
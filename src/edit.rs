@@ -0,0 +1,200 @@
+use crate::{
+    action::{describe_bytes, translate_offsets, Action, ActionError, MemoryUsage, OverwriteDelta},
+    buffer_pool::BufferPool,
+    checked_usize, clamp_usize,
+    io::{Read, Seek, Write},
+    read_exact_at, read_full_at, write_at,
+};
+#[cfg(feature = "no_std_io")]
+use alloc::{boxed::Box, string::String, vec, vec::Vec};
+use core::ops::Range;
+use usize_cast::FromUsize;
+
+/// An action where bytes are edited
+/// NOTE: if bytes written would increase the size of the file then that is an _error_, though
+/// they may end exactly at the end. See [`GrowthPolicy`] to grow the data for them instead.
+/// If writing fails partway, the previous bytes are written back, and it fails with
+/// `ActionError::PartialWrite`.
+///
+/// [`GrowthPolicy`]: crate::GrowthPolicy
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EditAction {
+    pub position: u64,
+    previous_data: Vec<u8>,
+    pub new_data: Vec<u8>,
+    /// Whether to check the data, see [`EditAction::with_verify`].
+    pub verify: bool,
+    /// Whether other edits have been merged into this one.
+    merged: bool,
+}
+impl EditAction {
+    pub fn new(position: u64, new_data: Vec<u8>) -> Self {
+        Self {
+            position,
+            new_data,
+            previous_data: Vec::new(),
+            verify: false,
+            merged: false,
+        }
+    }
+
+    /// When `verify` is set, applying re-reads the written bytes to check that they are the new
+    /// data, and unapplying first checks that the new data is still there, so that bytes modified
+    /// by something else aren't overwritten. Either fails with `ActionError::VerificationFailed`.
+    pub fn with_verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Check that the bytes at the position are the new data.
+    fn check_new_data<F>(&self, data: &mut F) -> Result<(), ActionError>
+    where
+        F: Read + Seek,
+    {
+        let mut current = vec![0; self.new_data.len()];
+        read_exact_at(data, self.position, &mut current)?;
+        match current.iter().zip(&self.new_data).position(|(a, b)| a != b) {
+            Some(index) => Err(ActionError::VerificationFailed {
+                expected_at: self.position + u64::from_usize(index),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+impl<F, E> Action<F, E> for EditAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        trace!(position = self.position, length = self.new_data.len(); "applying edit");
+        // Read in the data to store it for if the action is undone.
+        self.previous_data.resize(self.new_data.len(), 0);
+        // If we would exceed the file size then the action was invalid to perform, which reading
+        // the previous data finds without probing the length first.
+        if read_full_at(data, self.position, &mut self.previous_data)? < self.new_data.len() {
+            return Err(ActionError::Invalid);
+        }
+
+        if let Err(error) = write_at(data, self.position, &self.new_data) {
+            // Best-effort rollback, so that the data isn't left half-written.
+            let rolled_back = write_at(data, self.position, &self.previous_data).is_ok();
+            return Err(ActionError::PartialWrite { error, rolled_back });
+        }
+
+        if self.verify {
+            self.check_new_data(data)?;
+        }
+
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        trace!(position = self.position, length = self.previous_data.len(); "unapplying edit");
+        if self.verify {
+            self.check_new_data(data)?;
+        }
+        write_at(data, self.position, &self.previous_data)?;
+        Ok(())
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        let length = u64::from_usize(self.new_data.len());
+        Some(self.position..self.position.saturating_add(length))
+    }
+
+    fn description(&self) -> String {
+        describe_bytes(
+            "Overwrite",
+            self.position,
+            u64::from_usize(self.new_data.len()),
+        )
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(
+            EditAction::new(self.position, self.new_data.clone()).with_verify(self.verify),
+        ))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.position], delta)
+    }
+
+    fn take_buffers(&mut self, pool: &mut BufferPool) {
+        if self.previous_data.capacity() == 0 {
+            self.previous_data = pool.take_empty(self.new_data.len());
+        }
+    }
+
+    fn release_buffers(&mut self, pool: &mut BufferPool) {
+        pool.give(core::mem::take(&mut self.previous_data));
+        pool.give(core::mem::take(&mut self.new_data));
+    }
+
+    /// Single byte edits are merged into a single byte edit (or one built from them) when they
+    /// overwrite one of its bytes, or the byte just after it, as when typing.
+    fn can_merge(&self, next: &dyn Action<F, E>) -> bool {
+        let next = match next.as_any().downcast_ref::<EditAction>() {
+            Some(next) => next,
+            None => return false,
+        };
+        let end = self.position + u64::from_usize(self.new_data.len());
+        (self.new_data.len() == 1 || self.merged)
+            && next.new_data.len() == 1
+            && next.verify == self.verify
+            && (self.position..=end).contains(&next.position)
+    }
+
+    /// Fails if the merged edit would be too long to hold in memory on this platform.
+    fn merge(&mut self, next: &dyn Action<F, E>) -> bool {
+        let next = match next.as_any().downcast_ref::<EditAction>() {
+            Some(next) => next,
+            None => return false,
+        };
+        let index = match checked_usize(next.position - self.position) {
+            Ok(index) => index,
+            Err(_) => return false,
+        };
+        if index == self.new_data.len() {
+            self.new_data.extend_from_slice(&next.new_data);
+            self.previous_data.extend_from_slice(&next.previous_data);
+        } else {
+            // The previous byte is already stored.
+            self.new_data[index] = next.new_data[0];
+        }
+        self.merged = true;
+        true
+    }
+
+    fn rebase(&mut self, data: &mut F, modified: Option<Range<u64>>) -> Result<bool, ActionError> {
+        let end = self.position + u64::from_usize(self.previous_data.len());
+        let range = match modified {
+            Some(modified) => modified.start.max(self.position)..modified.end.min(end),
+            None => self.position..end,
+        };
+        if range.start < range.end {
+            let start = clamp_usize(range.start - self.position, self.previous_data.len());
+            let length = clamp_usize(range.end - range.start, self.previous_data.len() - start);
+            read_exact_at(
+                data,
+                range.start,
+                &mut self.previous_data[start..start + length],
+            )?;
+        }
+        Ok(true)
+    }
+
+    fn overwrite_delta(&self) -> Option<OverwriteDelta<'_>> {
+        Some(OverwriteDelta {
+            position: self.position,
+            previous: &self.previous_data,
+            new: &self.new_data,
+        })
+    }
+}
+impl MemoryUsage for EditAction {
+    fn memory_usage(&self) -> usize {
+        8 + self.previous_data.len() + self.new_data.len()
+    }
+}
@@ -0,0 +1,350 @@
+//! Guessing the text encoding (or lack of one) of a range of the data.
+use crate::{clamp_usize, stream_len, Hiex};
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+use usize_cast::FromUsize;
+
+/// The encodings which [`Hiex::detect_encoding`] can guess.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Encoding {
+    Ascii,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// Not text.
+    Binary,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EncodingCandidate {
+    pub encoding: Encoding,
+    /// From 0 to 1.
+    pub confidence: f64,
+}
+
+/// The result of [`Hiex::detect_encoding`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncodingGuess {
+    /// Every encoding, from the most to the least likely. Empty if the range is.
+    pub candidates: Vec<EncodingCandidate>,
+    /// The encoding indicated by a byte order mark at the start of the range, if there is one.
+    pub bom: Option<Encoding>,
+    /// The amount of bytes which were looked at.
+    pub sampled: u64,
+}
+impl EncodingGuess {
+    /// The most likely encoding.
+    pub fn best(&self) -> Option<Encoding> {
+        self.candidates.first().map(|candidate| candidate.encoding)
+    }
+
+    pub fn confidence(&self, encoding: Encoding) -> f64 {
+        self.candidates
+            .iter()
+            .find(|candidate| candidate.encoding == encoding)
+            .map_or(0.0, |candidate| candidate.confidence)
+    }
+}
+
+/// Options for [`Hiex::detect_encoding_with`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EncodingDetection {
+    /// How many bytes are looked at from each of the start and the end of the range. Ranges of up
+    /// to twice this are looked at entirely. Treated as `2` if it is less.
+    pub sample_size: usize,
+}
+impl Default for EncodingDetection {
+    fn default() -> Self {
+        Self { sample_size: 4096 }
+    }
+}
+
+/// Counts of what was seen in the samples.
+#[derive(Debug, Default)]
+struct Counts {
+    bytes: u64,
+    /// Printable ASCII and whitespace.
+    text: u64,
+    /// Bytes of `0x80` and above.
+    high: u64,
+    /// Bytes of valid multi-byte UTF-8 sequences.
+    utf8_multibyte: u64,
+    /// Bytes which aren't part of a valid UTF-8 sequence.
+    utf8_invalid: u64,
+    units: u64,
+    /// Code units which are printable ASCII or whitespace, in either byte order.
+    le_text: u64,
+    be_text: u64,
+}
+
+fn is_text(byte: u8) -> bool {
+    byte.is_ascii_graphic() || matches!(byte, b' ' | b'\t' | b'\n' | b'\r')
+}
+
+impl Counts {
+    /// Counts a sample, which may start in the middle of a UTF-8 sequence if it isn't `first`.
+    /// It starts at an even offset into the range, so that UTF-16 code units line up.
+    fn add(&mut self, sample: &[u8], first: bool) {
+        self.bytes += u64::from_usize(sample.len());
+        self.text += u64::from_usize(sample.iter().filter(|&&byte| is_text(byte)).count());
+        self.high += u64::from_usize(sample.iter().filter(|&&byte| byte >= 0x80).count());
+
+        for unit in sample.chunks_exact(2) {
+            self.units += 1;
+            self.le_text += u64::from(unit[1] == 0 && is_text(unit[0]));
+            self.be_text += u64::from(unit[0] == 0 && is_text(unit[1]));
+        }
+
+        let mut rest = sample;
+        if !first {
+            // Skip the end of a sequence which started before the sample.
+            let continuation = rest
+                .iter()
+                .take(3)
+                .take_while(|&&byte| byte & 0xc0 == 0x80)
+                .count();
+            rest = &rest[continuation..];
+        }
+        loop {
+            let (valid, invalid) = match std::str::from_utf8(rest) {
+                Ok(_) => (rest.len(), None),
+                Err(err) => (err.valid_up_to(), err.error_len()),
+            };
+            let multibyte = rest[..valid].iter().filter(|&&byte| byte >= 0x80).count();
+            self.utf8_multibyte += u64::from_usize(multibyte);
+            match invalid {
+                Some(invalid) => {
+                    self.utf8_invalid += u64::from_usize(invalid);
+                    rest = &rest[valid + invalid..];
+                }
+                // An incomplete sequence at the end may have been cut off by the sample.
+                None => break,
+            }
+        }
+    }
+
+    fn ratio(&self, amount: u64, of: u64) -> f64 {
+        if of == 0 {
+            0.0
+        } else {
+            amount as f64 / of as f64
+        }
+    }
+
+    fn confidences(&self) -> [(Encoding, f64); 5] {
+        let text = self.ratio(self.text, self.bytes);
+        let ascii = if self.high == 0 { text } else { 0.0 };
+        let utf8 = if self.utf8_multibyte == 0 {
+            // ASCII is also UTF-8, but the narrower guess is more useful.
+            ascii * 0.9
+        } else {
+            let text = self.ratio(self.text + self.utf8_multibyte, self.bytes);
+            let invalid = self.ratio(self.utf8_invalid, self.bytes);
+            if invalid == 0.0 {
+                text
+            } else {
+                text * (1.0 - invalid) * 0.5
+            }
+        };
+        let utf16_le = self.ratio(self.le_text, self.units);
+        let utf16_be = self.ratio(self.be_text, self.units);
+        let binary = 1.0 - ascii.max(utf8).max(utf16_le).max(utf16_be);
+        [
+            (Encoding::Ascii, ascii),
+            (Encoding::Utf8, utf8),
+            (Encoding::Utf16Le, utf16_le),
+            (Encoding::Utf16Be, utf16_be),
+            (Encoding::Binary, binary),
+        ]
+    }
+}
+
+/// Reads `length` bytes at `position`.
+fn read_sample<S>(stream: &mut S, position: u64, length: u64) -> std::io::Result<Vec<u8>>
+where
+    S: Read + Seek,
+{
+    let mut sample = vec![0; clamp_usize(length, usize::MAX)];
+    stream.seek(SeekFrom::Start(position))?;
+    stream.read_exact(&mut sample)?;
+    Ok(sample)
+}
+
+pub(crate) fn detect_encoding_in<S>(
+    stream: &mut S,
+    range: Range<u64>,
+    options: &EncodingDetection,
+) -> std::io::Result<EncodingGuess>
+where
+    S: Read + Seek,
+{
+    let end = range.end.min(stream_len(stream)?);
+    let start = range.start.min(end);
+    let sample_size = u64::from_usize(options.sample_size.max(2));
+
+    let prefix = read_sample(stream, start, (end - start).min(sample_size))?;
+    let prefix_end = start + u64::from_usize(prefix.len());
+    // The suffix starts an even amount into the range, after the prefix.
+    let suffix_start = end.saturating_sub(sample_size).max(prefix_end);
+    let suffix_start = suffix_start + (suffix_start - start) % 2;
+    let suffix = read_sample(stream, suffix_start, end.saturating_sub(suffix_start))?;
+
+    // The byte order mark is counted along with the rest, which rules out ASCII for UTF-8.
+    let bom = match prefix.as_slice() {
+        [0xef, 0xbb, 0xbf, ..] => Some(Encoding::Utf8),
+        [0xff, 0xfe, ..] => Some(Encoding::Utf16Le),
+        [0xfe, 0xff, ..] => Some(Encoding::Utf16Be),
+        _ => None,
+    };
+    let mut counts = Counts::default();
+    counts.add(&prefix, true);
+    counts.add(&suffix, suffix_start == prefix_end);
+
+    let sampled = counts.bytes;
+    let mut candidates = Vec::new();
+    if sampled != 0 {
+        for (encoding, mut confidence) in counts.confidences().iter().copied() {
+            if Some(encoding) == bom {
+                confidence = 0.5 + confidence * 0.5;
+            }
+            candidates.push(EncodingCandidate {
+                encoding,
+                confidence,
+            });
+        }
+        // Stable, so ties keep the order above.
+        candidates.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    }
+
+    Ok(EncodingGuess {
+        candidates,
+        bom,
+        sampled,
+    })
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Guesses the encoding of the bytes within `range` (clamped to the data), with the default
+    /// [`EncodingDetection`].
+    pub fn detect_encoding(&mut self, range: Range<u64>) -> std::io::Result<EncodingGuess> {
+        self.detect_encoding_with(range, &EncodingDetection::default())
+    }
+
+    /// Guesses the encoding of the bytes within `range` (clamped to the data), from samples of
+    /// its start and end. Byte order marks are recognized, and otherwise the guesses are from
+    /// how much of the samples is printable text in each encoding, which favours UTF-16 that is
+    /// mostly ASCII characters. The position is restored afterwards.
+    pub fn detect_encoding_with(
+        &mut self,
+        range: Range<u64>,
+        options: &EncodingDetection,
+    ) -> std::io::Result<EncodingGuess> {
+        let position = self.position()?;
+        let result = detect_encoding_in(self, range, options);
+        self.seek(SeekFrom::Start(position))?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Encoding, EncodingDetection};
+    use crate::Hiex;
+    use std::io::Cursor;
+
+    const TEXT: &str = "The quick brown fox jumps over the lazy dog.\r\n\tAgain!\n";
+
+    fn guess(data: Vec<u8>) -> super::EncodingGuess {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(data)).unwrap();
+        hex.detect_encoding(0..u64::MAX).unwrap()
+    }
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| {
+                if big_endian {
+                    unit.to_be_bytes()
+                } else {
+                    unit.to_le_bytes()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_detect_encoding() {
+        let ascii = guess(TEXT.as_bytes().to_vec());
+        assert_eq!(ascii.best(), Some(Encoding::Ascii));
+        assert_eq!(ascii.confidence(Encoding::Ascii), 1.0);
+        assert!(ascii.confidence(Encoding::Utf8) > ascii.confidence(Encoding::Utf16Le));
+        assert_eq!(ascii.candidates.len(), 5);
+
+        let utf8 = guess("Grüße, naïve café — ünïcødé ✓ text".as_bytes().to_vec());
+        assert_eq!(utf8.best(), Some(Encoding::Utf8));
+        assert_eq!(utf8.confidence(Encoding::Ascii), 0.0);
+
+        let mut with_bom = vec![0xef, 0xbb, 0xbf];
+        with_bom.extend_from_slice(TEXT.as_bytes());
+        let with_bom = guess(with_bom);
+        assert_eq!(with_bom.bom, Some(Encoding::Utf8));
+        assert_eq!(with_bom.best(), Some(Encoding::Utf8));
+
+        // Without a byte order mark.
+        let le = guess(utf16(TEXT, false));
+        assert_eq!(le.bom, None);
+        assert_eq!(le.best(), Some(Encoding::Utf16Le));
+
+        let mut be = vec![0xfe, 0xff];
+        be.extend(utf16(TEXT, true));
+        let be = guess(be);
+        assert_eq!(be.bom, Some(Encoding::Utf16Be));
+        assert_eq!(be.best(), Some(Encoding::Utf16Be));
+
+        // A simple generator, so that the bytes are the same on every run.
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let random = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 24) as u8
+            })
+            .collect();
+        let random = guess(random);
+        assert_eq!(random.best(), Some(Encoding::Binary));
+        assert!(random.confidence(Encoding::Binary) > 0.8);
+
+        assert!(guess(Vec::new()).candidates.is_empty());
+    }
+
+    #[test]
+    fn test_detect_encoding_samples() {
+        // Binary in the middle isn't looked at, only the text at either end.
+        let mut data = utf16(&TEXT.repeat(4), false);
+        let text_length = data.len() as u64;
+        data.extend(vec![0; 1000]);
+        data.extend(utf16(&TEXT.repeat(4), false));
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(data)).unwrap();
+
+        // Odd, so that the suffix has to be moved to line up with the code units.
+        let options = EncodingDetection { sample_size: 63 };
+        let guess = hex.detect_encoding_with(0..u64::MAX, &options).unwrap();
+        assert_eq!(guess.sampled, 63 + 62);
+        assert_eq!(guess.best(), Some(Encoding::Utf16Le));
+
+        // Code units are counted from the start of the range.
+        let guess = hex.detect_encoding_with(1..u64::MAX, &options).unwrap();
+        assert_eq!(guess.sampled, 63 + 63);
+        assert_eq!(guess.best(), Some(Encoding::Utf16Be));
+
+        let middle = text_length + 100..text_length + 900;
+        let guess = hex.detect_encoding_with(middle, &options).unwrap();
+        assert_eq!(guess.best(), Some(Encoding::Binary));
+        assert_eq!(guess.confidence(Encoding::Binary), 1.0);
+    }
+}
@@ -0,0 +1,59 @@
+#[cfg(feature = "no_std_io")]
+use alloc::vec::Vec;
+
+/// Byte order used when interpreting multi-byte values.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Endian {
+    Little,
+    Big,
+}
+impl Endian {
+    /// Decode an unsigned integer from `bytes`.
+    /// `bytes` must be at most 8 bytes long.
+    pub fn read_uint(self, bytes: &[u8]) -> u64 {
+        debug_assert!(bytes.len() <= 8);
+        let fold = |acc: u64, byte: &u8| (acc << 8) | u64::from(*byte);
+        match self {
+            Endian::Little => bytes.iter().rev().fold(0, fold),
+            Endian::Big => bytes.iter().fold(0, fold),
+        }
+    }
+
+    /// Encode the lowest `bytes.len()` bytes of `value` into `bytes`.
+    /// `bytes` must be at most 8 bytes long.
+    pub fn write_uint(self, value: u64, bytes: &mut [u8]) {
+        debug_assert!(bytes.len() <= 8);
+        let width = bytes.len();
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            let shift = match self {
+                Endian::Little => i,
+                Endian::Big => width - 1 - i,
+            } * 8;
+            *byte = (value >> shift) as u8;
+        }
+    }
+}
+
+/// Numbers which can be encoded with either byte order, such as for [`Hiex::write_number`].
+///
+/// [`Hiex::write_number`]: crate::Hiex::write_number
+pub trait Number: Copy {
+    /// Encode the number into as many bytes as its type is wide.
+    fn to_bytes(self, endian: Endian) -> Vec<u8>;
+}
+macro_rules! impl_number {
+    ($($ty:ty),*) => {
+        $(
+            impl Number for $ty {
+                fn to_bytes(self, endian: Endian) -> Vec<u8> {
+                    match endian {
+                        Endian::Little => self.to_le_bytes().to_vec(),
+                        Endian::Big => self.to_be_bytes().to_vec(),
+                    }
+                }
+            }
+        )*
+    };
+}
+impl_number!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
@@ -0,0 +1,441 @@
+//! Exporting parts of the data to files.
+use crate::{clamp_usize, Hiex, CHUNK_SIZE};
+use std::{
+    fs::{File, OpenOptions},
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+use usize_cast::FromUsize;
+
+/// What to do when a destination file already exists.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum OverwritePolicy {
+    /// Fail with `ErrorKind::AlreadyExists`.
+    Refuse,
+    /// Truncate the existing file and replace its contents.
+    Truncate,
+}
+
+/// An error from an operation which writes several output files.
+#[derive(Debug)]
+pub struct ExportError {
+    /// The outputs which were completely written before the failure, in the order they were
+    /// written.
+    pub completed: Vec<PathBuf>,
+    /// The output that was being written when the failure occurred.
+    pub failed: PathBuf,
+    pub error: std::io::Error,
+}
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "failed writing {} ({} outputs completed): {}",
+            self.failed.display(),
+            self.completed.len(),
+            self.error
+        )
+    }
+}
+impl std::error::Error for ExportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// The language that [`Hiex::export_source`] generates.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SourceLang {
+    /// `unsigned char name[N] = { ... };`
+    C,
+    /// `pub const NAME: [u8; N] = [ ... ];`
+    /// The name is converted to uppercase, as is conventional for constants.
+    Rust,
+}
+
+/// Options for [`Hiex::export_source`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SourceExportOptions {
+    /// The identifier of the array.
+    pub name: String,
+    /// How many bytes are written on each line. Treated as `1` if it is `0`.
+    pub bytes_per_line: usize,
+    /// Whether hex digits are written in uppercase.
+    pub uppercase: bool,
+}
+impl Default for SourceExportOptions {
+    fn default() -> Self {
+        Self {
+            name: String::from("data"),
+            bytes_per_line: 12,
+            uppercase: false,
+        }
+    }
+}
+
+/// Opens `path` for writing, according to `overwrite`.
+pub(crate) fn create_file(path: &Path, overwrite: OverwritePolicy) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    match overwrite {
+        OverwritePolicy::Refuse => options.create_new(true),
+        OverwritePolicy::Truncate => options.create(true).truncate(true),
+    };
+    options.open(path)
+}
+
+/// Formats `pattern` by replacing its single placeholder with `index`.
+/// The placeholder may be `{}`, `{:N}` (padded with spaces to `N` wide) or `{:0N}` (padded with
+/// zeroes to `N` wide).
+fn format_part_name(pattern: &str, index: usize) -> std::io::Result<String> {
+    let invalid = || std::io::Error::new(ErrorKind::InvalidInput, "invalid name pattern");
+    let start = pattern.find('{').ok_or_else(invalid)?;
+    let end = start + pattern[start..].find('}').ok_or_else(invalid)?;
+    let (prefix, suffix) = (&pattern[..start], &pattern[end + 1..]);
+    if suffix.contains('{') || suffix.contains('}') {
+        return Err(invalid());
+    }
+
+    let spec = &pattern[start + 1..end];
+    let number = match spec.strip_prefix(':') {
+        None if spec.is_empty() => index.to_string(),
+        Some(width) if width.starts_with('0') => {
+            let width: usize = width.parse().map_err(|_| invalid())?;
+            format!("{:0width$}", index, width = width)
+        }
+        Some(width) => {
+            let width: usize = width.parse().map_err(|_| invalid())?;
+            format!("{:width$}", index, width = width)
+        }
+        None => return Err(invalid()),
+    };
+
+    Ok(format!("{}{}{}", prefix, number, suffix))
+}
+
+/// Concatenates the files at `paths`, in order, into `dest`.
+/// Returns the total amount of bytes written.
+pub fn join_files<P, W>(paths: &[P], dest: &mut W) -> std::io::Result<u64>
+where
+    P: AsRef<Path>,
+    W: Write,
+{
+    let mut total = 0;
+    for path in paths {
+        total += std::io::copy(&mut File::open(path)?, dest)?;
+    }
+    dest.flush()?;
+    Ok(total)
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Writes the bytes within `range` to a file at `path`, syncing it to disk before returning.
+    /// Returns the number of bytes written.
+    /// Fails with `ErrorKind::InvalidInput` (without creating the file) if `range` extends past
+    /// the end of the data.
+    pub fn extract_to_path(
+        &mut self,
+        range: Range<u64>,
+        path: &Path,
+        overwrite: OverwritePolicy,
+    ) -> std::io::Result<u64> {
+        if range.end > self.length()? {
+            return Err(ErrorKind::InvalidInput.into());
+        }
+
+        let mut file = create_file(path, overwrite)?;
+        let written = self.save_range_to(range, &mut file)?;
+        file.sync_all()?;
+        Ok(written)
+    }
+
+    /// Extracts each range to its paired path.
+    /// The ranges are extracted in order of their start offset, so that the data is read forwards.
+    /// If any extraction fails, the error reports which outputs had already been completed.
+    pub fn extract_many(
+        &mut self,
+        specs: &[(Range<u64>, PathBuf)],
+        overwrite: OverwritePolicy,
+    ) -> Result<(), ExportError> {
+        let mut order: Vec<&(Range<u64>, PathBuf)> = specs.iter().collect();
+        order.sort_by_key(|(range, _)| range.start);
+
+        let mut completed = Vec::with_capacity(order.len());
+        for (range, path) in order {
+            if let Err(error) = self.extract_to_path(range.clone(), path, overwrite) {
+                return Err(ExportError {
+                    completed,
+                    failed: path.clone(),
+                    error,
+                });
+            }
+            completed.push(path.clone());
+        }
+
+        Ok(())
+    }
+
+    /// Formats the bytes within `range` as an array in source code, such as for embedding a binary
+    /// blob into a program. An empty range produces a zero-length array.
+    /// Fails with `ErrorKind::InvalidInput` if `range` extends past the end of the data.
+    pub fn export_source(
+        &mut self,
+        range: Range<u64>,
+        lang: SourceLang,
+        options: &SourceExportOptions,
+    ) -> std::io::Result<String> {
+        if range.end > self.length()? {
+            return Err(ErrorKind::InvalidInput.into());
+        }
+        let length = range.end.saturating_sub(range.start);
+        let bytes_per_line = options.bytes_per_line.max(1);
+
+        let mut output = match lang {
+            SourceLang::C => format!("unsigned char {}[{}] = {{\n", options.name, length),
+            SourceLang::Rust => format!(
+                "pub const {}: [u8; {}] = [\n",
+                options.name.to_uppercase(),
+                length
+            ),
+        };
+
+        self.seek(SeekFrom::Start(range.start))?;
+        let mut buffer = vec![0u8; clamp_usize(length, CHUNK_SIZE)];
+        let mut column = 0;
+        let mut offset = 0;
+        while offset < length {
+            let amount = clamp_usize(length - offset, buffer.len());
+            self.read_exact(&mut buffer[..amount])?;
+            for byte in &buffer[..amount] {
+                output.push_str(if column == 0 { "    " } else { " " });
+                if options.uppercase {
+                    output.push_str(&format!("0x{:02X},", byte));
+                } else {
+                    output.push_str(&format!("0x{:02x},", byte));
+                }
+
+                column += 1;
+                if column == bytes_per_line {
+                    output.push('\n');
+                    column = 0;
+                }
+            }
+            offset += u64::from_usize(amount);
+        }
+        if column != 0 {
+            output.push('\n');
+        }
+
+        output.push_str(match lang {
+            SourceLang::C => "};\n",
+            SourceLang::Rust => "];\n",
+        });
+        Ok(output)
+    }
+
+    /// Writes consecutive `chunk_size`-byte pieces of the data to new files within `dir`, each
+    /// synced to disk. The files are named by `name_pattern`, which must contain a single
+    /// placeholder for the piece's index (such as `part_{:04}.bin`).
+    /// The final piece may be shorter than `chunk_size`.
+    /// Existing files are not overwritten. On failure, the error reports which pieces had already
+    /// been written so that they can be cleaned up.
+    /// Returns the paths of the created files in order.
+    pub fn split_to_dir(
+        &mut self,
+        dir: &Path,
+        chunk_size: u64,
+        name_pattern: &str,
+    ) -> Result<Vec<PathBuf>, ExportError> {
+        let mut completed = Vec::new();
+        let length = match self.length() {
+            Ok(length) => length,
+            Err(error) => {
+                return Err(ExportError {
+                    completed,
+                    failed: dir.to_path_buf(),
+                    error,
+                })
+            }
+        };
+        if chunk_size == 0 {
+            return Err(ExportError {
+                completed,
+                failed: dir.to_path_buf(),
+                error: ErrorKind::InvalidInput.into(),
+            });
+        }
+
+        let mut start = 0;
+        while start < length {
+            let end = start.saturating_add(chunk_size).min(length);
+            let path = match format_part_name(name_pattern, completed.len()) {
+                Ok(name) => dir.join(name),
+                Err(error) => {
+                    return Err(ExportError {
+                        completed,
+                        failed: dir.to_path_buf(),
+                        error,
+                    })
+                }
+            };
+            if let Err(error) = self.extract_to_path(start..end, &path, OverwritePolicy::Refuse) {
+                return Err(ExportError {
+                    completed,
+                    failed: path,
+                    error,
+                });
+            }
+            completed.push(path);
+            start = end;
+        }
+
+        Ok(completed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_part_name, join_files, OverwritePolicy, SourceExportOptions, SourceLang};
+    use crate::Hiex;
+    use std::io::{Cursor, ErrorKind};
+
+    fn hiex(data: &[u8]) -> Hiex<Cursor<Vec<u8>>, ()> {
+        Hiex::from_reader(Cursor::new(data.to_vec())).unwrap()
+    }
+
+    #[test]
+    fn test_extract_to_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("payload.bin");
+        let mut hex = hiex(b"0123456789");
+
+        assert_eq!(
+            hex.extract_to_path(2..6, &path, OverwritePolicy::Refuse)
+                .unwrap(),
+            4
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), b"2345");
+
+        let err = hex
+            .extract_to_path(0..1, &path, OverwritePolicy::Refuse)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read(&path).unwrap(), b"2345");
+
+        assert_eq!(
+            hex.extract_to_path(8..10, &path, OverwritePolicy::Truncate)
+                .unwrap(),
+            2
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), b"89");
+
+        let err = hex
+            .extract_to_path(8..11, &path, OverwritePolicy::Truncate)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_extract_many() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first.bin");
+        let second = dir.path().join("second.bin");
+        let mut hex = hiex(b"0123456789");
+
+        let specs = [(6..9, second.clone()), (0..2, first.clone())];
+        hex.extract_many(&specs, OverwritePolicy::Refuse).unwrap();
+        assert_eq!(std::fs::read(&first).unwrap(), b"01");
+        assert_eq!(std::fs::read(&second).unwrap(), b"678");
+
+        // The first output (by offset) succeeds, then the second already exists.
+        let third = dir.path().join("third.bin");
+        let specs = [(5..6, second.clone()), (1..2, third.clone())];
+        let err = hex
+            .extract_many(&specs, OverwritePolicy::Refuse)
+            .unwrap_err();
+        assert_eq!(err.completed, vec![third.clone()]);
+        assert_eq!(err.failed, second);
+        assert_eq!(err.error.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read(&third).unwrap(), b"1");
+    }
+
+    #[test]
+    fn test_format_part_name() {
+        assert_eq!(
+            format_part_name("part_{:04}.bin", 7).unwrap(),
+            "part_0007.bin"
+        );
+        assert_eq!(format_part_name("{}", 12).unwrap(), "12");
+        assert_eq!(format_part_name("p{:3}", 5).unwrap(), "p  5");
+        assert!(format_part_name("part.bin", 0).is_err());
+        assert!(format_part_name("{}{}", 0).is_err());
+        assert!(format_part_name("{:x}", 0).is_err());
+    }
+
+    #[test]
+    fn test_split_and_join() {
+        let dir = tempfile::tempdir().unwrap();
+        let data: Vec<u8> = (0..25u8).collect();
+        let mut hex = hiex(&data);
+
+        let paths = hex.split_to_dir(dir.path(), 10, "part_{:04}.bin").unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                dir.path().join("part_0000.bin"),
+                dir.path().join("part_0001.bin"),
+                dir.path().join("part_0002.bin"),
+            ]
+        );
+        assert_eq!(std::fs::read(&paths[2]).unwrap(), &data[20..]);
+
+        let mut joined = Vec::new();
+        assert_eq!(join_files(&paths, &mut joined).unwrap(), 25);
+        assert_eq!(joined, data);
+
+        // Fails on the second part, since it already exists.
+        std::fs::remove_file(&paths[0]).unwrap();
+        std::fs::remove_file(&paths[2]).unwrap();
+        let err = hex
+            .split_to_dir(dir.path(), 10, "part_{:04}.bin")
+            .unwrap_err();
+        assert_eq!(err.completed, vec![paths[0].clone()]);
+        assert_eq!(err.failed, paths[1]);
+    }
+
+    #[test]
+    fn test_export_source() {
+        let mut hex = hiex(b"\x10\x00\x7f\x80\xff\x41");
+        let options = SourceExportOptions {
+            name: String::from("blob"),
+            bytes_per_line: 3,
+            uppercase: false,
+        };
+
+        let rust = hex.export_source(1..6, SourceLang::Rust, &options).unwrap();
+        assert_eq!(
+            rust,
+            "pub const BLOB: [u8; 5] = [\n    0x00, 0x7f, 0x80,\n    0xff, 0x41,\n];\n"
+        );
+
+        let options = SourceExportOptions {
+            uppercase: true,
+            ..options
+        };
+        let c = hex.export_source(2..5, SourceLang::C, &options).unwrap();
+        assert_eq!(c, "unsigned char blob[3] = {\n    0x7F, 0x80, 0xFF,\n};\n");
+
+        let empty = hex
+            .export_source(3..3, SourceLang::Rust, &SourceExportOptions::default())
+            .unwrap();
+        assert_eq!(empty, "pub const DATA: [u8; 0] = [\n];\n");
+
+        assert!(hex
+            .export_source(0..7, SourceLang::C, &SourceExportOptions::default())
+            .is_err());
+    }
+}
@@ -0,0 +1,279 @@
+//! An in-memory backend which makes repeated insertions and removals at nearby positions cheap.
+use crate::{
+    action::MemoryUsage, checked_usize, clamp_usize, known_length::KnownLength,
+    positioned_io::PositionedIo, truncate::Truncate,
+};
+use std::{
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+use usize_cast::FromUsize;
+
+/// The smallest gap made when the buffer has to grow.
+const MIN_GAP: usize = 64;
+
+/// In-memory data stored with a movable gap of unused bytes.
+/// Inserting or removing bytes moves the gap to that position and then only adjusts its size, so
+/// edits near the previous edit are amortized `O(1)`, rather than moving everything after them as
+/// with a `Cursor<Vec<u8>>`.
+/// It implements `Read + Write + Seek + Truncate` like a `Cursor`, so it can be given to
+/// [`Hiex::from_reader`](crate::Hiex::from_reader) in place of one.
+#[derive(Debug, Clone, Default)]
+pub struct GapBuffer {
+    buffer: Vec<u8>,
+    /// The unused bytes of `buffer`.
+    gap: Range<usize>,
+    position: u64,
+}
+impl GapBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_vec(data: Vec<u8>) -> Self {
+        let length = data.len();
+        Self {
+            buffer: data,
+            gap: length..length,
+            position: 0,
+        }
+    }
+
+    /// The amount of bytes of data, not including the gap.
+    pub fn len(&self) -> usize {
+        self.buffer.len() - self.gap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn into_vec(mut self) -> Vec<u8> {
+        self.move_gap(self.len());
+        self.buffer.truncate(self.gap.start);
+        self.buffer
+    }
+
+    /// The index into `buffer` of the byte at `offset`.
+    fn index(&self, offset: usize) -> usize {
+        if offset < self.gap.start {
+            offset
+        } else {
+            offset + self.gap.len()
+        }
+    }
+
+    /// Move the gap so that it starts at `offset`.
+    fn move_gap(&mut self, offset: usize) {
+        let gap_length = self.gap.len();
+        if offset < self.gap.start {
+            self.buffer
+                .copy_within(offset..self.gap.start, offset + gap_length);
+        } else if offset > self.gap.start {
+            self.buffer
+                .copy_within(self.gap.end..offset + gap_length, self.gap.start);
+        }
+        self.gap = offset..offset + gap_length;
+    }
+
+    /// Make the gap at least `length` bytes long, growing the buffer if needed.
+    fn reserve_gap(&mut self, length: usize) {
+        if self.gap.len() >= length {
+            return;
+        }
+        // Grow geometrically so that repeated insertions are amortized.
+        let extra = length.max(self.len() / 2).max(MIN_GAP) - self.gap.len();
+        let after = self.buffer.len() - self.gap.end;
+        self.buffer.resize(self.buffer.len() + extra, 0);
+        self.buffer
+            .copy_within(self.gap.end..self.gap.end + after, self.gap.end + extra);
+        self.gap.end += extra;
+    }
+
+    /// Insert `length` zero bytes at `offset`.
+    fn insert_zeroes(&mut self, offset: usize, length: usize) {
+        self.move_gap(offset);
+        self.reserve_gap(length);
+        let start = self.gap.start;
+        self.buffer[start..start + length].fill(0);
+        self.gap.start += length;
+    }
+
+    /// Remove the bytes within `range`.
+    fn remove(&mut self, range: Range<usize>) {
+        self.move_gap(range.start);
+        self.gap.end += range.len();
+    }
+}
+impl Read for GapBuffer {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let length = u64::from_usize(self.len());
+        if self.position >= length {
+            return Ok(0);
+        }
+        let offset = clamp_usize(self.position, self.len());
+        let index = self.index(offset);
+        // Read up to the gap or the end of the buffer, whichever comes first.
+        let end = if index < self.gap.start {
+            self.gap.start
+        } else {
+            self.buffer.len()
+        };
+        let amount = buf.len().min(end - index);
+        buf[..amount].copy_from_slice(&self.buffer[index..index + amount]);
+        self.position += u64::from_usize(amount);
+        Ok(amount)
+    }
+}
+impl Write for GapBuffer {
+    /// Overwrites bytes, extending the data when writing past the end.
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let end = checked_usize(self.position.saturating_add(u64::from_usize(buf.len())))?;
+        let offset = end - buf.len();
+        if end > self.len() {
+            let length = self.len();
+            self.insert_zeroes(length, end - length);
+        }
+        for (i, byte) in buf.iter().enumerate() {
+            let index = self.index(offset + i);
+            self.buffer[index] = *byte;
+        }
+        self.position += u64::from_usize(buf.len());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+impl Seek for GapBuffer {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(position) => {
+                self.position = position;
+                return Ok(position);
+            }
+            SeekFrom::End(offset) => (u64::from_usize(self.len()), offset),
+            SeekFrom::Current(offset) => (self.position, offset),
+        };
+        match base.checked_add_signed(offset) {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+impl Truncate for GapBuffer {
+    fn truncate(&mut self, new_len: u64) -> std::io::Result<()> {
+        let new_len = checked_usize(new_len)?;
+        let length = self.len();
+        if new_len < length {
+            self.remove(new_len..length);
+        } else {
+            self.insert_zeroes(length, new_len - length);
+        }
+        if self.position >= u64::from_usize(new_len) {
+            self.position = u64::from_usize(new_len.saturating_sub(1));
+        }
+        Ok(())
+    }
+
+    fn insert_space(&mut self, position: u64, length: u64) -> std::io::Result<bool> {
+        if position > u64::from_usize(self.len()) {
+            return Err(ErrorKind::InvalidInput.into());
+        }
+        let length = checked_usize(length)?;
+        self.insert_zeroes(clamp_usize(position, self.len()), length);
+        Ok(true)
+    }
+
+    fn remove_space(&mut self, range: Range<u64>) -> std::io::Result<bool> {
+        if range.start > range.end || range.end > u64::from_usize(self.len()) {
+            return Err(ErrorKind::InvalidInput.into());
+        }
+        let length = self.len();
+        self.remove(clamp_usize(range.start, length)..clamp_usize(range.end, length));
+        Ok(true)
+    }
+}
+impl KnownLength for GapBuffer {
+    fn known_length(&self) -> Option<u64> {
+        Some(u64::from_usize(self.len()))
+    }
+}
+impl PositionedIo for GapBuffer {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Option<std::io::Result<usize>> {
+        if offset >= u64::from_usize(self.len()) {
+            return Some(Ok(0));
+        }
+        let index = self.index(clamp_usize(offset, self.len()));
+        let end = if index < self.gap.start {
+            self.gap.start
+        } else {
+            self.buffer.len()
+        };
+        let amount = buf.len().min(end - index);
+        buf[..amount].copy_from_slice(&self.buffer[index..index + amount]);
+        Some(Ok(amount))
+    }
+}
+impl MemoryUsage for GapBuffer {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.buffer.capacity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GapBuffer;
+    use crate::{truncate::Truncate, Hiex};
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+    #[test]
+    fn test_gap_buffer_io() {
+        let mut data = GapBuffer::from_vec(b"0123456789".to_vec());
+        data.insert_space(4, 2).unwrap();
+        data.seek(SeekFrom::Start(4)).unwrap();
+        data.write_all(b"ab").unwrap();
+        data.remove_space(0..2).unwrap();
+
+        // Reads span the gap.
+        let mut contents = Vec::new();
+        data.seek(SeekFrom::Start(0)).unwrap();
+        data.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, b"23ab456789");
+
+        data.seek(SeekFrom::End(-1)).unwrap();
+        data.write_all(b"XYZ").unwrap();
+        data.truncate(11).unwrap();
+        assert_eq!(data.len(), 11);
+        assert!(data.seek(SeekFrom::Current(-20)).is_err());
+        assert_eq!(data.into_vec(), b"23ab45678XY");
+    }
+
+    #[test]
+    fn test_gap_buffer_insertions() {
+        let mut gap = Hiex::from_reader(GapBuffer::new()).unwrap();
+        let mut cursor = Hiex::from_reader(Cursor::new(Vec::new())).unwrap();
+        for i in 0..10_000u32 {
+            let byte = vec![(i % 251) as u8];
+            gap.insert_bytes(0, byte.clone(), ()).unwrap();
+            cursor.insert_bytes(0, byte, ()).unwrap();
+        }
+        for _ in 0..100 {
+            gap.undo(()).unwrap();
+            cursor.undo(()).unwrap();
+        }
+        gap.delete_bytes(10..5000, ()).unwrap();
+        cursor.delete_bytes(10..5000, ()).unwrap();
+
+        let cursor = cursor.into_inner().into_inner();
+        assert_eq!(cursor.len(), 10_000 - 100 - 4990);
+        assert_eq!(gap.into_inner().into_vec(), cursor);
+    }
+}
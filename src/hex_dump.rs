@@ -0,0 +1,260 @@
+//! Formatting data as a hex dump of offset, hex, and ASCII lines.
+use crate::Hiex;
+use std::{
+    fmt::Write as _,
+    io::{Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+use usize_cast::FromUsize;
+
+/// Options for [`Hiex::format_hex_dump`] and [`HexDumpWriter`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct HexDumpOptions {
+    /// How many bytes are shown on each line. Treated as `1` if it is `0`.
+    pub bytes_per_line: usize,
+}
+impl Default for HexDumpOptions {
+    fn default() -> Self {
+        Self { bytes_per_line: 16 }
+    }
+}
+
+/// Formats one line of a dump: the offset, the bytes in hex padded out to `bytes_per_line`, and
+/// the bytes as ASCII with `.` for anything unprintable.
+pub(crate) fn format_dump_line(
+    output: &mut String,
+    offset: u64,
+    bytes: &[u8],
+    bytes_per_line: usize,
+) {
+    let _ = write!(output, "{:08x} ", offset);
+    for byte in bytes {
+        let _ = write!(output, " {:02x}", byte);
+    }
+    for _ in bytes.len()..bytes_per_line {
+        output.push_str("   ");
+    }
+    output.push_str("  |");
+    for &byte in bytes {
+        output.push(if byte.is_ascii_graphic() || byte == b' ' {
+            char::from(byte)
+        } else {
+            '.'
+        });
+    }
+    output.push_str("|\n");
+}
+
+/// A writer which formats the bytes written to it as hex dump lines, writing them to a sink.
+/// Bytes are kept until they fill a line, so the lines don't depend on how the writes were split
+/// up. Flushing (or dropping) writes the partial line, so the lines after a flush start at
+/// wherever it left off.
+/// With [`HexDumpWriter::with_tee`] the bytes are also passed through to another writer, such as
+/// to watch what is written by [`Hiex::save_to`]. Only the bytes it accepts are formatted, but an
+/// error from the sink is returned even once they were passed through.
+#[derive(Debug)]
+pub struct HexDumpWriter<W, T = std::io::Sink>
+where
+    W: Write,
+    T: Write,
+{
+    sink: W,
+    tee: Option<T>,
+    bytes_per_line: usize,
+    /// The offset of the first pending byte.
+    offset: u64,
+    pending: Vec<u8>,
+    line: String,
+}
+impl<W> HexDumpWriter<W>
+where
+    W: Write,
+{
+    pub fn new(sink: W, options: &HexDumpOptions) -> Self {
+        Self::with_tee_inner(sink, None, options)
+    }
+}
+impl<W, T> HexDumpWriter<W, T>
+where
+    W: Write,
+    T: Write,
+{
+    /// Formats the bytes to `sink`, and also writes them to `tee`.
+    pub fn with_tee(sink: W, tee: T, options: &HexDumpOptions) -> Self {
+        Self::with_tee_inner(sink, Some(tee), options)
+    }
+
+    fn with_tee_inner(sink: W, tee: Option<T>, options: &HexDumpOptions) -> Self {
+        let bytes_per_line = options.bytes_per_line.max(1);
+        Self {
+            sink,
+            tee,
+            bytes_per_line,
+            offset: 0,
+            pending: Vec::with_capacity(bytes_per_line),
+            line: String::new(),
+        }
+    }
+
+    /// Label the lines starting from `offset`, rather than 0.
+    pub fn with_start_offset(mut self, offset: u64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.sink
+    }
+
+    pub fn tee_ref(&self) -> Option<&T> {
+        self.tee.as_ref()
+    }
+
+    /// Formats the pending bytes as a line, and writes it to the sink.
+    fn write_line(&mut self) -> std::io::Result<()> {
+        self.line.clear();
+        format_dump_line(
+            &mut self.line,
+            self.offset,
+            &self.pending,
+            self.bytes_per_line,
+        );
+        self.offset += u64::from_usize(self.pending.len());
+        self.pending.clear();
+        self.sink.write_all(self.line.as_bytes())
+    }
+}
+impl<W, T> Write for HexDumpWriter<W, T>
+where
+    W: Write,
+    T: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = match &mut self.tee {
+            Some(tee) => tee.write(buf)?,
+            None => buf.len(),
+        };
+
+        let mut bytes = &buf[..written];
+        while !bytes.is_empty() {
+            let amount = (self.bytes_per_line - self.pending.len()).min(bytes.len());
+            self.pending.extend_from_slice(&bytes[..amount]);
+            bytes = &bytes[amount..];
+            if self.pending.len() == self.bytes_per_line {
+                self.write_line()?;
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if !self.pending.is_empty() {
+            self.write_line()?;
+        }
+        if let Some(tee) = &mut self.tee {
+            tee.flush()?;
+        }
+        self.sink.flush()
+    }
+}
+impl<W, T> Drop for HexDumpWriter<W, T>
+where
+    W: Write,
+    T: Write,
+{
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Formats the bytes within `range` (clamped to the data) as hex dump lines, labelled with
+    /// their offsets. The data is read in chunks, and the position is restored afterwards.
+    pub fn format_hex_dump(
+        &mut self,
+        range: Range<u64>,
+        options: &HexDumpOptions,
+    ) -> std::io::Result<String> {
+        let position = self.position()?;
+        let mut output = Vec::new();
+        let result = self.seek(SeekFrom::Start(range.start)).and_then(|_| {
+            let mut writer =
+                HexDumpWriter::new(&mut output, options).with_start_offset(range.start);
+            let length = range.end.saturating_sub(range.start);
+            std::io::copy(&mut Read::by_ref(self).take(length), &mut writer)?;
+            writer.flush()
+        });
+        self.seek(SeekFrom::Start(position))?;
+        result?;
+        // The lines are always ASCII.
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HexDumpOptions, HexDumpWriter};
+    use crate::Hiex;
+    use std::io::{Cursor, Write};
+
+    fn data() -> Vec<u8> {
+        (0..33u8).map(|i| b'0' + i).collect()
+    }
+
+    #[test]
+    fn test_format_hex_dump() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(data())).unwrap();
+        let options = HexDumpOptions { bytes_per_line: 8 };
+        assert_eq!(
+            hex.format_hex_dump(14..100, &options).unwrap(),
+            concat!(
+                "0000000e  3e 3f 40 41 42 43 44 45  |>?@ABCDE|\n",
+                "00000016  46 47 48 49 4a 4b 4c 4d  |FGHIJKLM|\n",
+                "0000001e  4e 4f 50                 |NOP|\n",
+            )
+        );
+        assert_eq!(hex.format_hex_dump(5..5, &options).unwrap(), "");
+        assert_eq!(hex.position().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_hex_dump_writer() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(data())).unwrap();
+        let options = HexDumpOptions::default();
+        let expected = hex.format_hex_dump(0..33, &options).unwrap();
+
+        let mut output = Vec::new();
+        let mut passed = Vec::new();
+        {
+            let mut writer = HexDumpWriter::with_tee(&mut output, &mut passed, &options);
+            let data = data();
+            writer.write_all(&data[..3]).unwrap();
+            writer.write_all(&data[3..32]).unwrap();
+            assert_eq!(
+                writer.get_ref().len(),
+                2 * expected.lines().next().unwrap().len() + 2
+            );
+            writer.write_all(&data[32..]).unwrap();
+            // The partial line is written when it is dropped.
+        }
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+        assert_eq!(passed, data());
+
+        let mut output = Vec::new();
+        let mut writer = HexDumpWriter::new(&mut output, &options).with_start_offset(0x10);
+        writer.write_all(b"ab").unwrap();
+        writer.flush().unwrap();
+        writer.write_all(b"c").unwrap();
+        drop(writer);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "00000010  61 62{:42}  |ab|\n00000012  63{:45}  |c|\n",
+                "", ""
+            )
+        );
+    }
+}
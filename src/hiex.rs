@@ -1,21 +1,151 @@
 use crate::{
-    action::{Action, ActionError, ActionList, MemoryUsage},
+    action::{
+        describe_bytes, plural_bytes, translate_offsets, Action, ActionError, ActionList,
+        ActionMemory, Added, CompoundAction, HistoryDirection, HistoryEntry, HistoryError,
+        JumpError, MemoryUsage, OffsetShift, ResyncReport,
+    },
+    background::SaveState,
+    block_index::BlockIndex,
+    bookmark::{shift_anchors, unshift_anchors, Anchor, Bookmarks},
+    checked_usize,
+    checksum::{ChecksumAlgorithm, ChecksumFieldAction, ChecksumState, LiveChecksum},
+    clamp_usize,
+    import::PasteOverwriteAction,
+    jump::JumpList,
+    known_len,
+    known_length::KnownLength,
+    move_bytes,
+    positioned_io::{PositionedIo, ReadAtFn, ReaderAt},
+    recorder::Macro,
+    region::{RegionLocks, RegionTags},
+    savepoint::Savepoint,
     stream_len,
+    transaction::Commit,
+    transform::{
+        ArithmeticAction, ArithmeticMode, BitFlipAction, CaseConvertAction, CaseMode,
+        CopyRangeAction, EndianSwapAction, FillAction, MoveRangeAction, ReverseAction,
+        RotateDirection, RotateRangeAction, SwapRangesAction, XorRangeAction,
+    },
     truncate::Truncate,
+    write_at, EditAction, Endian, Number, CHUNK_SIZE,
+};
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    io::{Read, Seek, SeekFrom, Write},
+    ops::Range,
+    sync::Arc,
 };
-use std::io::{Read, Seek, SeekFrom, Write};
 use usize_cast::FromUsize;
 
 // TODO: write a WriteWrapper that stores the data that is being written in an efficient structure
 // this would be useful for things like memory, where it doesn't make complete sense
 /// F is the type of reader
-/// E is the arguments passed to actions when they are being done/undone
-pub struct Hiex<F, E>
+/// E is the arguments passed to actions when they are being done/undone, which defaults to `()`
+/// for when there is nothing to pass.
+///
+/// Defaults aren't used when inferring types, so either name the type or pass the argument:
+/// ```
+/// use hiex::{EditAction, Hiex};
+/// use std::io::Cursor;
+///
+/// let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"abc".to_vec())).unwrap();
+/// hex.add_action_simple(EditAction::new(0, b"x".to_vec())).unwrap();
+/// hex.undo_simple().unwrap();
+///
+/// let mut hex: Hiex<Cursor<Vec<u8>>, u32> = Hiex::from_reader(Cursor::new(b"abc".to_vec())).unwrap();
+/// hex.add_action(EditAction::new(0, b"x".to_vec()), 5).unwrap();
+/// hex.undo(5).unwrap();
+/// ```
+pub struct Hiex<F, E = ()>
 where
     F: Read + Seek + Write,
 {
     reader: F,
     pub actions: ActionList<F, E>,
+    /// The state of the most recent background save, if one has been started.
+    pub(crate) background_save: Option<Arc<SaveState>>,
+    /// A checksum kept current as actions are applied, if tracking was requested.
+    checksum: Option<LiveChecksum>,
+    /// Hashes of the data in blocks, with the blocks modified since being hashed marked dirty,
+    /// if one was built.
+    pub(crate) block_index: Option<BlockIndex>,
+    pub(crate) region_locks: RegionLocks,
+    pub(crate) region_tags: RegionTags,
+    pub(crate) bookmarks: Bookmarks,
+    /// The anchors which each applied delete moved to the start of its range, by history index,
+    /// so that undoing it can restore them.
+    collapsed_anchors: BTreeMap<usize, Vec<(Anchor, u64)>>,
+    pub(crate) jumps: JumpList,
+    pub(crate) savepoints: Vec<Savepoint>,
+    /// The history index when the data was last saved.
+    pub(crate) last_saved: Option<usize>,
+    /// The history index at which the data is the same as when it was opened or last saved, or
+    /// `None` if that point was discarded from the history.
+    pub(crate) clean_index: Option<usize>,
+    /// Set for [`GrowthPolicy::GrowOnEdit`].
+    edit_growth: Option<EditGrowth<F, E>>,
+    /// The range affected by the most recently applied or undone action, see
+    /// [`Hiex::last_affected_range`].
+    last_affected: Option<Range<u64>>,
+    /// The macro being recorded, see [`Hiex::start_macro`].
+    pub(crate) recording: Option<Macro<F, E>>,
+    /// Finds the length of the reader, see [`Hiex::with_known_length`].
+    length: fn(&mut F) -> std::io::Result<u64>,
+    /// Reads without seeking, see [`Hiex::with_positioned_io`].
+    read_at: Option<ReadAtFn<F>>,
+}
+
+/// What [`Hiex::add_action`] does with an [`EditAction`] (or a [`PasteOverwriteAction`]) which
+/// extends past the end of the data.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum GrowthPolicy {
+    /// Reject it with `ActionError::Invalid`.
+    #[default]
+    Deny,
+    /// Grow the data to fit it first, filling any bytes between the old end and the edit with
+    /// `fill`. The growth is undone along with the edit.
+    GrowOnEdit { fill: u8 },
+}
+
+/// The growth for [`GrowthPolicy::GrowOnEdit`]. `add_action` doesn't require `Truncate`, so the
+/// functions which need it are chosen by [`Hiex::set_growth_policy`], where it is known.
+struct EditGrowth<F, E>
+where
+    F: Read + Seek + Write,
+{
+    fill: u8,
+    add: AddGrownEdit<F, E>,
+}
+type AddGrownEdit<F, E> =
+    fn(&mut Hiex<F, E>, u64, Box<dyn Action<F, E>>, u8, E) -> Result<(), ActionError>;
+
+/// Adds `edit` along with growing the data to `end` to fit it, as a single action.
+fn add_grown_edit<F, E>(
+    hex: &mut Hiex<F, E>,
+    end: u64,
+    edit: Box<dyn Action<F, E>>,
+    fill: u8,
+    other: E,
+) -> Result<(), ActionError>
+where
+    F: 'static + Read + Seek + Write + Truncate,
+    E: 'static + Clone,
+{
+    let mut grown = CompoundAction::new();
+    grown.push(GrowAction::new(end).with_fill(fill));
+    grown.push_boxed(edit);
+    hex.add_action(grown, other).map_err(|(_, err)| err)
+}
+
+/// The edit which [`GrowthPolicy::GrowOnEdit`] applies to, if `action` is an [`EditAction`] or
+/// a [`PasteOverwriteAction`].
+fn growable_edit(action: &dyn Any) -> Option<&EditAction> {
+    action.downcast_ref::<EditAction>().or_else(|| {
+        action
+            .downcast_ref::<PasteOverwriteAction>()
+            .map(PasteOverwriteAction::edit)
+    })
 }
 impl<F, E> Hiex<F, E>
 where
@@ -27,9 +157,49 @@ where
         Ok(Hiex {
             reader,
             actions: ActionList::new(),
+            background_save: None,
+            checksum: None,
+            block_index: None,
+            region_locks: RegionLocks::default(),
+            region_tags: RegionTags::new(),
+            bookmarks: Bookmarks::default(),
+            collapsed_anchors: BTreeMap::new(),
+            jumps: JumpList::default(),
+            savepoints: Vec::new(),
+            last_saved: None,
+            clean_index: Some(0),
+            edit_growth: None,
+            last_affected: None,
+            recording: None,
+            length: stream_len::<F>,
+            read_at: None,
         })
     }
 
+    /// Uses [`KnownLength`] to find the length of the reader in [`Hiex::length`], rather than
+    /// seeking to its end.
+    pub fn with_known_length(mut self) -> Self
+    where
+        F: KnownLength,
+    {
+        self.length = known_len::<F>;
+        self
+    }
+
+    /// Uses [`PositionedIo`] for [`Hiex::read_at`] and [`Hiex::read_amount_at`], so that they don't
+    /// seek, and so don't move the current position, when the reader supports it.
+    /// Applying actions still seeks the reader; wrap it in a [`PositionedWrapper`] so that they
+    /// read and write at an offset too.
+    ///
+    /// [`PositionedWrapper`]: crate::positioned_io::PositionedWrapper
+    pub fn with_positioned_io(mut self) -> Self
+    where
+        F: PositionedIo,
+    {
+        self.read_at = Some(<F as PositionedIo>::read_at);
+        self
+    }
+
     /// Gets the inner reader
     pub fn into_inner(self) -> F {
         self.reader
@@ -39,48 +209,748 @@ where
         self.actions
     }
 
+    /// The inner reader, for reading without going through the history.
+    pub(crate) fn reader(&self) -> &F {
+        &self.reader
+    }
+
+    /// Flushes the inner reader.
+    pub(crate) fn flush_inner(&mut self) -> std::io::Result<()> {
+        self.reader.flush()
+    }
+
     // FIXME: replace this with an actual call once stream_position is stabilized
     /// Position into the reader.
     /// Uses `std::io::Seek::stream_position` internally.
     pub fn position(&mut self) -> std::io::Result<u64> {
-        self.seek(SeekFrom::Current(0))
+        self.stream_position()
     }
 
     // FIXME: replace this with an actual call once `stream_len` is stabilized
     /// Size of the data in reader
     /// Uses `std::io::Seek::stream_len` internally.
+    /// If [`Hiex::with_known_length`] was used and the reader knows its length then no seeking is
+    /// done.
     pub fn length(&mut self) -> std::io::Result<u64> {
-        // Get the current position, so that we can restore our position.
-        let position = self.position()?;
-        let length = self.seek(SeekFrom::End(0))?;
+        (self.length)(&mut self.reader)
+    }
 
-        // If we're still at the starting position, let's not seek again.
-        if position != length {
-            self.seek(SeekFrom::Start(position))?;
+    /// Fails with `ActionError::SaveInProgress` while a background save is running.
+    /// The range it may have modified is then given by [`Hiex::last_affected_range`].
+    pub fn add_action<A>(&mut self, action: A, other: E) -> Result<(), (A, ActionError)>
+    where
+        A: 'static + Action<F, E>,
+    {
+        self.settle_background_save();
+        if self.is_saving() {
+            return Err((action, ActionError::SaveInProgress));
+        }
+        if let Err(err) = self.region_locks.check(action.affected_range()) {
+            return Err((action, err));
+        }
+        if let Some(growth) = &self.edit_growth {
+            if let Some(edit) = growable_edit(&action) {
+                let (fill, add) = (growth.fill, growth.add);
+                let length = match (self.length)(&mut self.reader) {
+                    Ok(length) => length,
+                    Err(err) => return Err((action, err.into())),
+                };
+                let end = match edit
+                    .position
+                    .checked_add(u64::from_usize(edit.new_data.len()))
+                {
+                    Some(end) => end,
+                    None => return Err((action, ActionError::Invalid)),
+                };
+                if end > length {
+                    // A paste stays a paste, rather than becoming a plain edit.
+                    let any = &action as &dyn Any;
+                    let grown: Box<dyn Action<F, E>> =
+                        match any.downcast_ref::<PasteOverwriteAction>() {
+                            Some(paste) => Box::new(paste.clone()),
+                            None => Box::new(edit.clone()),
+                        };
+                    return add(self, end, grown, fill, other).map_err(|err| (action, err));
+                }
+            }
+        }
+        // Copied before it is applied, since the copy can't depend on what applying captures.
+        let recorded = self.recording.as_ref().map(|_| action.clone_for_replay());
+        let added = self
+            .actions
+            .add_coalescing(action, &mut self.reader, other)?;
+        if let Some(recorded) = recorded {
+            self.record_action(recorded);
+        }
+        let merged = match added {
+            Added::Pushed => None,
+            Added::Merged(action) => Some(action),
+            // Nothing changed, and the history is as it was.
+            Added::Skipped(mut action) => {
+                action.release_buffers(self.actions.pool_mut());
+                return Ok(());
+            }
+        };
+        let index = self.actions.past_len() - 1;
+        // The future was discarded.
+        self.collapsed_anchors.split_off(&index);
+        if self.clean_index.is_some_and(|clean| clean > index) {
+            self.clean_index = None;
         }
+        match merged {
+            // Merged actions don't move bytes, so only what depends on the bytes is updated.
+            Some(mut merged) => {
+                if let Some(checksum) = &mut self.checksum {
+                    checksum.update(merged.overwrite_delta());
+                }
+                if let Some(block_index) = &mut self.block_index {
+                    block_index.invalidate(merged.affected_range());
+                }
+                self.last_affected = Some(merged.affected_range().unwrap_or(0..u64::MAX));
+                // Its bytes are now held by the action it was merged into.
+                merged.release_buffers(self.actions.pool_mut());
+            }
+            None => self.on_action_changed(index, false),
+        }
+        let evicted = self.actions.enforce_memory_budget();
+        self.on_evicted(evicted);
+        Ok(())
+    }
 
-        Ok(length)
+    /// Sets the most memory the history may use, discarding the oldest applied actions if it is
+    /// already over it. See [`ActionList::set_memory_budget`].
+    /// Returns the amount of actions discarded.
+    pub fn set_memory_budget(&mut self, budget: Option<usize>) -> usize {
+        self.actions.set_memory_budget(budget);
+        let evicted = self.actions.enforce_memory_budget();
+        self.on_evicted(evicted);
+        evicted
     }
 
-    pub fn add_action<A>(&mut self, action: A, other: E) -> Result<(), (A, ActionError)>
+    /// Update the history indices kept alongside the history after the oldest `count` actions
+    /// were discarded from it.
+    fn on_evicted(&mut self, count: usize) {
+        if count == 0 {
+            return;
+        }
+        self.collapsed_anchors = std::mem::take(&mut self.collapsed_anchors)
+            .split_off(&count)
+            .into_iter()
+            .map(|(index, anchors)| (index - count, anchors))
+            .collect();
+        let update = |index: usize| index.checked_sub(count);
+        self.clean_index = self.clean_index.and_then(update);
+        self.last_saved = self.last_saved.and_then(update);
+        self.savepoints.retain(|savepoint| savepoint.index >= count);
+        for savepoint in &mut self.savepoints {
+            savepoint.index -= count;
+        }
+    }
+
+    /// Sets what [`Hiex::add_action`] does with an [`EditAction`] which extends past the end of
+    /// the data. This is [`GrowthPolicy::Deny`] by default.
+    pub fn set_growth_policy(&mut self, policy: GrowthPolicy)
+    where
+        F: 'static + Truncate,
+        E: 'static + Clone,
+    {
+        self.edit_growth = match policy {
+            GrowthPolicy::Deny => None,
+            GrowthPolicy::GrowOnEdit { fill } => Some(EditGrowth {
+                fill,
+                add: add_grown_edit::<F, E>,
+            }),
+        };
+    }
+
+    pub fn growth_policy(&self) -> GrowthPolicy {
+        match &self.edit_growth {
+            None => GrowthPolicy::Deny,
+            Some(growth) => GrowthPolicy::GrowOnEdit { fill: growth.fill },
+        }
+    }
+
+    /// [`Hiex::add_action`], passing the default value of `E`.
+    pub fn add_action_simple<A>(&mut self, action: A) -> Result<(), (A, ActionError)>
     where
         A: 'static + Action<F, E>,
+        E: Default,
+    {
+        self.add_action(action, E::default())
+    }
+
+    /// [`Hiex::undo`], passing the default value of `E`.
+    pub fn undo_simple(&mut self) -> Result<Option<()>, HistoryError>
+    where
+        E: Default,
+    {
+        self.undo(E::default())
+    }
+
+    /// [`Hiex::redo`], passing the default value of `E`.
+    pub fn redo_simple(&mut self) -> Result<Option<()>, HistoryError>
+    where
+        E: Default,
+    {
+        self.redo(E::default())
+    }
+
+    /// Undoes every applied action, returning the amount undone. If one fails, the error gives
+    /// its index and how many were undone before it. See [`Hiex::jump_to_history`].
+    pub fn undo_all(&mut self, other: E) -> Result<usize, JumpError>
+    where
+        E: Clone,
+    {
+        self.jump_to_history(0, other)
+    }
+
+    /// Redoes every undone action, returning the amount redone. If one fails, the error gives its
+    /// index and how many were redone before it. See [`Hiex::jump_to_history`].
+    pub fn redo_all(&mut self, other: E) -> Result<usize, JumpError>
+    where
+        E: Clone,
+    {
+        let len = self.actions.len();
+        self.jump_to_history(len, other)
+    }
+
+    /// Check the action at `index` in the history against the locked regions.
+    fn check_locks_at(&self, index: usize) -> Result<(), ActionError> {
+        match self.actions.get(index) {
+            Some(action) => self.region_locks.check(action.affected_range()),
+            None => Ok(()),
+        }
+    }
+
+    /// Update anything derived from the data after the action at `index` was applied, or unapplied
+    /// if `undone`.
+    fn on_action_changed(&mut self, index: usize, undone: bool) {
+        let affected = self
+            .actions
+            .get(index)
+            .and_then(|action| action.affected_range());
+        self.last_affected = Some(affected.clone().unwrap_or(0..u64::MAX));
+
+        let shift = self
+            .actions
+            .get(index)
+            .and_then(|action| action.offset_shift());
+        if let Some(shift) = shift {
+            if undone {
+                let collapsed = self.collapsed_anchors.remove(&index).unwrap_or_default();
+                unshift_anchors(
+                    &mut self.bookmarks,
+                    &mut self.region_tags,
+                    &shift,
+                    &collapsed,
+                );
+            } else {
+                let collapsed = shift_anchors(&mut self.bookmarks, &mut self.region_tags, &shift);
+                if !collapsed.is_empty() {
+                    self.collapsed_anchors.insert(index, collapsed);
+                }
+            }
+        }
+
+        if let Some(checksum) = &mut self.checksum {
+            let delta = self
+                .actions
+                .get(index)
+                .and_then(|action| action.overwrite_delta());
+            checksum.update(delta);
+        }
+
+        if let Some(block_index) = &mut self.block_index {
+            block_index.invalidate(affected);
+        }
+    }
+
+    /// The range of bytes which the most recently applied, undone, or redone action may have
+    /// modified, such as for invalidating only that part of a rendered view after
+    /// [`Hiex::add_action`], [`Hiex::undo`], or [`Hiex::redo`]. An action with an unknown
+    /// affected range (see [`Action::affected_range`]) gives `0..u64::MAX`.
+    /// Returns `None` if no action has been applied or undone yet.
+    pub fn last_affected_range(&self) -> Option<Range<u64>> {
+        self.last_affected.clone()
+    }
+
+    /// Collapses the applied actions from `start` onwards into a single entry in the history,
+    /// discarding the future, unless there are fewer than two of them or any of them move bytes.
+    /// Those are left alone, as the bookmarks and tags they move are restored one action at a
+    /// time. Returns which of these happened.
+    pub(crate) fn collapse_history_from(&mut self, start: usize) -> Commit
+    where
+        F: 'static,
+        E: 'static + Clone,
     {
-        self.actions.add(action, &mut self.reader, other)
+        let end = self.actions.past_len();
+        if end <= start + 1 {
+            return Commit::Single;
+        }
+        let moves_bytes = (start..end).any(|index| {
+            self.actions
+                .get(index)
+                .is_some_and(|action| action.offset_shift().is_some())
+        });
+        if moves_bytes {
+            return Commit::Separate;
+        }
+
+        self.actions.collapse_from(start);
+        self.collapsed_anchors.split_off(&start);
+        // Points within the collapsed actions no longer exist, and those after them are earlier.
+        let removed = end - start - 1;
+        let update = |index: usize| match index {
+            index if index <= start => Some(index),
+            index if index == end => Some(start + 1),
+            _ => None,
+        };
+        self.clean_index = self.clean_index.and_then(update);
+        self.last_saved = self.last_saved.and_then(update);
+        self.savepoints
+            .retain(|savepoint| savepoint.index <= start || savepoint.index >= end);
+        for savepoint in &mut self.savepoints {
+            if savepoint.index >= end {
+                savepoint.index -= removed;
+            }
+        }
+        Commit::Collapsed
     }
 
-    pub fn undo(&mut self, other: E) -> Result<Option<()>, ActionError> {
-        self.actions.undo(&mut self.reader, other)
+    /// Start keeping a checksum of the data current as actions are applied and unapplied.
+    /// The checksum is computed over all of the data once, and then updated incrementally for
+    /// actions which report the bytes they overwrite. Other actions make it stale, see
+    /// [`Hiex::recompute_checksum`].
+    /// Returns the initial checksum.
+    pub fn track_checksum(&mut self, algorithm: ChecksumAlgorithm) -> std::io::Result<u32> {
+        let length = self.length()?;
+        let value = self.checksum_of_data(algorithm)?;
+
+        self.checksum = Some(LiveChecksum::new(algorithm, value, length));
+        Ok(value)
+    }
+
+    /// The CRC-32 of all of the data, read in chunks.
+    pub(crate) fn crc32_of_data(&mut self) -> std::io::Result<u32> {
+        self.checksum_of_data(ChecksumAlgorithm::Crc32)
+    }
+
+    /// The checksum of all of the data, read in chunks.
+    pub(crate) fn checksum_of_data(
+        &mut self,
+        algorithm: ChecksumAlgorithm,
+    ) -> std::io::Result<u32> {
+        let mut hasher = algorithm.hasher();
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+        self.seek(SeekFrom::Start(0))?;
+        loop {
+            let amount = self.read(&mut buffer)?;
+            if amount == 0 {
+                break;
+            }
+            hasher.update(&buffer[..amount]);
+        }
+        Ok(hasher.finish())
     }
 
-    pub fn redo(&mut self, other: E) -> Result<Option<()>, ActionError> {
-        self.actions.redo(&mut self.reader, other)
+    /// Stop keeping a checksum current.
+    pub fn untrack_checksum(&mut self) {
+        self.checksum = None;
+    }
+
+    /// The tracked checksum, if [`Hiex::track_checksum`] has been called.
+    pub fn current_checksum(&self) -> Option<ChecksumState> {
+        self.checksum.as_ref().map(LiveChecksum::state)
+    }
+
+    /// Recompute the tracked checksum from all of the data, such as when it has become stale.
+    /// Returns `None` if no checksum is being tracked.
+    pub fn recompute_checksum(&mut self) -> std::io::Result<Option<u32>> {
+        match self.checksum.as_ref().map(|checksum| checksum.algorithm) {
+            Some(algorithm) => self.track_checksum(algorithm).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The memory used by each entry in the history.
+    pub fn memory_usage_per_action(&self) -> Vec<ActionMemory> {
+        self.actions.memory_usage_per_action()
+    }
+
+    /// Every entry in the history, with when it was added and last applied.
+    /// See [`ActionList::history`].
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        self.actions.history()
+    }
+
+    /// Fails with `ActionError::SaveInProgress` while a background save is running.
+    /// The range it may have modified is then given by [`Hiex::last_affected_range`].
+    /// See [`ActionList::undo`].
+    pub fn undo(&mut self, other: E) -> Result<Option<()>, HistoryError> {
+        self.settle_background_save();
+        let index = match self.actions.past_len().checked_sub(1) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        self.check_history_at(index, HistoryDirection::Undo)?;
+        let result = self.actions.undo(&mut self.reader, other)?;
+        if result.is_some() {
+            self.on_action_changed(self.actions.past_len(), true);
+        }
+        Ok(result)
+    }
+
+    /// Fails with `ActionError::SaveInProgress` while a background save is running.
+    /// The range it may have modified is then given by [`Hiex::last_affected_range`].
+    /// See [`ActionList::redo`].
+    pub fn redo(&mut self, other: E) -> Result<Option<()>, HistoryError> {
+        self.settle_background_save();
+        let index = self.actions.past_len();
+        if self.actions.is_future_empty() {
+            return Ok(None);
+        }
+        self.check_history_at(index, HistoryDirection::Redo)?;
+        let result = self.actions.redo(&mut self.reader, other)?;
+        if result.is_some() {
+            self.on_action_changed(self.actions.past_len() - 1, false);
+        }
+        Ok(result)
+    }
+
+    /// Undoes or redoes until `index` actions are applied, such as for going back to an entry
+    /// picked from [`Hiex::history`].
+    /// Fails with `ActionError::SaveInProgress` while a background save is running.
+    /// See [`ActionList::jump_to`].
+    pub fn jump_to_history(&mut self, index: usize, other: E) -> Result<usize, JumpError>
+    where
+        E: Clone,
+    {
+        let target = index.min(self.actions.len());
+        let mut steps = 0;
+        while self.actions.past_len() != target {
+            let result = if self.actions.past_len() > target {
+                self.undo(other.clone())
+            } else {
+                self.redo(other.clone())
+            };
+            if let Err(error) = result {
+                return Err(JumpError {
+                    reached: self.actions.past_len(),
+                    steps,
+                    error,
+                });
+            }
+            steps += 1;
+        }
+        Ok(steps)
+    }
+
+    /// Fails with `ActionError::SaveInProgress` while a background save is running.
+    /// See [`ActionList::switch_branch`]. The savepoints after where the branch follows on from
+    /// are removed, as they refer to the actions which were switched away from.
+    pub fn switch_branch(&mut self, branch: usize, other: E) -> Result<Option<()>, HistoryError>
+    where
+        E: Clone,
+    {
+        let start = match self.actions.branch_start(branch) {
+            Some(start) => start,
+            None => return Ok(None),
+        };
+        self.jump_to_history(start, other)
+            .map_err(|err| err.error)?;
+        self.actions.swap_branch(branch);
+        if self.clean_index.is_some_and(|clean| clean > start) {
+            self.clean_index = None;
+        }
+        self.last_saved = self.last_saved.filter(|&saved| saved <= start);
+        self.savepoints.retain(|savepoint| savepoint.index <= start);
+        Ok(Some(()))
+    }
+
+    /// Check whether the action at `index` can be moved in `direction` at all.
+    fn check_history_at(
+        &self,
+        index: usize,
+        direction: HistoryDirection,
+    ) -> Result<(), HistoryError> {
+        let result = if self.is_saving() {
+            Err(ActionError::SaveInProgress)
+        } else {
+            self.check_locks_at(index)
+        };
+        result.map_err(|err| {
+            self.actions
+                .history_error(index, direction, err, Some(false))
+        })
+    }
+
+    /// Appends `data` to the end, growing it, as a single action. Undoing truncates it back.
+    /// Appending nothing does nothing, and isn't recorded in the history.
+    pub fn append(&mut self, data: Vec<u8>, other: E) -> Result<(), (AppendAction, ActionError)>
+    where
+        F: Truncate,
+    {
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.add_action(AppendAction::new(data), other)
+    }
+
+    /// Overwrites the bytes at `position` with `value`, encoded with `endian` into as many bytes
+    /// as its type is wide, as an [`EditAction`].
+    /// ```
+    /// # use hiex::{Endian, Hiex};
+    /// # use std::io::Cursor;
+    /// let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(vec![0; 8])).unwrap();
+    /// hex.write_number(2, 0x1234u16, Endian::Big, ()).unwrap();
+    /// hex.write_number(4, 1.5f32, Endian::Little, ()).unwrap();
+    /// assert_eq!(hex.read_amount_at(0, 8).unwrap(), [0, 0, 0x12, 0x34, 0, 0, 0xC0, 0x3F]);
+    /// ```
+    pub fn write_number<N>(
+        &mut self,
+        position: u64,
+        value: N,
+        endian: Endian,
+        other: E,
+    ) -> Result<(), (EditAction, ActionError)>
+    where
+        N: Number,
+    {
+        self.add_action(EditAction::new(position, value.to_bytes(endian)), other)
+    }
+
+    /// Inserts `data` at `position`, moving the bytes after it forward, as a single action.
+    /// Bookmarks and tags after `position` are moved along with the bytes.
+    /// Inserting nothing does nothing, and isn't recorded in the history.
+    pub fn insert_bytes(
+        &mut self,
+        position: u64,
+        data: Vec<u8>,
+        other: E,
+    ) -> Result<(), (InsertAction, ActionError)>
+    where
+        F: Truncate,
+    {
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.add_action(InsertAction::new(position, data), other)
+    }
+
+    /// Removes the bytes within `range`, moving the bytes after it back, as a single action.
+    /// Bookmarks and tags after the start of `range` are moved along with the bytes, and those
+    /// within it are moved to its start (and restored if it is undone).
+    /// Deleting an empty range does nothing, and isn't recorded in the history.
+    pub fn delete_bytes(
+        &mut self,
+        range: Range<u64>,
+        other: E,
+    ) -> Result<(), (DeleteAction, ActionError)>
+    where
+        F: Truncate,
+    {
+        if range.start >= range.end {
+            return Ok(());
+        }
+        self.add_action(DeleteAction::new(range), other)
+    }
+
+    /// Computes the checksum of `data_range` and writes it at `field_offset` as a single action,
+    /// see [`ChecksumFieldAction`].
+    pub fn fixup_checksum(
+        &mut self,
+        data_range: Range<u64>,
+        field_offset: u64,
+        algorithm: ChecksumAlgorithm,
+        endian: Endian,
+        other: E,
+    ) -> Result<(), (ChecksumFieldAction, ActionError)> {
+        let action = ChecksumFieldAction::new(data_range, field_offset, algorithm, endian);
+        self.add_action(action, other)
+    }
+
+    /// Re-validate the history against the data, see [`ActionList::resync`].
+    pub fn resync(&mut self) -> std::io::Result<ResyncReport> {
+        self.actions.resync(&mut self.reader)
+    }
+
+    /// Records that something other than this editor modified the data within `affected` (or
+    /// anywhere, if `None`), see [`ActionList::mark_external_modification`].
+    /// Returns the indices of the newly invalidated actions.
+    pub fn mark_external_modification(&mut self, affected: Option<Range<u64>>) -> Vec<usize> {
+        self.mark_checksum_stale();
+        self.invalidate_block_index(affected.clone());
+        self.actions.mark_external_modification(affected)
+    }
+
+    /// Records that something other than this editor modified the data within `affected` (or
+    /// anywhere, if `None`), rebasing the history onto it where possible, see
+    /// [`ActionList::rebase_external_modification`].
+    /// Returns the indices of the newly invalidated actions.
+    pub fn rebase_external_modification(
+        &mut self,
+        affected: Option<Range<u64>>,
+    ) -> Result<Vec<usize>, ActionError> {
+        self.mark_checksum_stale();
+        self.invalidate_block_index(affected.clone());
+        self.actions
+            .rebase_external_modification(affected, &mut self.reader)
+    }
+
+    fn mark_checksum_stale(&mut self) {
+        if let Some(checksum) = &mut self.checksum {
+            checksum.update(None);
+        }
     }
 
-    /// Seeks to position, then calls `read_exact`
+    fn invalidate_block_index(&mut self, affected: Option<Range<u64>>) {
+        if let Some(block_index) = &mut self.block_index {
+            block_index.invalidate(affected);
+        }
+    }
+
+    /// Adds `delta` (wrapping) to every `width` byte element within `range`, read with `endian`,
+    /// as a single action. Use [`ArithmeticAction`] directly to saturate instead.
+    pub fn add_to_range(
+        &mut self,
+        range: Range<u64>,
+        width: usize,
+        endian: Endian,
+        delta: i64,
+        other: E,
+    ) -> Result<(), (ArithmeticAction, ActionError)> {
+        let length = range.end.saturating_sub(range.start);
+        let action =
+            ArithmeticAction::new(range.start, length, width, delta, ArithmeticMode::Wrapping);
+        self.add_action(action.with_endian(endian), other)
+    }
+
+    /// Reverses the bytes of every `width` byte group within `range`, as a single action.
+    pub fn swap_endian(
+        &mut self,
+        range: Range<u64>,
+        width: usize,
+        other: E,
+    ) -> Result<(), (EndianSwapAction, ActionError)> {
+        let length = range.end.saturating_sub(range.start);
+        self.add_action(EndianSwapAction::new(range.start, length, width), other)
+    }
+
+    /// Flips `bit` (`0` being the least significant) of the byte at `position`, as a single
+    /// action.
+    pub fn flip_bit(
+        &mut self,
+        position: u64,
+        bit: u8,
+        other: E,
+    ) -> Result<(), (BitFlipAction, ActionError)> {
+        self.add_action(BitFlipAction::new(position, bit), other)
+    }
+
+    /// Reverses the order of the bytes within `range`, as a single action.
+    pub fn reverse_range(
+        &mut self,
+        range: Range<u64>,
+        other: E,
+    ) -> Result<(), (ReverseAction, ActionError)> {
+        let length = range.end.saturating_sub(range.start);
+        self.add_action(ReverseAction::new(range.start, length), other)
+    }
+
+    /// Rotates the bytes within `range` by `amount` positions in `direction`, as a single
+    /// action.
+    pub fn rotate_range(
+        &mut self,
+        range: Range<u64>,
+        amount: u64,
+        direction: RotateDirection,
+        other: E,
+    ) -> Result<(), (RotateRangeAction, ActionError)> {
+        let length = range.end.saturating_sub(range.start);
+        self.add_action(
+            RotateRangeAction::new(range.start, length, amount, direction),
+            other,
+        )
+    }
+
+    /// Converts the case of the ASCII letters within `range`, as a single action.
+    pub fn convert_case(
+        &mut self,
+        range: Range<u64>,
+        mode: CaseMode,
+        other: E,
+    ) -> Result<(), (CaseConvertAction, ActionError)> {
+        let length = range.end.saturating_sub(range.start);
+        self.add_action(CaseConvertAction::new(range.start, length, mode), other)
+    }
+
+    /// Fills `range` with `pattern` repeated, as a single action.
+    pub fn fill(
+        &mut self,
+        range: Range<u64>,
+        pattern: Vec<u8>,
+        other: E,
+    ) -> Result<(), (FillAction, ActionError)> {
+        let length = range.end.saturating_sub(range.start);
+        self.add_action(FillAction::new(range.start, length, pattern), other)
+    }
+
+    /// Moves the bytes within `range` to `destination`, overwriting the bytes there, as a single
+    /// action. The bytes left behind are zeroed, see [`MoveRangeAction`].
+    pub fn move_range(
+        &mut self,
+        range: Range<u64>,
+        destination: u64,
+        other: E,
+    ) -> Result<(), (MoveRangeAction, ActionError)> {
+        let length = range.end.saturating_sub(range.start);
+        self.add_action(
+            MoveRangeAction::new(range.start, destination, length),
+            other,
+        )
+    }
+
+    /// Copies the bytes within `range` over the bytes at `destination`, as a single action.
+    pub fn copy_range(
+        &mut self,
+        range: Range<u64>,
+        destination: u64,
+        other: E,
+    ) -> Result<(), (CopyRangeAction, ActionError)> {
+        let length = range.end.saturating_sub(range.start);
+        self.add_action(
+            CopyRangeAction::new(range.start, destination, length),
+            other,
+        )
+    }
+
+    /// XORs the bytes within `range` with `key` repeated, as a single action.
+    pub fn xor_range(
+        &mut self,
+        range: Range<u64>,
+        key: Vec<u8>,
+        other: E,
+    ) -> Result<(), (XorRangeAction, ActionError)> {
+        let length = range.end.saturating_sub(range.start);
+        self.add_action(XorRangeAction::new(range.start, length, key), other)
+    }
+
+    /// Exchanges the `length` bytes at `a` with the `length` bytes at `b`, as a single action.
+    pub fn swap_ranges(
+        &mut self,
+        a: u64,
+        b: u64,
+        length: u64,
+        other: E,
+    ) -> Result<(), (SwapRangesAction, ActionError)> {
+        self.add_action(SwapRangesAction::new(a, b, length), other)
+    }
+
+    /// Fills `buf` with the data at `position`.
+    /// If [`Hiex::with_positioned_io`] was used and the reader supports it then this doesn't seek,
+    /// and so doesn't move the current position.
     pub fn read_at(&mut self, position: u64, buf: &mut [u8]) -> std::io::Result<()> {
-        self.seek(SeekFrom::Start(position))?;
-        self.read_exact(buf)
+        ReaderAt::new(&mut self.reader, self.read_at, position).read_exact(buf)
     }
 
     /// Reads as much as it can at current position
@@ -107,9 +977,14 @@ where
     /// Reads as much as it can
     /// The returned vector has `<= amount` bytes within it.
     /// `amount` is limited to usize, as the vector's size is limited to usize.
+    /// Like [`Hiex::read_at`], this doesn't move the current position if
+    /// [`Hiex::with_positioned_io`] was used and the reader supports it.
     pub fn read_amount_at(&mut self, position: u64, amount: usize) -> std::io::Result<Vec<u8>> {
-        self.seek(SeekFrom::Start(position))?;
-        self.read_amount(amount)
+        let mut buffer = Vec::new();
+        ReaderAt::new(&mut self.reader, self.read_at, position)
+            .take(u64::from_usize(amount))
+            .read_to_end(&mut buffer)?;
+        Ok(buffer)
     }
 
     // /// Seeks to position, then calls `write_all`
@@ -130,21 +1005,52 @@ where
     {
         self.seek(SeekFrom::Start(0))?;
         std::io::copy(self, writer)?;
+        self.last_saved = Some(self.actions.past_len());
+        self.clean_index = self.last_saved;
+        self.actions.seal();
         Ok(())
     }
 
+    /// Seeks to the start of `range` and copies the bytes within it to `writer`.
+    /// Returns the number of bytes copied, which may be less than the length of `range` if it
+    /// extends past the end of the data.
+    pub fn save_range_to<W>(&mut self, range: Range<u64>, writer: &mut W) -> std::io::Result<u64>
+    where
+        W: Write,
+    {
+        self.seek(SeekFrom::Start(range.start))?;
+        let length = range.end.saturating_sub(range.start);
+        std::io::copy(&mut Read::by_ref(self).take(length), writer)
+    }
+
+    /// Copies the data to `writer` from where it is, like [`Hiex::save_to_no_trunc`], and then
+    /// truncates the `writer` to end where the data does, so that none of what it held before
+    /// is left after the data.
+    pub fn save_to_truncating<W>(&mut self, writer: &mut W) -> std::io::Result<()>
+    where
+        W: Write + Truncate + Seek,
+    {
+        self.save_to_no_trunc(writer)?;
+        let end = writer.stream_position()?;
+        writer.truncate(end)
+    }
+
     // TODO: a save function that performs no seeking.s
 
     /// Seeks to the start of self.
     /// If the destination has more bytes than the source then it truncates the destination
     /// Note that this function assumes that writing more bytes than the destination originally
     /// started with will expand it.
+    /// NOTE: The destination is truncated to the length of the data before copying, which only
+    /// leaves no stale bytes after it if the `writer` is at its start. See
+    /// [`Hiex::save_to_truncating`] otherwise.
     pub fn save_to<W>(&mut self, writer: &mut W) -> std::io::Result<()>
     where
         W: Write + Truncate + Seek,
     {
         let destination_length = stream_len(writer)?;
         let self_length = stream_len(self)?;
+        debug!(length = self_length, previous_length = destination_length; "saving");
 
         if destination_length > self_length {
             // If we don't have enough bytes to write to the destination then it needs truncation
@@ -154,6 +1060,7 @@ where
         self.save_to_no_trunc(writer)?;
 
         debug_assert_eq!(stream_len(writer)?, stream_len(self)?);
+        debug!(length = self_length; "saved");
 
         Ok(())
     }
@@ -183,6 +1090,14 @@ where
         self.reader.read(buf)
     }
 }
+impl<F, E> KnownLength for Hiex<F, E>
+where
+    F: Read + Seek + Write + KnownLength,
+{
+    fn known_length(&self) -> Option<u64> {
+        self.reader.known_length()
+    }
+}
 impl<F, E> Seek for Hiex<F, E>
 where
     F: Read + Seek + Write,
@@ -192,55 +1107,344 @@ where
     }
 }
 
-/// An action where bytes are edited
-/// NOTE: if bytes written would increase the size of the file then that is an _error_
+/// An action which grows the data to `new_length` bytes.
+/// The new bytes are whatever the backend's `Truncate` implementation fills with, unless a fill
+/// byte is given.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GrowAction {
+    pub new_length: u64,
+    pub fill: Option<u8>,
+    previous_length: u64,
+}
+impl GrowAction {
+    pub fn new(new_length: u64) -> Self {
+        Self {
+            new_length,
+            fill: None,
+            previous_length: 0,
+        }
+    }
+
+    /// Write `fill` over the new bytes.
+    pub fn with_fill(mut self, fill: u8) -> Self {
+        self.fill = Some(fill);
+        self
+    }
+}
+impl<F, E> Action<F, E> for GrowAction
+where
+    F: Read + Seek + Write + Truncate,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        let length = stream_len(data)?;
+        if self.new_length < length {
+            // Shrinking would lose data that we don't store.
+            return Err(ActionError::Invalid);
+        }
+
+        self.previous_length = length;
+        data.truncate(self.new_length)?;
+        if let Some(fill) = self.fill {
+            let chunk = vec![fill; clamp_usize(self.new_length - length, CHUNK_SIZE)];
+            let mut position = length;
+            while position < self.new_length {
+                let amount = clamp_usize(self.new_length - position, chunk.len());
+                write_at(data, position, &chunk[..amount])?;
+                position += u64::from_usize(amount);
+            }
+        }
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        data.truncate(self.previous_length)?;
+        Ok(())
+    }
+
+    fn description(&self) -> String {
+        format!("Grow to {}", plural_bytes(self.new_length))
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(GrowAction {
+            new_length: self.new_length,
+            fill: self.fill,
+            previous_length: 0,
+        }))
+    }
+}
+impl MemoryUsage for GrowAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+    }
+}
+
+/// An action which appends data to the end, growing it.
+/// Undoing simply truncates back to the previous length, so no previous data is stored.
+/// The data is kept in chunks so that large appends don't need one large allocation.
 #[derive(Debug, Clone, Eq, PartialEq)]
-pub struct EditAction {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AppendAction {
+    chunks: Vec<Vec<u8>>,
+    previous_length: u64,
+}
+impl AppendAction {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self {
+            chunks: vec![data],
+            previous_length: 0,
+        }
+    }
+
+    /// Reads `source` until EOF, in chunks.
+    pub fn from_reader<R>(mut source: R) -> std::io::Result<Self>
+    where
+        R: Read,
+    {
+        let mut chunks = Vec::new();
+        loop {
+            let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+            let amount = Read::by_ref(&mut source)
+                .take(u64::from_usize(CHUNK_SIZE))
+                .read_to_end(&mut chunk)?;
+            if amount == 0 {
+                break;
+            }
+            chunks.push(chunk);
+        }
+
+        Ok(Self {
+            chunks,
+            previous_length: 0,
+        })
+    }
+
+    /// The amount of bytes that will be appended.
+    pub fn len(&self) -> u64 {
+        self.chunks
+            .iter()
+            .map(|chunk| u64::from_usize(chunk.len()))
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.iter().all(Vec::is_empty)
+    }
+}
+impl<F, E> Action<F, E> for AppendAction
+where
+    F: Read + Seek + Write + Truncate,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        self.previous_length = stream_len(data)?;
+        let mut length = self.previous_length;
+        for chunk in &self.chunks {
+            let new_length = length
+                .checked_add(u64::from_usize(chunk.len()))
+                .ok_or(ActionError::Invalid)?;
+            data.truncate(new_length)?;
+            write_at(data, length, chunk)?;
+            length = new_length;
+        }
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        data.truncate(self.previous_length)?;
+        Ok(())
+    }
+
+    fn description(&self) -> String {
+        format!("Append {}", plural_bytes(self.len()))
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(self.clone()))
+    }
+}
+impl MemoryUsage for AppendAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self
+                .chunks
+                .iter()
+                .map(|chunk| std::mem::size_of::<Vec<u8>>() + chunk.len())
+                .sum::<usize>()
+    }
+}
+
+/// An action which inserts bytes at a position, moving the bytes after it forward.
+/// Undoing moves them back, so no previous data is stored.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InsertAction {
     pub position: u64,
-    previous_data: Vec<u8>,
-    pub new_data: Vec<u8>,
+    pub data: Vec<u8>,
+    previous_length: u64,
 }
-impl EditAction {
-    pub fn new(position: u64, new_data: Vec<u8>) -> Self {
+impl InsertAction {
+    pub fn new(position: u64, data: Vec<u8>) -> Self {
         Self {
             position,
-            new_data,
-            previous_data: Vec::new(),
+            data,
+            previous_length: 0,
         }
     }
 }
-impl<F, E> Action<F, E> for EditAction
+impl<F, E> Action<F, E> for InsertAction
 where
-    F: Read + Seek + Write,
+    F: Read + Seek + Write + Truncate,
 {
-    fn apply(&mut self, mut data: &mut F, _other: E) -> Result<(), ActionError> {
-        let length = stream_len(&mut data)?;
-        let new_data_len = u64::from_usize(self.new_data.len());
-        // If we would exceed the file size then the action was invalid to perform.
-        if self.position.saturating_add(new_data_len) >= length {
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        let length = stream_len(data)?;
+        // Inserting at the very end is allowed.
+        if self.position > length {
             return Err(ActionError::Invalid);
         }
+        let amount = u64::from_usize(self.data.len());
+        let new_length = length.checked_add(amount).ok_or(ActionError::Invalid)?;
+
+        self.previous_length = length;
+        if !data.insert_space(self.position, amount)? {
+            data.truncate(new_length)?;
+            move_bytes(
+                data,
+                self.position..length,
+                self.position + amount,
+                CHUNK_SIZE,
+            )?;
+        }
+        write_at(data, self.position, &self.data)?;
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        let amount = u64::from_usize(self.data.len());
+        if !data.remove_space(self.position..self.position + amount)? {
+            move_bytes(
+                data,
+                self.position + amount..self.previous_length + amount,
+                self.position,
+                CHUNK_SIZE,
+            )?;
+            data.truncate(self.previous_length)?;
+        }
+        Ok(())
+    }
 
-        // Read in the data to store it for if the action is undone.
-        data.seek(SeekFrom::Start(self.position))?;
-        self.previous_data.resize(self.new_data.len(), 0);
-        data.read_exact(&mut self.previous_data)?;
+    /// Everything from the position onwards is moved.
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.position..u64::MAX)
+    }
+
+    fn description(&self) -> String {
+        describe_bytes("Insert", self.position, u64::from_usize(self.data.len()))
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(InsertAction::new(
+            self.position,
+            self.data.clone(),
+        )))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.position], delta)
+    }
+
+    fn offset_shift(&self) -> Option<OffsetShift> {
+        Some(OffsetShift::Insert {
+            position: self.position,
+            length: u64::from_usize(self.data.len()),
+        })
+    }
+}
+impl MemoryUsage for InsertAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.data.len()
+    }
+}
+
+/// An action which removes the bytes within a range, moving the bytes after it back.
+/// The removed bytes are stored for if the action is undone.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeleteAction {
+    pub range: Range<u64>,
+    removed: Vec<u8>,
+}
+impl DeleteAction {
+    pub fn new(range: Range<u64>) -> Self {
+        Self {
+            range,
+            removed: Vec::new(),
+        }
+    }
+}
+impl<F, E> Action<F, E> for DeleteAction
+where
+    F: Read + Seek + Write + Truncate,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        let length = stream_len(data)?;
+        if self.range.start > self.range.end || self.range.end > length {
+            return Err(ActionError::Invalid);
+        }
 
-        // TODO: if this fails, try writing previous data?
-        data.seek(SeekFrom::Start(self.position))?;
-        data.write_all(&self.new_data)?;
+        // The removed bytes are kept in memory for if the action is undone.
+        let removed = checked_usize(self.range.end - self.range.start)?;
+        self.removed.resize(removed, 0);
+        data.seek(SeekFrom::Start(self.range.start))?;
+        data.read_exact(&mut self.removed)?;
 
+        if !data.remove_space(self.range.clone())? {
+            move_bytes(data, self.range.end..length, self.range.start, CHUNK_SIZE)?;
+            data.truncate(length - u64::from_usize(self.removed.len()))?;
+        }
         Ok(())
     }
 
     fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
-        data.seek(SeekFrom::Start(self.position))?;
-        data.write_all(&self.previous_data)?;
+        let length = stream_len(data)?;
+        let amount = u64::from_usize(self.removed.len());
+        if !data.insert_space(self.range.start, amount)? {
+            data.truncate(length + amount)?;
+            move_bytes(data, self.range.start..length, self.range.end, CHUNK_SIZE)?;
+        }
+        write_at(data, self.range.start, &self.removed)?;
         Ok(())
     }
+
+    /// Everything from the start of the range onwards is moved.
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.range.start..u64::MAX)
+    }
+
+    fn description(&self) -> String {
+        describe_bytes(
+            "Delete",
+            self.range.start,
+            self.range.end.saturating_sub(self.range.start),
+        )
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(DeleteAction::new(self.range.clone())))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.range.start, &mut self.range.end], delta)
+    }
+
+    fn offset_shift(&self) -> Option<OffsetShift> {
+        Some(OffsetShift::Delete {
+            range: self.range.clone(),
+        })
+    }
 }
-impl MemoryUsage for EditAction {
+impl MemoryUsage for DeleteAction {
     fn memory_usage(&self) -> usize {
-        8 + self.previous_data.len() + self.new_data.len()
+        std::mem::size_of::<Self>() + self.removed.len()
     }
 }
@@ -0,0 +1,246 @@
+//! Saving the history to a separate file, so that it can still be undone and redone in a later
+//! session, with [`ActionList::serialize_history`] and [`ActionList::deserialize_history`].
+//!
+//! The format is JSON, with a version number which is only increased for changes that older
+//! versions can't read. Each action is stored along with what it needs to be undone (such as the
+//! bytes it overwrote), tagged with its kind. Only the built-in actions below can be stored.
+use crate::{
+    action::{Action, ActionList, CompoundAction},
+    checksum::ChecksumFieldAction,
+    import::{PasteInsertAction, PasteOverwriteAction},
+    ips::ApplyPatchAction,
+    search::ReplaceAllAction,
+    string_write::StringWriteAction,
+    transform::{
+        ArithmeticAction, BitFlipAction, BitwiseRangeAction, CaseConvertAction, CopyRangeAction,
+        EndianSwapAction, FillAction, MoveRangeAction, RandomFillAction, ReverseAction,
+        RotateRangeAction, ShredRangeAction, SwapRangesAction, XorRangeAction,
+    },
+    truncate::Truncate,
+    varint::{VarintResizeAction, VarintWriteAction},
+    AppendAction, DeleteAction, EditAction, GrowAction, InsertAction,
+};
+use serde::{Deserialize, Serialize};
+use std::io::{ErrorKind, Read, Seek, Write};
+
+/// The newest version of the format which can be read.
+pub const HISTORY_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct HistoryFile {
+    version: u32,
+    /// The amount of actions which are applied.
+    index: usize,
+    actions: Vec<StoredAction>,
+}
+
+macro_rules! stored_actions {
+    ($($variant:ident($action:ty),)*) => {
+        /// The built-in actions which can be stored.
+        #[derive(Serialize, Deserialize)]
+        enum StoredAction {
+            $($variant($action),)*
+            Compound(Vec<StoredAction>),
+        }
+        impl StoredAction {
+            fn from_action<F, E>(action: &dyn Action<F, E>) -> Option<Self>
+            where
+                F: 'static + Read + Seek,
+                E: 'static,
+            {
+                let action = action.as_any();
+                $(
+                    if let Some(action) = action.downcast_ref::<$action>() {
+                        return Some(StoredAction::$variant(action.clone()));
+                    }
+                )*
+                let compound = action.downcast_ref::<CompoundAction<F, E>>()?;
+                (0..compound.len())
+                    .map(|index| StoredAction::from_action(compound.get(index)?))
+                    .collect::<Option<_>>()
+                    .map(StoredAction::Compound)
+            }
+
+            fn into_action<F, E>(self) -> Box<dyn Action<F, E>>
+            where
+                F: 'static + Read + Seek + Write + Truncate,
+                E: 'static + Clone,
+            {
+                match self {
+                    $(StoredAction::$variant(action) => Box::new(action),)*
+                    StoredAction::Compound(children) => {
+                        let mut compound = CompoundAction::new();
+                        for child in children {
+                            compound.push_boxed(child.into_action());
+                        }
+                        Box::new(compound)
+                    }
+                }
+            }
+        }
+    };
+}
+stored_actions! {
+    Edit(EditAction),
+    Grow(GrowAction),
+    Append(AppendAction),
+    Insert(InsertAction),
+    Delete(DeleteAction),
+    Arithmetic(ArithmeticAction),
+    EndianSwap(EndianSwapAction),
+    BitFlip(BitFlipAction),
+    CaseConvert(CaseConvertAction),
+    Reverse(ReverseAction),
+    RotateRange(RotateRangeAction),
+    SwapRanges(SwapRangesAction),
+    MoveRange(MoveRangeAction),
+    CopyRange(CopyRangeAction),
+    XorRange(XorRangeAction),
+    ShredRange(ShredRangeAction),
+    RandomFill(RandomFillAction),
+    BitwiseRange(BitwiseRangeAction),
+    Fill(FillAction),
+    ReplaceAll(ReplaceAllAction),
+    ChecksumField(ChecksumFieldAction),
+    VarintWrite(VarintWriteAction),
+    VarintResize(VarintResizeAction),
+    StringWrite(StringWriteAction),
+    ApplyPatch(ApplyPatchAction),
+    PasteOverwrite(PasteOverwriteAction),
+    PasteInsert(PasteInsertAction),
+}
+
+impl<F, E> ActionList<F, E>
+where
+    F: 'static + Read + Seek + Write,
+    E: 'static,
+{
+    /// Writes every action in the history to `writer`, both applied and undone, along with what
+    /// each needs to be undone. The ids and times of the entries aren't kept, and neither are the
+    /// branches (see [`ActionList::set_branching`]).
+    /// Fails with `ErrorKind::InvalidInput` if any of them isn't one of the built-in actions that
+    /// can be stored (see the [module](self) documentation), such as a
+    /// [`TransformAction`](crate::transform::TransformAction), in which case nothing is written.
+    pub fn serialize_history<W>(&self, writer: W) -> std::io::Result<()>
+    where
+        W: Write,
+    {
+        let actions = (0..self.len())
+            .map(|index| {
+                self.get(index)
+                    .and_then(StoredAction::from_action)
+                    .ok_or_else(|| {
+                        std::io::Error::new(
+                            ErrorKind::InvalidInput,
+                            format!("the action at {} can't be serialized", index),
+                        )
+                    })
+            })
+            .collect::<std::io::Result<_>>()?;
+        let file = HistoryFile {
+            version: HISTORY_VERSION,
+            index: self.past_len(),
+            actions,
+        };
+        serde_json::to_writer(writer, &file)?;
+        Ok(())
+    }
+
+    /// Reads a history written by [`ActionList::serialize_history`].
+    /// The data it is used with has to be as it was when the history was written, since undoing
+    /// writes back the bytes that were stored, without checking them.
+    /// Fails with `ErrorKind::InvalidData` if it isn't a valid history, or is from a newer version
+    /// of the format.
+    pub fn deserialize_history<R>(reader: R) -> std::io::Result<Self>
+    where
+        R: Read,
+        F: Truncate,
+        E: Clone,
+    {
+        let file: HistoryFile = serde_json::from_reader(reader)?;
+        if file.version > HISTORY_VERSION {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "history is from a newer version",
+            ));
+        }
+        if file.index > file.actions.len() {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "history index is past its end",
+            ));
+        }
+        let actions = file
+            .actions
+            .into_iter()
+            .map(StoredAction::into_action)
+            .collect();
+        Ok(ActionList::from_actions(actions, file.index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        action::{ActionList, CompoundAction},
+        transform::{FillAction, TransformAction},
+        EditAction, Hiex, InsertAction,
+    };
+    use std::io::{Cursor, ErrorKind};
+
+    #[test]
+    fn test_history_round_trip() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"0123456789".to_vec())).unwrap();
+        hex.add_action_simple(EditAction::new(0, b"ab".to_vec()))
+            .unwrap();
+        hex.add_action_simple(InsertAction::new(4, b"xyz".to_vec()))
+            .unwrap();
+        let mut compound = CompoundAction::new();
+        compound.push(FillAction::new(8, 2, b"-".to_vec()));
+        compound.push(EditAction::new(12, b"!".to_vec()));
+        hex.add_action_simple(compound).unwrap();
+        hex.undo_simple().unwrap();
+        let mut saved = Vec::new();
+        hex.actions.serialize_history(&mut saved).unwrap();
+
+        // Loaded onto the data as it was when the history was saved.
+        let mut data = Cursor::new(hex.reader().get_ref().clone());
+        let mut list: ActionList<_> = ActionList::deserialize_history(&saved[..]).unwrap();
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.past_len(), 2);
+        list.redo(&mut data, ()).unwrap();
+        assert_eq!(data.get_ref(), b"ab23xyz4--78!");
+        list.undo(&mut data, ()).unwrap();
+        list.undo(&mut data, ()).unwrap();
+        list.undo(&mut data, ()).unwrap();
+        assert_eq!(data.get_ref(), b"0123456789");
+        assert_eq!(list.undo(&mut data, ()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_history_errors() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"0123".to_vec())).unwrap();
+        hex.add_action_simple(TransformAction::new(0, 1, Box::new(|_| ())))
+            .unwrap();
+        let mut saved = Vec::new();
+        let err = hex.actions.serialize_history(&mut saved).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert!(saved.is_empty());
+
+        let load = |text: &[u8]| {
+            ActionList::<Cursor<Vec<u8>>>::deserialize_history(text)
+                .err()
+                .map(|err| err.kind())
+        };
+        assert!(load(br#"{"version": 1, "index": 0, "actions": []}"#).is_none());
+        assert_eq!(
+            load(br#"{"version": 2, "index": 0, "actions": []}"#),
+            Some(ErrorKind::InvalidData)
+        );
+        assert_eq!(
+            load(br#"{"version": 1, "index": 1, "actions": []}"#),
+            Some(ErrorKind::InvalidData)
+        );
+        assert!(load(b"not json").is_some());
+    }
+}
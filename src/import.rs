@@ -0,0 +1,451 @@
+//! Importing data from other readers as undoable actions.
+use crate::{
+    action::{
+        describe_bytes, Action, ActionError, CompoundAction, MemoryUsage, OffsetShift,
+        OverwriteDelta,
+    },
+    buffer_pool::BufferPool,
+    truncate::Truncate,
+    AppendAction, EditAction, GrowAction, Hiex, InsertAction, CHUNK_SIZE,
+};
+use std::{
+    fs::File,
+    io::{Read, Seek, Write},
+    ops::Range,
+    path::Path,
+};
+use usize_cast::FromUsize;
+
+/// Pastes a buffer (such as from a clipboard or another file) over the bytes at a position.
+/// This is an [`EditAction`] which is described as a paste, and which isn't merged with edits.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PasteOverwriteAction(EditAction);
+impl PasteOverwriteAction {
+    pub fn new(position: u64, data: Vec<u8>) -> Self {
+        Self(EditAction::new(position, data))
+    }
+
+    pub fn edit(&self) -> &EditAction {
+        &self.0
+    }
+}
+impl<F, E> Action<F, E> for PasteOverwriteAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, other: E) -> Result<(), ActionError> {
+        Action::<F, E>::apply(&mut self.0, data, other)
+    }
+
+    fn unapply(&mut self, data: &mut F, other: E) -> Result<(), ActionError> {
+        Action::<F, E>::unapply(&mut self.0, data, other)
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(Self::new(
+            self.0.position,
+            self.0.new_data.clone(),
+        )))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        Action::<F, E>::translate(&mut self.0, delta)
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Action::<F, E>::affected_range(&self.0)
+    }
+
+    fn overwrite_delta(&self) -> Option<OverwriteDelta<'_>> {
+        Action::<F, E>::overwrite_delta(&self.0)
+    }
+
+    fn rebase(&mut self, data: &mut F, modified: Option<Range<u64>>) -> Result<bool, ActionError> {
+        Action::<F, E>::rebase(&mut self.0, data, modified)
+    }
+
+    fn take_buffers(&mut self, pool: &mut BufferPool) {
+        Action::<F, E>::take_buffers(&mut self.0, pool)
+    }
+
+    fn release_buffers(&mut self, pool: &mut BufferPool) {
+        Action::<F, E>::release_buffers(&mut self.0, pool)
+    }
+
+    fn description(&self) -> String {
+        let length = u64::from_usize(self.0.new_data.len());
+        describe_bytes("Paste over", self.0.position, length)
+    }
+}
+impl MemoryUsage for PasteOverwriteAction {
+    fn memory_usage(&self) -> usize {
+        self.0.memory_usage()
+    }
+}
+
+/// Pastes a buffer (such as from a clipboard or another file) at a position, moving the bytes
+/// after it forward. This is an [`InsertAction`] which is described as a paste.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PasteInsertAction(InsertAction);
+impl PasteInsertAction {
+    pub fn new(position: u64, data: Vec<u8>) -> Self {
+        Self(InsertAction::new(position, data))
+    }
+
+    pub fn insert(&self) -> &InsertAction {
+        &self.0
+    }
+}
+impl<F, E> Action<F, E> for PasteInsertAction
+where
+    F: Read + Seek + Write + Truncate,
+{
+    fn apply(&mut self, data: &mut F, other: E) -> Result<(), ActionError> {
+        Action::<F, E>::apply(&mut self.0, data, other)
+    }
+
+    fn unapply(&mut self, data: &mut F, other: E) -> Result<(), ActionError> {
+        Action::<F, E>::unapply(&mut self.0, data, other)
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(Self::new(self.0.position, self.0.data.clone())))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        Action::<F, E>::translate(&mut self.0, delta)
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Action::<F, E>::affected_range(&self.0)
+    }
+
+    fn offset_shift(&self) -> Option<OffsetShift> {
+        Action::<F, E>::offset_shift(&self.0)
+    }
+
+    fn description(&self) -> String {
+        let length = u64::from_usize(self.0.data.len());
+        describe_bytes("Paste", self.0.position, length)
+    }
+}
+impl MemoryUsage for PasteInsertAction {
+    fn memory_usage(&self) -> usize {
+        self.0.memory_usage()
+    }
+}
+
+/// Reads up to `len` bytes (or until EOF, if `None`) from `source` into chunk-sized
+/// `EditAction`s starting at `position`.
+/// Returns the actions and the total amount of bytes read.
+fn read_chunks<F, E, R>(
+    position: u64,
+    source: R,
+    len: Option<u64>,
+) -> std::io::Result<(CompoundAction<F, E>, u64)>
+where
+    F: Read + Seek + Write,
+    R: Read,
+{
+    let mut source = source.take(len.unwrap_or(u64::MAX));
+    let mut actions = CompoundAction::new();
+    let mut total = 0;
+    loop {
+        let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+        let amount = Read::by_ref(&mut source)
+            .take(u64::from_usize(CHUNK_SIZE))
+            .read_to_end(&mut chunk)?;
+        if amount == 0 {
+            break;
+        }
+
+        actions.push(EditAction::new(position + total, chunk));
+        total += u64::from_usize(amount);
+    }
+
+    Ok((actions, total))
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+    E: Clone + 'static,
+{
+    /// Overwrites the data at `position` with up to `len` bytes read from `source` (or until its
+    /// EOF, if `len` is `None`), as a single undoable action.
+    /// The source is read in chunks, rather than into one large buffer.
+    /// Imports which would extend past the end of the data are rejected with
+    /// `ActionError::Invalid`; see [`Hiex::import_at_growing`] to allow that.
+    /// Returns the amount of bytes imported. Importing nothing does not create a history entry.
+    pub fn import_at<R>(
+        &mut self,
+        position: u64,
+        source: R,
+        len: Option<u64>,
+        other: E,
+    ) -> Result<u64, ActionError>
+    where
+        F: 'static,
+        R: Read,
+    {
+        let (actions, total) = read_chunks(position, source, len)?;
+        if total == 0 {
+            return Ok(0);
+        }
+
+        self.add_action(actions, other).map_err(|(_, err)| err)?;
+        Ok(total)
+    }
+
+    /// As [`Hiex::import_at`], but if the import extends past the end of the data then the data
+    /// is grown to fit it. The growth is undone along with the import.
+    pub fn import_at_growing<R>(
+        &mut self,
+        position: u64,
+        source: R,
+        len: Option<u64>,
+        other: E,
+    ) -> Result<u64, ActionError>
+    where
+        F: Truncate + 'static,
+        R: Read,
+    {
+        let (actions, total) = read_chunks(position, source, len)?;
+        if total == 0 {
+            return Ok(0);
+        }
+
+        let end = position.checked_add(total).ok_or(ActionError::Invalid)?;
+        if end > self.length()? {
+            let mut grown = CompoundAction::new();
+            grown.push(GrowAction::new(end));
+            grown.push(actions);
+            self.add_action(grown, other).map_err(|(_, err)| err)?;
+        } else {
+            self.add_action(actions, other).map_err(|(_, err)| err)?;
+        }
+        Ok(total)
+    }
+
+    /// Appends the contents of `source` (read until its EOF) to the end of the data, as a single
+    /// undoable action.
+    /// Returns the amount of bytes appended. Appending nothing does not create a history entry.
+    pub fn append_from<R>(&mut self, source: R, other: E) -> Result<u64, ActionError>
+    where
+        F: Truncate + 'static,
+        R: Read,
+    {
+        let action = AppendAction::from_reader(source)?;
+        if action.is_empty() {
+            return Ok(0);
+        }
+
+        let length = action.len();
+        self.add_action(action, other).map_err(|(_, err)| err)?;
+        Ok(length)
+    }
+
+    /// Inserts up to `len` bytes read from `source` (or until its EOF, if `len` is `None`) at
+    /// `position`, moving the bytes after it forward, as a single undoable
+    /// [`PasteInsertAction`]. This is the inserting counterpart to [`Hiex::import_at`].
+    /// The bytes are held in memory, as the action stores them for redoing.
+    /// Returns the amount of bytes inserted. Inserting nothing does not create a history entry.
+    pub fn insert_from<R>(
+        &mut self,
+        position: u64,
+        source: R,
+        len: Option<u64>,
+        other: E,
+    ) -> Result<u64, ActionError>
+    where
+        F: Truncate + 'static,
+        R: Read,
+    {
+        let mut data = Vec::new();
+        source
+            .take(len.unwrap_or(u64::MAX))
+            .read_to_end(&mut data)?;
+        if data.is_empty() {
+            return Ok(0);
+        }
+        let total = u64::from_usize(data.len());
+        self.add_action(PasteInsertAction::new(position, data), other)
+            .map_err(|(_, err)| err)?;
+        Ok(total)
+    }
+
+    /// Overwrites the data at `position` with the contents of the file at `path`, as a single
+    /// undoable action.
+    pub fn import_path_at(
+        &mut self,
+        position: u64,
+        path: &Path,
+        other: E,
+    ) -> Result<u64, ActionError>
+    where
+        F: 'static,
+    {
+        self.import_at(position, File::open(path)?, None, other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PasteInsertAction, PasteOverwriteAction};
+    use crate::{action::ActionError, contents, GrowthPolicy, Hiex};
+    use std::io::{Cursor, Write};
+
+    #[test]
+    fn test_import() {
+        let original: Vec<u8> = vec![0u8; 200_000];
+        let mut hex = Hiex::from_reader(Cursor::new(original.clone())).unwrap();
+
+        // Spans several chunks, and ends exactly at the end of the data.
+        let source: Vec<u8> = (0..150_000u32).map(|i| (i % 251) as u8).collect();
+        let imported = hex
+            .import_at(50_000, Cursor::new(&source), None, ())
+            .unwrap();
+        assert_eq!(imported, 150_000);
+        let mut expected = original.clone();
+        expected[50_000..].copy_from_slice(&source);
+        assert_eq!(contents(&mut hex), expected);
+        assert_eq!(hex.actions.len(), 1);
+
+        // A single undo reverts the whole import.
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), original);
+        hex.redo(()).unwrap();
+        assert_eq!(contents(&mut hex), expected);
+
+        // Limited length.
+        let imported = hex
+            .import_at(0, Cursor::new(b"abcdef"), Some(3), ())
+            .unwrap();
+        assert_eq!(imported, 3);
+        assert_eq!(&contents(&mut hex)[..4], b"abc\0");
+
+        // Empty imports don't create history.
+        assert_eq!(hex.import_at(0, Cursor::new(b""), None, ()).unwrap(), 0);
+        assert_eq!(hex.actions.len(), 2);
+    }
+
+    #[test]
+    fn test_import_past_eof() {
+        let mut hex = Hiex::from_reader(Cursor::new(b"0123".to_vec())).unwrap();
+        let err = hex.import_at(2, Cursor::new(b"abc"), None, ()).unwrap_err();
+        assert!(matches!(err, ActionError::Invalid));
+        assert_eq!(contents(&mut hex), b"0123");
+        assert!(hex.actions.is_empty());
+
+        let imported = hex
+            .import_at_growing(2, Cursor::new(b"abc"), None, ())
+            .unwrap();
+        assert_eq!(imported, 3);
+        assert_eq!(contents(&mut hex), b"01abc");
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), b"0123");
+    }
+
+    #[test]
+    fn test_insert_from() {
+        let mut hex = Hiex::from_reader(Cursor::new(b"0123".to_vec())).unwrap();
+        assert_eq!(
+            hex.insert_from(2, Cursor::new(b"abcdef"), Some(3), ())
+                .unwrap(),
+            3
+        );
+        assert_eq!(contents(&mut hex), b"01abc23");
+        assert_eq!(hex.insert_from(7, Cursor::new(b"!"), None, ()).unwrap(), 1);
+        assert_eq!(contents(&mut hex), b"01abc23!");
+
+        hex.undo(()).unwrap();
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), b"0123");
+
+        assert_eq!(hex.insert_from(0, Cursor::new(b""), None, ()).unwrap(), 0);
+        assert!(matches!(
+            hex.insert_from(5, Cursor::new(b"x"), None, ()),
+            Err(ActionError::Invalid)
+        ));
+        assert_eq!(hex.actions.past_len(), 0);
+    }
+
+    #[test]
+    fn test_paste() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"012345".to_vec())).unwrap();
+        hex.add_action_simple(PasteOverwriteAction::new(1, b"ab".to_vec()))
+            .unwrap();
+        assert_eq!(contents(&mut hex), b"0ab345");
+        hex.add_action_simple(PasteInsertAction::new(6, b"xyz".to_vec()))
+            .unwrap();
+        assert_eq!(contents(&mut hex), b"0ab345xyz");
+        assert_eq!(
+            hex.actions.undo_description().unwrap(),
+            "Paste 3 bytes at 0x6"
+        );
+
+        hex.undo_simple().unwrap();
+        assert_eq!(contents(&mut hex), b"0ab345");
+        assert_eq!(
+            hex.actions.undo_description().unwrap(),
+            "Paste over 2 bytes at 0x1"
+        );
+        hex.undo_simple().unwrap();
+        assert_eq!(contents(&mut hex), b"012345");
+        hex.redo_simple().unwrap();
+        hex.redo_simple().unwrap();
+        assert_eq!(contents(&mut hex), b"0ab345xyz");
+
+        // Pasting over past the end isn't allowed, and neither is inserting past it.
+        let err = hex
+            .add_action_simple(PasteOverwriteAction::new(8, b"!!".to_vec()))
+            .unwrap_err();
+        assert!(matches!(err.1, ActionError::Invalid));
+        let err = hex
+            .add_action_simple(PasteInsertAction::new(10, b"!".to_vec()))
+            .unwrap_err();
+        assert!(matches!(err.1, ActionError::Invalid));
+        assert_eq!(contents(&mut hex), b"0ab345xyz");
+
+        // Unless the growth policy allows edits past the end.
+        hex.set_growth_policy(GrowthPolicy::GrowOnEdit { fill: b'.' });
+        hex.add_action_simple(PasteOverwriteAction::new(10, b"!!".to_vec()))
+            .unwrap();
+        assert_eq!(contents(&mut hex), b"0ab345xyz.!!");
+        hex.undo_simple().unwrap();
+        assert_eq!(contents(&mut hex), b"0ab345xyz");
+    }
+
+    #[test]
+    fn test_import_path() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"xy").unwrap();
+        let mut hex = Hiex::from_reader(Cursor::new(b"0123".to_vec())).unwrap();
+        assert_eq!(hex.import_path_at(1, file.path(), ()).unwrap(), 2);
+        assert_eq!(contents(&mut hex), b"0xy3");
+    }
+
+    #[test]
+    fn test_append_from() {
+        let original = b"head".to_vec();
+        let mut hex = Hiex::from_reader(Cursor::new(original.clone())).unwrap();
+        let source: Vec<u8> = (0..150_000u32).map(|i| (i % 253) as u8).collect();
+
+        assert_eq!(hex.append_from(Cursor::new(&source), ()).unwrap(), 150_000);
+        assert_eq!(hex.length().unwrap(), 150_004);
+        let mut expected = original.clone();
+        expected.extend_from_slice(&source);
+        assert_eq!(contents(&mut hex), expected);
+
+        hex.undo(()).unwrap();
+        assert_eq!(hex.length().unwrap(), 4);
+        assert_eq!(contents(&mut hex), original);
+        hex.redo(()).unwrap();
+        assert_eq!(contents(&mut hex), expected);
+
+        assert_eq!(hex.append_from(Cursor::new(b""), ()).unwrap(), 0);
+        assert_eq!(hex.actions.len(), 1);
+    }
+}
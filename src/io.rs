@@ -0,0 +1,398 @@
+//! The I/O traits that actions are applied through.
+//!
+//! Normally these are just those of `std::io`. With the `no_std_io` feature, where the crate is
+//! built without `std`, they are minimal equivalents which only need `core` and `alloc`, and
+//! [`StorageCursor`] implements them over a caller-provided [`Storage`], such as flash on an
+//! embedded device.
+#[cfg(not(feature = "no_std_io"))]
+pub(crate) use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+
+#[cfg(feature = "no_std_io")]
+pub use self::core_io::*;
+
+#[cfg(feature = "no_std_io")]
+mod core_io {
+    use crate::{clamp_usize, constrained_wrapper::apply_offset};
+    use alloc::{
+        string::{String, ToString},
+        vec::Vec,
+    };
+    use core::{convert::TryFrom, fmt};
+    use usize_cast::FromUsize;
+
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    /// A list specifying general categories of I/O error, like `std::io::ErrorKind`, with the
+    /// kinds that the crate produces or checks for.
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+    #[non_exhaustive]
+    pub enum ErrorKind {
+        NotFound,
+        PermissionDenied,
+        InvalidInput,
+        InvalidData,
+        UnexpectedEof,
+        WriteZero,
+        Interrupted,
+        Unsupported,
+        OutOfMemory,
+        Other,
+    }
+
+    /// An I/O error, like `std::io::Error`, with a kind and a message.
+    #[derive(Debug)]
+    pub struct Error {
+        kind: ErrorKind,
+        message: String,
+    }
+    impl Error {
+        pub fn new<M>(kind: ErrorKind, message: M) -> Self
+        where
+            M: fmt::Display,
+        {
+            Self {
+                kind,
+                message: message.to_string(),
+            }
+        }
+
+        pub fn other<M>(message: M) -> Self
+        where
+            M: fmt::Display,
+        {
+            Self::new(ErrorKind::Other, message)
+        }
+
+        pub fn kind(&self) -> ErrorKind {
+            self.kind
+        }
+    }
+    impl From<ErrorKind> for Error {
+        fn from(kind: ErrorKind) -> Self {
+            Self {
+                kind,
+                message: String::new(),
+            }
+        }
+    }
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            if self.message.is_empty() {
+                write!(f, "{:?}", self.kind)
+            } else {
+                f.write_str(&self.message)
+            }
+        }
+    }
+
+    /// Where to seek to, like `std::io::SeekFrom`.
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub enum SeekFrom {
+        Start(u64),
+        End(i64),
+        Current(i64),
+    }
+
+    /// Like `std::io::Read`.
+    pub trait Read {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+        fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+            while !buf.is_empty() {
+                match self.read(buf) {
+                    Ok(0) => break,
+                    Ok(read) => buf = &mut buf[read..],
+                    Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                    Err(err) => return Err(err),
+                }
+            }
+            if buf.is_empty() {
+                Ok(())
+            } else {
+                Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "failed to fill whole buffer",
+                ))
+            }
+        }
+    }
+
+    /// Like `std::io::Write`.
+    pub trait Write {
+        fn write(&mut self, buf: &[u8]) -> Result<usize>;
+
+        fn flush(&mut self) -> Result<()>;
+
+        fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+            while !buf.is_empty() {
+                match self.write(buf) {
+                    Ok(0) => {
+                        return Err(Error::new(
+                            ErrorKind::WriteZero,
+                            "failed to write whole buffer",
+                        ))
+                    }
+                    Ok(written) => buf = &buf[written..],
+                    Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                    Err(err) => return Err(err),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Like `std::io::Seek`.
+    pub trait Seek {
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64>;
+
+        fn stream_position(&mut self) -> Result<u64> {
+            self.seek(SeekFrom::Current(0))
+        }
+    }
+
+    impl<R> Read for &mut R
+    where
+        R: Read + ?Sized,
+    {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            (**self).read(buf)
+        }
+    }
+    impl<W> Write for &mut W
+    where
+        W: Write + ?Sized,
+    {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            (**self).write(buf)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            (**self).flush()
+        }
+    }
+    impl<S> Seek for &mut S
+    where
+        S: Seek + ?Sized,
+    {
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+            (**self).seek(pos)
+        }
+    }
+
+    /// Random access storage of bytes, provided by the caller, which [`StorageCursor`] makes
+    /// usable as the data of actions.
+    pub trait Storage {
+        /// The amount of bytes in the storage.
+        fn len(&self) -> u64;
+
+        fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+
+        /// Reads into `buf` from `offset`, returning how many bytes were read, which is only less
+        /// than the length of `buf` at the end of the storage.
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize>;
+
+        /// Writes `buf` at `offset`, returning how many bytes were written. Storage which can't
+        /// grow writes nothing past its end.
+        fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<usize>;
+    }
+    impl<S> Storage for &mut S
+    where
+        S: Storage + ?Sized,
+    {
+        fn len(&self) -> u64 {
+            (**self).len()
+        }
+
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+            (**self).read_at(offset, buf)
+        }
+
+        fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<usize> {
+            (**self).write_at(offset, buf)
+        }
+    }
+    /// Storage of a fixed length.
+    impl Storage for [u8] {
+        fn len(&self) -> u64 {
+            u64::from_usize(<[u8]>::len(self))
+        }
+
+        // `len` is written out as `<[u8]>::len` here, since on a `&mut [u8]` it would be
+        // `Storage::len`, through the implementation for `&mut S`.
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+            let data = &self[clamp_usize(offset, <[u8]>::len(self))..];
+            let read = data.len().min(<[u8]>::len(buf));
+            buf[..read].copy_from_slice(&data[..read]);
+            Ok(read)
+        }
+
+        fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<usize> {
+            let start = clamp_usize(offset, <[u8]>::len(self));
+            let data = &mut self[start..];
+            let written = <[u8]>::len(data).min(buf.len());
+            data[..written].copy_from_slice(&buf[..written]);
+            Ok(written)
+        }
+    }
+    /// Storage which grows when written past its end, filling any gap with zeroes.
+    impl Storage for Vec<u8> {
+        fn len(&self) -> u64 {
+            u64::from_usize(Vec::len(self))
+        }
+
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+            self.as_slice().read_at(offset, buf)
+        }
+
+        fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<usize> {
+            let end = offset
+                .checked_add(u64::from_usize(buf.len()))
+                .and_then(|end| usize::try_from(end).ok())
+                .ok_or_else(|| Error::new(ErrorKind::OutOfMemory, "write past usize::MAX"))?;
+            if end > Vec::len(self) {
+                self.resize(end, 0);
+            }
+            self.as_mut_slice().write_at(offset, buf)
+        }
+    }
+
+    /// Reads, writes, and seeks over a [`Storage`], like a `Cursor` does over a buffer in `std`.
+    /// As with `Cursor`, seeking past the end is allowed, and reading there reads nothing.
+    #[derive(Debug, Clone, Default)]
+    pub struct StorageCursor<S> {
+        storage: S,
+        position: u64,
+    }
+    impl<S> StorageCursor<S> {
+        /// Wraps `storage`, starting at position 0.
+        pub fn new(storage: S) -> Self {
+            Self {
+                storage,
+                position: 0,
+            }
+        }
+
+        pub fn position(&self) -> u64 {
+            self.position
+        }
+
+        pub fn get_ref(&self) -> &S {
+            &self.storage
+        }
+
+        pub fn get_mut(&mut self) -> &mut S {
+            &mut self.storage
+        }
+
+        pub fn into_inner(self) -> S {
+            self.storage
+        }
+    }
+    impl<S> Read for StorageCursor<S>
+    where
+        S: Storage,
+    {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let read = self.storage.read_at(self.position, buf)?;
+            self.position += u64::from_usize(read);
+            Ok(read)
+        }
+    }
+    impl<S> Write for StorageCursor<S>
+    where
+        S: Storage,
+    {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            let written = self.storage.write_at(self.position, buf)?;
+            self.position += u64::from_usize(written);
+            Ok(written)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+    impl<S> Seek for StorageCursor<S>
+    where
+        S: Storage,
+    {
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+            let (position, offset) = match pos {
+                SeekFrom::Current(offset) => (self.position, offset),
+                SeekFrom::End(offset) => (self.storage.len(), offset),
+                SeekFrom::Start(position) => (position, 0),
+            };
+            self.position = apply_offset(position, offset)?;
+            Ok(self.position)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{Read, Seek, SeekFrom, StorageCursor, Write};
+        use crate::{
+            action::{ActionError, ActionList},
+            constrained_wrapper::ConstrainedWrapper,
+            EditAction,
+        };
+        use alloc::vec;
+
+        #[test]
+        fn test_storage_cursor() {
+            let mut data = [0u8; 4];
+            let mut cursor = StorageCursor::new(&mut data[..]);
+            assert_eq!(cursor.write(b"abcdef").unwrap(), 4);
+            assert_eq!(cursor.write(b"g").unwrap(), 0);
+            assert_eq!(cursor.seek(SeekFrom::End(-3)).unwrap(), 1);
+            let mut buf = [0u8; 8];
+            assert_eq!(cursor.read(&mut buf).unwrap(), 3);
+            assert_eq!(&buf[..3], b"bcd");
+            assert!(cursor.seek(SeekFrom::Current(-5)).is_err());
+            assert_eq!(cursor.position(), 4);
+
+            let mut cursor = StorageCursor::new(vec![1u8]);
+            cursor.seek(SeekFrom::Start(3)).unwrap();
+            cursor.write_all(b"x").unwrap();
+            assert_eq!(cursor.into_inner(), [1, 0, 0, b'x']);
+        }
+
+        #[test]
+        fn test_edit_action() {
+            let mut data = *b"0123456789";
+            let mut cursor = StorageCursor::new(&mut data[..]);
+            let mut list = ActionList::<_, ()>::new();
+            list.add(EditAction::new(2, b"ab".to_vec()), &mut cursor, ())
+                .unwrap();
+            list.add(EditAction::new(6, b"cd".to_vec()), &mut cursor, ())
+                .unwrap();
+            assert!(matches!(
+                list.add(EditAction::new(9, b"ef".to_vec()), &mut cursor, ()),
+                Err((_, ActionError::Invalid))
+            ));
+            assert_eq!(cursor.get_ref(), b"01ab45cd89");
+
+            list.undo(&mut cursor, ()).unwrap();
+            assert_eq!(cursor.get_ref(), b"01ab456789");
+            list.undo(&mut cursor, ()).unwrap();
+            assert_eq!(cursor.get_ref(), b"0123456789");
+            assert!(list.undo(&mut cursor, ()).unwrap().is_none());
+            list.redo(&mut cursor, ()).unwrap();
+            assert_eq!(cursor.get_ref(), b"01ab456789");
+        }
+
+        #[test]
+        fn test_constrained_wrapper() {
+            let cursor = StorageCursor::new(b"0123456789".to_vec());
+            let mut view = ConstrainedWrapper::new(cursor, 2..6).unwrap();
+            let mut list = ActionList::<_, ()>::new();
+            list.add(EditAction::new(1, b"ab".to_vec()), &mut view, ())
+                .unwrap();
+            assert!(list
+                .add(EditAction::new(3, b"cd".to_vec()), &mut view, ())
+                .is_err());
+            assert_eq!(view.into_inner().into_inner(), b"012ab56789");
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//! Counting the accesses made to the backend, for diagnosing slow operations.
+use crate::{known_length::KnownLength, positioned_io::PositionedIo, truncate::Truncate, Hiex};
+use std::{
+    cell::Cell,
+    io::{Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+use usize_cast::FromUsize;
+
+/// Counts of the accesses made to a [`CountingIo`] backend.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct IoStats {
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// Calls to `read` and positioned reads, including ones which failed.
+    pub read_calls: u64,
+    /// Calls to `write` and positioned writes, including ones which failed.
+    pub write_calls: u64,
+    pub seek_calls: u64,
+    /// Calls to truncate, or to insert or remove space.
+    pub resize_calls: u64,
+}
+
+/// Wraps a backend, counting the reads, writes, and seeks made to it.
+/// Give it to [`Hiex::from_reader`] to count everything the editor and its actions do to the
+/// backend, which is then available from [`Hiex::io_stats`]. It can wrap any backend, including
+/// a [`ConstrainedWrapper`](crate::constrained_wrapper::ConstrainedWrapper), to count the
+/// accesses to a view. Counting is a few increments per call.
+#[derive(Debug, Default)]
+pub struct CountingIo<F> {
+    inner: F,
+    // Positioned reads and the length only take `&self`.
+    stats: Cell<IoStats>,
+}
+impl<F> CountingIo<F> {
+    pub fn new(inner: F) -> Self {
+        Self {
+            inner,
+            stats: Cell::new(IoStats::default()),
+        }
+    }
+
+    pub fn stats(&self) -> IoStats {
+        self.stats.get()
+    }
+
+    pub fn reset_stats(&self) {
+        self.stats.set(IoStats::default());
+    }
+
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut F {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+
+    fn count<T>(&self, update: T)
+    where
+        T: FnOnce(&mut IoStats),
+    {
+        let mut stats = self.stats.get();
+        update(&mut stats);
+        self.stats.set(stats);
+    }
+
+    fn count_read(&self, result: &std::io::Result<usize>) {
+        self.count(|stats| {
+            stats.read_calls += 1;
+            if let Ok(read) = result {
+                stats.bytes_read += u64::from_usize(*read);
+            }
+        });
+    }
+
+    fn count_write(&self, result: &std::io::Result<usize>) {
+        self.count(|stats| {
+            stats.write_calls += 1;
+            if let Ok(written) = result {
+                stats.bytes_written += u64::from_usize(*written);
+            }
+        });
+    }
+}
+impl<F> Read for CountingIo<F>
+where
+    F: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let result = self.inner.read(buf);
+        self.count_read(&result);
+        result
+    }
+}
+impl<F> Write for CountingIo<F>
+where
+    F: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let result = self.inner.write(buf);
+        self.count_write(&result);
+        result
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+impl<F> Seek for CountingIo<F>
+where
+    F: Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.count(|stats| stats.seek_calls += 1);
+        self.inner.seek(pos)
+    }
+}
+impl<F> KnownLength for CountingIo<F>
+where
+    F: KnownLength,
+{
+    fn known_length(&self) -> Option<u64> {
+        self.inner.known_length()
+    }
+}
+impl<F> PositionedIo for CountingIo<F>
+where
+    F: PositionedIo,
+{
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Option<std::io::Result<usize>> {
+        let result = self.inner.read_at(offset, buf)?;
+        self.count_read(&result);
+        Some(result)
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Option<std::io::Result<usize>> {
+        let result = self.inner.write_at(offset, buf)?;
+        self.count_write(&result);
+        Some(result)
+    }
+}
+impl<F> Truncate for CountingIo<F>
+where
+    F: Truncate,
+{
+    fn truncate(&mut self, new_len: u64) -> std::io::Result<()> {
+        self.count(|stats| stats.resize_calls += 1);
+        self.inner.truncate(new_len)
+    }
+
+    fn insert_space(&mut self, position: u64, length: u64) -> std::io::Result<bool> {
+        self.count(|stats| stats.resize_calls += 1);
+        self.inner.insert_space(position, length)
+    }
+
+    fn remove_space(&mut self, range: Range<u64>) -> std::io::Result<bool> {
+        self.count(|stats| stats.resize_calls += 1);
+        self.inner.remove_space(range)
+    }
+}
+
+impl<F, E> Hiex<CountingIo<F>, E>
+where
+    F: Read + Seek + Write,
+{
+    /// The accesses made to the backend since it was wrapped or the counts were last reset.
+    pub fn io_stats(&self) -> IoStats {
+        self.reader().stats()
+    }
+
+    pub fn reset_io_stats(&self) {
+        self.reader().reset_stats();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CountingIo, IoStats};
+    use crate::{EditAction, Hiex, InsertAction};
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    #[test]
+    fn test_io_stats() {
+        let data = CountingIo::new(Cursor::new(b"0123456789".to_vec()));
+        let mut hex: Hiex<_> = Hiex::from_reader(data).unwrap();
+        assert_eq!(hex.io_stats(), IoStats::default());
+
+        let mut buf = [0; 3];
+        hex.read_at(2, &mut buf).unwrap();
+        // Reading the overwritten bytes and writing the new ones, then writing the old ones back,
+        // each after seeking to them.
+        hex.add_action_simple(EditAction::new(1, b"ab".to_vec()))
+            .unwrap();
+        hex.undo_simple().unwrap();
+        assert_eq!(
+            hex.io_stats(),
+            IoStats {
+                bytes_read: 5,
+                bytes_written: 4,
+                read_calls: 2,
+                write_calls: 2,
+                seek_calls: 4,
+                resize_calls: 0,
+            }
+        );
+
+        hex.reset_io_stats();
+        hex.seek(SeekFrom::Start(8)).unwrap();
+        let mut rest = Vec::new();
+        hex.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"89");
+        let stats = hex.io_stats();
+        assert_eq!(stats.seek_calls, 1);
+        assert_eq!(stats.bytes_read, 2);
+
+        hex.reset_io_stats();
+        hex.add_action_simple(InsertAction::new(0, b"!".to_vec()))
+            .unwrap();
+        let stats = hex.io_stats();
+        assert!(stats.resize_calls > 0);
+        assert!(stats.bytes_written > 0);
+        assert_eq!(hex.into_inner().into_inner().into_inner(), b"!0123456789");
+    }
+}
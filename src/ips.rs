@@ -0,0 +1,387 @@
+//! Applying IPS patches as undoable actions.
+//!
+//! An IPS patch is the header `PATCH`, followed by records, followed by `EOF`. Each record is a
+//! 3 byte big endian offset and a 2 byte big endian size, followed by that many bytes. A size of
+//! 0 instead marks a run: a 2 byte big endian length, followed by the byte to repeat.
+//! A 3 byte big endian length may follow `EOF`, which the data is truncated (or grown) to after
+//! the records are applied.
+use crate::{
+    action::{Action, ActionError, MemoryUsage},
+    checked_usize, stream_len,
+    truncate::Truncate,
+    write_at, Hiex,
+};
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+use usize_cast::FromUsize;
+
+/// The bytes written by an [`IpsRecord`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IpsData {
+    Bytes(Vec<u8>),
+    /// `value` repeated `length` times.
+    Run {
+        length: u16,
+        value: u8,
+    },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IpsRecord {
+    pub offset: u64,
+    pub data: IpsData,
+}
+impl IpsRecord {
+    /// The amount of bytes the record writes.
+    pub fn len(&self) -> u64 {
+        match &self.data {
+            IpsData::Bytes(bytes) => u64::from_usize(bytes.len()),
+            IpsData::Run { length, .. } => u64::from(*length),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IpsPatch {
+    pub records: Vec<IpsRecord>,
+    /// The length to truncate the data to once the records have been applied, if given.
+    pub truncate: Option<u64>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum IpsError {
+    /// The patch doesn't start with `PATCH`.
+    MissingHeader,
+    /// The patch ended partway through a record, or without `EOF`.
+    UnexpectedEnd,
+    /// There were bytes after `EOF`, which weren't a truncation length, starting at `offset`.
+    TrailingData { offset: usize },
+}
+impl std::fmt::Display for IpsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IpsError::MissingHeader => write!(f, "IPS patch is missing its header"),
+            IpsError::UnexpectedEnd => write!(f, "IPS patch ended unexpectedly"),
+            IpsError::TrailingData { offset } => {
+                write!(f, "IPS patch has trailing data at offset {}", offset)
+            }
+        }
+    }
+}
+impl std::error::Error for IpsError {}
+
+/// Splits off the first `amount` bytes of `rest`.
+fn split<'a>(rest: &mut &'a [u8], amount: usize) -> Result<&'a [u8], IpsError> {
+    if rest.len() < amount {
+        return Err(IpsError::UnexpectedEnd);
+    }
+    let (first, remaining) = rest.split_at(amount);
+    *rest = remaining;
+    Ok(first)
+}
+
+fn read_be(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0, |value, &byte| (value << 8) | u64::from(byte))
+}
+
+/// Parses an IPS patch, see the [module](self) documentation for the format.
+pub fn parse_ips(patch: &[u8]) -> Result<IpsPatch, IpsError> {
+    let mut rest = patch
+        .strip_prefix(b"PATCH")
+        .ok_or(IpsError::MissingHeader)?;
+    let mut records = Vec::new();
+    loop {
+        let offset = split(&mut rest, 3)?;
+        if offset == b"EOF" {
+            break;
+        }
+        let offset = read_be(offset);
+        let size = read_be(split(&mut rest, 2)?) as usize;
+        let data = if size == 0 {
+            let length = read_be(split(&mut rest, 2)?) as u16;
+            let value = split(&mut rest, 1)?[0];
+            IpsData::Run { length, value }
+        } else {
+            IpsData::Bytes(split(&mut rest, size)?.to_vec())
+        };
+        records.push(IpsRecord { offset, data });
+    }
+
+    let truncate = match rest.len() {
+        0 => None,
+        3 => Some(read_be(rest)),
+        _ => {
+            return Err(IpsError::TrailingData {
+                offset: patch.len() - rest.len(),
+            })
+        }
+    };
+    Ok(IpsPatch { records, truncate })
+}
+
+/// An action which applies every record of an IPS patch, in order, as a single entry in the
+/// history. Records past the end of the data grow it.
+/// The bytes each record overwrites are stored for undoing, along with any bytes removed by
+/// truncating. If a record fails to apply, the records before it are undone.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ApplyPatchAction {
+    pub patch: IpsPatch,
+    previous_length: u64,
+    /// The bytes overwritten by each applied record, excluding any which were past the end,
+    /// followed by the bytes removed by truncating once that has been done.
+    previous: Vec<Vec<u8>>,
+}
+impl ApplyPatchAction {
+    pub fn new(patch: IpsPatch) -> Self {
+        Self {
+            patch,
+            previous_length: 0,
+            previous: Vec::new(),
+        }
+    }
+
+    fn apply_records<F>(&mut self, data: &mut F) -> Result<(), ActionError>
+    where
+        F: Read + Seek + Write + Truncate,
+    {
+        let mut length = self.previous_length;
+        for record in &self.patch.records {
+            let end = record
+                .offset
+                .checked_add(record.len())
+                .ok_or(ActionError::Invalid)?;
+            let existing = end.min(length).saturating_sub(record.offset);
+            let mut previous = vec![0; checked_usize(existing)?];
+            data.seek(SeekFrom::Start(record.offset))?;
+            data.read_exact(&mut previous)?;
+            self.previous.push(previous);
+
+            if end > length {
+                data.truncate(end)?;
+                length = end;
+            }
+            match &record.data {
+                IpsData::Bytes(bytes) => write_at(data, record.offset, bytes)?,
+                IpsData::Run { length, value } => {
+                    write_at(data, record.offset, &vec![*value; usize::from(*length)])?
+                }
+            }
+        }
+
+        if let Some(target) = self.patch.truncate {
+            let mut removed = vec![0; checked_usize(length.saturating_sub(target))?];
+            data.seek(SeekFrom::Start(target))?;
+            data.read_exact(&mut removed)?;
+            data.truncate(target)?;
+            self.previous.push(removed);
+        }
+        Ok(())
+    }
+
+    /// Undoes the truncation and whichever records were applied, in reverse.
+    fn restore<F>(&self, data: &mut F) -> Result<(), ActionError>
+    where
+        F: Read + Seek + Write + Truncate,
+    {
+        let mut previous = &self.previous[..];
+        if previous.len() > self.patch.records.len() {
+            if let (Some(target), Some((removed, rest))) =
+                (self.patch.truncate, previous.split_last())
+            {
+                data.truncate(target)?;
+                write_at(data, target, removed)?;
+                previous = rest;
+            }
+        }
+        for (record, previous) in self.patch.records.iter().zip(previous).rev() {
+            write_at(data, record.offset, previous)?;
+        }
+        data.truncate(self.previous_length)?;
+        Ok(())
+    }
+}
+impl<F, E> Action<F, E> for ApplyPatchAction
+where
+    F: Read + Seek + Write + Truncate,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        self.previous_length = stream_len(data)?;
+        self.previous.clear();
+        if let Err(err) = self.apply_records(data) {
+            // Best-effort rollback of the records that were applied.
+            let _ = self.restore(data);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        self.restore(data)
+    }
+
+    /// Covers every record, and everything past the truncation length if there is one.
+    fn affected_range(&self) -> Option<Range<u64>> {
+        let records = &self.patch.records;
+        let start = records
+            .iter()
+            .map(|record| record.offset)
+            .chain(self.patch.truncate)
+            .min()
+            .unwrap_or(0);
+        let end = match self.patch.truncate {
+            Some(_) => u64::MAX,
+            None => records
+                .iter()
+                .map(|record| record.offset.saturating_add(record.len()))
+                .max()
+                .unwrap_or(0),
+        };
+        Some(start..end)
+    }
+
+    fn description(&self) -> String {
+        match self.patch.records.len() {
+            1 => "Apply IPS patch of 1 record".to_string(),
+            count => format!("Apply IPS patch of {} records", count),
+        }
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(ApplyPatchAction::new(self.patch.clone())))
+    }
+}
+impl MemoryUsage for ApplyPatchAction {
+    fn memory_usage(&self) -> usize {
+        let records = self
+            .patch
+            .records
+            .iter()
+            .map(|record| match &record.data {
+                IpsData::Bytes(bytes) => std::mem::size_of::<IpsRecord>() + bytes.len(),
+                IpsData::Run { .. } => std::mem::size_of::<IpsRecord>(),
+            })
+            .sum::<usize>();
+        let previous = self
+            .previous
+            .iter()
+            .map(|previous| std::mem::size_of::<Vec<u8>>() + previous.len())
+            .sum::<usize>();
+        std::mem::size_of::<Self>() + records + previous
+    }
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write + Truncate,
+{
+    /// Applies every record of `patch`, as a single action. See [`ApplyPatchAction`].
+    pub fn apply_ips(
+        &mut self,
+        patch: IpsPatch,
+        other: E,
+    ) -> Result<(), (ApplyPatchAction, ActionError)> {
+        self.add_action(ApplyPatchAction::new(patch), other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_ips, IpsData, IpsError, IpsPatch, IpsRecord};
+    use crate::{action::ActionError, contents, Hiex};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_parse_ips() {
+        let patch = b"PATCH\x00\x00\x02\x00\x02ab\x00\x01\x00\x00\x00\x00\x03zEOF";
+        assert_eq!(
+            parse_ips(patch).unwrap(),
+            IpsPatch {
+                records: vec![
+                    IpsRecord {
+                        offset: 2,
+                        data: IpsData::Bytes(b"ab".to_vec())
+                    },
+                    IpsRecord {
+                        offset: 0x100,
+                        data: IpsData::Run {
+                            length: 3,
+                            value: b'z'
+                        }
+                    },
+                ],
+                truncate: None,
+            }
+        );
+
+        let truncated = parse_ips(b"PATCHEOF\x01\x00\x00").unwrap();
+        assert!(truncated.records.is_empty());
+        assert_eq!(truncated.truncate, Some(0x10000));
+
+        assert_eq!(parse_ips(b"PATCX"), Err(IpsError::MissingHeader));
+        assert_eq!(
+            parse_ips(b"PATCH\x00\x00\x02\x00\x02a"),
+            Err(IpsError::UnexpectedEnd)
+        );
+        assert_eq!(parse_ips(b"PATCH"), Err(IpsError::UnexpectedEnd));
+        assert_eq!(
+            parse_ips(b"PATCHEOF\x00"),
+            Err(IpsError::TrailingData { offset: 8 })
+        );
+    }
+
+    #[test]
+    fn test_apply_ips() {
+        let original = b"0123456789".to_vec();
+        let mut hex = Hiex::from_reader(Cursor::new(original.clone())).unwrap();
+        // Overlapping records, one of which grows the data past a gap.
+        let patch = parse_ips(
+            b"PATCH\x00\x00\x01\x00\x03abc\x00\x00\x02\x00\x00\x00\x02-\x00\x00\x0c\x00\x01!EOF",
+        )
+        .unwrap();
+        hex.apply_ips(patch, ()).unwrap();
+        assert_eq!(contents(&mut hex), b"0a--456789\0\0!");
+        assert_eq!(hex.actions.len(), 1);
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), original);
+        hex.redo(()).unwrap();
+        assert_eq!(contents(&mut hex), b"0a--456789\0\0!");
+        hex.undo(()).unwrap();
+
+        // Truncating after the records.
+        let patch = parse_ips(b"PATCH\x00\x00\x08\x00\x02xyEOF\x00\x00\x09").unwrap();
+        hex.apply_ips(patch, ()).unwrap();
+        assert_eq!(contents(&mut hex), b"01234567x");
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), original);
+
+        // A record which can't be applied undoes the ones before it.
+        let patch = IpsPatch {
+            records: vec![
+                IpsRecord {
+                    offset: 0,
+                    data: IpsData::Bytes(b"ab".to_vec()),
+                },
+                IpsRecord {
+                    offset: u64::MAX,
+                    data: IpsData::Bytes(b"cd".to_vec()),
+                },
+            ],
+            truncate: None,
+        };
+        let (_, err) = hex.apply_ips(patch, ()).unwrap_err();
+        assert!(matches!(err, ActionError::Invalid));
+        assert_eq!(contents(&mut hex), original);
+    }
+}
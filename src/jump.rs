@@ -0,0 +1,198 @@
+//! Back and forward navigation through previously visited positions.
+use crate::Hiex;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// The default maximum amount of positions kept in the jump list.
+pub const DEFAULT_JUMP_DEPTH: usize = 100;
+
+/// Where a jump moved to.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Jump {
+    pub position: u64,
+    /// Whether the recorded position was past the end of the data, and so was moved to the end.
+    pub clamped: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct JumpList {
+    positions: Vec<u64>,
+    /// The index into `positions` that we are at. If this is `positions.len()`, then we are past the
+    /// most recent jump rather than at a recorded position.
+    current: usize,
+    depth: usize,
+}
+impl Default for JumpList {
+    fn default() -> Self {
+        Self {
+            positions: Vec::new(),
+            current: 0,
+            depth: DEFAULT_JUMP_DEPTH,
+        }
+    }
+}
+impl JumpList {
+    /// Record `position` after the current entry, discarding any entries after it.
+    fn push(&mut self, position: u64) {
+        self.positions
+            .truncate((self.current + 1).min(self.positions.len()));
+        if self.positions.last() != Some(&position) {
+            self.positions.push(position);
+        }
+        self.limit();
+        self.current = self.positions.len();
+    }
+
+    /// Drop the oldest positions until there are at most `depth`.
+    fn limit(&mut self) {
+        if self.positions.len() > self.depth {
+            let excess = self.positions.len() - self.depth;
+            self.positions.drain(..excess);
+            self.current = self.current.saturating_sub(excess);
+        }
+    }
+
+    /// The position before the current one, given the current `position`.
+    fn back(&mut self, position: u64) -> Option<u64> {
+        if self.current == self.positions.len() {
+            match self.positions.last() {
+                None => return None,
+                Some(last) if *last == position => {}
+                // Remember where we are so that jumping forward returns here.
+                Some(_) => {
+                    self.positions.push(position);
+                    self.limit();
+                }
+            }
+            self.current = self.positions.len() - 1;
+        }
+        self.current = self.current.checked_sub(1)?;
+        Some(self.positions[self.current])
+    }
+
+    fn forward(&mut self) -> Option<u64> {
+        if self.current + 1 >= self.positions.len() {
+            return None;
+        }
+        self.current += 1;
+        Some(self.positions[self.current])
+    }
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Record the current position in the jump list, such as before jumping elsewhere.
+    /// Positions after the current one in the list are discarded, like browser history.
+    /// Recording the same position as the most recent one does nothing.
+    pub fn push_jump(&mut self) -> std::io::Result<()> {
+        let position = self.position()?;
+        self.jumps.push(position);
+        Ok(())
+    }
+
+    /// Seek to the previous position in the jump list. Returns `None` if there is none.
+    /// If we weren't at a position in the list, then the current position is recorded first so
+    /// that [`Hiex::jump_forward`] can return to it.
+    pub fn jump_back(&mut self) -> std::io::Result<Option<Jump>> {
+        let position = self.position()?;
+        match self.jumps.back(position) {
+            Some(target) => self.jump_to(target).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Seek to the next position in the jump list. Returns `None` if there is none.
+    pub fn jump_forward(&mut self) -> std::io::Result<Option<Jump>> {
+        match self.jumps.forward() {
+            Some(target) => self.jump_to(target).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Set the maximum amount of positions kept in the jump list, dropping the oldest positions
+    /// if there are more. Defaults to [`DEFAULT_JUMP_DEPTH`].
+    pub fn set_jump_depth(&mut self, depth: usize) {
+        self.jumps.depth = depth.max(1);
+        self.jumps.limit();
+    }
+
+    fn jump_to(&mut self, target: u64) -> std::io::Result<Jump> {
+        let length = self.length()?;
+        let jump = Jump {
+            position: target.min(length),
+            clamped: target > length,
+        };
+        self.seek(SeekFrom::Start(jump.position))?;
+        Ok(jump)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Jump;
+    use crate::Hiex;
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    fn jump(position: u64) -> Option<Jump> {
+        Some(Jump {
+            position,
+            clamped: false,
+        })
+    }
+
+    #[test]
+    fn test_jump_list() {
+        let mut hex = Hiex::<_, ()>::from_reader(Cursor::new(vec![0; 100])).unwrap();
+        assert_eq!(hex.jump_back().unwrap(), None);
+        for position in [10, 20, 20, 30] {
+            hex.seek(SeekFrom::Start(position)).unwrap();
+            hex.push_jump().unwrap();
+        }
+        hex.seek(SeekFrom::Start(40)).unwrap();
+
+        assert_eq!(hex.jump_back().unwrap(), jump(30));
+        assert_eq!(hex.jump_back().unwrap(), jump(20));
+        assert_eq!(hex.position().unwrap(), 20);
+        assert_eq!(hex.jump_forward().unwrap(), jump(30));
+        assert_eq!(hex.jump_forward().unwrap(), jump(40));
+        assert_eq!(hex.jump_forward().unwrap(), None);
+        assert_eq!(hex.jump_back().unwrap(), jump(30));
+        assert_eq!(hex.jump_back().unwrap(), jump(20));
+
+        // A new jump discards everything after the current position.
+        hex.seek(SeekFrom::Start(50)).unwrap();
+        hex.push_jump().unwrap();
+        assert_eq!(hex.jump_forward().unwrap(), None);
+        hex.seek(SeekFrom::Start(60)).unwrap();
+        assert_eq!(hex.jump_back().unwrap(), jump(50));
+        assert_eq!(hex.jump_back().unwrap(), jump(20));
+        assert_eq!(hex.jump_back().unwrap(), jump(10));
+        assert_eq!(hex.jump_back().unwrap(), None);
+        assert_eq!(hex.jump_forward().unwrap(), jump(20));
+
+        // Only the two most recent positions are kept.
+        hex.set_jump_depth(2);
+        assert_eq!(hex.jump_back().unwrap(), None);
+        assert_eq!(hex.jump_forward().unwrap(), jump(60));
+        assert_eq!(hex.jump_back().unwrap(), jump(50));
+    }
+
+    #[test]
+    fn test_jump_clamped() {
+        let mut hex = Hiex::<_, ()>::from_reader(Cursor::new(vec![0; 100])).unwrap();
+        hex.seek(SeekFrom::Start(90)).unwrap();
+        hex.push_jump().unwrap();
+        hex.seek(SeekFrom::Start(0)).unwrap();
+        hex.delete_bytes(0..50, ()).unwrap();
+
+        assert_eq!(
+            hex.jump_back().unwrap(),
+            Some(Jump {
+                position: 50,
+                clamped: true
+            })
+        );
+        assert_eq!(hex.position().unwrap(), 50);
+    }
+}
@@ -0,0 +1,136 @@
+use std::{fs::File, io::Cursor};
+use usize_cast::FromUsize;
+
+/// A trait for objects which may know their length without having to seek.
+/// Finding the length through seeking can take up to three seeks (see `stream_len`), which adds up
+/// on paths that are used often, like applying an action.
+/// Backends which can't cheaply know their length should use the default implementation, which
+/// returns `None` and makes users of this trait fall back to seeking.
+/// Nothing requires it, so it is used where asked for, with [`Hiex::with_known_length`] and
+/// [`ConstrainedWrapper::with_known_length`].
+///
+/// [`Hiex::with_known_length`]: crate::Hiex::with_known_length
+/// [`ConstrainedWrapper::with_known_length`]: crate::constrained_wrapper::ConstrainedWrapper::with_known_length
+pub trait KnownLength {
+    fn known_length(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl<T> KnownLength for Cursor<T>
+where
+    T: AsRef<[u8]>,
+{
+    fn known_length(&self) -> Option<u64> {
+        Some(u64::from_usize(self.get_ref().as_ref().len()))
+    }
+}
+
+impl KnownLength for File {
+    fn known_length(&self) -> Option<u64> {
+        self.metadata().ok().map(|metadata| metadata.len())
+    }
+}
+
+impl<T> KnownLength for &mut T
+where
+    T: KnownLength + ?Sized,
+{
+    fn known_length(&self) -> Option<u64> {
+        (**self).known_length()
+    }
+}
+
+#[cfg(feature = "tempfile")]
+impl KnownLength for tempfile::NamedTempFile {
+    fn known_length(&self) -> Option<u64> {
+        self.as_file().known_length()
+    }
+}
+/// The length of a `SpooledTempFile` can't be found without seeking.
+#[cfg(feature = "tempfile")]
+impl KnownLength for tempfile::SpooledTempFile {}
+
+#[cfg(test)]
+mod tests {
+    use super::KnownLength;
+    use crate::{EditAction, Hiex};
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+    /// Counts the seeks done on a `Cursor`, optionally hiding its known length.
+    struct CountingSeek {
+        inner: Cursor<Vec<u8>>,
+        seeks: usize,
+        known: bool,
+    }
+    impl Read for CountingSeek {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+    impl Write for CountingSeek {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.inner.flush()
+        }
+    }
+    impl Seek for CountingSeek {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.seeks += 1;
+            self.inner.seek(pos)
+        }
+    }
+    impl KnownLength for CountingSeek {
+        fn known_length(&self) -> Option<u64> {
+            if self.known {
+                self.inner.known_length()
+            } else {
+                None
+            }
+        }
+    }
+
+    fn edit_seeks(known: bool) -> (usize, Vec<u8>) {
+        let backend = CountingSeek {
+            inner: Cursor::new(vec![0u8; 256]),
+            seeks: 0,
+            known,
+        };
+        let mut hex = Hiex::from_reader(backend).unwrap().with_known_length();
+        for i in 0..100u8 {
+            hex.add_action(EditAction::new(u64::from(i), vec![i]), ())
+                .unwrap();
+            assert_eq!(hex.length().unwrap(), 256);
+        }
+        let backend = hex.into_inner();
+        (backend.seeks, backend.inner.into_inner())
+    }
+
+    #[test]
+    fn test_known_length_seeks() {
+        let (known_seeks, known_data) = edit_seeks(true);
+        let (probed_seeks, probed_data) = edit_seeks(false);
+        assert_eq!(known_data, probed_data);
+        // Two seeks per edit (read previous data, write new data), plus the three needed for every
+        // probe of the length when it isn't known.
+        assert_eq!(known_seeks, 200);
+        assert_eq!(probed_seeks, 200 + 300);
+    }
+
+    #[test]
+    fn test_known_length_impls() {
+        let cursor = Cursor::new(vec![0u8; 7]);
+        assert_eq!(cursor.known_length(), Some(7));
+        let mut data = [0u8; 3];
+        let mut cursor = Cursor::new(&mut data[..]);
+        let reference = &mut cursor;
+        assert_eq!(KnownLength::known_length(&reference), Some(3));
+
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(b"abcd").unwrap();
+        assert_eq!(file.known_length(), Some(4));
+    }
+}
@@ -1,18 +1,194 @@
-mod constrained_wrapper;
+#![cfg_attr(all(feature = "no_std_io", not(test)), no_std)]
+//! With the `no_std_io` feature, the crate is built without `std`, needing just `core` and
+//! `alloc`. Only the action machinery ([`ActionList`], [`Action`], [`EditAction`] and
+//! [`ConstrainedWrapper`]) is available then, over the I/O traits in the `io` module.
+//!
+//! [`ActionList`]: crate::action::ActionList
+//! [`Action`]: crate::action::Action
+//! [`ConstrainedWrapper`]: crate::constrained_wrapper::ConstrainedWrapper
 
+extern crate alloc;
+
+/// Emits a trace level diagnostic with `log`, if the `log` feature is enabled.
+/// Structured fields go before a `;`, as with `log::trace!`.
+#[allow(unused_macros)]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        log::trace!($($arg)*);
+    };
+}
+
+/// Emits a debug level diagnostic with `log`, if the `log` feature is enabled.
+#[allow(unused_macros)]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        log::debug!($($arg)*);
+    };
+}
+
+pub mod constrained_wrapper;
+
+#[cfg(not(feature = "no_std_io"))]
 mod hiex;
+#[cfg(not(feature = "no_std_io"))]
 pub use crate::hiex::*;
 pub mod action;
+#[cfg(not(feature = "no_std_io"))]
+pub mod background;
+#[cfg(not(feature = "no_std_io"))]
+pub mod block_index;
+#[cfg(not(feature = "no_std_io"))]
+pub mod bookmark;
+pub mod buffer_pool;
+#[cfg(not(feature = "no_std_io"))]
+pub mod checksum;
+#[cfg(not(feature = "no_std_io"))]
+pub mod close;
+#[cfg(not(feature = "no_std_io"))]
+pub mod compare;
+#[cfg(all(feature = "compression", not(feature = "no_std_io")))]
+pub mod compression;
+#[cfg(all(feature = "crypto", not(feature = "no_std_io")))]
+pub mod crypto;
+#[cfg(not(feature = "no_std_io"))]
+pub mod diff;
+mod edit;
+pub use crate::edit::EditAction;
+mod endian;
+mod memory_usage;
+pub use crate::endian::Endian;
+pub use crate::endian::Number;
+#[cfg(not(feature = "no_std_io"))]
+pub mod encoding;
+#[cfg(not(feature = "no_std_io"))]
+pub mod export;
+#[cfg(not(feature = "no_std_io"))]
+pub mod gap_buffer;
+#[cfg(not(feature = "no_std_io"))]
+pub mod hex_dump;
+#[cfg(all(feature = "serde", not(feature = "no_std_io")))]
+pub mod history_file;
+#[cfg(not(feature = "no_std_io"))]
+pub use crate::export::join_files;
+#[cfg(not(feature = "no_std_io"))]
+pub mod import;
+#[cfg(not(feature = "no_std_io"))]
+mod io;
+#[cfg(feature = "no_std_io")]
+pub mod io;
+#[cfg(not(feature = "no_std_io"))]
+pub mod io_stats;
+#[cfg(not(feature = "no_std_io"))]
+pub mod ips;
+#[cfg(not(feature = "no_std_io"))]
+pub mod jump;
+#[cfg(not(feature = "no_std_io"))]
+pub mod known_length;
+#[cfg(all(feature = "serde", not(feature = "no_std_io")))]
+pub mod metadata;
+#[cfg(not(feature = "no_std_io"))]
+pub mod modified;
+#[cfg(not(feature = "no_std_io"))]
+pub mod numeric_stats;
+#[cfg(not(feature = "no_std_io"))]
+pub mod piece_table;
+#[cfg(not(feature = "no_std_io"))]
+pub mod pointer_scan;
+#[cfg(not(feature = "no_std_io"))]
+pub mod positioned_io;
+#[cfg(not(feature = "no_std_io"))]
+pub mod read_view;
+#[cfg(not(feature = "no_std_io"))]
+pub mod recorder;
+#[cfg(all(feature = "regex", not(feature = "no_std_io")))]
+pub mod regex_search;
+#[cfg(not(feature = "no_std_io"))]
+pub mod region;
+#[cfg(not(feature = "no_std_io"))]
+pub mod savepoint;
+#[cfg(not(feature = "no_std_io"))]
+pub mod script;
+#[cfg(not(feature = "no_std_io"))]
+pub mod search;
+#[cfg(all(feature = "tempfile", not(feature = "no_std_io")))]
+pub mod spill;
+#[cfg(not(feature = "no_std_io"))]
+pub mod strided;
+#[cfg(not(feature = "no_std_io"))]
+pub mod string_write;
+#[cfg(not(feature = "no_std_io"))]
+pub mod template;
+#[cfg(not(feature = "no_std_io"))]
+pub mod transaction;
+#[cfg(not(feature = "no_std_io"))]
+pub mod transform;
+#[cfg(not(feature = "no_std_io"))]
 pub mod truncate;
+#[cfg(not(feature = "no_std_io"))]
+pub mod varint;
+#[cfg(all(feature = "notify", not(feature = "no_std_io")))]
+pub mod watch;
+
+use crate::io::{Read, Seek, SeekFrom, Write};
+use core::convert::TryFrom;
+#[cfg(not(feature = "no_std_io"))]
+use std::ops::Range;
+#[cfg(not(feature = "no_std_io"))]
+use usize_cast::FromUsize;
+
+/// The maximum size of the buffers used when streaming over a range of a reader.
+pub(crate) const CHUNK_SIZE: usize = 64 * 1024;
+
+/// `value`, clamped to at most `max` (such as the length of a buffer) so that it fits in a `usize`
+/// on every platform.
+pub(crate) fn clamp_usize(value: u64, max: usize) -> usize {
+    usize::try_from(value).map_or(max, |value| value.min(max))
+}
+
+/// A length or offset which has to be held in memory, but doesn't fit in a `usize` on this
+/// platform, such as growing a `Cursor<Vec<u8>>` past 4 GiB on a 32-bit target.
+/// The chunked and streaming APIs, which never hold more than a chunk in memory, don't have this
+/// limit, and are the way to work with data this large.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TooLargeForPlatform {
+    pub value: u64,
+}
+impl core::fmt::Display for TooLargeForPlatform {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} is too large for this platform", self.value)
+    }
+}
+#[cfg(not(feature = "no_std_io"))]
+impl std::error::Error for TooLargeForPlatform {}
+
+/// `value` as a `usize`, failing with `ErrorKind::InvalidInput` carrying a
+/// [`TooLargeForPlatform`] if it is larger than `max`.
+fn usize_within(value: u64, max: usize) -> io::Result<usize> {
+    match usize::try_from(value) {
+        Ok(converted) if converted <= max => Ok(converted),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            TooLargeForPlatform { value },
+        )),
+    }
+}
+
+/// `value` as a `usize`, for lengths and offsets which have to be held in memory.
+/// Fails with `ErrorKind::InvalidInput` carrying a [`TooLargeForPlatform`] if it doesn't fit.
+pub(crate) fn checked_usize(value: u64) -> io::Result<usize> {
+    usize_within(value, usize::MAX)
+}
 
 /// Get position in stream using seeks.
 /// FIXME: This only exists since the rust version is currently only in nightly
-pub(crate) fn stream_position<S>(seeker: &mut S) -> std::io::Result<u64>
+pub(crate) fn stream_position<S>(seeker: &mut S) -> io::Result<u64>
 where
-    S: std::io::Seek,
+    S: io::Seek,
 {
     // Seeking to the current position gives our position
-    seeker.seek(std::io::SeekFrom::Current(0))
+    seeker.stream_position()
 }
 
 /// Get the stream length using seeks
@@ -20,18 +196,510 @@ where
 /// If there was an error then the position is unspecified.
 /// FIXME: This only exists since the rust version is currently only in nightly
 /// If this errors, then the position in `seeker` is not defined.
-pub(crate) fn stream_len<S>(seeker: &mut S) -> std::io::Result<u64>
+pub(crate) fn stream_len<S>(seeker: &mut S) -> io::Result<u64>
 where
-    S: std::io::Seek,
+    S: io::Seek,
 {
     // Get the current position, so that we can restore our position.
     let position = stream_position(seeker)?;
-    let length = seeker.seek(std::io::SeekFrom::End(0))?;
+    let length = seeker.seek(io::SeekFrom::End(0))?;
 
     // If we're still at the starting position, let's not seek again.
     if position != length {
-        seeker.seek(std::io::SeekFrom::Start(position))?;
+        seeker.seek(io::SeekFrom::Start(position))?;
     }
 
     Ok(length)
 }
+
+/// Get the length of the stream, using [`KnownLength`] if it knows the length and falling back to
+/// seeking (see [`stream_len`]) otherwise.
+/// Used as the length hook set by [`Hiex::with_known_length`].
+///
+/// [`KnownLength`]: crate::known_length::KnownLength
+#[cfg(not(feature = "no_std_io"))]
+pub(crate) fn known_len<S>(stream: &mut S) -> io::Result<u64>
+where
+    S: Seek + known_length::KnownLength,
+{
+    match stream.known_length() {
+        Some(length) => Ok(length),
+        None => stream_len(stream),
+    }
+}
+
+/// Seeks to `position` and writes all of `buf`.
+pub(crate) fn write_at<S>(stream: &mut S, position: u64, buf: &[u8]) -> io::Result<()>
+where
+    S: Write + Seek,
+{
+    stream.seek(SeekFrom::Start(position))?;
+    stream.write_all(buf)
+}
+
+/// Reads into `buf` from `position` until it is full or the data ends, returning how many bytes
+/// were read.
+pub(crate) fn read_full_at<S>(stream: &mut S, position: u64, buf: &mut [u8]) -> io::Result<usize>
+where
+    S: Read + Seek,
+{
+    if buf.is_empty() {
+        return Ok(0);
+    }
+    stream.seek(SeekFrom::Start(position))?;
+    let mut read = 0;
+    while read < buf.len() {
+        match stream.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(amount) => read += amount,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(read)
+}
+
+/// Fills `buf` from `position`, failing with `ErrorKind::UnexpectedEof` if the data ends first.
+pub(crate) fn read_exact_at<S>(stream: &mut S, position: u64, buf: &mut [u8]) -> io::Result<()>
+where
+    S: Read + Seek,
+{
+    if buf.is_empty() {
+        return Ok(());
+    }
+    stream.seek(SeekFrom::Start(position))?;
+    stream.read_exact(buf)
+}
+
+/// Streams the `length` bytes at `position` through `transform` in chunks of at most
+/// `chunk_size` bytes, writing each chunk back in place after it has been transformed.
+/// `transform` is given the offset of the chunk relative to `position`.
+/// `chunk_size` should be a multiple of any element width that `transform` relies upon.
+#[cfg(not(feature = "no_std_io"))]
+pub(crate) fn transform_range<S, T>(
+    stream: &mut S,
+    position: u64,
+    length: u64,
+    chunk_size: usize,
+    mut transform: T,
+) -> io::Result<()>
+where
+    S: Read + Write + Seek,
+    T: FnMut(u64, &mut [u8]),
+{
+    let mut buffer = vec![0u8; clamp_usize(length, chunk_size)];
+    let mut offset = 0;
+    while offset < length {
+        let amount = clamp_usize(length - offset, buffer.len());
+        let chunk = &mut buffer[..amount];
+
+        stream.seek(SeekFrom::Start(position + offset))?;
+        stream.read_exact(chunk)?;
+        transform(offset, chunk);
+        write_at(stream, position + offset, chunk)?;
+
+        offset += u64::from_usize(amount);
+    }
+
+    Ok(())
+}
+
+/// Moves the bytes within `range` so that they start at `destination`, in chunks of at most
+/// `chunk_size` bytes. The source and destination may overlap. The data must already be long
+/// enough to hold the destination; the bytes left behind are unspecified.
+#[cfg(not(feature = "no_std_io"))]
+pub(crate) fn move_bytes<S>(
+    stream: &mut S,
+    range: Range<u64>,
+    destination: u64,
+    chunk_size: usize,
+) -> io::Result<()>
+where
+    S: Read + Write + Seek,
+{
+    let length = range.end.saturating_sub(range.start);
+    if length == 0 || destination == range.start {
+        return Ok(());
+    }
+
+    let mut buffer = vec![0u8; clamp_usize(length, chunk_size)];
+    let mut moved = 0;
+    while moved < length {
+        let amount = clamp_usize(length - moved, buffer.len());
+        // When moving towards the end, copy the last chunks first so that they aren't overwritten
+        // before they are read.
+        let offset = if destination > range.start {
+            length - moved - u64::from_usize(amount)
+        } else {
+            moved
+        };
+        let chunk = &mut buffer[..amount];
+
+        stream.seek(SeekFrom::Start(range.start + offset))?;
+        stream.read_exact(chunk)?;
+        write_at(stream, destination + offset, chunk)?;
+
+        moved += u64::from_usize(amount);
+    }
+
+    Ok(())
+}
+
+/// Changes the `from` bytes at `position` to be `to` bytes long, by opening or removing bytes at
+/// the end of them, moving the bytes after them. The contents of any opened bytes are
+/// unspecified.
+#[cfg(not(feature = "no_std_io"))]
+pub(crate) fn resize_at<S>(stream: &mut S, position: u64, from: u64, to: u64) -> io::Result<()>
+where
+    S: Read + Write + Seek + truncate::Truncate,
+{
+    let length = stream_len(stream)?;
+    if to > from {
+        let at = position + from;
+        let amount = to - from;
+        if !stream.insert_space(at, amount)? {
+            stream.truncate(length + amount)?;
+            move_bytes(stream, at..length, at + amount, CHUNK_SIZE)?;
+        }
+    } else if to < from {
+        let range = position + to..position + from;
+        if !stream.remove_space(range.clone())? {
+            move_bytes(stream, range.end..length, range.start, CHUNK_SIZE)?;
+            stream.truncate(length - (from - to))?;
+        }
+    }
+    Ok(())
+}
+
+/// All of the data in `hex`, for checking it in tests.
+#[cfg(all(test, not(feature = "no_std_io")))]
+pub(crate) fn contents<F, E>(hex: &mut Hiex<F, E>) -> Vec<u8>
+where
+    F: Read + Seek + Write,
+{
+    let length = checked_usize(hex.length().unwrap()).unwrap();
+    hex.read_amount_at(0, length).unwrap()
+}
+
+#[cfg(all(test, not(feature = "no_std_io")))]
+mod tests {
+    use crate::{
+        action::ActionError, clamp_usize, contents, truncate::Truncate, usize_within, EditAction,
+        GrowthPolicy, Hiex, InsertAction, TooLargeForPlatform,
+    };
+    use std::io::{Cursor, ErrorKind};
+
+    fn edit_cycle() {
+        let mut hex = Hiex::from_reader(Cursor::new(b"0123".to_vec())).unwrap();
+        hex.add_action(EditAction::new(1, b"ab".to_vec()), ())
+            .unwrap();
+        hex.undo(()).unwrap();
+        hex.redo(()).unwrap();
+        hex.save_to(&mut Cursor::new(Vec::new())).unwrap();
+    }
+
+    /// Nothing is printed to stdout, since that could corrupt the output of a program using us.
+    #[test]
+    fn test_quiet_stdout() {
+        const CHILD: &str = "HIEX_QUIET_STDOUT_CHILD";
+        if std::env::var_os(CHILD).is_some() {
+            println!("<start>");
+            edit_cycle();
+            println!("<end>");
+            return;
+        }
+
+        // Run this test again in a child process, so that its stdout can be inspected.
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["tests::test_quiet_stdout", "--exact", "--nocapture"])
+            .env(CHILD, "1")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let start = stdout.find("<start>\n").unwrap() + "<start>\n".len();
+        let end = stdout.find("<end>").unwrap();
+        assert_eq!(&stdout[start..end], "");
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn test_log_events() {
+        use log::{
+            kv::{Key, Value, VisitSource},
+            Level, Log, Metadata, Record,
+        };
+        use std::cell::RefCell;
+
+        thread_local! {
+            static EVENTS: RefCell<Vec<(Level, String, Vec<String>)>> = const { RefCell::new(Vec::new()) };
+        }
+
+        struct Fields(Vec<String>);
+        impl<'kvs> VisitSource<'kvs> for Fields {
+            fn visit_pair(
+                &mut self,
+                key: Key<'kvs>,
+                value: Value<'kvs>,
+            ) -> Result<(), log::kv::Error> {
+                self.0.push(format!("{}={}", key, value));
+                Ok(())
+            }
+        }
+
+        /// Records the events of each thread separately, since tests run in parallel.
+        struct Capture;
+        impl Log for Capture {
+            fn enabled(&self, _metadata: &Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &Record) {
+                let mut fields = Fields(Vec::new());
+                record.key_values().visit(&mut fields).unwrap();
+                let event = (record.level(), record.args().to_string(), fields.0);
+                EVENTS.with(|events| events.borrow_mut().push(event));
+            }
+
+            fn flush(&self) {}
+        }
+
+        static CAPTURE: Capture = Capture;
+        // Another test may have already set it.
+        let _ = log::set_logger(&CAPTURE);
+        log::set_max_level(log::LevelFilter::Trace);
+
+        edit_cycle();
+        let events = EVENTS.with(|events| events.take());
+        let messages = events
+            .iter()
+            .map(|(_, message, _)| message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                "applying edit",
+                "added action",
+                "unapplying edit",
+                "undid action",
+                "applying edit",
+                "redid action",
+                "saving",
+                "saved"
+            ]
+        );
+        assert_eq!(events[0].0, Level::Trace);
+        assert_eq!(events[0].2, ["position=1", "length=2"]);
+        assert_eq!(events[6].0, Level::Debug);
+        assert_eq!(events[6].2, ["length=4", "previous_length=0"]);
+    }
+
+    #[test]
+    fn test_usize_limits() {
+        assert_eq!(clamp_usize(10, 4), 4);
+        assert_eq!(clamp_usize(3, 4), 3);
+        assert_eq!(clamp_usize(u64::MAX, 4), 4);
+
+        // Simulates a platform where `usize` is only a byte wide.
+        assert_eq!(usize_within(255, 255).unwrap(), 255);
+        let err = usize_within(256, 255).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        let inner = err.get_ref().unwrap().downcast_ref::<TooLargeForPlatform>();
+        assert_eq!(inner, Some(&TooLargeForPlatform { value: 256 }));
+
+        // Growing past what can be allocated is an error rather than an abort, and leaves the
+        // cursor alone.
+        let mut cursor = Cursor::new(b"0123".to_vec());
+        cursor.set_position(2);
+        let err = cursor.truncate(u64::MAX).unwrap_err();
+        #[cfg(target_pointer_width = "32")]
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(err.kind(), ErrorKind::OutOfMemory);
+        assert_eq!(cursor.position(), 2);
+        assert_eq!(cursor.get_ref(), b"0123");
+    }
+
+    #[test]
+    fn test_save_to_truncating() {
+        let source: Vec<u8> = (0..100).collect();
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(source.clone())).unwrap();
+        let mut dest = Cursor::new(vec![0xff; 200]);
+        hex.save_to_truncating(&mut dest).unwrap();
+        assert_eq!(dest.get_ref().len(), 100);
+        assert_eq!(dest.get_ref(), &source);
+        assert!(!hex.is_dirty());
+
+        // The data ends where it was written, after what was already there.
+        let mut dest = Cursor::new(vec![0xff; 200]);
+        dest.set_position(4);
+        hex.save_to_truncating(&mut dest).unwrap();
+        assert_eq!(dest.get_ref().len(), 104);
+        assert_eq!(dest.get_ref()[..4], [0xff; 4]);
+        assert_eq!(dest.get_ref()[4..], source[..]);
+    }
+
+    #[test]
+    fn test_insert_action() {
+        // `Cursor` can't insert space itself, so the backing store is grown with `Truncate`.
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"01234567".to_vec())).unwrap();
+        hex.add_action_simple(InsertAction::new(3, b"abc".to_vec()))
+            .unwrap();
+        hex.add_action_simple(InsertAction::new(11, b"!".to_vec()))
+            .unwrap();
+        assert_eq!(hex.read_amount_at(0, 32).unwrap(), b"012abc34567!");
+        let (_, err) = hex
+            .add_action_simple(InsertAction::new(13, b"x".to_vec()))
+            .unwrap_err();
+        assert!(matches!(err, ActionError::Invalid));
+
+        hex.undo_simple().unwrap();
+        hex.undo_simple().unwrap();
+        assert_eq!(hex.reader().get_ref(), b"01234567");
+        hex.redo_simple().unwrap();
+        assert_eq!(hex.reader().get_ref(), b"012abc34567");
+    }
+
+    #[test]
+    fn test_append() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"0123".to_vec())).unwrap();
+        hex.append(b"abc".to_vec(), ()).unwrap();
+        hex.append(Vec::new(), ()).unwrap();
+        hex.append(b"!".to_vec(), ()).unwrap();
+        assert_eq!(hex.actions.len(), 2);
+        assert_eq!(hex.reader().get_ref(), b"0123abc!");
+
+        hex.undo_simple().unwrap();
+        assert_eq!(hex.reader().get_ref(), b"0123abc");
+        hex.undo_simple().unwrap();
+        assert_eq!(hex.reader().get_ref(), b"0123");
+        hex.redo_simple().unwrap();
+        assert_eq!(hex.reader().get_ref(), b"0123abc");
+    }
+
+    #[test]
+    fn test_edit_at_end() {
+        let mut hex = Hiex::from_reader(Cursor::new(b"0123".to_vec())).unwrap();
+        // Ending exactly at the end is fine, and doesn't grow the data.
+        hex.add_action(EditAction::new(2, b"ab".to_vec()), ())
+            .unwrap();
+        hex.add_action(EditAction::new(0, b"wxyz".to_vec()), ())
+            .unwrap();
+        assert_eq!(hex.length().unwrap(), 4);
+        assert_eq!(hex.read_amount_at(0, 4).unwrap(), b"wxyz");
+        let (_, err) = hex
+            .add_action(EditAction::new(3, b"ab".to_vec()), ())
+            .unwrap_err();
+        assert!(matches!(err, ActionError::Invalid));
+        let (_, err) = hex
+            .add_action(EditAction::new(4, b"a".to_vec()), ())
+            .unwrap_err();
+        assert!(matches!(err, ActionError::Invalid));
+        assert_eq!(hex.into_inner().into_inner(), b"wxyz");
+    }
+
+    #[test]
+    fn test_grow_on_edit() {
+        let mut hex = Hiex::from_reader(Cursor::new(b"0123".to_vec())).unwrap();
+        let (_, err) = hex
+            .add_action(EditAction::new(104, b"xy".to_vec()), ())
+            .unwrap_err();
+        assert!(matches!(err, ActionError::Invalid));
+
+        hex.set_growth_policy(GrowthPolicy::GrowOnEdit { fill: 0xee });
+        assert_eq!(hex.growth_policy(), GrowthPolicy::GrowOnEdit { fill: 0xee });
+        // 100 bytes past the end, so there is a gap to fill.
+        hex.add_action(EditAction::new(104, b"xy".to_vec()), ())
+            .unwrap();
+        let mut expected = b"0123".to_vec();
+        expected.extend_from_slice(&[0xee; 100]);
+        expected.extend_from_slice(b"xy");
+        assert_eq!(contents(&mut hex), expected);
+        assert_eq!(hex.actions.len(), 1);
+
+        // Edits within the data are unaffected, and ones partly past the end only grow.
+        hex.add_action(EditAction::new(0, b"a".to_vec()), ())
+            .unwrap();
+        hex.add_action(EditAction::new(105, b"zzz".to_vec()), ())
+            .unwrap();
+        assert_eq!(hex.length().unwrap(), 108);
+        assert_eq!(&contents(&mut hex)[104..], b"xzzz");
+
+        // Undoing restores the length exactly.
+        hex.undo(()).unwrap();
+        assert_eq!(hex.length().unwrap(), 106);
+        hex.undo(()).unwrap();
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), b"0123");
+        hex.redo(()).unwrap();
+        assert_eq!(contents(&mut hex), expected);
+
+        hex.set_growth_policy(GrowthPolicy::Deny);
+        assert!(hex
+            .add_action(EditAction::new(200, b"x".to_vec()), ())
+            .is_err());
+    }
+
+    #[test]
+    fn test_last_affected_range() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"01234567".to_vec())).unwrap();
+        assert_eq!(hex.last_affected_range(), None);
+        hex.add_action_simple(EditAction::new(2, b"a".to_vec()))
+            .unwrap();
+        assert_eq!(hex.last_affected_range(), Some(2..3));
+        // Only the merged byte is reported.
+        hex.add_action_simple(EditAction::new(3, b"b".to_vec()))
+            .unwrap();
+        assert_eq!(hex.last_affected_range(), Some(3..4));
+        hex.add_action_simple(InsertAction::new(1, b"!".to_vec()))
+            .unwrap();
+        assert_eq!(hex.last_affected_range(), Some(1..u64::MAX));
+
+        hex.undo_simple().unwrap();
+        assert_eq!(hex.last_affected_range(), Some(1..u64::MAX));
+        hex.undo_simple().unwrap();
+        assert_eq!(hex.last_affected_range(), Some(2..4));
+        hex.redo_simple().unwrap();
+        assert_eq!(hex.last_affected_range(), Some(2..4));
+        // A failed action leaves it alone.
+        assert!(hex
+            .add_action_simple(EditAction::new(8, b"x".to_vec()))
+            .is_err());
+        assert_eq!(hex.last_affected_range(), Some(2..4));
+    }
+
+    #[test]
+    fn test_undo_redo_all() {
+        let mut hex = Hiex::from_reader(Cursor::new(b"0000".to_vec())).unwrap();
+        assert_eq!(hex.undo_all(()).unwrap(), 0);
+        hex.add_action(EditAction::new(0, b"ab".to_vec()), ())
+            .unwrap();
+        hex.add_action(EditAction::new(2, b"cd".to_vec()), ())
+            .unwrap();
+        assert_eq!(hex.undo_all(()).unwrap(), 2);
+        assert_eq!(hex.read_amount_at(0, 4).unwrap(), b"0000");
+        assert_eq!(hex.undo_all(()).unwrap(), 0);
+
+        // Fails halfway on the locked second edit.
+        let lock = hex.lock_region(2..3);
+        let err = hex.redo_all(()).unwrap_err();
+        assert_eq!((err.steps, err.reached, err.error.index), (1, 1, 1));
+        assert_eq!(hex.read_amount_at(0, 4).unwrap(), b"ab00");
+        hex.unlock_region(lock);
+        assert_eq!(hex.redo_all(()).unwrap(), 1);
+        assert_eq!(hex.read_amount_at(0, 4).unwrap(), b"abcd");
+    }
+
+    /// Offsets past 4 GiB can't be held in memory on 32-bit targets.
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_32_bit_limits() {
+        let mut cursor = Cursor::new(Vec::new());
+        let err = cursor.truncate(1 << 32).unwrap_err();
+        assert!(err.get_ref().unwrap().is::<TooLargeForPlatform>());
+
+        use crate::positioned_io::PositionedIo;
+        let err = cursor.write_at(1 << 32, b"a").unwrap().unwrap_err();
+        assert!(err.get_ref().unwrap().is::<TooLargeForPlatform>());
+    }
+}
@@ -0,0 +1,5 @@
+/// Used to measure how much memory something uses.
+pub trait MemoryUsage {
+    /// About how much memory this structure uses.
+    fn memory_usage(&self) -> usize;
+}
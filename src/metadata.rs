@@ -0,0 +1,288 @@
+//! Saving the annotations on the data (bookmarks, tags, and savepoints) to a separate file, so that
+//! they can be loaded again later even though the history isn't.
+//!
+//! The format is JSON, with a version number which is only increased for changes that older
+//! versions can't read. Unknown fields are ignored, so that fields can be added without that.
+use crate::{
+    bookmark::BookmarkId,
+    region::{TagId, TagInfo},
+    savepoint::{Fingerprint, Savepoint},
+    Hiex,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::{ErrorKind, Read, Seek, Write},
+};
+
+/// The newest version of the format which can be read.
+pub const METADATA_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct MetadataFile {
+    version: u32,
+    #[serde(default)]
+    fingerprint: Option<FingerprintEntry>,
+    #[serde(default)]
+    bookmarks: Vec<BookmarkEntry>,
+    #[serde(default)]
+    tags: Vec<TagEntry>,
+    #[serde(default)]
+    savepoints: Vec<SavepointEntry>,
+    #[serde(default)]
+    last_saved: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone)]
+struct FingerprintEntry {
+    length: u64,
+    crc32: u32,
+}
+impl From<Fingerprint> for FingerprintEntry {
+    fn from(fingerprint: Fingerprint) -> Self {
+        Self {
+            length: fingerprint.length,
+            crc32: fingerprint.crc32,
+        }
+    }
+}
+impl From<FingerprintEntry> for Fingerprint {
+    fn from(entry: FingerprintEntry) -> Self {
+        Self {
+            length: entry.length,
+            crc32: entry.crc32,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct BookmarkEntry {
+    name: String,
+    offset: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TagEntry {
+    start: u64,
+    end: u64,
+    name: String,
+    #[serde(default)]
+    color: Option<u32>,
+    #[serde(default)]
+    metadata: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavepointEntry {
+    name: String,
+    index: usize,
+    fingerprint: FingerprintEntry,
+}
+
+/// What was loaded by [`Hiex::load_metadata`], and which of it may no longer be accurate.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct MetadataReport {
+    /// Whether the data is the same as when the metadata was saved. If it isn't, every bookmark
+    /// and tag is suspect, since the bytes they point at may have moved.
+    pub fingerprint_matches: bool,
+    pub bookmarks: Vec<BookmarkId>,
+    pub tags: Vec<TagId>,
+    pub suspect_bookmarks: Vec<BookmarkId>,
+    pub suspect_tags: Vec<TagId>,
+    /// The names of the savepoints which are suspect. As well as when the data has changed, a
+    /// savepoint is suspect when its index is past the end of the current history.
+    pub suspect_savepoints: Vec<String>,
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Writes the bookmarks, tags, savepoints, and last saved marker to `writer`, along with a
+    /// fingerprint of the data so that loading them can tell whether the data has changed since.
+    /// This reads all of the data to fingerprint it.
+    pub fn save_metadata<W>(&mut self, writer: W) -> std::io::Result<()>
+    where
+        W: Write,
+    {
+        let file = MetadataFile {
+            version: METADATA_VERSION,
+            fingerprint: Some(self.fingerprint()?.into()),
+            bookmarks: self
+                .bookmarks()
+                .map(|bookmark| BookmarkEntry {
+                    name: bookmark.name.clone(),
+                    offset: bookmark.offset,
+                })
+                .collect(),
+            tags: self
+                .tags()
+                .iter()
+                .map(|tag| TagEntry {
+                    start: tag.range.start,
+                    end: tag.range.end,
+                    name: tag.info.name.clone(),
+                    color: tag.info.color,
+                    metadata: tag.info.metadata.clone(),
+                })
+                .collect(),
+            savepoints: self
+                .savepoints()
+                .map(|savepoint| SavepointEntry {
+                    name: savepoint.name.clone(),
+                    index: savepoint.index,
+                    fingerprint: savepoint.fingerprint.into(),
+                })
+                .collect(),
+            last_saved: self.last_saved(),
+        };
+        serde_json::to_writer_pretty(writer, &file)?;
+        Ok(())
+    }
+
+    /// Adds the bookmarks, tags, and savepoints written by [`Hiex::save_metadata`], and restores
+    /// the last saved marker.
+    /// Metadata for data which has since changed is still loaded, but reported as suspect.
+    /// Fails with `ErrorKind::InvalidData` if it isn't valid metadata, or is from a newer version
+    /// of the format, in which case nothing is added.
+    pub fn load_metadata<R>(&mut self, reader: R) -> std::io::Result<MetadataReport>
+    where
+        R: Read,
+    {
+        let file: MetadataFile = serde_json::from_reader(reader)?;
+        if file.version > METADATA_VERSION {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "metadata is from a newer version",
+            ));
+        }
+
+        let fingerprint_matches = match file.fingerprint {
+            Some(fingerprint) => Fingerprint::from(fingerprint) == self.fingerprint()?,
+            None => false,
+        };
+        let mut report = MetadataReport {
+            fingerprint_matches,
+            ..MetadataReport::default()
+        };
+
+        for entry in file.bookmarks {
+            report
+                .bookmarks
+                .push(self.add_bookmark(entry.name, entry.offset));
+        }
+        for entry in file.tags {
+            let info = TagInfo {
+                name: entry.name,
+                color: entry.color,
+                metadata: entry.metadata,
+            };
+            let end = entry.end.max(entry.start);
+            report
+                .tags
+                .push(self.tags_mut().add_tag(entry.start..end, info));
+        }
+        if !fingerprint_matches {
+            report.suspect_bookmarks = report.bookmarks.clone();
+            report.suspect_tags = report.tags.clone();
+        }
+
+        let history_length = self.actions.len();
+        for entry in file.savepoints {
+            if !fingerprint_matches || entry.index > history_length {
+                report.suspect_savepoints.push(entry.name.clone());
+            }
+            self.savepoints
+                .retain(|existing| existing.name != entry.name);
+            self.savepoints.push(Savepoint {
+                name: entry.name,
+                index: entry.index,
+                fingerprint: entry.fingerprint.into(),
+            });
+        }
+        self.last_saved = file.last_saved;
+        // The loaded savepoints may refer to the latest action.
+        self.actions.seal();
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{region::TagInfo, EditAction, Hiex};
+    use std::io::{Cursor, ErrorKind};
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"0123456789".to_vec())).unwrap();
+        hex.add_bookmark("start", 0);
+        hex.add_bookmark("middle", 5);
+        hex.tags_mut().add_tag(
+            2..6,
+            TagInfo::new("header")
+                .with_color(0xff0000)
+                .with_metadata("a", "b"),
+        );
+        hex.create_savepoint("clean").unwrap();
+        hex.save_to(&mut Cursor::new(Vec::new())).unwrap();
+        let mut saved = Vec::new();
+        hex.save_metadata(&mut saved).unwrap();
+
+        let mut loaded: Hiex<_> = Hiex::from_reader(Cursor::new(b"0123456789".to_vec())).unwrap();
+        let report = loaded.load_metadata(&saved[..]).unwrap();
+        assert!(report.fingerprint_matches);
+        assert_eq!(report.bookmarks.len(), 2);
+        assert!(report.suspect_bookmarks.is_empty());
+        assert!(report.suspect_tags.is_empty());
+        assert!(report.suspect_savepoints.is_empty());
+
+        let bookmarks: Vec<(&str, u64)> = loaded
+            .bookmarks()
+            .map(|bookmark| (&bookmark.name[..], bookmark.offset))
+            .collect();
+        assert_eq!(bookmarks, [("start", 0), ("middle", 5)]);
+        let tag = loaded.tags().get(report.tags[0]).unwrap();
+        assert_eq!(tag.range, 2..6);
+        assert_eq!(
+            tag.info,
+            TagInfo::new("header")
+                .with_color(0xff0000)
+                .with_metadata("a", "b")
+        );
+        assert_eq!(loaded.savepoint("clean"), hex.savepoint("clean"));
+        assert_eq!(loaded.last_saved(), Some(0));
+    }
+
+    #[test]
+    fn test_metadata_mismatch() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"0123456789".to_vec())).unwrap();
+        let bookmark = hex.add_bookmark("mark", 3);
+        hex.add_action_simple(EditAction::new(0, b"a".to_vec()))
+            .unwrap();
+        hex.create_savepoint("edited").unwrap();
+        let mut saved = Vec::new();
+        hex.save_metadata(&mut saved).unwrap();
+
+        // Loaded onto the data as it was before the edit, with no history.
+        let mut loaded: Hiex<_> = Hiex::from_reader(Cursor::new(b"0123456789".to_vec())).unwrap();
+        let report = loaded.load_metadata(&saved[..]).unwrap();
+        assert!(!report.fingerprint_matches);
+        assert_eq!(report.suspect_bookmarks, report.bookmarks);
+        assert_eq!(report.suspect_savepoints, ["edited"]);
+        assert_eq!(
+            loaded.bookmark(report.bookmarks[0]).unwrap().offset,
+            hex.bookmark(bookmark).unwrap().offset
+        );
+
+        // Fields from newer versions are ignored, but newer versions aren't read.
+        let mut loaded: Hiex<_> = Hiex::from_reader(Cursor::new(b"0123".to_vec())).unwrap();
+        let future =
+            br#"{"version": 1, "bookmarks": [{"name": "x", "offset": 1, "kind": 2}], "extra": []}"#;
+        let report = loaded.load_metadata(&future[..]).unwrap();
+        assert_eq!(report.bookmarks.len(), 1);
+        let err = loaded.load_metadata(&br#"{"version": 2}"#[..]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(loaded.load_metadata(&b"not json"[..]).is_err());
+    }
+}
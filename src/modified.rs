@@ -0,0 +1,148 @@
+//! Which bytes differ from when the data was last saved, for highlighting them.
+use crate::{stream_len, Hiex};
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Whether each byte within `range` (clamped to the data) may differ from when the data was
+    /// opened or last saved, from the ranges affected by the actions applied or undone since then.
+    /// Only the history is consulted, so this is cheap for a small window. It errs towards marking
+    /// bytes: an action which changes the bytes and then one which restores them leaves them
+    /// marked, an insertion or deletion marks everything after it, and once the saved point has
+    /// been discarded from the history (see [`Hiex::is_dirty`]) every byte affected by an applied
+    /// action is marked. See [`Hiex::modified_mask_against`] for an exact mask.
+    pub fn modified_mask(&mut self, range: Range<u64>) -> std::io::Result<Vec<bool>> {
+        self.settle_background_save();
+        let range = range.start..range.end.min(self.length()?);
+        let mut mask = vec![false; crate::checked_usize(range.end.saturating_sub(range.start))?];
+        if mask.is_empty() {
+            return Ok(mask);
+        }
+
+        let current = self.actions.past_len();
+        let clean = self.clean_index.unwrap_or(0);
+        for index in clean.min(current)..clean.max(current) {
+            let affected = self
+                .actions
+                .get(index)
+                .and_then(|action| action.affected_range())
+                .unwrap_or(0..u64::MAX);
+            let start = affected.start.max(range.start);
+            let end = affected.end.min(range.end);
+            if start < end {
+                let start = crate::clamp_usize(start - range.start, mask.len());
+                let end = crate::clamp_usize(end - range.start, mask.len());
+                mask[start..end].iter_mut().for_each(|byte| *byte = true);
+            }
+        }
+        Ok(mask)
+    }
+
+    /// Like [`Hiex::modified_mask`], but the marked bytes are compared against `saved`, which
+    /// should hold the data as it was when last saved, so that only bytes which really differ are
+    /// marked. Bytes past the end of `saved` differ. If the saved point was discarded from the
+    /// history then every byte in the window is compared.
+    pub fn modified_mask_against<R>(
+        &mut self,
+        range: Range<u64>,
+        saved: &mut R,
+    ) -> std::io::Result<Vec<bool>>
+    where
+        R: Read + Seek,
+    {
+        let mut mask = self.modified_mask(range.clone())?;
+        if self.clean_index.is_none() {
+            mask.iter_mut().for_each(|byte| *byte = true);
+        }
+        let (first, last) = match (
+            mask.iter().position(|&byte| byte),
+            mask.iter().rposition(|&byte| byte),
+        ) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Ok(mask),
+        };
+
+        // Only the span of the marked bytes is read, from both.
+        let start = range.start + first as u64;
+        let current = self.read_amount_at(start, last + 1 - first)?;
+        let saved_length = stream_len(saved)?;
+        let mut previous =
+            vec![0; crate::clamp_usize(saved_length.saturating_sub(start), current.len())];
+        saved.seek(SeekFrom::Start(start))?;
+        saved.read_exact(&mut previous)?;
+
+        for (offset, byte) in mask[first..=last].iter_mut().enumerate() {
+            *byte &= previous.get(offset) != current.get(offset);
+        }
+        Ok(mask)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DeleteAction, EditAction, Hiex};
+    use std::io::Cursor;
+
+    fn marked(mask: &[bool]) -> Vec<usize> {
+        (0..mask.len()).filter(|&index| mask[index]).collect()
+    }
+
+    #[test]
+    fn test_modified_mask() {
+        let original: Vec<u8> = (0..64).collect();
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(original.clone())).unwrap();
+        assert_eq!(hex.modified_mask(16..32).unwrap(), [false; 16]);
+
+        hex.add_action_simple(EditAction::new(18, b"a".to_vec()))
+            .unwrap();
+        hex.add_action_simple(EditAction::new(29, b"b".to_vec()))
+            .unwrap();
+        // Outside of the window.
+        hex.add_action_simple(EditAction::new(40, b"c".to_vec()))
+            .unwrap();
+        assert_eq!(marked(&hex.modified_mask(16..32).unwrap()), [2, 13]);
+        assert_eq!(marked(&hex.modified_mask(36..100).unwrap()), [4]);
+
+        hex.undo_simple().unwrap();
+        hex.undo_simple().unwrap();
+        assert_eq!(marked(&hex.modified_mask(16..32).unwrap()), [2]);
+
+        // Saving makes everything unmodified, and undoing past the save marks the undone bytes.
+        hex.save_to(&mut Cursor::new(Vec::new())).unwrap();
+        assert!(marked(&hex.modified_mask(0..64).unwrap()).is_empty());
+        hex.undo_simple().unwrap();
+        assert_eq!(marked(&hex.modified_mask(16..32).unwrap()), [2]);
+        hex.redo_simple().unwrap();
+        assert!(marked(&hex.modified_mask(0..64).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_modified_mask_against() {
+        let original: Vec<u8> = (0..64).collect();
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(original.clone())).unwrap();
+        let mut saved = Cursor::new(original.clone());
+
+        // Changed and then changed back.
+        hex.add_action_simple(EditAction::new(18, b"ab".to_vec()))
+            .unwrap();
+        hex.add_action_simple(EditAction::new(18, vec![18]))
+            .unwrap();
+        assert_eq!(marked(&hex.modified_mask(16..32).unwrap()), [2, 3]);
+        assert_eq!(
+            marked(&hex.modified_mask_against(16..32, &mut saved).unwrap()),
+            [3]
+        );
+
+        // Everything after a deletion moves, and the saved data is longer.
+        hex.add_action_simple(DeleteAction::new(60..61)).unwrap();
+        assert_eq!(
+            marked(&hex.modified_mask_against(56..64, &mut saved).unwrap()),
+            [4, 5, 6]
+        );
+    }
+}
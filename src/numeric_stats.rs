@@ -0,0 +1,256 @@
+//! Statistics over a range interpreted as an array of integers, such as sensor samples.
+use crate::{clamp_usize, stream_len, Endian, Hiex, CHUNK_SIZE};
+use std::{
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+use usize_cast::FromUsize;
+
+/// The result of [`Hiex::numeric_stats`].
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct NumericStats {
+    pub count: u64,
+    /// `None` if there are no values.
+    pub min: Option<i128>,
+    pub max: Option<i128>,
+    /// The sum of the values, which can't overflow, as there are fewer than 2^64 values of at
+    /// most 64 bits.
+    pub sum: i128,
+}
+impl NumericStats {
+    /// The mean of the values, or `None` if there are none.
+    pub fn mean(&self) -> Option<f64> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.sum as f64 / self.count as f64)
+        }
+    }
+
+    fn add(&mut self, value: i128) {
+        self.count += 1;
+        self.sum += value;
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.max = Some(self.max.map_or(value, |max| max.max(value)));
+    }
+}
+
+/// The result of [`Hiex::numeric_histogram`]: the amount of values within each of a number of
+/// equally sized buckets spanning from the minimum to the maximum value.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct NumericHistogram {
+    pub stats: NumericStats,
+    /// Empty if there are no values.
+    pub counts: Vec<u64>,
+}
+impl NumericHistogram {
+    /// The values counted by the bucket at `index`, which is empty if the buckets outnumber the
+    /// distinct values they span.
+    pub fn bucket_range(&self, index: usize) -> Range<i128> {
+        let (min, max) = match (self.stats.min, self.stats.max) {
+            (Some(min), Some(max)) => (min, max),
+            _ => return 0..0,
+        };
+        let buckets = self.counts.len() as u128;
+        let span = (max - min) as u128 + 1;
+        // The first value in a bucket, the inverse of how values are placed in buckets.
+        let start = |index: u128| min + (index * span).div_ceil(buckets) as i128;
+        start(index as u128)..start(index as u128 + 1)
+    }
+}
+
+/// Calls `f` with each value of `width` bytes within `range`, reading at most `chunk_size` bytes
+/// at a time.
+fn for_each_value<S, T>(
+    stream: &mut S,
+    range: Range<u64>,
+    width: usize,
+    signed: bool,
+    endian: Endian,
+    chunk_size: usize,
+    mut f: T,
+) -> std::io::Result<()>
+where
+    S: Read + Seek,
+    T: FnMut(i128),
+{
+    if ![1, 2, 4, 8].contains(&width) {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            "numeric width must be 1, 2, 4, or 8 bytes",
+        ));
+    }
+    let end = range.end.min(stream_len(stream)?);
+    let length = end.saturating_sub(range.start);
+    if length % u64::from_usize(width) != 0 {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            "range length is not a multiple of the numeric width",
+        ));
+    }
+
+    let shift = 64 - width * 8;
+    // Whole values, so that none straddle two chunks.
+    let chunk_size = (chunk_size / width).max(1) * width;
+    let mut buffer = vec![0; clamp_usize(length, chunk_size)];
+    let mut position = range.start;
+    while position < end {
+        let chunk = &mut buffer[..clamp_usize(end - position, chunk_size)];
+        stream.seek(SeekFrom::Start(position))?;
+        stream.read_exact(chunk)?;
+        position += u64::from_usize(chunk.len());
+
+        for bytes in chunk.chunks_exact(width) {
+            let value = endian.read_uint(bytes);
+            f(if signed {
+                // Sign extend from the top bit of the value.
+                i128::from(((value << shift) as i64) >> shift)
+            } else {
+                i128::from(value)
+            });
+        }
+    }
+    Ok(())
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Statistics over the values of `width` bytes within `range` (clamped to the data), which
+    /// are read as `signed` or unsigned integers in `endian` order. The data is read in chunks,
+    /// and the position is restored afterwards.
+    /// Fails with `ErrorKind::InvalidInput` if `width` isn't 1, 2, 4, or 8, or the range's length
+    /// isn't a multiple of it.
+    pub fn numeric_stats(
+        &mut self,
+        range: Range<u64>,
+        width: usize,
+        signed: bool,
+        endian: Endian,
+    ) -> std::io::Result<NumericStats> {
+        let position = self.position()?;
+        let mut stats = NumericStats::default();
+        let result = for_each_value(self, range, width, signed, endian, CHUNK_SIZE, |value| {
+            stats.add(value)
+        });
+        self.seek(SeekFrom::Start(position))?;
+        result.map(|()| stats)
+    }
+
+    /// Like [`Hiex::numeric_stats`], but also counts the values within each of `buckets` buckets
+    /// for plotting. This reads the range twice, once to find the span of the values.
+    /// Also fails with `ErrorKind::InvalidInput` if `buckets` is 0.
+    pub fn numeric_histogram(
+        &mut self,
+        range: Range<u64>,
+        width: usize,
+        signed: bool,
+        endian: Endian,
+        buckets: usize,
+    ) -> std::io::Result<NumericHistogram> {
+        if buckets == 0 {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "a histogram needs at least 1 bucket",
+            ));
+        }
+        let stats = self.numeric_stats(range.clone(), width, signed, endian)?;
+        let min = match stats.min {
+            Some(min) => min,
+            None => return Ok(NumericHistogram::default()),
+        };
+        let span = stats.max.map_or(0, |max| max - min) as u128 + 1;
+
+        let position = self.position()?;
+        let mut counts = vec![0; buckets];
+        let result = for_each_value(self, range, width, signed, endian, CHUNK_SIZE, |value| {
+            // Can't overflow, as both are at most 2^64.
+            let bucket = (value - min) as u128 * buckets as u128 / span;
+            counts[bucket as usize] += 1;
+        });
+        self.seek(SeekFrom::Start(position))?;
+        result.map(|()| NumericHistogram { stats, counts })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::for_each_value;
+    use crate::{Endian, Hiex};
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    fn samples() -> Vec<u8> {
+        [-300i16, 5, 1200, -1, 0, 7]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn test_numeric_stats() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(samples())).unwrap();
+        hex.seek(SeekFrom::Start(3)).unwrap();
+
+        let stats = hex.numeric_stats(0..12, 2, true, Endian::Little).unwrap();
+        assert_eq!(stats.count, 6);
+        assert_eq!(stats.min, Some(-300));
+        assert_eq!(stats.max, Some(1200));
+        assert_eq!(stats.sum, 911);
+        assert!((stats.mean().unwrap() - 911.0 / 6.0).abs() < 1e-9);
+        assert_eq!(hex.position().unwrap(), 3);
+
+        // Unsigned, and clamped to the data.
+        let stats = hex.numeric_stats(2..100, 2, false, Endian::Little).unwrap();
+        assert_eq!(stats.count, 5);
+        assert_eq!(stats.min, Some(0));
+        assert_eq!(stats.max, Some(0xffff));
+        assert_eq!(
+            hex.numeric_stats(12..12, 2, true, Endian::Little).unwrap(),
+            Default::default()
+        );
+
+        assert!(hex.numeric_stats(0..11, 2, true, Endian::Little).is_err());
+        assert!(hex.numeric_stats(0..12, 3, true, Endian::Little).is_err());
+        assert_eq!(hex.position().unwrap(), 3);
+
+        // Every chunk size gives the same values.
+        for chunk_size in 1..=13 {
+            let mut values = Vec::new();
+            let mut data = Cursor::new(samples());
+            for_each_value(
+                &mut data,
+                0..12,
+                2,
+                true,
+                Endian::Little,
+                chunk_size,
+                |value| values.push(value),
+            )
+            .unwrap();
+            assert_eq!(values, [-300, 5, 1200, -1, 0, 7]);
+        }
+    }
+
+    #[test]
+    fn test_numeric_histogram() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(samples())).unwrap();
+        // Buckets of about 300 values each, from -300.
+        let histogram = hex
+            .numeric_histogram(0..12, 2, true, Endian::Little, 5)
+            .unwrap();
+        assert_eq!(histogram.counts, [3, 2, 0, 0, 1]);
+        assert_eq!(histogram.bucket_range(0), -300..1);
+        assert_eq!(histogram.bucket_range(1), 1..301);
+        assert_eq!(histogram.bucket_range(4), 901..1201);
+
+        let histogram = hex
+            .numeric_histogram(0..12, 1, false, Endian::Big, 256)
+            .unwrap();
+        assert_eq!(histogram.counts.iter().sum::<u64>(), 12);
+        assert_eq!(histogram.bucket_range(255), 255..256);
+        assert!(hex
+            .numeric_histogram(0..12, 2, true, Endian::Little, 0)
+            .is_err());
+    }
+}
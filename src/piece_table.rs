@@ -0,0 +1,416 @@
+//! A backend which records edits as a list of pieces, leaving the original data untouched.
+use crate::{
+    action::MemoryUsage, checked_usize, clamp_usize, known_length::KnownLength,
+    positioned_io::PositionedIo, stream_len, truncate::Truncate,
+};
+use std::{
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+use usize_cast::FromUsize;
+
+/// Where the bytes of a piece come from.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum PieceSource {
+    /// The original, read-only, data.
+    Original,
+    /// The buffer of bytes added by edits.
+    Added,
+}
+
+/// A run of bytes within one of the sources.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Piece {
+    source: PieceSource,
+    start: u64,
+    length: u64,
+}
+
+/// Data represented as an ordered list of pieces, each of which refers to either a range of the
+/// original read-only source or a range of a buffer of added bytes.
+/// Inserting and removing bytes only modifies the list of pieces, so no data is moved and the
+/// source is never written to. Overwriting bytes of the source replaces them with a piece of
+/// added bytes.
+/// It implements `Read + Write + Seek + Truncate` over the resulting data, so it can be given to
+/// [`Hiex::from_reader`](crate::Hiex::from_reader), and reading it (such as with
+/// [`Hiex::save_to`](crate::Hiex::save_to)) streams each piece from where it lives.
+#[derive(Debug)]
+pub struct PieceTable<R: Read + Seek> {
+    source: R,
+    /// Bytes added by edits. Each byte is referred to by at most one piece.
+    added: Vec<u8>,
+    pieces: Vec<Piece>,
+    length: u64,
+    position: u64,
+}
+impl<R> PieceTable<R>
+where
+    R: Read + Seek,
+{
+    pub fn new(mut source: R) -> std::io::Result<Self> {
+        let length = stream_len(&mut source)?;
+        let pieces = if length == 0 {
+            Vec::new()
+        } else {
+            vec![Piece {
+                source: PieceSource::Original,
+                start: 0,
+                length,
+            }]
+        };
+        Ok(Self {
+            source,
+            added: Vec::new(),
+            pieces,
+            length,
+            position: 0,
+        })
+    }
+
+    /// The length of the data.
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// The amount of pieces the data is made up of.
+    pub fn piece_count(&self) -> usize {
+        self.pieces.len()
+    }
+
+    /// Gets the original source back, discarding the edits.
+    pub fn into_source(self) -> R {
+        self.source
+    }
+
+    /// Copies the data to `writer`, streaming each piece from its source.
+    /// Returns the amount of bytes written.
+    pub fn write_to<W>(&mut self, writer: &mut W) -> std::io::Result<u64>
+    where
+        W: Write,
+    {
+        for piece in &self.pieces {
+            match piece.source {
+                PieceSource::Original => {
+                    self.source.seek(SeekFrom::Start(piece.start))?;
+                    let copied = std::io::copy(
+                        &mut Read::by_ref(&mut self.source).take(piece.length),
+                        writer,
+                    )?;
+                    if copied != piece.length {
+                        return Err(ErrorKind::UnexpectedEof.into());
+                    }
+                }
+                PieceSource::Added => {
+                    let start = clamp_usize(piece.start, self.added.len());
+                    let length = clamp_usize(piece.length, self.added.len() - start);
+                    writer.write_all(&self.added[start..start + length])?;
+                }
+            }
+        }
+        Ok(self.length)
+    }
+
+    /// The index of the piece containing `offset`, and the offset within it.
+    /// `offset` must be less than the length.
+    fn find(&self, offset: u64) -> (usize, u64) {
+        let mut start = 0;
+        for (index, piece) in self.pieces.iter().enumerate() {
+            if offset < start + piece.length {
+                return (index, offset - start);
+            }
+            start += piece.length;
+        }
+        unreachable!("offset {} is past the end of the pieces", offset)
+    }
+
+    /// Split the pieces so that one starts at `offset`, returning its index.
+    /// If `offset` is the length, then this is the amount of pieces.
+    fn split_at(&mut self, offset: u64) -> usize {
+        if offset >= self.length {
+            return self.pieces.len();
+        }
+        let (index, within) = self.find(offset);
+        if within == 0 {
+            return index;
+        }
+        let piece = self.pieces[index];
+        self.pieces[index].length = within;
+        self.pieces.insert(
+            index + 1,
+            Piece {
+                source: piece.source,
+                start: piece.start + within,
+                length: piece.length - within,
+            },
+        );
+        index + 1
+    }
+
+    /// Insert `bytes` at `offset`, as a new piece of added bytes.
+    fn insert(&mut self, offset: u64, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        let piece = Piece {
+            source: PieceSource::Added,
+            start: u64::from_usize(self.added.len()),
+            length: u64::from_usize(bytes.len()),
+        };
+        self.added.extend_from_slice(bytes);
+
+        let index = self.split_at(offset);
+        match index
+            .checked_sub(1)
+            .map(|previous| &mut self.pieces[previous])
+        {
+            // Extend the previous piece if it ends where these bytes were added, which is common
+            // when bytes are inserted one after another.
+            Some(previous)
+                if previous.source == PieceSource::Added
+                    && previous.start + previous.length == piece.start =>
+            {
+                previous.length += piece.length;
+            }
+            _ => self.pieces.insert(index, piece),
+        }
+        self.length += piece.length;
+    }
+
+    /// Remove the bytes within `range`, which must be within the data.
+    fn remove(&mut self, range: Range<u64>) {
+        if range.start >= range.end {
+            return;
+        }
+        let start = self.split_at(range.start);
+        let end = self.split_at(range.end);
+        self.pieces.drain(start..end);
+        self.length -= range.end - range.start;
+    }
+
+    /// Overwrite the bytes starting at `offset` with `bytes`, which must be within the data.
+    fn overwrite(&mut self, offset: u64, bytes: &[u8]) {
+        let mut written = 0;
+        while written < bytes.len() {
+            let position = offset + u64::from_usize(written);
+            let (index, within) = self.find(position);
+            let piece = self.pieces[index];
+            let amount = clamp_usize(piece.length - within, bytes.len() - written);
+            let chunk = &bytes[written..written + amount];
+            match piece.source {
+                PieceSource::Added => {
+                    // Added bytes aren't shared between pieces, so they can be modified in place.
+                    let start = clamp_usize(piece.start + within, self.added.len());
+                    self.added[start..start + amount].copy_from_slice(chunk);
+                }
+                PieceSource::Original => {
+                    self.remove(position..position + u64::from_usize(amount));
+                    self.insert(position, chunk);
+                }
+            }
+            written += amount;
+        }
+    }
+}
+impl<R> Read for PieceTable<R>
+where
+    R: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position >= self.length || buf.is_empty() {
+            return Ok(0);
+        }
+        // Only read from a single piece, `read_exact` and friends will call again for the rest.
+        let (index, within) = self.find(self.position);
+        let piece = self.pieces[index];
+        let amount = clamp_usize(piece.length - within, buf.len());
+        let amount = match piece.source {
+            PieceSource::Original => {
+                self.source.seek(SeekFrom::Start(piece.start + within))?;
+                self.source.read(&mut buf[..amount])?
+            }
+            PieceSource::Added => {
+                let start = clamp_usize(piece.start + within, self.added.len());
+                buf[..amount].copy_from_slice(&self.added[start..start + amount]);
+                amount
+            }
+        };
+        self.position += u64::from_usize(amount);
+        Ok(amount)
+    }
+}
+impl<R> Write for PieceTable<R>
+where
+    R: Read + Seek,
+{
+    /// Overwrites bytes, extending the data when writing past the end.
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.position > self.length {
+            let gap = checked_usize(self.position - self.length)?;
+            self.insert(self.length, &vec![0; gap]);
+        }
+        let overlap = clamp_usize(self.length - self.position, buf.len());
+        self.overwrite(self.position, &buf[..overlap]);
+        self.insert(self.length, &buf[overlap..]);
+        self.position += u64::from_usize(buf.len());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+impl<R> Seek for PieceTable<R>
+where
+    R: Read + Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(position) => {
+                self.position = position;
+                return Ok(position);
+            }
+            SeekFrom::End(offset) => (self.length, offset),
+            SeekFrom::Current(offset) => (self.position, offset),
+        };
+        match base.checked_add_signed(offset) {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+impl<R> Truncate for PieceTable<R>
+where
+    R: Read + Seek,
+{
+    fn truncate(&mut self, new_len: u64) -> std::io::Result<()> {
+        if new_len < self.length {
+            self.remove(new_len..self.length);
+        } else {
+            let extra = checked_usize(new_len - self.length)?;
+            self.insert(self.length, &vec![0; extra]);
+        }
+        if self.position >= new_len {
+            self.position = new_len.saturating_sub(1);
+        }
+        Ok(())
+    }
+
+    fn insert_space(&mut self, position: u64, length: u64) -> std::io::Result<bool> {
+        if position > self.length {
+            return Err(ErrorKind::InvalidInput.into());
+        }
+        self.insert(position, &vec![0; checked_usize(length)?]);
+        Ok(true)
+    }
+
+    fn remove_space(&mut self, range: Range<u64>) -> std::io::Result<bool> {
+        if range.start > range.end || range.end > self.length {
+            return Err(ErrorKind::InvalidInput.into());
+        }
+        self.remove(range);
+        Ok(true)
+    }
+}
+impl<R> KnownLength for PieceTable<R>
+where
+    R: Read + Seek,
+{
+    fn known_length(&self) -> Option<u64> {
+        Some(self.length)
+    }
+}
+/// Reading goes through the source's cursor, so this falls back to seeking.
+impl<R> PositionedIo for PieceTable<R> where R: Read + Seek {}
+impl<R> MemoryUsage for PieceTable<R>
+where
+    R: Read + Seek,
+{
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.added.capacity()
+            + self.pieces.capacity() * std::mem::size_of::<Piece>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PieceTable;
+    use crate::{EditAction, Hiex};
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+    #[test]
+    fn test_piece_table_io() {
+        let mut table = PieceTable::new(Cursor::new(b"0123456789".to_vec())).unwrap();
+        table.seek(SeekFrom::Start(3)).unwrap();
+        table.write_all(b"ab").unwrap();
+        table.seek(SeekFrom::End(-1)).unwrap();
+        table.write_all(b"XYZ").unwrap();
+        assert_eq!(table.len(), 12);
+        assert_eq!(table.piece_count(), 4);
+
+        // Reads across all of the pieces, in a small buffer.
+        table.seek(SeekFrom::Start(1)).unwrap();
+        let mut contents = Vec::new();
+        let mut buf = [0; 3];
+        loop {
+            let amount = table.read(&mut buf).unwrap();
+            if amount == 0 {
+                break;
+            }
+            contents.extend_from_slice(&buf[..amount]);
+        }
+        assert_eq!(contents, b"12ab5678XYZ");
+
+        // Seeking relative to the end uses the edited length.
+        assert_eq!(table.seek(SeekFrom::End(-2)).unwrap(), 10);
+        let mut buf = [0; 2];
+        table.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"YZ");
+        assert!(table.seek(SeekFrom::End(-13)).is_err());
+
+        // Writing past the end fills with zeroes.
+        table.seek(SeekFrom::End(1)).unwrap();
+        table.write_all(b"!").unwrap();
+        let mut saved = Vec::new();
+        assert_eq!(table.write_to(&mut saved).unwrap(), 14);
+        assert_eq!(saved, b"012ab5678XYZ\0!");
+        // The source is never modified.
+        assert_eq!(table.into_source().into_inner(), b"0123456789");
+    }
+
+    #[test]
+    fn test_piece_table_matches_vec() {
+        let source = (0..=255u8).cycle().take(1000).collect::<Vec<_>>();
+        let mut table =
+            Hiex::from_reader(PieceTable::new(Cursor::new(source.clone())).unwrap()).unwrap();
+        let mut vec = Hiex::from_reader(Cursor::new(source.clone())).unwrap();
+
+        macro_rules! both {
+            ($($call:tt)*) => {
+                table.$($call)*.unwrap();
+                vec.$($call)*.unwrap();
+            };
+        }
+        both!(insert_bytes(10, b"inserted".to_vec(), ()));
+        both!(delete_bytes(500..700, ()));
+        both!(add_action(EditAction::new(5, b"edited".to_vec()), ()));
+        both!(insert_bytes(808, b"end".to_vec(), ()));
+        both!(delete_bytes(0..3, ()));
+        both!(undo(()));
+        both!(insert_bytes(0, b"start".to_vec(), ()));
+
+        let mut saved = Cursor::new(Vec::new());
+        table.save_to(&mut saved).unwrap();
+        assert_eq!(saved.into_inner(), vec.into_inner().into_inner());
+    }
+}
@@ -0,0 +1,257 @@
+//! Finding values in the data which could be offsets into it, for working out unknown formats.
+use crate::{clamp_usize, stream_len, Endian, Hiex, CHUNK_SIZE};
+use std::{
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+use usize_cast::FromUsize;
+
+/// The size of the values looked at by [`Hiex::scan_pointers`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PointerWidth {
+    U32,
+    U64,
+}
+impl PointerWidth {
+    /// The amount of bytes a value occupies.
+    pub fn width(self) -> usize {
+        match self {
+            PointerWidth::U32 => 4,
+            PointerWidth::U64 => 8,
+        }
+    }
+}
+
+/// The options for [`Hiex::scan_pointers`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PointerScan {
+    pub width: PointerWidth,
+    pub endian: Endian,
+    /// The values to report. `None` reports values which are offsets within the data.
+    pub target_range: Option<Range<u64>>,
+    /// Only values at offsets which are a multiple of this are looked at.
+    pub alignment: u64,
+    /// The most hits to return.
+    pub max_hits: usize,
+}
+impl PointerScan {
+    /// The default for [`PointerScan::max_hits`], since small values are plausible offsets in
+    /// most data.
+    pub const DEFAULT_MAX_HITS: usize = 10_000;
+
+    /// Values at every offset, without an alignment.
+    pub fn new(width: PointerWidth, endian: Endian) -> Self {
+        Self {
+            width,
+            endian,
+            target_range: None,
+            alignment: 1,
+            max_hits: Self::DEFAULT_MAX_HITS,
+        }
+    }
+
+    pub fn with_target_range(mut self, target_range: Range<u64>) -> Self {
+        self.target_range = Some(target_range);
+        self
+    }
+
+    pub fn with_alignment(mut self, alignment: u64) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    pub fn with_max_hits(mut self, max_hits: usize) -> Self {
+        self.max_hits = max_hits;
+        self
+    }
+}
+
+/// A value which could be an offset, see [`Hiex::scan_pointers`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PointerHit {
+    /// The offset of the value in the data.
+    pub offset: u64,
+    pub value: u64,
+}
+
+/// The result of [`Hiex::scan_pointers`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PointerHits {
+    pub hits: Vec<PointerHit>,
+    /// Whether the scan stopped early after finding [`PointerScan::max_hits`] hits.
+    pub truncated: bool,
+}
+
+/// Scans the values within `range` for ones within the target range, reading at most
+/// `chunk_size` new bytes at a time.
+pub(crate) fn scan_pointers_in<S>(
+    stream: &mut S,
+    range: Range<u64>,
+    scan: &PointerScan,
+    chunk_size: usize,
+) -> std::io::Result<PointerHits>
+where
+    S: Read + Seek,
+{
+    if scan.alignment == 0 {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            "pointer alignment must be at least 1",
+        ));
+    }
+
+    let length = stream_len(stream)?;
+    let target = scan.target_range.clone().unwrap_or(0..length);
+    let width = scan.width.width();
+    let end = range.end.min(length);
+    let mut result = PointerHits {
+        hits: Vec::new(),
+        truncated: false,
+    };
+
+    let chunk_size = chunk_size.max(1);
+    // As with searching, the buffer keeps the last `width - 1` bytes of the previous chunk so
+    // that values which straddle two chunks are seen, and no value is seen twice.
+    let mut buffer = Vec::with_capacity(chunk_size + width - 1);
+    let mut buffer_start = range.start;
+    let mut position = range.start;
+    while position < end {
+        let amount = clamp_usize(end - position, chunk_size);
+        let kept = buffer.len();
+        buffer.resize(kept + amount, 0);
+        stream.seek(SeekFrom::Start(position))?;
+        stream.read_exact(&mut buffer[kept..])?;
+        position += u64::from_usize(amount);
+
+        if buffer.len() >= width {
+            let misaligned = buffer_start % scan.alignment;
+            let first = if misaligned == 0 {
+                0
+            } else {
+                scan.alignment - misaligned
+            };
+            let last = u64::from_usize(buffer.len() - width);
+            let mut index = first;
+            while index <= last {
+                let at = clamp_usize(index, buffer.len());
+                let value = scan.endian.read_uint(&buffer[at..at + width]);
+                if target.contains(&value) {
+                    if result.hits.len() == scan.max_hits {
+                        result.truncated = true;
+                        return Ok(result);
+                    }
+                    result.hits.push(PointerHit {
+                        offset: buffer_start + index,
+                        value,
+                    });
+                }
+                index = match index.checked_add(scan.alignment) {
+                    Some(index) => index,
+                    None => break,
+                };
+            }
+        }
+
+        let keep = buffer.len().min(width - 1);
+        buffer.drain(..buffer.len() - keep);
+        buffer_start = position - u64::from_usize(keep);
+    }
+
+    Ok(result)
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Finds the values entirely within `range` which could be offsets, being within the data
+    /// or within [`PointerScan::target_range`]. The data is read in chunks, and at most
+    /// [`PointerScan::max_hits`] hits are kept. The position is restored afterwards.
+    /// Fails with `ErrorKind::InvalidInput` if the alignment is 0.
+    pub fn scan_pointers(
+        &mut self,
+        range: Range<u64>,
+        scan: &PointerScan,
+    ) -> std::io::Result<PointerHits> {
+        let position = self.position()?;
+        let result = scan_pointers_in(self, range, scan, CHUNK_SIZE);
+        self.seek(SeekFrom::Start(position))?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{scan_pointers_in, PointerHit, PointerScan, PointerWidth};
+    use crate::{Endian, Hiex};
+    use std::io::Cursor;
+
+    /// 64 bytes of `0xff`, which is never part of a valid offset, with planted values.
+    fn planted(endian: Endian) -> Vec<u8> {
+        let mut data = vec![0xff; 64];
+        endian.write_uint(40, &mut data[4..8]);
+        endian.write_uint(63, &mut data[13..17]);
+        // Out of range.
+        endian.write_uint(64, &mut data[20..24]);
+        endian.write_uint(0x1_0000, &mut data[28..32]);
+        data
+    }
+
+    fn offsets(data: &[u8], scan: &PointerScan, chunk_size: usize) -> Vec<(u64, u64)> {
+        let mut data = Cursor::new(data.to_vec());
+        scan_pointers_in(&mut data, 0..u64::MAX, scan, chunk_size)
+            .unwrap()
+            .hits
+            .into_iter()
+            .map(|hit| (hit.offset, hit.value))
+            .collect()
+    }
+
+    #[test]
+    fn test_scan_pointers() {
+        for &endian in &[Endian::Little, Endian::Big] {
+            let data = planted(endian);
+            let scan = PointerScan::new(PointerWidth::U32, endian);
+            // Every chunk size, so that the values straddle chunk boundaries in every way.
+            for chunk_size in 1..=65 {
+                assert_eq!(
+                    offsets(&data, &scan, chunk_size),
+                    vec![(4, 40), (13, 63)],
+                    "{:?} {}",
+                    endian,
+                    chunk_size
+                );
+                let aligned = scan.clone().with_alignment(4);
+                assert_eq!(offsets(&data, &aligned, chunk_size), vec![(4, 40)]);
+                let targeted = scan.clone().with_target_range(60..0xff);
+                assert_eq!(
+                    offsets(&data, &targeted, chunk_size),
+                    vec![(13, 63), (20, 64)]
+                );
+            }
+
+            let mut data = vec![0xff; 24];
+            endian.write_uint(8, &mut data[8..16]);
+            let scan = PointerScan::new(PointerWidth::U64, endian);
+            assert_eq!(offsets(&data, &scan, 5), vec![(8, 8)]);
+            assert_eq!(offsets(&data, &scan.with_alignment(3), 5), vec![]);
+        }
+
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(planted(Endian::Little))).unwrap();
+        let scan = PointerScan::new(PointerWidth::U32, Endian::Little).with_max_hits(1);
+        let result = hex.scan_pointers(0..64, &scan).unwrap();
+        assert!(result.truncated);
+        assert_eq!(
+            result.hits,
+            [PointerHit {
+                offset: 4,
+                value: 40
+            }]
+        );
+        let result = hex.scan_pointers(4..16, &scan.with_max_hits(2)).unwrap();
+        assert!(!result.truncated);
+        assert_eq!(result.hits.len(), 1);
+        let misaligned = PointerScan::new(PointerWidth::U32, Endian::Big).with_alignment(0);
+        assert!(hex.scan_pointers(0..64, &misaligned).is_err());
+    }
+}
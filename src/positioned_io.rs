@@ -0,0 +1,505 @@
+use crate::{
+    checked_usize, clamp_usize, constrained_wrapper::apply_offset, known_length::KnownLength,
+    stream_len, truncate::Truncate,
+};
+use std::{
+    fs::File,
+    io::{Cursor, Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+use usize_cast::FromUsize;
+
+/// A trait for objects which can read and write at an offset without seeking first.
+/// Seeking and then reading is two calls (and two syscalls for a file), and it moves the shared
+/// position, which then has to be restored. Reading at an offset does neither.
+/// Backends which can't do this should use the default implementations, which return `None` and
+/// make users of this trait fall back to seeking.
+/// Nothing requires it, so it is used where asked for: by [`Hiex::with_positioned_io`] for reads,
+/// and by [`PositionedWrapper`] for everything, including applying actions.
+///
+/// [`Hiex::with_positioned_io`]: crate::Hiex::with_positioned_io
+pub trait PositionedIo {
+    /// Reads into `buf` from `offset`, returning how many bytes were read like `Read::read`.
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> Option<std::io::Result<usize>> {
+        None
+    }
+
+    /// Writes `buf` at `offset`, returning how many bytes were written like `Write::write`.
+    fn write_at(&mut self, _offset: u64, _buf: &[u8]) -> Option<std::io::Result<usize>> {
+        None
+    }
+}
+
+/// Reads at `offset` from `data`, as `Read for Cursor` would.
+fn read_slice_at(data: &[u8], offset: u64, buf: &mut [u8]) -> usize {
+    let start = clamp_usize(offset, data.len());
+    let amount = buf.len().min(data.len() - start);
+    buf[..amount].copy_from_slice(&data[start..start + amount]);
+    amount
+}
+
+impl PositionedIo for Cursor<&[u8]> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Option<std::io::Result<usize>> {
+        Some(Ok(read_slice_at(self.get_ref(), offset, buf)))
+    }
+}
+
+impl PositionedIo for Cursor<&mut [u8]> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Option<std::io::Result<usize>> {
+        Some(Ok(read_slice_at(self.get_ref(), offset, buf)))
+    }
+
+    /// Writes up to the end of the slice, as `Write for Cursor<&mut [u8]>` does.
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Option<std::io::Result<usize>> {
+        let data = self.get_mut();
+        let start = clamp_usize(offset, data.len());
+        let amount = buf.len().min(data.len() - start);
+        data[start..start + amount].copy_from_slice(&buf[..amount]);
+        Some(Ok(amount))
+    }
+}
+
+/// Writes all of `buf` into `data` at `offset`, extending it with zeroes as needed, as
+/// `Write for Cursor<Vec<u8>>` does.
+fn write_vec_at(data: &mut Vec<u8>, offset: u64, buf: &[u8]) -> std::io::Result<usize> {
+    let end = checked_usize(offset.saturating_add(u64::from_usize(buf.len())))?;
+    let start = end - buf.len();
+    if end > data.len() {
+        data.resize(end, 0);
+    }
+    data[start..end].copy_from_slice(buf);
+    Ok(buf.len())
+}
+
+impl PositionedIo for Cursor<Vec<u8>> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Option<std::io::Result<usize>> {
+        Some(Ok(read_slice_at(self.get_ref(), offset, buf)))
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Option<std::io::Result<usize>> {
+        Some(write_vec_at(self.get_mut(), offset, buf))
+    }
+}
+
+impl PositionedIo for Cursor<&mut Vec<u8>> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Option<std::io::Result<usize>> {
+        Some(Ok(read_slice_at(self.get_ref(), offset, buf)))
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Option<std::io::Result<usize>> {
+        Some(write_vec_at(self.get_mut(), offset, buf))
+    }
+}
+
+/// Reads and writes with `pread` and `pwrite`, which leave the file's position alone.
+#[cfg(unix)]
+impl PositionedIo for File {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Option<std::io::Result<usize>> {
+        Some(std::os::unix::fs::FileExt::read_at(self, buf, offset))
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Option<std::io::Result<usize>> {
+        Some(std::os::unix::fs::FileExt::write_at(self, buf, offset))
+    }
+}
+
+/// Reads and writes with `seek_read` and `seek_write`, which are a single call but do move the
+/// file's position to the end of the bytes read or written.
+#[cfg(windows)]
+impl PositionedIo for File {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Option<std::io::Result<usize>> {
+        Some(std::os::windows::fs::FileExt::seek_read(self, buf, offset))
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Option<std::io::Result<usize>> {
+        Some(std::os::windows::fs::FileExt::seek_write(self, buf, offset))
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+impl PositionedIo for File {}
+
+impl<T> PositionedIo for &mut T
+where
+    T: PositionedIo + ?Sized,
+{
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Option<std::io::Result<usize>> {
+        (**self).read_at(offset, buf)
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Option<std::io::Result<usize>> {
+        (**self).write_at(offset, buf)
+    }
+}
+
+#[cfg(feature = "tempfile")]
+impl PositionedIo for tempfile::NamedTempFile {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Option<std::io::Result<usize>> {
+        self.as_file().read_at(offset, buf)
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Option<std::io::Result<usize>> {
+        self.as_file_mut().write_at(offset, buf)
+    }
+}
+/// A `SpooledTempFile` may be in memory or on disk, and only exposes the cursor for either.
+#[cfg(feature = "tempfile")]
+impl PositionedIo for tempfile::SpooledTempFile {}
+
+/// Wraps a backend so that reading and writing go through its [`PositionedIo`], and seeking only
+/// moves a position kept by the wrapper. Actions, such as [`EditAction`], seek before each read
+/// and write, so over a file this turns each of them into a single `pread` or `pwrite`, and
+/// leaves the file's own position where it was.
+/// If the backend can't read or write at an offset, the wrapper seeks it and reads or writes
+/// there instead. Seeking from the end finds the backend's length without moving its position.
+///
+/// [`EditAction`]: crate::EditAction
+#[derive(Debug)]
+pub struct PositionedWrapper<F> {
+    inner: F,
+    position: u64,
+}
+impl<F> PositionedWrapper<F> {
+    /// Wraps `inner`, starting at position 0.
+    pub fn new(inner: F) -> Self {
+        Self { inner, position: 0 }
+    }
+
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+}
+impl<F> Read for PositionedWrapper<F>
+where
+    F: Read + Seek + PositionedIo,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = match self.inner.read_at(self.position, buf) {
+            Some(result) => result?,
+            None => {
+                self.inner.seek(SeekFrom::Start(self.position))?;
+                self.inner.read(buf)?
+            }
+        };
+        self.position += u64::from_usize(read);
+        Ok(read)
+    }
+}
+impl<F> Write for PositionedWrapper<F>
+where
+    F: Write + Seek + PositionedIo,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = match self.inner.write_at(self.position, buf) {
+            Some(result) => result?,
+            None => {
+                self.inner.seek(SeekFrom::Start(self.position))?;
+                self.inner.write(buf)?
+            }
+        };
+        self.position += u64::from_usize(written);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+impl<F> Seek for PositionedWrapper<F>
+where
+    F: Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let (position, offset) = match pos {
+            SeekFrom::Current(offset) => (self.position, offset),
+            SeekFrom::End(offset) => (stream_len(&mut self.inner)?, offset),
+            SeekFrom::Start(position) => (position, 0),
+        };
+        self.position = apply_offset(position, offset)?;
+        Ok(self.position)
+    }
+}
+impl<F> PositionedIo for PositionedWrapper<F>
+where
+    F: PositionedIo,
+{
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Option<std::io::Result<usize>> {
+        self.inner.read_at(offset, buf)
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Option<std::io::Result<usize>> {
+        self.inner.write_at(offset, buf)
+    }
+}
+impl<F> KnownLength for PositionedWrapper<F>
+where
+    F: KnownLength,
+{
+    fn known_length(&self) -> Option<u64> {
+        self.inner.known_length()
+    }
+}
+impl<F> Truncate for PositionedWrapper<F>
+where
+    F: Truncate,
+{
+    fn truncate(&mut self, new_len: u64) -> std::io::Result<()> {
+        self.inner.truncate(new_len)
+    }
+
+    fn insert_space(&mut self, position: u64, length: u64) -> std::io::Result<bool> {
+        self.inner.insert_space(position, length)
+    }
+
+    fn remove_space(&mut self, range: Range<u64>) -> std::io::Result<bool> {
+        self.inner.remove_space(range)
+    }
+}
+
+/// Reads at an offset without seeking, set by [`Hiex::with_positioned_io`].
+///
+/// [`Hiex::with_positioned_io`]: crate::Hiex::with_positioned_io
+pub(crate) type ReadAtFn<S> = fn(&S, u64, &mut [u8]) -> Option<std::io::Result<usize>>;
+
+/// Reads from `offset` onwards, using `read_at` while the stream supports it and otherwise seeking
+/// once and reading from there.
+pub(crate) struct ReaderAt<'a, S> {
+    stream: &'a mut S,
+    read_at: Option<ReadAtFn<S>>,
+    offset: u64,
+    seeked: bool,
+}
+impl<'a, S> ReaderAt<'a, S> {
+    pub(crate) fn new(stream: &'a mut S, read_at: Option<ReadAtFn<S>>, offset: u64) -> Self {
+        Self {
+            stream,
+            read_at,
+            offset,
+            seeked: false,
+        }
+    }
+}
+impl<'a, S> Read for ReaderAt<'a, S>
+where
+    S: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if !self.seeked {
+            let result = match self.read_at {
+                Some(read_at) => read_at(self.stream, self.offset, buf),
+                None => None,
+            };
+            if let Some(result) = result {
+                let read = result?;
+                self.offset += u64::from_usize(read);
+                return Ok(read);
+            }
+            self.stream.seek(SeekFrom::Start(self.offset))?;
+            self.seeked = true;
+        }
+        self.stream.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PositionedIo, PositionedWrapper};
+    use crate::{known_length::KnownLength, stream_position, EditAction, Hiex};
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+    /// Counts the seeks done on a `Cursor`, optionally hiding its positioned reads and writes.
+    struct CountingSeek {
+        inner: Cursor<Vec<u8>>,
+        seeks: usize,
+        positioned: bool,
+    }
+    impl Read for CountingSeek {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+    impl Write for CountingSeek {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.inner.flush()
+        }
+    }
+    impl Seek for CountingSeek {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.seeks += 1;
+            self.inner.seek(pos)
+        }
+    }
+    impl KnownLength for CountingSeek {
+        fn known_length(&self) -> Option<u64> {
+            self.inner.known_length()
+        }
+    }
+    impl PositionedIo for CountingSeek {
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> Option<std::io::Result<usize>> {
+            if self.positioned {
+                self.inner.read_at(offset, buf)
+            } else {
+                None
+            }
+        }
+
+        fn write_at(&mut self, offset: u64, buf: &[u8]) -> Option<std::io::Result<usize>> {
+            if self.positioned {
+                self.inner.write_at(offset, buf)
+            } else {
+                None
+            }
+        }
+    }
+
+    fn read_seeks(positioned: bool) -> (usize, Vec<u8>) {
+        let backend = CountingSeek {
+            inner: Cursor::new(vec![0u8; 256]),
+            seeks: 0,
+            positioned,
+        };
+        let mut hex: Hiex<_> = Hiex::from_reader(backend).unwrap().with_positioned_io();
+        for i in 0..100u8 {
+            let position = u64::from(i) * 2;
+            hex.add_action_simple(EditAction::new(position, vec![i, i]))
+                .unwrap();
+            let mut buf = [0u8; 4];
+            hex.read_at(position, &mut buf).unwrap();
+            assert_eq!(buf, [i, i, 0, 0]);
+        }
+        let backend = hex.into_inner();
+        (backend.seeks, backend.inner.into_inner())
+    }
+
+    #[test]
+    fn test_positioned_seeks() {
+        let (positioned_seeks, positioned_data) = read_seeks(true);
+        let (fallback_seeks, fallback_data) = read_seeks(false);
+        assert_eq!(positioned_data, fallback_data);
+        // Two seeks per edit (read previous data, write new data), plus one per read when it
+        // can't read at an offset.
+        assert_eq!(positioned_seeks, 200);
+        assert_eq!(fallback_seeks, 200 + 100);
+    }
+
+    #[test]
+    fn test_read_keeps_position() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"0123456789".to_vec()))
+            .unwrap()
+            .with_positioned_io();
+        hex.seek(SeekFrom::Start(3)).unwrap();
+        assert_eq!(hex.read_amount_at(6, 10).unwrap(), b"6789");
+        let mut buf = [0u8; 2];
+        hex.read_at(1, &mut buf).unwrap();
+        assert_eq!(&buf, b"12");
+        assert!(hex.read_at(9, &mut buf).is_err());
+        assert_eq!(stream_position(&mut hex).unwrap(), 3);
+
+        let mut data = [0u8; 4];
+        let mut cursor = Cursor::new(&mut data[..]);
+        assert_eq!(cursor.write_at(2, b"abc").unwrap().unwrap(), 2);
+        assert_eq!(cursor.write_at(9, b"abc").unwrap().unwrap(), 0);
+        assert_eq!(data, [0, 0, b'a', b'b']);
+
+        let mut cursor = Cursor::new(vec![1u8]);
+        cursor.write_at(3, b"x").unwrap().unwrap();
+        assert_eq!(cursor.into_inner(), [1, 0, 0, b'x']);
+
+        let mut data = vec![1u8];
+        let mut cursor = Cursor::new(&mut data);
+        cursor.write_at(2, b"y").unwrap().unwrap();
+        let mut buf = [0u8; 3];
+        assert_eq!(cursor.read_at(0, &mut buf).unwrap().unwrap(), 3);
+        assert_eq!(buf, [1, 0, b'y']);
+    }
+
+    #[test]
+    fn test_wrapper_keeps_position() {
+        let mut cursor = Cursor::new(b"0123456789".to_vec());
+        cursor.seek(SeekFrom::Start(5)).unwrap();
+        let mut hex: Hiex<_> = Hiex::from_reader(PositionedWrapper::new(cursor))
+            .unwrap()
+            .with_positioned_io();
+        hex.add_action_simple(EditAction::new(1, b"ab".to_vec()))
+            .unwrap();
+        hex.add_action_simple(EditAction::new(8, b"cd".to_vec()))
+            .unwrap();
+        hex.undo_simple().unwrap();
+        hex.redo_simple().unwrap();
+        assert_eq!(hex.read_amount_at(0, usize::MAX).unwrap(), b"0ab34567cd");
+        assert_eq!(hex.into_inner().into_inner().position(), 5);
+
+        // Edits on a wrapped backend which can read and write at an offset don't seek it, and
+        // ones which can't still apply.
+        for positioned in [true, false] {
+            let backend = CountingSeek {
+                inner: Cursor::new(b"0123".to_vec()),
+                seeks: 0,
+                positioned,
+            };
+            let mut hex: Hiex<_> = Hiex::from_reader(PositionedWrapper::new(backend))
+                .unwrap()
+                .with_known_length();
+            hex.add_action_simple(EditAction::new(1, b"ab".to_vec()))
+                .unwrap();
+            let backend = hex.into_inner().into_inner();
+            assert_eq!(backend.seeks, if positioned { 0 } else { 2 });
+            assert_eq!(backend.inner.into_inner(), b"0ab3");
+        }
+    }
+
+    /// A backend which implements neither [`PositionedIo`] nor `KnownLength`.
+    struct Plain(Cursor<Vec<u8>>);
+    impl Read for Plain {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+    impl Write for Plain {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.0.flush()
+        }
+    }
+    impl Seek for Plain {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_plain_backend() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Plain(Cursor::new(b"0123".to_vec()))).unwrap();
+        hex.add_action_simple(EditAction::new(1, b"ab".to_vec()))
+            .unwrap();
+        assert!(hex
+            .add_action_simple(EditAction::new(3, b"cd".to_vec()))
+            .is_err());
+        assert_eq!(hex.length().unwrap(), 4);
+        assert_eq!(hex.read_amount_at(0, usize::MAX).unwrap(), b"0ab3");
+        hex.undo_simple().unwrap();
+        assert_eq!(hex.into_inner().0.into_inner(), b"0123");
+    }
+
+    #[cfg(any(unix, windows))]
+    #[test]
+    fn test_positioned_file() {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(b"abcdef").unwrap();
+        assert_eq!(file.write_at(1, b"XY").unwrap().unwrap(), 2);
+        let mut buf = [0u8; 6];
+        assert_eq!(file.read_at(0, &mut buf).unwrap().unwrap(), 6);
+        assert_eq!(&buf, b"aXYdef");
+    }
+}
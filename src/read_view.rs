@@ -0,0 +1,197 @@
+//! Read-only views of the data which can be used from another thread while the editor is in use.
+use crate::{
+    constrained_wrapper::apply_offset, known_length::KnownLength, positioned_io::PositionedIo, Hiex,
+};
+use std::{
+    fs::File,
+    io::{Cursor, ErrorKind, Read, Seek, SeekFrom, Write},
+};
+use usize_cast::FromUsize;
+
+/// A backend which can give an independent reader over its data, see [`Hiex::reader_view`].
+pub trait CloneableBackend {
+    type Reader: PositionedIo + KnownLength + Send;
+
+    fn clone_reader(&self) -> std::io::Result<Self::Reader>;
+}
+
+/// A handle to the same file, so the view sees edits as they are written to it.
+impl CloneableBackend for File {
+    type Reader = File;
+
+    fn clone_reader(&self) -> std::io::Result<File> {
+        self.try_clone()
+    }
+}
+
+/// A copy of the data, so the view doesn't see any later edits.
+impl CloneableBackend for Cursor<Vec<u8>> {
+    type Reader = Cursor<Vec<u8>>;
+
+    fn clone_reader(&self) -> std::io::Result<Cursor<Vec<u8>>> {
+        Ok(Cursor::new(self.get_ref().clone()))
+    }
+}
+
+/// A read-only view of the data with its own position, from [`Hiex::reader_view`].
+///
+/// Edits are written directly to the data, so a view over a file sees them as soon as they are
+/// written, without consulting the editor. This is only eventually consistent: an edit isn't
+/// atomic from the view's point of view, so a read which races with an edit may see some of the
+/// new bytes and some of the old ones, and a read which races with a change in length may be
+/// short. Every byte read is one that was in the data at some point, and once the edit has been
+/// added every later read sees all of it.
+#[derive(Debug)]
+pub struct HiexReadView<R = File> {
+    reader: R,
+    position: u64,
+}
+impl<R> HiexReadView<R>
+where
+    R: PositionedIo + KnownLength,
+{
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            position: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn length(&self) -> std::io::Result<u64> {
+        self.reader.known_length().ok_or_else(|| {
+            std::io::Error::new(ErrorKind::Unsupported, "the view's length is unknown")
+        })
+    }
+}
+impl<R> Read for HiexReadView<R>
+where
+    R: PositionedIo + KnownLength,
+{
+    /// Reads at the view's position, leaving the position of the editor's reader alone. On
+    /// Windows, reading moves the position of a file shared with the view, which the editor
+    /// seeks before using.
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.reader.read_at(self.position, buf).unwrap_or_else(|| {
+            Err(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "reading at an offset is unsupported by the backend",
+            ))
+        })?;
+        self.position += u64::from_usize(read);
+        Ok(read)
+    }
+}
+impl<R> Seek for HiexReadView<R>
+where
+    R: PositionedIo + KnownLength,
+{
+    /// Seeks like `Cursor`, allowing seeking past the end of the data.
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let (position, offset) = match pos {
+            SeekFrom::Start(position) => (position, 0),
+            SeekFrom::Current(offset) => (self.position, offset),
+            SeekFrom::End(offset) => (self.length()?, offset),
+        };
+        self.position = apply_offset(position, offset)?;
+        Ok(self.position)
+    }
+}
+impl<R> KnownLength for HiexReadView<R>
+where
+    R: KnownLength,
+{
+    fn known_length(&self) -> Option<u64> {
+        self.reader.known_length()
+    }
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write + CloneableBackend,
+{
+    /// Creates a view of the data which can be read from another thread without borrowing the
+    /// editor. See [`HiexReadView`] for what it sees of edits made afterwards.
+    pub fn reader_view(&self) -> std::io::Result<HiexReadView<F::Reader>> {
+        Ok(HiexReadView::new(self.reader().clone_reader()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{EditAction, Hiex};
+    use std::{
+        io::{Cursor, Read, Seek, SeekFrom, Write},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+
+    #[test]
+    fn test_file_view() {
+        const LENGTH: usize = 4096;
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&[b'a'; LENGTH]).unwrap();
+        let mut hex: Hiex<_> = Hiex::from_reader(file).unwrap();
+        let mut view = hex.reader_view().unwrap();
+
+        let done = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let done = Arc::clone(&done);
+            std::thread::spawn(move || {
+                let mut buffer = vec![0; LENGTH];
+                let mut reads = 0;
+                while !done.load(Ordering::SeqCst) || reads == 0 {
+                    view.seek(SeekFrom::Start(0)).unwrap();
+                    view.read_exact(&mut buffer).unwrap();
+                    // Edits may be seen partially, but never as anything that wasn't written.
+                    assert!(buffer.iter().all(|&byte| byte == b'a' || byte == b'b'));
+                    reads += 1;
+                }
+                view
+            })
+        };
+
+        for index in 0..200 {
+            let byte = if index % 2 == 0 { b'b' } else { b'a' };
+            hex.add_action_simple(EditAction::new(0, vec![byte; LENGTH]))
+                .unwrap();
+        }
+        done.store(true, Ordering::SeqCst);
+        let mut view = reader.join().unwrap();
+
+        // Once the edits are added, the view sees all of them.
+        hex.add_action_simple(EditAction::new(10, b"cd".to_vec()))
+            .unwrap();
+        hex.seek(SeekFrom::Start(5)).unwrap();
+        let mut bytes = [0; 4];
+        view.seek(SeekFrom::Start(9)).unwrap();
+        view.read_exact(&mut bytes).unwrap();
+        assert_eq!(&bytes, b"acda");
+        assert_eq!(view.seek(SeekFrom::End(-1)).unwrap(), LENGTH as u64 - 1);
+        assert!(view.seek(SeekFrom::Current(-(LENGTH as i64))).is_err());
+        // The editor's position is its own.
+        assert_eq!(hex.position().unwrap(), 5);
+    }
+
+    #[test]
+    fn test_cursor_view() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"0123".to_vec())).unwrap();
+        let mut view = hex.reader_view().unwrap();
+        hex.add_action_simple(EditAction::new(0, b"ab".to_vec()))
+            .unwrap();
+
+        // A snapshot from when it was made.
+        let mut bytes = Vec::new();
+        view.read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes, b"0123");
+        assert_eq!(
+            hex.reader_view().unwrap().into_inner().into_inner(),
+            b"ab23"
+        );
+    }
+}
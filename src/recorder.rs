@@ -0,0 +1,200 @@
+//! Recording the actions added to a [`Hiex`] as a macro, which can be replayed elsewhere in the
+//! data or onto other data, such as to repeat the same edits to each record of a table.
+use crate::{
+    action::{Action, CompoundAction, ReplayError},
+    Hiex,
+};
+use std::{
+    convert::TryFrom,
+    io::{Read, Seek, Write},
+};
+
+/// Fresh copies of the actions added between [`Hiex::start_macro`] and [`Hiex::stop_macro`], in
+/// the order they were added. Undoing and redoing while recording isn't recorded, so an action
+/// which was undone is still part of the macro.
+pub struct Macro<F, E>
+where
+    F: Read + Seek,
+{
+    actions: Vec<Box<dyn Action<F, E>>>,
+    unreplayable: usize,
+}
+impl<F, E> Macro<F, E>
+where
+    F: Read + Seek,
+{
+    fn new() -> Self {
+        Self {
+            actions: Vec::new(),
+            unreplayable: 0,
+        }
+    }
+
+    /// The amount of actions which were recorded.
+    pub fn len(&self) -> usize {
+        self.actions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// The amount of actions which were added while recording but left out of the macro, since
+    /// they don't support being replayed (see [`Action::clone_for_replay`]).
+    pub fn unreplayable(&self) -> usize {
+        self.unreplayable
+    }
+
+    fn record(&mut self, action: Option<Box<dyn Action<F, E>>>) {
+        match action {
+            Some(action) => self.actions.push(action),
+            None => self.unreplayable += 1,
+        }
+    }
+}
+impl<F, E> Macro<F, E>
+where
+    F: 'static + Read + Seek + Write,
+    E: 'static + Clone,
+{
+    /// Applies the recorded actions to `hex`, moved by `delta` bytes from where they were
+    /// recorded, as a single action. `hex` may be the one they were recorded on or another.
+    /// Fails with `ReplayError::NotReplayable` for the first action which can't be moved (see
+    /// [`Action::translate`]), and with `ReplayError::Failed` for the first which fails to apply,
+    /// in which case none of them are kept.
+    pub fn replay(&self, hex: &mut Hiex<F, E>, delta: i64, other: E) -> Result<(), ReplayError> {
+        self.replay_repeated(hex, delta, 1, other)
+    }
+
+    /// Like [`Macro::replay`], but applies the recorded actions `count` times, each `stride` bytes
+    /// after the last, starting `stride` bytes after where they were recorded. They are all a
+    /// single action.
+    pub fn replay_repeated(
+        &self,
+        hex: &mut Hiex<F, E>,
+        stride: i64,
+        count: usize,
+        other: E,
+    ) -> Result<(), ReplayError> {
+        if self.actions.is_empty() || count == 0 {
+            return Ok(());
+        }
+        let mut replayed = CompoundAction::new();
+        for repetition in 1..=count {
+            let delta = i64::try_from(repetition)
+                .ok()
+                .and_then(|repetition| stride.checked_mul(repetition));
+            for (index, action) in self.actions.iter().enumerate() {
+                let mut action = action
+                    .clone_for_replay()
+                    .ok_or(ReplayError::NotReplayable { index })?;
+                let moved = match delta {
+                    Some(0) => true,
+                    Some(delta) => action.translate(delta),
+                    None => false,
+                };
+                if !moved {
+                    return Err(ReplayError::NotReplayable { index });
+                }
+                replayed.push_boxed(action);
+            }
+        }
+        hex.add_action(replayed, other)
+            .map_err(|(replayed, error)| {
+                // If it failed before any were applied, such as due to a locked region, then it is
+                // reported for the first.
+                let index = replayed.failed_at().unwrap_or(0) % self.actions.len();
+                ReplayError::Failed { index, error }
+            })
+    }
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Starts recording the actions added with [`Hiex::add_action`] (and the functions built on
+    /// it), discarding any recording in progress.
+    pub fn start_macro(&mut self) {
+        self.recording = Some(Macro::new());
+    }
+
+    /// Stops recording, returning what was recorded, or `None` if nothing was being recorded.
+    pub fn stop_macro(&mut self) -> Option<Macro<F, E>> {
+        self.recording.take()
+    }
+
+    pub fn is_recording_macro(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Records a fresh copy of an action which was just added, if recording.
+    pub(crate) fn record_action(&mut self, action: Option<Box<dyn Action<F, E>>>) {
+        if let Some(recording) = &mut self.recording {
+            recording.record(action);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        action::{ActionError, ReplayError},
+        transform::{FillAction, TransformAction},
+        EditAction, Hiex,
+    };
+    use std::io::Cursor;
+
+    #[test]
+    fn test_macro_replay() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(vec![b'.'; 32])).unwrap();
+        assert!(hex.stop_macro().is_none());
+        hex.start_macro();
+        assert!(hex.is_recording_macro());
+        hex.add_action_simple(EditAction::new(0, b"ab".to_vec()))
+            .unwrap();
+        hex.add_action_simple(FillAction::new(3, 1, b"x".to_vec()))
+            .unwrap();
+        hex.add_action_simple(TransformAction::new(0, 1, Box::new(|_| ())))
+            .unwrap();
+        let recorded = hex.stop_macro().unwrap();
+        assert!(!hex.is_recording_macro());
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded.unreplayable(), 1);
+
+        // Repeated every 8 bytes, as a single action.
+        let entries = hex.history().len();
+        recorded.replay_repeated(&mut hex, 8, 3, ()).unwrap();
+        assert_eq!(hex.history().len(), entries + 1);
+        assert_eq!(
+            hex.read_amount_at(0, 32).unwrap(),
+            b"ab.x....ab.x....ab.x....ab.x....".to_vec()
+        );
+        hex.undo_simple().unwrap();
+        assert_eq!(
+            hex.read_amount_at(0, 32).unwrap(),
+            b"ab.x............................".to_vec()
+        );
+
+        // Onto other data, where it was recorded.
+        let mut other: Hiex<_> = Hiex::from_reader(Cursor::new(vec![b'-'; 4])).unwrap();
+        recorded.replay(&mut other, 0, ()).unwrap();
+        assert_eq!(other.read_amount_at(0, 4).unwrap(), b"ab-x".to_vec());
+
+        // Moved before the start, and past the end.
+        let err = recorded.replay(&mut hex, -1, ()).unwrap_err();
+        assert!(matches!(err, ReplayError::NotReplayable { index: 0 }));
+        let err = recorded.replay(&mut hex, 29, ()).unwrap_err();
+        assert!(matches!(
+            err,
+            ReplayError::Failed {
+                index: 1,
+                error: ActionError::Invalid
+            }
+        ));
+        assert_eq!(
+            hex.read_amount_at(0, 32).unwrap(),
+            b"ab.x............................".to_vec()
+        );
+    }
+}
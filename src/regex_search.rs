@@ -0,0 +1,220 @@
+//! Searching the data with regexes over raw bytes.
+use crate::{clamp_usize, stream_len, Hiex, CHUNK_SIZE};
+use regex::bytes::{Regex, RegexBuilder};
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+use usize_cast::FromUsize;
+
+/// A match found by [`Hiex::find_regex`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct RegexMatch {
+    /// The offset of the match in the data.
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// Why [`Hiex::find_regex`] couldn't search.
+#[derive(Debug)]
+pub enum SearchError {
+    /// The pattern couldn't be compiled.
+    Pattern(regex::Error),
+    /// The pattern can match arbitrarily long text, such as with `.*` or `a+`, so matches can't
+    /// be found without reading all of the data into memory. Use a bounded repetition instead,
+    /// such as `.{0,64}`.
+    Unbounded,
+    IoError(std::io::Error),
+}
+impl From<std::io::Error> for SearchError {
+    fn from(err: std::io::Error) -> Self {
+        Self::IoError(err)
+    }
+}
+impl From<regex::Error> for SearchError {
+    fn from(err: regex::Error) -> Self {
+        Self::Pattern(err)
+    }
+}
+
+/// Compiles `pattern` to match bytes, with Unicode disabled unless the pattern enables it with
+/// `(?u)`, and returns it along with the maximum length of a match.
+fn compile(pattern: &str) -> Result<(Regex, usize), SearchError> {
+    let regex = RegexBuilder::new(pattern).unicode(false).build()?;
+    let hir = regex_syntax::ParserBuilder::new()
+        .unicode(false)
+        .utf8(false)
+        .build()
+        .parse(pattern)
+        .map_err(|err| regex::Error::Syntax(err.to_string()))?;
+    let max_len = hir
+        .properties()
+        .maximum_len()
+        .ok_or(SearchError::Unbounded)?;
+    Ok((regex, max_len))
+}
+
+/// Finds the non-overlapping matches of `regex` within `range`, which is treated as the whole
+/// haystack, reading at most `chunk_size` new bytes at a time. No match is longer than
+/// `max_len`. Empty matches are skipped.
+pub(crate) fn find_regex_in<S>(
+    stream: &mut S,
+    regex: &Regex,
+    max_len: usize,
+    range: Range<u64>,
+    chunk_size: usize,
+) -> std::io::Result<Vec<RegexMatch>>
+where
+    S: Read + Seek,
+{
+    let end = range.end.min(stream_len(stream)?);
+    let mut matches = Vec::new();
+    if range.start >= end {
+        return Ok(matches);
+    }
+
+    let chunk_size = chunk_size.max(1);
+    let mut buffer = Vec::with_capacity(chunk_size + max_len + 1);
+    let mut buffer_start = range.start;
+    // Where the next search begins, which is after the end of the last match.
+    let mut next = range.start;
+    let mut position = range.start;
+    while position < end {
+        let amount = clamp_usize(end - position, chunk_size);
+        let kept = buffer.len();
+        buffer.resize(kept + amount, 0);
+        stream.seek(SeekFrom::Start(position))?;
+        stream.read_exact(&mut buffer[kept..])?;
+        position += u64::from_usize(amount);
+
+        // A match starting at or before `limit` is followed by more than `max_len` bytes, so it
+        // is found exactly as it would be in the whole range, including assertions like `$` and
+        // `\b` that look at the byte after it. Later matches are found once more bytes are read.
+        let limit = if position == end {
+            Some(buffer.len())
+        } else {
+            buffer.len().checked_sub(max_len + 1)
+        };
+        if let Some(limit) = limit {
+            let mut at = clamp_usize(next - buffer_start, buffer.len());
+            while at <= limit {
+                let found = match regex.find_at(&buffer, at) {
+                    Some(found) if found.start() <= limit => found,
+                    _ => break,
+                };
+                if found.is_empty() {
+                    at = found.end() + 1;
+                    continue;
+                }
+                matches.push(RegexMatch {
+                    offset: buffer_start + u64::from_usize(found.start()),
+                    length: u64::from_usize(found.len()),
+                });
+                at = found.end();
+            }
+            // No match starts between `at` and `limit`.
+            next = buffer_start + u64::from_usize(at.max(limit + 1));
+        }
+
+        // Keep the byte before the next search for assertions that look behind, like `^` in
+        // multi-line mode and `\b`.
+        let keep_from = clamp_usize(next - buffer_start, buffer.len()).saturating_sub(1);
+        buffer.drain(..keep_from);
+        buffer_start += u64::from_usize(keep_from);
+    }
+
+    Ok(matches)
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Finds the non-overlapping matches of the regex `pattern` within `range`, which is treated
+    /// as the whole haystack, so `^` and `$` match at its ends.
+    /// The pattern matches raw bytes, and Unicode is off unless enabled with `(?u)`, so `.` and
+    /// `\xFF` match any single byte other than a newline and the byte `0xFF` respectively.
+    /// The data is read in chunks, so the pattern must have a maximum match length, otherwise
+    /// this fails with [`SearchError::Unbounded`]. Empty matches are skipped.
+    pub fn find_regex(
+        &mut self,
+        pattern: &str,
+        range: Range<u64>,
+    ) -> Result<Vec<RegexMatch>, SearchError> {
+        let (regex, max_len) = compile(pattern)?;
+        Ok(find_regex_in(self, &regex, max_len, range, CHUNK_SIZE)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compile, find_regex_in, RegexMatch, SearchError};
+    use crate::Hiex;
+    use std::io::Cursor;
+
+    fn find(data: &[u8], pattern: &str, chunk_size: usize) -> Vec<(u64, u64)> {
+        let (regex, max_len) = compile(pattern).unwrap();
+        let mut data = Cursor::new(data.to_vec());
+        find_regex_in(&mut data, &regex, max_len, 0..u64::MAX, chunk_size)
+            .unwrap()
+            .into_iter()
+            .map(|found| (found.offset, found.length))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_regex() {
+        let mut data = vec![0u8; 40];
+        data[3..7].copy_from_slice(b"PK\x03\x04");
+        data[17..21].copy_from_slice(b"PK\x03\x04");
+        data[30..34].copy_from_slice(b"\x7fELF");
+        let pattern = r"PK\x03\x04.{4}|\x7fELF";
+        let expected = vec![(3, 8), (17, 8), (30, 4)];
+        // Every chunk size, so that the matches straddle chunk boundaries in every way.
+        for chunk_size in 1..=data.len() + 1 {
+            assert_eq!(find(&data, pattern, chunk_size), expected, "{}", chunk_size);
+        }
+
+        // Bytes outside of ASCII, and assertions at the chunk boundaries.
+        for chunk_size in 1..=12 {
+            assert_eq!(find(b"ab\xff\xfe ab", r"\xff.", chunk_size), vec![(2, 2)]);
+            assert_eq!(
+                find(b"abc abcd abc", r"\babc\b", chunk_size),
+                vec![(0, 3), (9, 3)]
+            );
+            assert_eq!(find(b"aaaa", "^a{1,2}", chunk_size), vec![(0, 2)]);
+            assert_eq!(find(b"aaaa", "a{1,3}$", chunk_size), vec![(1, 3)]);
+            assert_eq!(find(b"aaaaa", "a{2}", chunk_size), vec![(0, 2), (2, 2)]);
+            assert_eq!(find(b"xax", "a?", chunk_size), vec![(1, 1)]);
+        }
+
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(data)).unwrap();
+        assert_eq!(
+            hex.find_regex(pattern, 4..40).unwrap(),
+            vec![
+                RegexMatch {
+                    offset: 17,
+                    length: 8
+                },
+                RegexMatch {
+                    offset: 30,
+                    length: 4
+                }
+            ]
+        );
+        assert!(hex.find_regex(pattern, 10..20).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_find_regex_errors() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"abc".to_vec())).unwrap();
+        assert!(matches!(
+            hex.find_regex("a(b", 0..3),
+            Err(SearchError::Pattern(_))
+        ));
+        assert!(matches!(
+            hex.find_regex("a.*c", 0..3),
+            Err(SearchError::Unbounded)
+        ));
+    }
+}
@@ -0,0 +1,374 @@
+//! Metadata about regions of the data.
+use crate::{action::ActionError, Hiex};
+use std::{
+    collections::BTreeMap,
+    io::{Read, Seek, Write},
+    ops::Range,
+};
+
+/// Whether two ranges share any position. Empty ranges never overlap anything.
+pub(crate) fn ranges_overlap(a: &Range<u64>, b: &Range<u64>) -> bool {
+    a.start < a.end && b.start < b.end && a.start < b.end && b.start < a.end
+}
+
+/// Identifies a locked region, see [`Hiex::lock_region`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct RegionLockId(u64);
+
+/// The set of locked regions of an editor.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RegionLocks {
+    locks: Vec<(RegionLockId, Range<u64>)>,
+    next_id: u64,
+}
+impl RegionLocks {
+    /// Check whether an action affecting `range` is allowed.
+    /// If the affected range is unknown then it is rejected if there are any locks at all.
+    pub(crate) fn check(&self, range: Option<Range<u64>>) -> Result<(), ActionError> {
+        let locked = self.locks.iter().find(|(_, locked)| match &range {
+            Some(range) => ranges_overlap(range, locked),
+            None => true,
+        });
+        match locked {
+            Some((_, locked)) => Err(ActionError::RegionLocked {
+                range: locked.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Identifies a tag within a [`RegionTags`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct TagId(u64);
+
+/// Information about a tagged region, such as "ELF header".
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct TagInfo {
+    pub name: String,
+    /// A hint for the color to display the region with, as `0xRRGGBB`.
+    pub color: Option<u32>,
+    /// Arbitrary extra information.
+    pub metadata: BTreeMap<String, String>,
+}
+impl TagInfo {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_color(mut self, color: u32) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+}
+
+/// A tagged region.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Tag {
+    pub id: TagId,
+    pub range: Range<u64>,
+    pub info: TagInfo,
+}
+
+/// A store of tagged regions, which may overlap.
+/// Tags are kept sorted by their start, forming an implicit interval tree (each subtree knows the
+/// furthest end within it), so that finding the tags at an offset or within a range takes
+/// `O(log n + k)` for `k` results.
+#[derive(Debug, Clone, Default)]
+pub struct RegionTags {
+    /// Sorted by `(range.start, id)`.
+    tags: Vec<Tag>,
+    /// For the implicit tree where the node for the slice `[low, high)` is at its midpoint, the
+    /// maximum `range.end` within that slice.
+    max_end: Vec<u64>,
+    next_id: u64,
+}
+impl RegionTags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tags.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
+
+    pub fn add_tag(&mut self, range: Range<u64>, info: TagInfo) -> TagId {
+        let id = TagId(self.next_id);
+        self.next_id += 1;
+        let index = self
+            .tags
+            .partition_point(|tag| (tag.range.start, tag.id) < (range.start, id));
+        self.tags.insert(index, Tag { id, range, info });
+        self.rebuild();
+        id
+    }
+
+    /// Remove a tag, returning it if it existed.
+    pub fn remove_tag(&mut self, id: TagId) -> Option<Tag> {
+        let index = self.tags.iter().position(|tag| tag.id == id)?;
+        let tag = self.tags.remove(index);
+        self.rebuild();
+        Some(tag)
+    }
+
+    pub fn get(&self, id: TagId) -> Option<&Tag> {
+        self.tags.iter().find(|tag| tag.id == id)
+    }
+
+    /// Iterate over the tags in order of their start offset.
+    pub fn iter(&self) -> impl Iterator<Item = &Tag> {
+        self.tags.iter()
+    }
+
+    /// The tags containing `offset`, in order of their start offset.
+    pub fn tags_at(&self, offset: u64) -> Vec<&Tag> {
+        self.tags_overlapping(offset..offset.saturating_add(1))
+    }
+
+    /// The tags overlapping `range`, in order of their start offset.
+    pub fn tags_overlapping(&self, range: Range<u64>) -> Vec<&Tag> {
+        let mut found = Vec::new();
+        self.collect_overlapping(0, self.tags.len(), &range, &mut found);
+        found
+    }
+
+    fn collect_overlapping<'a>(
+        &'a self,
+        low: usize,
+        high: usize,
+        range: &Range<u64>,
+        found: &mut Vec<&'a Tag>,
+    ) {
+        if low >= high {
+            return;
+        }
+        let middle = low + (high - low) / 2;
+        if self.max_end[middle] <= range.start {
+            // Nothing within this subtree ends after the range starts.
+            return;
+        }
+
+        self.collect_overlapping(low, middle, range, found);
+        let tag = &self.tags[middle];
+        if tag.range.start < range.end {
+            if ranges_overlap(&tag.range, range) {
+                found.push(tag);
+            }
+            self.collect_overlapping(middle + 1, high, range, found);
+        }
+        // Otherwise everything to the right starts at or after the end of the range.
+    }
+
+    /// Modify the range of every tag, keeping them sorted.
+    pub(crate) fn remap(&mut self, mut map: impl FnMut(&mut Tag)) {
+        self.tags.iter_mut().for_each(&mut map);
+        self.tags.sort_by_key(|tag| (tag.range.start, tag.id));
+        self.rebuild();
+    }
+
+    /// Recompute `max_end` after `tags` has been modified.
+    fn rebuild(&mut self) {
+        self.max_end = vec![0; self.tags.len()];
+        self.rebuild_node(0, self.tags.len());
+    }
+
+    fn rebuild_node(&mut self, low: usize, high: usize) -> u64 {
+        if low >= high {
+            return 0;
+        }
+        let middle = low + (high - low) / 2;
+        let end = self.tags[middle]
+            .range
+            .end
+            .max(self.rebuild_node(low, middle))
+            .max(self.rebuild_node(middle + 1, high));
+        self.max_end[middle] = end;
+        end
+    }
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// The tagged regions of the data.
+    pub fn tags(&self) -> &RegionTags {
+        &self.region_tags
+    }
+
+    pub fn tags_mut(&mut self) -> &mut RegionTags {
+        &mut self.region_tags
+    }
+
+    /// Mark `range` as read-only.
+    /// Adding, undoing, or redoing an action whose [affected range] overlaps a locked region fails
+    /// with `ActionError::RegionLocked`, without modifying the data or the history.
+    /// Actions which don't know their affected range are rejected while any region is locked, as
+    /// they may modify anything.
+    /// Locks are only metadata of the editor; they don't affect the underlying reader.
+    ///
+    /// [affected range]: crate::action::Action::affected_range
+    pub fn lock_region(&mut self, range: Range<u64>) -> RegionLockId {
+        let id = RegionLockId(self.region_locks.next_id);
+        self.region_locks.next_id += 1;
+        self.region_locks.locks.push((id, range));
+        id
+    }
+
+    /// Remove a lock. Returns the range that was locked, or `None` if there was no such lock.
+    pub fn unlock_region(&mut self, id: RegionLockId) -> Option<Range<u64>> {
+        let index = self
+            .region_locks
+            .locks
+            .iter()
+            .position(|(lock, _)| *lock == id)?;
+        Some(self.region_locks.locks.remove(index).1)
+    }
+
+    /// The currently locked regions.
+    pub fn locked_regions(&self) -> impl Iterator<Item = (RegionLockId, &Range<u64>)> {
+        self.region_locks
+            .locks
+            .iter()
+            .map(|(id, range)| (*id, range))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RegionTags, TagInfo};
+    use crate::{
+        action::{ActionError, HistoryError},
+        contents, AppendAction, EditAction, Hiex,
+    };
+    use std::io::Cursor;
+
+    #[test]
+    fn test_region_lock() {
+        let mut hex = Hiex::from_reader(Cursor::new(b"HEADbody".to_vec())).unwrap();
+        let lock = hex.lock_region(0..4);
+
+        // Inside.
+        let (_, err) = hex
+            .add_action(EditAction::new(1, b"x".to_vec()), ())
+            .unwrap_err();
+        assert!(matches!(err, ActionError::RegionLocked { range } if range == (0..4)));
+        // Partially overlapping.
+        let (_, err) = hex
+            .add_action(EditAction::new(3, b"xyz".to_vec()), ())
+            .unwrap_err();
+        assert!(matches!(err, ActionError::RegionLocked { .. }));
+        assert_eq!(contents(&mut hex), b"HEADbody");
+        assert!(hex.actions.is_empty());
+
+        // Outside, including directly adjacent.
+        hex.add_action(EditAction::new(4, b"BO".to_vec()), ())
+            .unwrap();
+        assert_eq!(contents(&mut hex), b"HEADBOdy");
+
+        // Actions with unknown ranges are rejected while anything is locked.
+        let (_, err) = hex
+            .add_action(AppendAction::new(b"!".to_vec()), ())
+            .unwrap_err();
+        assert!(matches!(err, ActionError::RegionLocked { .. }));
+
+        assert_eq!(hex.unlock_region(lock), Some(0..4));
+        assert_eq!(hex.unlock_region(lock), None);
+        hex.add_action(EditAction::new(0, b"h".to_vec()), ())
+            .unwrap();
+        assert_eq!(contents(&mut hex), b"hEADBOdy");
+
+        // Undo and redo are checked too.
+        hex.undo(()).unwrap();
+        hex.lock_region(0..1);
+        assert!(matches!(
+            hex.redo(()),
+            Err(HistoryError {
+                index: 1,
+                modified: Some(false),
+                error: ActionError::RegionLocked { .. },
+                ..
+            })
+        ));
+        assert_eq!(contents(&mut hex), b"HEADBOdy");
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), b"HEADbody");
+    }
+
+    #[test]
+    fn test_region_tags() {
+        let mut tags = RegionTags::new();
+        let file = tags.add_tag(0..0x1000, TagInfo::new("file"));
+        let header = tags.add_tag(
+            0..0x40,
+            TagInfo::new("ELF header")
+                .with_color(0xFF0000)
+                .with_metadata("class", "64"),
+        );
+        let text = tags.add_tag(0x200..0x1200, TagInfo::new(".text"));
+        let ident = tags.add_tag(0..0x10, TagInfo::new("e_ident"));
+        let overlap = tags.add_tag(0x30..0x210, TagInfo::new("overlap"));
+
+        let ids = |found: Vec<&super::Tag>| found.iter().map(|tag| tag.id).collect::<Vec<_>>();
+        assert_eq!(ids(tags.tags_at(0)), [file, header, ident]);
+        assert_eq!(ids(tags.tags_at(0x3F)), [file, header, overlap]);
+        assert_eq!(ids(tags.tags_at(0x100)), [file, overlap]);
+        assert_eq!(ids(tags.tags_at(0x1000)), [text]);
+        assert_eq!(ids(tags.tags_at(0x1200)), []);
+        assert_eq!(ids(tags.tags_overlapping(0x40..0x200)), [file, overlap]);
+        assert_eq!(
+            ids(tags.tags_overlapping(0x1ff..0x201)),
+            [file, overlap, text]
+        );
+        assert_eq!(ids(tags.tags_overlapping(0x10..0x10)), []);
+        assert_eq!(
+            tags.get(header)
+                .unwrap()
+                .info
+                .metadata
+                .get("class")
+                .map(String::as_str),
+            Some("64")
+        );
+
+        // Iteration is in offset order.
+        let starts: Vec<u64> = tags.iter().map(|tag| tag.range.start).collect();
+        assert_eq!(starts, [0, 0, 0, 0x30, 0x200]);
+
+        assert_eq!(tags.remove_tag(file).unwrap().info.name, "file");
+        assert!(tags.remove_tag(file).is_none());
+        assert_eq!(ids(tags.tags_at(0x100)), [overlap]);
+        assert_eq!(ids(tags.tags_at(0)), [header, ident]);
+        assert_eq!(tags.len(), 4);
+
+        // Compare against a brute-force search over many tags.
+        let mut tags = RegionTags::new();
+        let mut all = Vec::new();
+        for i in 0..200u64 {
+            let start = (i * 37) % 500;
+            let range = start..start + (i * 13) % 50;
+            all.push((tags.add_tag(range.clone(), TagInfo::new("")), range));
+        }
+        for offset in 0..560 {
+            let mut expected: Vec<_> = all
+                .iter()
+                .filter(|(_, range)| range.contains(&offset))
+                .collect();
+            expected.sort_by_key(|(id, range)| (range.start, *id));
+            let expected: Vec<_> = expected.iter().map(|(id, _)| *id).collect();
+            assert_eq!(ids(tags.tags_at(offset)), expected);
+        }
+    }
+}
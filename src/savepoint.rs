@@ -0,0 +1,123 @@
+//! Named points in the history, along with a fingerprint of the data at that point.
+use crate::Hiex;
+use std::io::{Read, Seek, Write};
+
+/// A cheap summary of the data, for noticing when it has changed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Fingerprint {
+    pub length: u64,
+    /// The CRC-32 of all of the data.
+    pub crc32: u32,
+}
+
+/// A named point in the history, see [`Hiex::create_savepoint`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Savepoint {
+    pub name: String,
+    /// The amount of applied actions (see [`ActionList::past_len`]) when it was created.
+    ///
+    /// [`ActionList::past_len`]: crate::action::ActionList::past_len
+    pub index: usize,
+    /// The data when it was created.
+    pub fingerprint: Fingerprint,
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Reads all of the data to fingerprint it.
+    pub fn fingerprint(&mut self) -> std::io::Result<Fingerprint> {
+        Ok(Fingerprint {
+            length: self.length()?,
+            crc32: self.crc32_of_data()?,
+        })
+    }
+
+    /// Names the current point in the history, replacing any savepoint with the same name.
+    pub fn create_savepoint(&mut self, name: impl Into<String>) -> std::io::Result<&Savepoint> {
+        let savepoint = Savepoint {
+            name: name.into(),
+            index: self.actions.past_len(),
+            fingerprint: self.fingerprint()?,
+        };
+        self.savepoints
+            .retain(|existing| existing.name != savepoint.name);
+        self.savepoints.push(savepoint);
+        self.actions.seal();
+        Ok(&self.savepoints[self.savepoints.len() - 1])
+    }
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    pub fn savepoint(&self, name: &str) -> Option<&Savepoint> {
+        self.savepoints
+            .iter()
+            .find(|savepoint| savepoint.name == name)
+    }
+
+    /// Remove a savepoint, returning it if it existed.
+    pub fn remove_savepoint(&mut self, name: &str) -> Option<Savepoint> {
+        let index = self
+            .savepoints
+            .iter()
+            .position(|savepoint| savepoint.name == name)?;
+        Some(self.savepoints.remove(index))
+    }
+
+    /// Iterate over the savepoints in the order they were created.
+    pub fn savepoints(&self) -> impl Iterator<Item = &Savepoint> {
+        self.savepoints.iter()
+    }
+
+    /// The amount of applied actions (see [`ActionList::past_len`]) when the data was last saved
+    /// with [`Hiex::save_to`], [`Hiex::save_to_no_trunc`], or [`Hiex::save_to_background`], if it
+    /// has been.
+    ///
+    /// [`ActionList::past_len`]: crate::action::ActionList::past_len
+    pub fn last_saved(&self) -> Option<usize> {
+        self.background_saved_index().or(self.last_saved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{EditAction, Hiex};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_savepoints() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"0123".to_vec())).unwrap();
+        let initial = hex.create_savepoint("initial").unwrap().clone();
+        assert_eq!(initial.index, 0);
+        assert_eq!(initial.fingerprint.length, 4);
+
+        hex.add_action_simple(EditAction::new(0, b"a".to_vec()))
+            .unwrap();
+        hex.create_savepoint("edited").unwrap();
+        assert_ne!(
+            hex.savepoint("edited").unwrap().fingerprint,
+            initial.fingerprint
+        );
+        hex.undo_simple().unwrap();
+        assert_eq!(hex.fingerprint().unwrap(), initial.fingerprint);
+
+        // Replacing keeps a single savepoint of that name.
+        hex.create_savepoint("edited").unwrap();
+        let names: Vec<&str> = hex
+            .savepoints()
+            .map(|savepoint| &savepoint.name[..])
+            .collect();
+        assert_eq!(names, ["initial", "edited"]);
+        assert_eq!(hex.remove_savepoint("initial"), Some(initial));
+        assert_eq!(hex.savepoints().count(), 1);
+
+        assert_eq!(hex.last_saved(), None);
+        hex.redo_simple().unwrap();
+        hex.save_to(&mut Cursor::new(Vec::new())).unwrap();
+        assert_eq!(hex.last_saved(), Some(1));
+    }
+}
@@ -0,0 +1,464 @@
+//! Batch edit scripts, a simple text format of commands for automating edits.
+//!
+//! Each line holds one command, and anything after a `#` is a comment. Numbers are decimal, or
+//! hexadecimal with a `0x` prefix, and bytes are pairs of hexadecimal digits, such as `DEADBEEF`
+//! or `DE AD BE EF`. Ranges include their start and exclude their end.
+//! ```text
+//! write 0x10 DE AD BE EF         # overwrite bytes at an offset
+//! fill 0x100 0x200 00            # overwrite a range with a byte
+//! truncate 0x1000                # shrink or grow (with zeroes) to a length
+//! checksum crc32 0x0 0x100 -> 0x104 le   # write a `crc16` or `crc32`, `le` (the default) or `be`
+//! ```
+use crate::{
+    action::{plural_bytes, Action, ActionError, CompoundAction, MemoryUsage},
+    checked_usize,
+    checksum::{ChecksumAlgorithm, ChecksumFieldAction},
+    read_exact_at, stream_len,
+    transform::FillAction,
+    truncate::Truncate,
+    write_at, EditAction, Endian, Hiex,
+};
+use std::{
+    io::{Read, Seek, Write},
+    ops::Range,
+};
+
+/// A parsed command of a script.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ScriptCommand {
+    Write {
+        offset: u64,
+        data: Vec<u8>,
+    },
+    Fill {
+        range: Range<u64>,
+        byte: u8,
+    },
+    Truncate {
+        length: u64,
+    },
+    Checksum {
+        algorithm: ChecksumAlgorithm,
+        data_range: Range<u64>,
+        field_offset: u64,
+        endian: Endian,
+    },
+}
+
+/// A command along with the line it is on, counting from 1.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ScriptLine {
+    pub line: usize,
+    pub command: ScriptCommand,
+}
+
+/// Why a line of a script couldn't be parsed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SyntaxError {
+    UnknownCommand(String),
+    UnknownAlgorithm(String),
+    MissingArgument,
+    /// There were more arguments than the command takes, starting with this one.
+    UnexpectedArgument(String),
+    InvalidNumber(String),
+    InvalidBytes(String),
+    /// The range's end is before its start, or it is too large to fill.
+    InvalidRange,
+}
+
+/// What happened to a command when running a script.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CommandStatus {
+    Applied,
+    /// The command was applied, but then undone as a later command failed.
+    RolledBack,
+    Failed,
+    /// The command wasn't run, as an earlier command failed.
+    Skipped,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CommandOutcome {
+    pub line: usize,
+    pub status: CommandStatus,
+}
+
+/// The outcome of each command of a script, in order.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ScriptReport {
+    pub outcomes: Vec<CommandOutcome>,
+}
+
+#[derive(Debug)]
+pub enum ScriptError {
+    /// A line couldn't be parsed, so nothing was run.
+    Syntax { line: usize, error: SyntaxError },
+    /// A command failed, and the commands before it were rolled back. `line` is `None` if the
+    /// script as a whole was rejected before any command was run, such as by a region lock.
+    Failed {
+        line: Option<usize>,
+        error: ActionError,
+        report: ScriptReport,
+    },
+}
+
+fn parse_number(token: &str) -> Result<u64, SyntaxError> {
+    let parsed = match token
+        .strip_prefix("0x")
+        .or_else(|| token.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => token.parse(),
+    };
+    parsed.map_err(|_| SyntaxError::InvalidNumber(token.to_string()))
+}
+
+/// Appends the bytes of `token`, which is pairs of hexadecimal digits, to `data`.
+fn parse_bytes(token: &str, data: &mut Vec<u8>) -> Result<(), SyntaxError> {
+    let invalid = || SyntaxError::InvalidBytes(token.to_string());
+    if token.len() % 2 != 0 || !token.is_ascii() {
+        return Err(invalid());
+    }
+    for pair in token.as_bytes().chunks(2) {
+        // Both are ASCII, so this is on a character boundary.
+        let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+        data.push(u8::from_str_radix(pair, 16).map_err(|_| invalid())?);
+    }
+    Ok(())
+}
+
+fn parse_command<'a, I>(name: &str, mut args: I) -> Result<ScriptCommand, SyntaxError>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut next = || args.next().ok_or(SyntaxError::MissingArgument);
+    let command = match name {
+        "write" => {
+            let offset = parse_number(next()?)?;
+            let mut data = Vec::new();
+            parse_bytes(next()?, &mut data)?;
+            for token in args.by_ref() {
+                parse_bytes(token, &mut data)?;
+            }
+            ScriptCommand::Write { offset, data }
+        }
+        "fill" => {
+            let range = parse_number(next()?)?..parse_number(next()?)?;
+            let mut byte = Vec::new();
+            let token = next()?;
+            parse_bytes(token, &mut byte)?;
+            if byte.len() != 1 {
+                return Err(SyntaxError::InvalidBytes(token.to_string()));
+            }
+            if range.start > range.end || checked_usize(range.end - range.start).is_err() {
+                return Err(SyntaxError::InvalidRange);
+            }
+            ScriptCommand::Fill {
+                range,
+                byte: byte[0],
+            }
+        }
+        "truncate" => ScriptCommand::Truncate {
+            length: parse_number(next()?)?,
+        },
+        "checksum" => {
+            let algorithm = match next()? {
+                "crc16" => ChecksumAlgorithm::Crc16,
+                "crc32" => ChecksumAlgorithm::Crc32,
+                other => return Err(SyntaxError::UnknownAlgorithm(other.to_string())),
+            };
+            let data_range = parse_number(next()?)?..parse_number(next()?)?;
+            match next()? {
+                "->" => {}
+                other => return Err(SyntaxError::UnexpectedArgument(other.to_string())),
+            }
+            let field_offset = parse_number(next()?)?;
+            let endian = match args.next() {
+                None | Some("le") => Endian::Little,
+                Some("be") => Endian::Big,
+                Some(other) => return Err(SyntaxError::UnexpectedArgument(other.to_string())),
+            };
+            ScriptCommand::Checksum {
+                algorithm,
+                data_range,
+                field_offset,
+                endian,
+            }
+        }
+        other => return Err(SyntaxError::UnknownCommand(other.to_string())),
+    };
+    match args.next() {
+        Some(extra) => Err(SyntaxError::UnexpectedArgument(extra.to_string())),
+        None => Ok(command),
+    }
+}
+
+/// Parses a script, see the [module documentation](self) for the format.
+pub fn parse_script(src: &str) -> Result<Vec<ScriptLine>, ScriptError> {
+    let mut commands = Vec::new();
+    for (index, text) in src.lines().enumerate() {
+        let line = index + 1;
+        let text = text.split('#').next().unwrap_or_default();
+        let mut tokens = text.split_whitespace();
+        if let Some(name) = tokens.next() {
+            let command =
+                parse_command(name, tokens).map_err(|error| ScriptError::Syntax { line, error })?;
+            commands.push(ScriptLine { line, command });
+        }
+    }
+    Ok(commands)
+}
+
+/// Sets the length of the data, keeping the bytes it removes for if it is undone.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct SetLengthAction {
+    length: u64,
+    previous_length: u64,
+    removed: Vec<u8>,
+}
+impl<F, E> Action<F, E> for SetLengthAction
+where
+    F: Read + Seek + Write + Truncate,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        self.previous_length = stream_len(data)?;
+        let removed = self.previous_length.saturating_sub(self.length);
+        self.removed.resize(checked_usize(removed)?, 0);
+        read_exact_at(data, self.length, &mut self.removed)?;
+        data.truncate(self.length)?;
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        data.truncate(self.previous_length)?;
+        write_at(data, self.length, &self.removed)?;
+        Ok(())
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.length..u64::MAX)
+    }
+
+    fn description(&self) -> String {
+        format!("Set length to {}", plural_bytes(self.length))
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(SetLengthAction {
+            length: self.length,
+            previous_length: 0,
+            removed: Vec::new(),
+        }))
+    }
+}
+impl MemoryUsage for SetLengthAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.removed.capacity()
+    }
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write + Truncate + 'static,
+    E: Clone + 'static,
+{
+    /// Parses and runs a script (see the [`script`](crate::script) module) as a single action,
+    /// so that one undo reverts all of it.
+    /// If a command fails, the commands before it are rolled back, and the script isn't recorded
+    /// in the history. A script without any commands does nothing.
+    pub fn run_script(&mut self, src: &str, other: E) -> Result<ScriptReport, ScriptError> {
+        let commands = parse_script(src)?;
+        let mut report = ScriptReport {
+            outcomes: commands
+                .iter()
+                .map(|command| CommandOutcome {
+                    line: command.line,
+                    status: CommandStatus::Applied,
+                })
+                .collect(),
+        };
+        if commands.is_empty() {
+            return Ok(report);
+        }
+
+        let mut actions = CompoundAction::new();
+        for ScriptLine { command, .. } in commands {
+            match command {
+                ScriptCommand::Write { offset, data } => {
+                    actions.push(EditAction::new(offset, data))
+                }
+                ScriptCommand::Fill { range, byte } => actions.push(FillAction::new(
+                    range.start,
+                    range.end - range.start,
+                    vec![byte],
+                )),
+                ScriptCommand::Truncate { length } => actions.push(SetLengthAction {
+                    length,
+                    previous_length: 0,
+                    removed: Vec::new(),
+                }),
+                ScriptCommand::Checksum {
+                    algorithm,
+                    data_range,
+                    field_offset,
+                    endian,
+                } => actions.push(ChecksumFieldAction::new(
+                    data_range,
+                    field_offset,
+                    algorithm,
+                    endian,
+                )),
+            }
+        }
+
+        match self.add_action(actions, other) {
+            Ok(()) => Ok(report),
+            Err((actions, error)) => {
+                let failed = actions.failed_at();
+                for (index, outcome) in report.outcomes.iter_mut().enumerate() {
+                    outcome.status = match failed {
+                        Some(failed) if index < failed => CommandStatus::RolledBack,
+                        Some(failed) if index == failed => CommandStatus::Failed,
+                        _ => CommandStatus::Skipped,
+                    };
+                }
+                Err(ScriptError::Failed {
+                    line: failed.map(|index| report.outcomes[index].line),
+                    error,
+                    report,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_script, CommandOutcome, CommandStatus, ScriptCommand, ScriptError, SyntaxError,
+    };
+    use crate::{action::ActionError, checksum::crc32, contents, Hiex};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_parse_script() {
+        let commands =
+            parse_script("# header\n\n  write 0x2 DEAD be ef # comment\nfill 4 8 0a\n").unwrap();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].line, 3);
+        assert_eq!(
+            commands[0].command,
+            ScriptCommand::Write {
+                offset: 2,
+                data: vec![0xde, 0xad, 0xbe, 0xef]
+            }
+        );
+        assert_eq!(
+            commands[1].command,
+            ScriptCommand::Fill {
+                range: 4..8,
+                byte: 0x0a
+            }
+        );
+
+        let syntax = |src: &str| match parse_script(src) {
+            Err(ScriptError::Syntax { line, error }) => (line, error),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(
+            syntax("truncate 4\n\nwrte 0 00"),
+            (3, SyntaxError::UnknownCommand("wrte".to_string()))
+        );
+        assert_eq!(syntax("write 0"), (1, SyntaxError::MissingArgument));
+        assert_eq!(
+            syntax("write 0 ABC"),
+            (1, SyntaxError::InvalidBytes("ABC".to_string()))
+        );
+        assert_eq!(
+            syntax("truncate 0x"),
+            (1, SyntaxError::InvalidNumber("0x".to_string()))
+        );
+        assert_eq!(syntax("fill 8 4 00"), (1, SyntaxError::InvalidRange));
+        assert_eq!(
+            syntax("checksum crc32 0 4 => 8"),
+            (1, SyntaxError::UnexpectedArgument("=>".to_string()))
+        );
+        assert_eq!(
+            syntax("truncate 4 5"),
+            (1, SyntaxError::UnexpectedArgument("5".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_run_script() {
+        let original: Vec<u8> = (0..32).collect();
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(original.clone())).unwrap();
+        let report = hex
+            .run_script(
+                "write 0x10 DE AD BE EF\nfill 0 4 ff\ntruncate 24\nchecksum crc32 0 16 -> 20 be",
+                (),
+            )
+            .unwrap();
+        assert_eq!(report.outcomes.len(), 4);
+        assert!(report
+            .outcomes
+            .iter()
+            .all(|outcome| outcome.status == CommandStatus::Applied));
+
+        let mut expected = original.clone();
+        expected[16..20].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        expected[..4].copy_from_slice(&[0xff; 4]);
+        expected.truncate(24);
+        let checksum = crc32(&expected[..16]).to_be_bytes();
+        expected[20..24].copy_from_slice(&checksum);
+        assert_eq!(contents(&mut hex), expected);
+
+        // One undo reverts the whole script.
+        hex.undo_simple().unwrap();
+        assert_eq!(contents(&mut hex), original);
+        hex.redo_simple().unwrap();
+        assert_eq!(contents(&mut hex), expected);
+    }
+
+    #[test]
+    fn test_run_script_failure() {
+        let original: Vec<u8> = (0..32).collect();
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(original.clone())).unwrap();
+        // The write fails, as it is past the end after truncating.
+        let err = hex
+            .run_script(
+                "fill 0 4 00\ntruncate 8\n# comment\nwrite 30 AA\nwrite 0 BB",
+                (),
+            )
+            .unwrap_err();
+        match err {
+            ScriptError::Failed {
+                line,
+                error,
+                report,
+            } => {
+                assert_eq!(line, Some(4));
+                assert!(matches!(error, ActionError::Invalid));
+                let status = |line, status| CommandOutcome { line, status };
+                assert_eq!(
+                    report.outcomes,
+                    [
+                        status(1, CommandStatus::RolledBack),
+                        status(2, CommandStatus::RolledBack),
+                        status(4, CommandStatus::Failed),
+                        status(5, CommandStatus::Skipped),
+                    ]
+                );
+            }
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(contents(&mut hex), original);
+        assert_eq!(hex.actions.len(), 0);
+
+        assert!(matches!(
+            hex.run_script("write 0 00\nbogus", ()),
+            Err(ScriptError::Syntax { line: 2, .. })
+        ));
+        assert_eq!(contents(&mut hex), original);
+    }
+}
@@ -0,0 +1,807 @@
+//! Searching the data for byte sequences.
+use crate::{
+    action::{translate_offsets, Action, ActionError, MemoryUsage, OffsetShift},
+    checked_usize, clamp_usize, stream_len, write_at, EditAction, Hiex, CHUNK_SIZE,
+};
+use std::{
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+use usize_cast::FromUsize;
+
+/// A match along with the bytes surrounding it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchWithContext {
+    /// The offset of the match in the data.
+    pub offset: u64,
+    /// Up to `context` bytes before the match, fewer if the match is near the start of the data.
+    pub before: Vec<u8>,
+    pub matched: Vec<u8>,
+    /// Up to `context` bytes after the match, fewer if the match is near the end of the data.
+    pub after: Vec<u8>,
+}
+
+/// The result of [`Hiex::compare_ranges`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RangeComparison {
+    Equal,
+    /// The ranges first differ at `offset_within` bytes from their starts.
+    FirstDifference {
+        offset_within: u64,
+    },
+}
+
+/// Compares the `length` bytes at `a` with the `length` bytes at `b`, reading at most `chunk_size`
+/// bytes of each at a time. The ranges must be within the data, but may overlap.
+pub(crate) fn compare_ranges_in<S>(
+    stream: &mut S,
+    a: u64,
+    b: u64,
+    length: u64,
+    chunk_size: usize,
+) -> std::io::Result<RangeComparison>
+where
+    S: Read + Seek,
+{
+    let size = clamp_usize(length, chunk_size.max(1));
+    let mut chunk_a = vec![0u8; size];
+    let mut chunk_b = vec![0u8; size];
+    let mut offset = 0;
+    while offset < length {
+        let amount = clamp_usize(length - offset, size);
+        stream.seek(SeekFrom::Start(a + offset))?;
+        stream.read_exact(&mut chunk_a[..amount])?;
+        stream.seek(SeekFrom::Start(b + offset))?;
+        stream.read_exact(&mut chunk_b[..amount])?;
+
+        if let Some(index) = chunk_a[..amount]
+            .iter()
+            .zip(&chunk_b[..amount])
+            .position(|(x, y)| x != y)
+        {
+            return Ok(RangeComparison::FirstDifference {
+                offset_within: offset + u64::from_usize(index),
+            });
+        }
+        offset += u64::from_usize(amount);
+    }
+    Ok(RangeComparison::Equal)
+}
+
+/// Scans the bytes within `range` for occurrences of `needle`, reading at most `chunk_size` new
+/// bytes at a time. Matches may overlap.
+/// `on_match` is given the stream, the offset of the match, and the buffer it was found in along
+/// with the offset of the start of that buffer. It returns whether to continue scanning.
+/// An empty `needle` has no matches. `buffer` is scratch space, which is cleared first.
+pub(crate) fn scan_matches<S, C>(
+    stream: &mut S,
+    range: Range<u64>,
+    needle: &[u8],
+    chunk_size: usize,
+    buffer: &mut Vec<u8>,
+    mut on_match: C,
+) -> std::io::Result<()>
+where
+    S: Read + Seek,
+    C: FnMut(&mut S, u64, &[u8], u64) -> std::io::Result<bool>,
+{
+    let end = range.end.min(stream_len(stream)?);
+    if needle.is_empty() || range.start >= end {
+        return Ok(());
+    }
+
+    let chunk_size = chunk_size.max(1);
+    // The buffer keeps the last `needle.len() - 1` bytes of the previous chunk, so that matches
+    // which straddle two chunks are found. A match can't fit within the kept bytes alone, so no
+    // match is found twice.
+    buffer.clear();
+    buffer.reserve(chunk_size + needle.len() - 1);
+    let mut buffer_start = range.start;
+    let mut position = range.start;
+    while position < end {
+        let amount = clamp_usize(end - position, chunk_size);
+        let kept = buffer.len();
+        buffer.resize(kept + amount, 0);
+        stream.seek(SeekFrom::Start(position))?;
+        stream.read_exact(&mut buffer[kept..])?;
+        position += u64::from_usize(amount);
+
+        if buffer.len() >= needle.len() {
+            for (index, window) in buffer.windows(needle.len()).enumerate() {
+                if window == needle
+                    && !on_match(
+                        stream,
+                        buffer_start + u64::from_usize(index),
+                        buffer,
+                        buffer_start,
+                    )?
+                {
+                    return Ok(());
+                }
+            }
+        }
+
+        let keep = buffer.len().min(needle.len() - 1);
+        buffer.drain(..buffer.len() - keep);
+        buffer_start = position - u64::from_usize(keep);
+    }
+
+    Ok(())
+}
+
+/// Collects the matches of `needle` within `range` with up to `context` bytes on each side.
+/// The context is taken from the buffer the match was found in, and is only read separately when
+/// it extends past that buffer.
+pub(crate) fn find_all_with_context_in<S>(
+    stream: &mut S,
+    needle: &[u8],
+    range: Range<u64>,
+    context: usize,
+    chunk_size: usize,
+) -> std::io::Result<Vec<MatchWithContext>>
+where
+    S: Read + Seek,
+{
+    let length = stream_len(stream)?;
+    let context = u64::from_usize(context);
+    let mut matches = Vec::new();
+    scan_matches(
+        stream,
+        range,
+        needle,
+        chunk_size,
+        &mut Vec::new(),
+        |stream, offset, buffer, buffer_start| {
+            let match_end = offset + u64::from_usize(needle.len());
+            let buffer_end = buffer_start + u64::from_usize(buffer.len());
+            let before_start = offset.saturating_sub(context);
+            let after_end = match_end.saturating_add(context).min(length);
+
+            let slice = |stream: &mut S, start: u64, end: u64| -> std::io::Result<Vec<u8>> {
+                if start >= buffer_start && end <= buffer_end {
+                    let start = clamp_usize(start - buffer_start, buffer.len());
+                    let end = clamp_usize(end - buffer_start, buffer.len());
+                    Ok(buffer[start..end].to_vec())
+                } else {
+                    let mut bytes = vec![0; checked_usize(end - start)?];
+                    stream.seek(SeekFrom::Start(start))?;
+                    stream.read_exact(&mut bytes)?;
+                    Ok(bytes)
+                }
+            };
+
+            matches.push(MatchWithContext {
+                offset,
+                before: slice(stream, before_start, offset)?,
+                matched: needle.to_vec(),
+                after: slice(stream, match_end, after_end)?,
+            });
+            Ok(true)
+        },
+    )?;
+    Ok(matches)
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Finds the offset of the first occurrence of `needle` entirely within `range`.
+    pub fn find_next(&mut self, needle: &[u8], range: Range<u64>) -> std::io::Result<Option<u64>> {
+        let mut found = None;
+        let mut buffer = self
+            .actions
+            .pool_mut()
+            .take_empty(CHUNK_SIZE + needle.len());
+        let result = scan_matches(
+            self,
+            range,
+            needle,
+            CHUNK_SIZE,
+            &mut buffer,
+            |_, offset, _, _| {
+                found = Some(offset);
+                Ok(false)
+            },
+        );
+        self.actions.pool_mut().give(buffer);
+        result.map(|()| found)
+    }
+
+    /// Finds the offsets of every occurrence of `needle` entirely within `range`.
+    /// Occurrences may overlap.
+    pub fn find_all(&mut self, needle: &[u8], range: Range<u64>) -> std::io::Result<Vec<u64>> {
+        let mut found = Vec::new();
+        let mut buffer = self
+            .actions
+            .pool_mut()
+            .take_empty(CHUNK_SIZE + needle.len());
+        let result = scan_matches(
+            self,
+            range,
+            needle,
+            CHUNK_SIZE,
+            &mut buffer,
+            |_, offset, _, _| {
+                found.push(offset);
+                Ok(true)
+            },
+        );
+        self.actions.pool_mut().give(buffer);
+        result.map(|()| found)
+    }
+
+    /// Replaces every occurrence of `needle` within `range` with `replacement`, as a single
+    /// action, see [`ReplaceAllAction`].
+    pub fn replace_all(
+        &mut self,
+        needle: Vec<u8>,
+        replacement: Vec<u8>,
+        range: Range<u64>,
+        other: E,
+    ) -> Result<(), (ReplaceAllAction, ActionError)> {
+        self.add_action(ReplaceAllAction::new(needle, replacement, range), other)
+    }
+
+    /// Compares the `length` bytes at `a` with the `length` bytes at `b`, in chunks so that long
+    /// ranges don't need to be read into memory, stopping at the first difference.
+    /// The ranges may overlap. Fails with `ErrorKind::InvalidInput` if either runs past the end of
+    /// the data. The position is restored afterwards.
+    pub fn compare_ranges(
+        &mut self,
+        a: u64,
+        b: u64,
+        length: u64,
+    ) -> std::io::Result<RangeComparison> {
+        let data_length = self.length()?;
+        let in_bounds = |start: u64| {
+            start
+                .checked_add(length)
+                .is_some_and(|end| end <= data_length)
+        };
+        if !in_bounds(a) || !in_bounds(b) {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "compared range runs past the end of the data",
+            ));
+        }
+
+        let position = self.position()?;
+        let result = compare_ranges_in(self, a, b, length, CHUNK_SIZE);
+        self.seek(SeekFrom::Start(position))?;
+        result
+    }
+
+    /// Like [`Hiex::find_all`], but also gives up to `context` bytes on each side of every match.
+    /// The context may extend outside of `range`, but not outside of the data.
+    pub fn find_all_with_context(
+        &mut self,
+        needle: &[u8],
+        range: Range<u64>,
+        context: usize,
+    ) -> std::io::Result<Vec<MatchWithContext>> {
+        find_all_with_context_in(self, needle, range, context, CHUNK_SIZE)
+    }
+}
+
+/// The matches of a needle over all of the data, kept current as the data is edited without
+/// re-scanning all of it.
+///
+/// Nothing notifies this of edits by itself: after each change to the data, call
+/// [`LiveSearch::notify_edit`] with the range of bytes that were overwritten, or
+/// [`LiveSearch::notify_shift`] for bytes being inserted or removed (see
+/// [`Action::offset_shift`](crate::action::Action::offset_shift)). Only the bytes around the
+/// change are re-scanned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiveSearch {
+    needle: Vec<u8>,
+    /// Sorted. Matches may overlap.
+    matches: Vec<u64>,
+}
+impl LiveSearch {
+    /// Scans all of the data for `needle`.
+    /// Fails with `ErrorKind::InvalidInput` if `needle` is empty.
+    pub fn new<F, E>(hex: &mut Hiex<F, E>, needle: Vec<u8>) -> std::io::Result<Self>
+    where
+        F: Read + Seek + Write,
+    {
+        if needle.is_empty() {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "needle is empty",
+            ));
+        }
+        let matches = hex.find_all(&needle, 0..u64::MAX)?;
+        Ok(Self { needle, matches })
+    }
+
+    pub fn needle(&self) -> &[u8] {
+        &self.needle
+    }
+
+    /// The offsets of the matches, in ascending order.
+    pub fn matches(&self) -> &[u64] {
+        &self.matches
+    }
+
+    /// Updates the matches after the bytes within `range` were overwritten, or after the data grew
+    /// or shrank to `range.start` (with `range` covering the bytes added).
+    /// Matches which include any of the bytes are re-checked, and matches past the end of the data
+    /// are dropped.
+    pub fn notify_edit<F, E>(
+        &mut self,
+        hex: &mut Hiex<F, E>,
+        range: Range<u64>,
+    ) -> std::io::Result<()>
+    where
+        F: Read + Seek + Write,
+    {
+        let length = hex.length()?;
+        let needle_length = u64::from_usize(self.needle.len());
+        let last_start = length.checked_sub(needle_length);
+        self.matches
+            .retain(|&offset| last_start.is_some_and(|last| offset <= last));
+
+        // Every match starting here includes some of the bytes.
+        let start = range.start.saturating_sub(needle_length - 1);
+        let end = range.end.max(range.start);
+        let found = hex.find_all(&self.needle, start..end.saturating_add(needle_length - 1))?;
+
+        let first = self.matches.partition_point(|&offset| offset < start);
+        let last = self.matches.partition_point(|&offset| offset < end);
+        self.matches.splice(first..last, found);
+        Ok(())
+    }
+
+    /// Updates the matches after bytes were inserted or removed, as described by `shift`.
+    /// Matches after the change are moved along with their bytes, and matches which include the
+    /// change are re-checked.
+    pub fn notify_shift<F, E>(
+        &mut self,
+        hex: &mut Hiex<F, E>,
+        shift: &OffsetShift,
+    ) -> std::io::Result<()>
+    where
+        F: Read + Seek + Write,
+    {
+        let edited = match shift {
+            OffsetShift::Insert { position, length } => *position..position + length,
+            OffsetShift::Delete { range } => {
+                // Matches starting within the removed bytes are gone.
+                self.matches.retain(|offset| !range.contains(offset));
+                range.start..range.start
+            }
+        };
+        for offset in self.matches.iter_mut() {
+            *offset = shift.offset(*offset);
+        }
+        self.notify_edit(hex, edited)
+    }
+}
+
+/// An interactive find-and-replace over a range, where each match can be replaced or skipped.
+/// Created by [`Hiex::replace_session`].
+///
+/// The replacement is the same length as the needle, so replacing never moves later bytes, but it
+/// can create or destroy matches. Scanning always continues after the end of a replaced match (or
+/// one byte after a skipped match), reading the data as it currently is, so matches created by a
+/// replacement which start before that point are not found.
+///
+/// Replacements are normal actions in the history. If the history is changed while the session is
+/// in use (such as undoing a replacement through [`ReplaceSession::hex`]), the session does not
+/// revisit earlier bytes, and [`ReplaceSession::replace_current`] fails with
+/// `ActionError::Invalid` if the current match no longer exists.
+pub struct ReplaceSession<'a, F, E>
+where
+    F: Read + Seek + Write,
+{
+    hex: &'a mut Hiex<F, E>,
+    needle: Vec<u8>,
+    replacement: Vec<u8>,
+    range: Range<u64>,
+    /// Where the next scan starts.
+    cursor: u64,
+    /// The offset of the match found by the last call to `find_next`, if it hasn't been replaced
+    /// or skipped yet.
+    current: Option<u64>,
+}
+impl<'a, F, E> ReplaceSession<'a, F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// The editor being searched.
+    pub fn hex(&mut self) -> &mut Hiex<F, E> {
+        self.hex
+    }
+
+    /// The offset of the current match, if there is one.
+    pub fn current(&self) -> Option<u64> {
+        self.current
+    }
+
+    /// Finds the next match, which becomes the current match.
+    /// If there already was a current match, it is skipped.
+    pub fn find_next(&mut self) -> std::io::Result<Option<u64>> {
+        let found = self
+            .hex
+            .find_next(&self.needle, self.cursor..self.range.end)?;
+        if let Some(offset) = found {
+            self.cursor = offset + 1;
+        }
+        self.current = found;
+        Ok(found)
+    }
+
+    /// Replaces the current match, as a single action, and continues scanning after it.
+    /// Fails with `ActionError::Invalid` if there is no current match, or if the data at the
+    /// current match no longer matches the needle.
+    pub fn replace_current(&mut self, other: E) -> Result<(), ActionError> {
+        let offset = self.current.ok_or(ActionError::Invalid)?;
+        let existing = self.hex.read_amount_at(offset, self.needle.len())?;
+        if existing != self.needle {
+            self.current = None;
+            return Err(ActionError::Invalid);
+        }
+
+        self.hex
+            .add_action(EditAction::new(offset, self.replacement.clone()), other)
+            .map_err(|(_, err)| err)?;
+        self.cursor = offset + u64::from_usize(self.needle.len());
+        self.current = None;
+        Ok(())
+    }
+
+    /// Leaves the current match as it is. The next scan starts one byte after it, so matches
+    /// overlapping it can still be found.
+    pub fn skip_current(&mut self) {
+        self.current = None;
+    }
+}
+
+/// An action which replaces every occurrence of `needle` within `range` with `replacement`, as a
+/// single entry in the history.
+/// Occurrences are found from the start of the range, and don't overlap: scanning continues after
+/// the end of each one. As with [`ReplaceSession`], the replacement has to be the same length as
+/// the needle, so that later bytes never move.
+/// Only the offsets of the occurrences are stored, since undoing writes the needle back there.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReplaceAllAction {
+    pub needle: Vec<u8>,
+    pub replacement: Vec<u8>,
+    pub range: Range<u64>,
+    matches: Vec<u64>,
+}
+impl ReplaceAllAction {
+    pub fn new(needle: Vec<u8>, replacement: Vec<u8>, range: Range<u64>) -> Self {
+        Self {
+            needle,
+            replacement,
+            range,
+            matches: Vec::new(),
+        }
+    }
+
+    /// The offsets of the occurrences which were replaced, once applied.
+    pub fn matches(&self) -> &[u64] {
+        &self.matches
+    }
+}
+impl<F, E> Action<F, E> for ReplaceAllAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        if self.needle.is_empty() || self.needle.len() != self.replacement.len() {
+            return Err(ActionError::Invalid);
+        }
+
+        let length = u64::from_usize(self.needle.len());
+        let mut matches = Vec::new();
+        let mut buffer = Vec::new();
+        scan_matches(
+            data,
+            self.range.clone(),
+            &self.needle,
+            CHUNK_SIZE,
+            &mut buffer,
+            |_, offset, _, _| {
+                if matches.last().map_or(true, |last| offset >= last + length) {
+                    matches.push(offset);
+                }
+                Ok(true)
+            },
+        )?;
+        // Every occurrence is found before any are replaced, so a replacement can't create
+        // another occurrence.
+        for &offset in &matches {
+            write_at(data, offset, &self.replacement)?;
+        }
+        self.matches = matches;
+
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        for &offset in &self.matches {
+            write_at(data, offset, &self.needle)?;
+        }
+        Ok(())
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.range.clone())
+    }
+
+    fn description(&self) -> String {
+        match self.matches.len() {
+            1 => "Replace 1 match".to_string(),
+            count => format!("Replace {} matches", count),
+        }
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(ReplaceAllAction::new(
+            self.needle.clone(),
+            self.replacement.clone(),
+            self.range.clone(),
+        )))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.range.start, &mut self.range.end], delta)
+    }
+}
+impl MemoryUsage for ReplaceAllAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.needle.len()
+            + self.replacement.len()
+            + self.matches.len() * std::mem::size_of::<u64>()
+    }
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Start replacing occurrences of `needle` within `range` with `replacement` one at a time.
+    /// Fails with `ActionError::Invalid` if `needle` is empty or `replacement` is a different
+    /// length.
+    pub fn replace_session(
+        &mut self,
+        needle: Vec<u8>,
+        replacement: Vec<u8>,
+        range: Range<u64>,
+    ) -> Result<ReplaceSession<'_, F, E>, ActionError> {
+        if needle.is_empty() || needle.len() != replacement.len() {
+            return Err(ActionError::Invalid);
+        }
+
+        Ok(ReplaceSession {
+            hex: self,
+            needle,
+            replacement,
+            cursor: range.start,
+            range,
+            current: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        compare_ranges_in, find_all_with_context_in, LiveSearch, RangeComparison, ReplaceAllAction,
+    };
+    use crate::{
+        action::{ActionError, OffsetShift},
+        EditAction, Hiex,
+    };
+    use std::io::{Cursor, ErrorKind, Seek, SeekFrom};
+
+    #[test]
+    fn test_find() {
+        let mut hex = Hiex::<_, ()>::from_reader(Cursor::new(b"abcabcaab".to_vec())).unwrap();
+        assert_eq!(hex.find_next(b"bc", 0..9).unwrap(), Some(1));
+        assert_eq!(hex.find_next(b"bc", 2..9).unwrap(), Some(4));
+        assert_eq!(hex.find_next(b"bc", 2..5).unwrap(), None);
+        assert_eq!(hex.find_all(b"a", 0..100).unwrap(), vec![0, 3, 6, 7]);
+        assert_eq!(hex.find_all(b"", 0..9).unwrap(), Vec::<u64>::new());
+
+        // Matches straddling chunks are found exactly once.
+        let mut data = Cursor::new(b"xxabxxabab".to_vec());
+        let found = find_all_with_context_in(&mut data, b"ab", 0..10, 0, 3).unwrap();
+        let offsets = found.iter().map(|m| m.offset).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![2, 6, 8]);
+    }
+
+    #[test]
+    fn test_find_with_context() {
+        let mut hex =
+            Hiex::<_, ()>::from_reader(Cursor::new(b"key=1;key=22;key".to_vec())).unwrap();
+        let found = hex.find_all_with_context(b"key", 0..16, 3).unwrap();
+        assert_eq!(found.len(), 3);
+        // At the start of the data.
+        assert_eq!(found[0].offset, 0);
+        assert_eq!(found[0].before, b"");
+        assert_eq!(found[0].matched, b"key");
+        assert_eq!(found[0].after, b"=1;");
+        assert_eq!(found[1].before, b"=1;");
+        assert_eq!(found[1].after, b"=22");
+        // At the end of the data.
+        assert_eq!(found[2].offset, 13);
+        assert_eq!(found[2].before, b"22;");
+        assert_eq!(found[2].after, b"");
+
+        // Context spanning chunk boundaries in both directions.
+        let mut data = Cursor::new(b"0123456789".to_vec());
+        let found = find_all_with_context_in(&mut data, b"45", 0..10, 3, 4).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].before, b"123");
+        assert_eq!(found[0].after, b"678");
+    }
+
+    #[test]
+    fn test_replace_session() {
+        let mut hex = Hiex::<_, ()>::from_reader(Cursor::new(b"ababab".to_vec())).unwrap();
+        {
+            let mut session = hex
+                .replace_session(b"ab".to_vec(), b"XY".to_vec(), 0..6)
+                .unwrap();
+            assert_eq!(session.find_next().unwrap(), Some(0));
+            session.skip_current();
+            assert_eq!(session.find_next().unwrap(), Some(2));
+            session.replace_current(()).unwrap();
+            assert_eq!(session.find_next().unwrap(), Some(4));
+            session.replace_current(()).unwrap();
+            assert_eq!(session.find_next().unwrap(), None);
+            assert!(matches!(
+                session.replace_current(()),
+                Err(ActionError::Invalid)
+            ));
+        }
+        assert_eq!(hex.read_amount_at(0, 6).unwrap(), b"abXYXY");
+        // Each replacement is its own action.
+        assert_eq!(hex.actions.past_len(), 2);
+        hex.undo(()).unwrap();
+        assert_eq!(hex.read_amount_at(0, 6).unwrap(), b"abXYab");
+    }
+
+    #[test]
+    fn test_replace_all() {
+        let mut hex = Hiex::<_, ()>::from_reader(Cursor::new(b"aaaXaabaa".to_vec())).unwrap();
+        hex.add_action(EditAction::new(8, b"b".to_vec()), ())
+            .unwrap();
+        // Occurrences don't overlap, and the replacements don't create new ones.
+        hex.replace_all(b"aa".to_vec(), b"ba".to_vec(), 0..8, ())
+            .unwrap();
+        assert_eq!(hex.read_amount_at(0, 9).unwrap(), b"baaXbabab");
+        assert_eq!(hex.actions.past_len(), 2);
+        hex.undo(()).unwrap();
+        assert_eq!(hex.read_amount_at(0, 9).unwrap(), b"aaaXaabab");
+        hex.redo(()).unwrap();
+        assert_eq!(hex.read_amount_at(0, 9).unwrap(), b"baaXbabab");
+
+        let mut action = ReplaceAllAction::new(b"ab".to_vec(), b"--".to_vec(), 0..100);
+        let mut data = Cursor::new(b"abxabab".to_vec());
+        crate::action::Action::<_, ()>::apply(&mut action, &mut data, ()).unwrap();
+        assert_eq!(action.matches(), [0, 3, 5]);
+        assert_eq!(data.get_ref(), b"--x----");
+
+        // The replacement has to be the same length.
+        assert!(matches!(
+            hex.replace_all(b"a".to_vec(), b"bb".to_vec(), 0..9, ()),
+            Err((_, ActionError::Invalid))
+        ));
+    }
+
+    #[test]
+    fn test_replace_session_changed_data() {
+        // Replacing creates a match which starts before the end of the replacement, which isn't
+        // revisited.
+        let mut hex = Hiex::<_, ()>::from_reader(Cursor::new(b"aab".to_vec())).unwrap();
+        let mut session = hex
+            .replace_session(b"ab".to_vec(), b"ba".to_vec(), 0..3)
+            .unwrap();
+        assert_eq!(session.find_next().unwrap(), Some(1));
+        session.replace_current(()).unwrap();
+        assert_eq!(session.find_next().unwrap(), None);
+        assert_eq!(session.hex().read_amount_at(0, 3).unwrap(), b"aba");
+
+        // The current match is destroyed from outside the session.
+        let mut hex = Hiex::<_, ()>::from_reader(Cursor::new(b"xabab".to_vec())).unwrap();
+        let mut session = hex
+            .replace_session(b"ab".to_vec(), b"--".to_vec(), 0..5)
+            .unwrap();
+        assert_eq!(session.find_next().unwrap(), Some(1));
+        session
+            .hex()
+            .add_action(EditAction::new(1, b"z".to_vec()), ())
+            .unwrap();
+        assert!(matches!(
+            session.replace_current(()),
+            Err(ActionError::Invalid)
+        ));
+        assert_eq!(session.find_next().unwrap(), Some(3));
+        session.replace_current(()).unwrap();
+        assert_eq!(session.hex().read_amount_at(0, 5).unwrap(), b"xzb--");
+    }
+
+    #[test]
+    fn test_live_search() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"xxabcxxxxabcx".to_vec())).unwrap();
+        let mut live = LiveSearch::new(&mut hex, b"abc".to_vec()).unwrap();
+        assert_eq!(live.matches(), [2, 9]);
+        let rescan = |hex: &mut Hiex<_>| LiveSearch::new(hex, b"abc".to_vec()).unwrap();
+
+        // Destroys the match at 2, and creates one at 4.
+        hex.add_action_simple(EditAction::new(4, b"abc".to_vec()))
+            .unwrap();
+        live.notify_edit(&mut hex, 4..7).unwrap();
+        assert_eq!(live.matches(), [4, 9]);
+        assert_eq!(live, rescan(&mut hex));
+
+        // Inserting within a match destroys it, and moves the later one.
+        hex.insert_bytes(5, b"c".to_vec(), ()).unwrap();
+        let shift = OffsetShift::Insert {
+            position: 5,
+            length: 1,
+        };
+        live.notify_shift(&mut hex, &shift).unwrap();
+        assert_eq!(live.matches(), [10]);
+        assert_eq!(live, rescan(&mut hex));
+
+        // Removing bytes can join a match back together.
+        hex.delete_bytes(5..6, ()).unwrap();
+        live.notify_shift(&mut hex, &OffsetShift::Delete { range: 5..6 })
+            .unwrap();
+        assert_eq!(live.matches(), [4, 9]);
+        assert_eq!(live, rescan(&mut hex));
+
+        // Shrinking drops the matches past the end.
+        hex.delete_bytes(10..13, ()).unwrap();
+        live.notify_shift(&mut hex, &OffsetShift::Delete { range: 10..13 })
+            .unwrap();
+        assert_eq!(live.matches(), [4]);
+        assert_eq!(live, rescan(&mut hex));
+
+        // Growing finds matches in the new bytes, including ones that straddle the old end.
+        hex.append_from(Cursor::new(b"bcabc"), ()).unwrap();
+        live.notify_edit(&mut hex, 10..15).unwrap();
+        assert_eq!(live.matches(), [4, 9, 12]);
+        assert_eq!(live, rescan(&mut hex));
+
+        assert!(LiveSearch::new(&mut hex, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_compare_ranges() {
+        let mut hex = Hiex::<_, ()>::from_reader(Cursor::new(b"abcdXabcdYabcdX".to_vec())).unwrap();
+        hex.seek(SeekFrom::Start(7)).unwrap();
+        assert_eq!(
+            hex.compare_ranges(0, 10, 5).unwrap(),
+            RangeComparison::Equal
+        );
+        assert_eq!(
+            hex.compare_ranges(0, 5, 5).unwrap(),
+            RangeComparison::FirstDifference { offset_within: 4 }
+        );
+        assert_eq!(hex.position().unwrap(), 7);
+        assert_eq!(
+            hex.compare_ranges(0, 11, 5).unwrap_err().kind(),
+            ErrorKind::InvalidInput
+        );
+
+        // Overlapping ranges, across several chunks.
+        let mut data = Cursor::new(b"abababababX".to_vec());
+        assert_eq!(
+            compare_ranges_in(&mut data, 0, 2, 8, 3).unwrap(),
+            RangeComparison::Equal
+        );
+        assert_eq!(
+            compare_ranges_in(&mut data, 0, 2, 9, 3).unwrap(),
+            RangeComparison::FirstDifference { offset_within: 8 }
+        );
+    }
+}
@@ -0,0 +1,421 @@
+//! Keeping large data in temporary files rather than memory: a backend which starts in memory
+//! and moves to a temporary file once it grows large, and an edit which stores the bytes it
+//! overwrites in one.
+use crate::{
+    action::{check_range, describe_bytes, translate_offsets, Action, ActionError, MemoryUsage},
+    known_length::KnownLength,
+    positioned_io::PositionedIo,
+    read_exact_at,
+    truncate::Truncate,
+    write_at, Hiex, CHUNK_SIZE,
+};
+use std::{
+    fs::File,
+    io::{Cursor, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+use tempfile::NamedTempFile;
+use usize_cast::FromUsize;
+
+#[derive(Debug)]
+enum Storage {
+    Memory(Cursor<Vec<u8>>),
+    File(NamedTempFile),
+}
+
+/// A backend which keeps its data in memory until its length would exceed a threshold, through
+/// a write or growing it with [`Truncate`], at which point it copies the data into a temporary
+/// file and forwards everything to that from then on. The position is kept across the move.
+/// Since the type of the backend doesn't change, the history is unaffected, and actions applied
+/// before the move can be undone after it.
+/// The temporary file is deleted when this is dropped. Nothing moves it back into memory.
+#[derive(Debug)]
+pub struct SpillingBackend {
+    storage: Storage,
+    threshold: u64,
+    temp_dir: Option<PathBuf>,
+}
+impl SpillingBackend {
+    /// The default for [`SpillingBackend::with_threshold`].
+    pub const DEFAULT_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+    pub fn new() -> Self {
+        Self::from_vec(Vec::new())
+    }
+
+    /// Starts in memory with `data`, even if it is already past the threshold. It is moved to a
+    /// file when it next grows, or with [`SpillingBackend::spill`].
+    pub fn from_vec(data: Vec<u8>) -> Self {
+        Self {
+            storage: Storage::Memory(Cursor::new(data)),
+            threshold: Self::DEFAULT_THRESHOLD,
+            temp_dir: None,
+        }
+    }
+
+    /// Move to a file once the length would exceed `threshold` bytes.
+    pub fn with_threshold(mut self, threshold: u64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Create the temporary file within `dir`, rather than the system's temporary directory.
+    pub fn with_temp_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.temp_dir = Some(dir.into());
+        self
+    }
+
+    pub fn threshold(&self) -> u64 {
+        self.threshold
+    }
+
+    /// Whether the data has been moved to a temporary file.
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.storage, Storage::File(_))
+    }
+
+    /// The path of the temporary file, if the data has been moved to one.
+    pub fn path(&self) -> Option<&Path> {
+        match &self.storage {
+            Storage::Memory(_) => None,
+            Storage::File(file) => Some(file.path()),
+        }
+    }
+
+    /// Moves the data to a temporary file now, if it isn't in one already.
+    /// If this fails then the data stays in memory.
+    pub fn spill(&mut self) -> std::io::Result<()> {
+        let memory = match &self.storage {
+            Storage::Memory(memory) => memory,
+            Storage::File(_) => return Ok(()),
+        };
+        let mut file = match &self.temp_dir {
+            Some(dir) => NamedTempFile::new_in(dir)?,
+            None => NamedTempFile::new()?,
+        };
+        file.write_all(memory.get_ref())?;
+        file.seek(SeekFrom::Start(memory.position()))?;
+        trace!(length = memory.get_ref().len(); "spilled backend to a temporary file");
+        self.storage = Storage::File(file);
+        Ok(())
+    }
+
+    /// Spills if growing to `end` would exceed the threshold.
+    fn grow_to(&mut self, end: u64) -> std::io::Result<()> {
+        if let Storage::Memory(memory) = &self.storage {
+            if end > self.threshold && end > u64::from_usize(memory.get_ref().len()) {
+                self.spill()?;
+            }
+        }
+        Ok(())
+    }
+}
+impl Default for SpillingBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Read for SpillingBackend {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match &mut self.storage {
+            Storage::Memory(memory) => memory.read(buf),
+            Storage::File(file) => file.read(buf),
+        }
+    }
+}
+impl Write for SpillingBackend {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Storage::Memory(memory) = &self.storage {
+            let end = memory.position().saturating_add(u64::from_usize(buf.len()));
+            self.grow_to(end)?;
+        }
+        match &mut self.storage {
+            Storage::Memory(memory) => memory.write(buf),
+            Storage::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.storage {
+            Storage::Memory(memory) => memory.flush(),
+            Storage::File(file) => file.flush(),
+        }
+    }
+}
+impl Seek for SpillingBackend {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match &mut self.storage {
+            Storage::Memory(memory) => memory.seek(pos),
+            Storage::File(file) => file.seek(pos),
+        }
+    }
+}
+impl KnownLength for SpillingBackend {
+    fn known_length(&self) -> Option<u64> {
+        match &self.storage {
+            Storage::Memory(memory) => memory.known_length(),
+            Storage::File(file) => file.known_length(),
+        }
+    }
+}
+impl PositionedIo for SpillingBackend {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Option<std::io::Result<usize>> {
+        match &self.storage {
+            Storage::Memory(memory) => memory.read_at(offset, buf),
+            Storage::File(file) => file.read_at(offset, buf),
+        }
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Option<std::io::Result<usize>> {
+        if let Err(err) = self.grow_to(offset.saturating_add(u64::from_usize(buf.len()))) {
+            return Some(Err(err));
+        }
+        match &mut self.storage {
+            Storage::Memory(memory) => memory.write_at(offset, buf),
+            Storage::File(file) => file.write_at(offset, buf),
+        }
+    }
+}
+impl Truncate for SpillingBackend {
+    fn truncate(&mut self, new_len: u64) -> std::io::Result<()> {
+        self.grow_to(new_len)?;
+        match &mut self.storage {
+            Storage::Memory(memory) => memory.truncate(new_len),
+            Storage::File(file) => file.truncate(new_len),
+        }
+    }
+}
+
+/// An [`EditAction`](crate::EditAction) for large amounts of data, such as pasting hundreds of
+/// megabytes, which stores the bytes it overwrites in a temporary file rather than in memory,
+/// so that only the new bytes are held in memory. The bytes are read, stored, and written in
+/// chunks. If writing fails partway, the bytes already overwritten are written back, and it
+/// fails with `ActionError::PartialWrite`.
+/// The temporary file is deleted when the action is dropped.
+#[derive(Debug)]
+pub struct SpilledEditAction {
+    pub position: u64,
+    pub new_data: Vec<u8>,
+    temp_dir: Option<PathBuf>,
+    /// The overwritten bytes, once the action has been applied.
+    previous: Option<File>,
+}
+impl SpilledEditAction {
+    pub fn new(position: u64, new_data: Vec<u8>) -> Self {
+        Self {
+            position,
+            new_data,
+            temp_dir: None,
+            previous: None,
+        }
+    }
+
+    /// Create the temporary file within `dir`, rather than the system's temporary directory.
+    pub fn with_temp_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.temp_dir = Some(dir.into());
+        self
+    }
+
+    /// Write the first `length` bytes stored in `previous` back over the data.
+    fn restore<F>(&self, data: &mut F, previous: &mut File, length: usize) -> std::io::Result<()>
+    where
+        F: Read + Seek + Write,
+    {
+        previous.seek(SeekFrom::Start(0))?;
+        let mut buffer = vec![0u8; length.min(CHUNK_SIZE)];
+        let mut offset = 0;
+        while offset < length {
+            let chunk = &mut buffer[..(length - offset).min(CHUNK_SIZE)];
+            previous.read_exact(chunk)?;
+            write_at(data, self.position + u64::from_usize(offset), chunk)?;
+            offset += chunk.len();
+        }
+        Ok(())
+    }
+}
+impl<F, E> Action<F, E> for SpilledEditAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        check_range(data, self.position, u64::from_usize(self.new_data.len()))?;
+        let mut previous = match self.previous.take() {
+            Some(previous) => previous,
+            None => match &self.temp_dir {
+                Some(dir) => tempfile::tempfile_in(dir)?,
+                None => tempfile::tempfile()?,
+            },
+        };
+        previous.seek(SeekFrom::Start(0))?;
+
+        let mut buffer = vec![0u8; self.new_data.len().min(CHUNK_SIZE)];
+        let mut offset = 0;
+        for chunk in self.new_data.chunks(CHUNK_SIZE) {
+            let position = self.position + u64::from_usize(offset);
+            let previous_chunk = &mut buffer[..chunk.len()];
+            let stored = read_exact_at(data, position, previous_chunk)
+                .and_then(|()| previous.write_all(previous_chunk));
+            let (error, rolled_back) = match stored {
+                Err(error) if offset == 0 => {
+                    self.previous = Some(previous);
+                    return Err(error.into());
+                }
+                // Best-effort rollback of the chunks already written.
+                Err(error) => {
+                    let rolled_back = self.restore(data, &mut previous, offset).is_ok();
+                    (error, rolled_back)
+                }
+                Ok(()) => match write_at(data, position, chunk) {
+                    Ok(()) => {
+                        offset += chunk.len();
+                        continue;
+                    }
+                    // This chunk may have been partly written, so it is rolled back as well.
+                    Err(error) => {
+                        let rolled_back = write_at(data, position, previous_chunk).is_ok()
+                            && self.restore(data, &mut previous, offset).is_ok();
+                        (error, rolled_back)
+                    }
+                },
+            };
+            self.previous = Some(previous);
+            return Err(ActionError::PartialWrite { error, rolled_back });
+        }
+        self.previous = Some(previous);
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        let mut previous = self.previous.take().ok_or(ActionError::Invalid)?;
+        let result = self.restore(data, &mut previous, self.new_data.len());
+        self.previous = Some(previous);
+        Ok(result?)
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        let length = u64::from_usize(self.new_data.len());
+        Some(self.position..self.position.saturating_add(length))
+    }
+
+    fn description(&self) -> String {
+        describe_bytes(
+            "Overwrite",
+            self.position,
+            u64::from_usize(self.new_data.len()),
+        )
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(SpilledEditAction {
+            position: self.position,
+            new_data: self.new_data.clone(),
+            temp_dir: self.temp_dir.clone(),
+            previous: None,
+        }))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.position], delta)
+    }
+}
+impl MemoryUsage for SpilledEditAction {
+    /// The overwritten bytes are on disk, so aren't counted.
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.new_data.capacity()
+    }
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Overwrites the bytes at `position` with `data` as a single action, storing the bytes it
+    /// overwrites in a temporary file. See [`SpilledEditAction`].
+    pub fn edit_spilled(
+        &mut self,
+        position: u64,
+        data: Vec<u8>,
+        other: E,
+    ) -> Result<(), (SpilledEditAction, ActionError)> {
+        self.add_action(SpilledEditAction::new(position, data), other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SpillingBackend;
+    use crate::{
+        action::{ActionError, MemoryUsage},
+        contents, AppendAction, EditAction, Hiex, CHUNK_SIZE,
+    };
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+    #[test]
+    fn test_spill_on_write() {
+        let mut backend = SpillingBackend::from_vec(b"0123456789".to_vec()).with_threshold(16);
+        backend.seek(SeekFrom::Start(8)).unwrap();
+        backend.write_all(b"ab").unwrap();
+        assert!(!backend.is_spilled());
+
+        // Crosses the threshold partway through.
+        backend.write_all(b"cdefghijkl").unwrap();
+        assert!(backend.is_spilled());
+        let path = backend.path().unwrap().to_path_buf();
+        assert!(path.exists());
+        assert_eq!(backend.stream_position().unwrap(), 20);
+        backend.write_all(b"!").unwrap();
+
+        let mut contents = Vec::new();
+        backend.seek(SeekFrom::Start(0)).unwrap();
+        backend.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, b"01234567abcdefghijkl!");
+        drop(backend);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_spill_undo() {
+        let backend = SpillingBackend::from_vec(vec![0; 24]).with_threshold(32);
+        let mut hex: Hiex<_> = Hiex::from_reader(backend).unwrap();
+        hex.add_action_simple(EditAction::new(4, b"abcd".to_vec()))
+            .unwrap();
+
+        hex.add_action_simple(AppendAction::new(vec![b'x'; 16]))
+            .unwrap();
+        assert!(hex.reader().is_spilled());
+        assert_eq!(hex.length().unwrap(), 40);
+
+        // Both the action which spilled and the one before it can be undone.
+        hex.undo_simple().unwrap();
+        hex.undo_simple().unwrap();
+        assert_eq!(hex.length().unwrap(), 24);
+        assert_eq!(contents(&mut hex), vec![0; 24]);
+        hex.redo_simple().unwrap();
+        assert_eq!(hex.read_amount_at(0, 8).unwrap(), b"\0\0\0\0abcd");
+    }
+    #[test]
+    fn test_spilled_edit() {
+        let original: Vec<u8> = (0..CHUNK_SIZE * 2 + 10).map(|i| i as u8).collect();
+        let mut hex = Hiex::from_reader(Cursor::new(original.clone())).unwrap();
+        let new_data = vec![b'x'; CHUNK_SIZE * 2];
+        hex.edit_spilled(5, new_data.clone(), ()).unwrap();
+        let mut expected = original.clone();
+        expected[5..5 + new_data.len()].copy_from_slice(&new_data);
+        assert_eq!(hex.reader().get_ref(), &expected);
+        // Only the new bytes are held in memory.
+        assert!(hex.actions.memory_usage() < new_data.len() * 2);
+
+        hex.undo(()).unwrap();
+        assert_eq!(hex.reader().get_ref(), &original);
+        hex.redo(()).unwrap();
+        assert_eq!(hex.reader().get_ref(), &expected);
+        hex.undo(()).unwrap();
+        assert_eq!(hex.reader().get_ref(), &original);
+
+        let (_, err) = hex
+            .edit_spilled(original.len() as u64 - 1, b"ab".to_vec(), ())
+            .unwrap_err();
+        assert!(matches!(err, ActionError::Invalid));
+    }
+}
@@ -0,0 +1,143 @@
+//! Reading elements spaced evenly through the data, such as one channel of interleaved samples.
+use crate::{clamp_usize, stream_len, Hiex, CHUNK_SIZE};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use usize_cast::FromUsize;
+
+/// Reads up to `count` elements of `width` bytes, starting at `start` and spaced `stride` bytes
+/// apart, stopping at the first element which doesn't fit within the data.
+/// Elements are read in batches spanning at most `chunk_size` bytes (or one element, if they are
+/// further apart than that), so that close elements are read together without reading the gaps
+/// between far apart ones.
+pub(crate) fn read_strided_in<S>(
+    stream: &mut S,
+    start: u64,
+    stride: u64,
+    count: usize,
+    width: usize,
+    chunk_size: usize,
+) -> std::io::Result<Vec<u8>>
+where
+    S: Read + Seek,
+{
+    let length = stream_len(stream)?;
+    let width_u64 = u64::from_usize(width);
+    let count = match start.checked_add(width_u64) {
+        Some(end) if end <= length && width != 0 => match (length - end).checked_div(stride) {
+            Some(more) => clamp_usize(more.saturating_add(1), count),
+            // Every element is the same one.
+            None => count,
+        },
+        _ => 0,
+    };
+    let total = count
+        .checked_mul(width)
+        .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidInput, "strided read is too large"))?;
+
+    let mut elements = Vec::with_capacity(total);
+    let mut chunk = Vec::new();
+    let mut index = 0;
+    while index < count {
+        // All of the elements in the batch fit within the chunk, which is also within the data.
+        let batch_start = start + u64::from_usize(index) * stride;
+        let batch = match stride {
+            0 => count - index,
+            stride => {
+                let spare = u64::from_usize(chunk_size.saturating_sub(width));
+                clamp_usize(spare / stride, count - index - 1) + 1
+            }
+        };
+        let span = clamp_usize(u64::from_usize(batch - 1) * stride, chunk_size) + width;
+        chunk.resize(span, 0);
+        stream.seek(SeekFrom::Start(batch_start))?;
+        stream.read_exact(&mut chunk)?;
+
+        for element in 0..batch {
+            let offset = clamp_usize(u64::from_usize(element) * stride, span);
+            elements.extend_from_slice(&chunk[offset..offset + width]);
+        }
+        index += batch;
+    }
+
+    Ok(elements)
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Reads `count` elements of `width` bytes, the first at `start` and each `stride` bytes after
+    /// the previous, such as every 4th byte from offset 2 with a `stride` of 4 and a `width` of 1.
+    /// Returns the elements back to back, stopping early at the first element which doesn't fit
+    /// within the data, so the amount of elements read is the length divided by `width`.
+    /// Nearby elements are read together, rather than with a read for each. The position is
+    /// restored afterwards.
+    pub fn read_strided(
+        &mut self,
+        start: u64,
+        stride: u64,
+        count: usize,
+        width: usize,
+    ) -> std::io::Result<Vec<u8>> {
+        let position = self.position()?;
+        let result = read_strided_in(self, start, stride, count, width, CHUNK_SIZE);
+        self.seek(SeekFrom::Start(position))?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_strided_in;
+    use crate::Hiex;
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    #[test]
+    fn test_read_strided() {
+        // Four channels, with the sample number in the low nibble, and the last sample missing
+        // its final channel.
+        let mut data = Vec::new();
+        for sample in 0..10u8 {
+            data.extend_from_slice(&[0x10 | sample, 0x20 | sample, 0x30 | sample, 0x40 | sample]);
+        }
+        data.pop();
+        let third: Vec<u8> = (0..10).map(|sample| 0x30 | sample).collect();
+        let fourth: Vec<u8> = (0..9).map(|sample| 0x40 | sample).collect();
+
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(data.clone())).unwrap();
+        hex.seek(SeekFrom::Start(5)).unwrap();
+        assert_eq!(hex.read_strided(2, 4, 100, 1).unwrap(), third);
+        assert_eq!(hex.read_strided(3, 4, 100, 1).unwrap(), fourth);
+        assert_eq!(hex.read_strided(3, 4, 2, 1).unwrap(), [0x40, 0x41]);
+        // The last sample's first three channels, with its partial final element left out.
+        assert_eq!(
+            hex.read_strided(32, 4, 3, 4).unwrap(),
+            [0x18, 0x28, 0x38, 0x48]
+        );
+        assert_eq!(hex.read_strided(36, 4, 3, 4).unwrap(), Vec::<u8>::new());
+        // Overlapping and repeated elements.
+        assert_eq!(
+            hex.read_strided(0, 1, 3, 2).unwrap(),
+            [0x10, 0x20, 0x20, 0x30, 0x30, 0x40]
+        );
+        assert_eq!(hex.read_strided(1, 0, 3, 1).unwrap(), [0x20, 0x20, 0x20]);
+        assert_eq!(
+            hex.read_strided(u64::MAX, 1, 3, 1).unwrap(),
+            Vec::<u8>::new()
+        );
+        assert_eq!(hex.read_strided(0, u64::MAX, 3, 2).unwrap(), [0x10, 0x20]);
+        assert_eq!(hex.position().unwrap(), 5);
+
+        // Every chunk size, so that batches end in every position.
+        for chunk_size in 0..20 {
+            let mut data = Cursor::new(data.clone());
+            let channel = |data: &mut Cursor<Vec<u8>>, start| {
+                read_strided_in(data, start, 4, 100, 1, chunk_size).unwrap()
+            };
+            assert_eq!(channel(&mut data, 2), third);
+            assert_eq!(channel(&mut data, 3), fourth);
+            let pairs = read_strided_in(&mut data, 2, 4, 100, 2, chunk_size).unwrap();
+            assert_eq!(pairs.len(), 18);
+            assert_eq!(&pairs[16..], [0x38, 0x48]);
+        }
+    }
+}
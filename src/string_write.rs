@@ -0,0 +1,249 @@
+//! Writing strings into the data, encoded and laid out as the format embedding them expects.
+use crate::{
+    action::{check_range, translate_offsets, Action, ActionError, MemoryUsage, OverwriteDelta},
+    read_exact_at, write_at, Hiex,
+};
+use std::{
+    convert::TryFrom,
+    io::{Read, Seek, Write},
+    ops::Range,
+};
+use usize_cast::FromUsize;
+
+/// The encodings a [`StringWriteAction`] can write.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StringEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// ISO-8859-1, which can only encode characters up to `U+00FF`.
+    Latin1,
+}
+impl StringEncoding {
+    /// The size of a NUL terminator.
+    pub fn nul_len(self) -> usize {
+        match self {
+            StringEncoding::Utf8 | StringEncoding::Latin1 => 1,
+            StringEncoding::Utf16Le | StringEncoding::Utf16Be => 2,
+        }
+    }
+
+    /// Encode `text`, without a terminator. Returns `None` if it has characters which the encoding
+    /// can't represent.
+    pub fn encode(self, text: &str) -> Option<Vec<u8>> {
+        Some(match self {
+            StringEncoding::Utf8 => text.as_bytes().to_vec(),
+            StringEncoding::Utf16Le => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+            StringEncoding::Utf16Be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+            StringEncoding::Latin1 => text
+                .chars()
+                .map(|c| u8::try_from(u32::from(c)).ok())
+                .collect::<Option<Vec<u8>>>()?,
+        })
+    }
+}
+
+/// How the end of a string written by a [`StringWriteAction`] is marked.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StringTermination {
+    /// Only the string itself is written.
+    None,
+    /// The string is followed by a NUL, of the encoding's width.
+    Nul,
+    /// The string is padded with NULs to `width` bytes. It may fill the field entirely, in which
+    /// case it has no terminator.
+    Padded { width: usize },
+}
+
+/// Encode `text` with `encoding`, terminated as given. Returns `None` if it has characters which
+/// the encoding can't represent, or is longer than a padded field.
+pub fn encode_string(
+    text: &str,
+    encoding: StringEncoding,
+    termination: StringTermination,
+) -> Option<Vec<u8>> {
+    let mut bytes = encoding.encode(text)?;
+    match termination {
+        StringTermination::None => {}
+        StringTermination::Nul => bytes.resize(bytes.len() + encoding.nul_len(), 0),
+        StringTermination::Padded { width } if bytes.len() <= width => bytes.resize(width, 0),
+        StringTermination::Padded { .. } => return None,
+    }
+    Some(bytes)
+}
+
+/// An action which writes a string at a position, encoded and terminated as given.
+/// This fails with `ActionError::Invalid` if the string can't be encoded, or is longer than a
+/// padded field.
+/// The overwritten bytes are stored for if the action is undone.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StringWriteAction {
+    pub position: u64,
+    /// The encoded string, or `None` if it couldn't be encoded.
+    new_data: Option<Vec<u8>>,
+    previous_data: Vec<u8>,
+}
+impl StringWriteAction {
+    pub fn new(
+        position: u64,
+        text: &str,
+        encoding: StringEncoding,
+        termination: StringTermination,
+    ) -> Self {
+        Self {
+            position,
+            new_data: encode_string(text, encoding, termination),
+            previous_data: Vec::new(),
+        }
+    }
+
+    /// The bytes that are written, if the string could be encoded.
+    pub fn new_data(&self) -> Option<&[u8]> {
+        self.new_data.as_deref()
+    }
+}
+impl<F, E> Action<F, E> for StringWriteAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        let new_data = self.new_data.as_ref().ok_or(ActionError::Invalid)?;
+        check_range(data, self.position, u64::from_usize(new_data.len()))?;
+
+        self.previous_data.resize(new_data.len(), 0);
+        read_exact_at(data, self.position, &mut self.previous_data)?;
+        write_at(data, self.position, new_data)?;
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        write_at(data, self.position, &self.previous_data)?;
+        Ok(())
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        let length = self
+            .new_data()
+            .map_or(0, |bytes| u64::from_usize(bytes.len()));
+        Some(self.position..self.position.saturating_add(length))
+    }
+
+    fn description(&self) -> String {
+        format!("Write string at {:#X}", self.position)
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(StringWriteAction {
+            position: self.position,
+            new_data: self.new_data.clone(),
+            previous_data: Vec::new(),
+        }))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.position], delta)
+    }
+
+    fn overwrite_delta(&self) -> Option<OverwriteDelta<'_>> {
+        Some(OverwriteDelta {
+            position: self.position,
+            previous: &self.previous_data,
+            new: self.new_data()?,
+        })
+    }
+}
+impl MemoryUsage for StringWriteAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.new_data().map_or(0, <[u8]>::len)
+            + self.previous_data.len()
+    }
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Writes `text` at `position`, encoded and terminated as given, as a single action.
+    /// See [`StringWriteAction`].
+    pub fn write_string(
+        &mut self,
+        position: u64,
+        text: &str,
+        encoding: StringEncoding,
+        termination: StringTermination,
+        other: E,
+    ) -> Result<(), (StringWriteAction, ActionError)> {
+        let action = StringWriteAction::new(position, text, encoding, termination);
+        self.add_action(action, other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StringEncoding, StringTermination};
+    use crate::{action::ActionError, Hiex};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_write_string() {
+        let original = vec![b'.'; 12];
+        let mut hex = Hiex::from_reader(Cursor::new(original.clone())).unwrap();
+        hex.write_string(0, "hé", StringEncoding::Utf8, StringTermination::Nul, ())
+            .unwrap();
+        assert_eq!(hex.read_amount_at(0, 12).unwrap(), b"h\xC3\xA9\0........");
+        hex.undo(()).unwrap();
+        assert_eq!(hex.read_amount_at(0, 12).unwrap(), original);
+
+        hex.write_string(
+            2,
+            "hé",
+            StringEncoding::Utf16Be,
+            StringTermination::Padded { width: 8 },
+            (),
+        )
+        .unwrap();
+        assert_eq!(hex.read_amount_at(0, 12).unwrap(), b"..\0h\0\xE9\0\0\0\0..");
+        hex.write_string(0, "hé", StringEncoding::Utf16Le, StringTermination::Nul, ())
+            .unwrap();
+        assert_eq!(
+            hex.read_amount_at(0, 12).unwrap(),
+            b"h\0\xE9\0\0\0\0\0\0\0.."
+        );
+        hex.write_string(
+            8,
+            "hé!!",
+            StringEncoding::Latin1,
+            StringTermination::Padded { width: 4 },
+            (),
+        )
+        .unwrap();
+        assert_eq!(hex.read_amount_at(8, 4).unwrap(), b"h\xE9!!");
+        hex.undo(()).unwrap();
+        hex.undo(()).unwrap();
+        hex.undo(()).unwrap();
+        assert_eq!(hex.read_amount_at(0, 12).unwrap(), original);
+
+        // Not representable, too long for the field, and past the end.
+        let (_, err) = hex
+            .write_string(0, "€", StringEncoding::Latin1, StringTermination::None, ())
+            .unwrap_err();
+        assert!(matches!(err, ActionError::Invalid));
+        let (_, err) = hex
+            .write_string(
+                0,
+                "hello",
+                StringEncoding::Utf8,
+                StringTermination::Padded { width: 4 },
+                (),
+            )
+            .unwrap_err();
+        assert!(matches!(err, ActionError::Invalid));
+        let (_, err) = hex
+            .write_string(10, "ab", StringEncoding::Utf8, StringTermination::Nul, ())
+            .unwrap_err();
+        assert!(matches!(err, ActionError::Invalid));
+        assert_eq!(hex.read_amount_at(0, 12).unwrap(), original);
+    }
+}
@@ -0,0 +1,481 @@
+//! Structure templates: declare a layout of fields and parse it from the data.
+use crate::{action::ActionError, clamp_usize, EditAction, Endian, Hiex};
+use std::io::{ErrorKind, Read, Seek, Write};
+use usize_cast::FromUsize;
+
+/// The type of a field in a [`StructTemplate`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FieldKind {
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+    /// A fixed amount of bytes.
+    Bytes(usize),
+    /// A string terminated by a NUL byte, which is at most `max_len` bytes long including the NUL.
+    /// If no NUL is found within `max_len` bytes then the field is `max_len` bytes long.
+    CString {
+        max_len: usize,
+    },
+}
+impl FieldKind {
+    /// The size of the field, if it is fixed.
+    pub fn size(self) -> Option<usize> {
+        Some(match self {
+            FieldKind::U8 | FieldKind::I8 => 1,
+            FieldKind::U16 | FieldKind::I16 => 2,
+            FieldKind::U32 | FieldKind::I32 | FieldKind::F32 => 4,
+            FieldKind::U64 | FieldKind::I64 | FieldKind::F64 => 8,
+            FieldKind::Bytes(size) => size,
+            FieldKind::CString { .. } => return None,
+        })
+    }
+}
+
+/// A field of a [`StructTemplate`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub kind: FieldKind,
+    /// Ignored for fields that aren't numbers.
+    pub endian: Endian,
+}
+impl Field {
+    pub fn new(name: impl Into<String>, kind: FieldKind, endian: Endian) -> Self {
+        Self {
+            name: name.into(),
+            kind,
+            endian,
+        }
+    }
+}
+
+/// A layout of fields, which follow each other without padding.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StructTemplate {
+    pub fields: Vec<Field>,
+    /// If set, parsing fails if any field can't be parsed.
+    /// Otherwise, each field that can't be parsed has its own error.
+    pub strict: bool,
+}
+impl StructTemplate {
+    pub fn new(fields: Vec<Field>) -> Self {
+        Self {
+            fields,
+            strict: false,
+        }
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+}
+
+/// The value of a parsed field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateValue {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Bytes(Vec<u8>),
+    /// The string's bytes, without the NUL terminator.
+    CString(Vec<u8>),
+}
+
+/// Why a field couldn't be parsed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FieldError {
+    /// The field runs past the end of the data.
+    UnexpectedEof,
+}
+
+/// Why [`ParsedStruct::set_field`] couldn't write a field.
+#[derive(Debug)]
+pub enum SetFieldError {
+    /// There is no field with that name.
+    UnknownField,
+    /// The field couldn't be parsed, so there is nothing to overwrite.
+    Unparsed(FieldError),
+    /// The value is a different type from the field, or a different size from a `Bytes` field,
+    /// or is a string containing a NUL byte.
+    Mismatch,
+    /// The string is longer than the field.
+    TooLong,
+    /// Adding the edit failed.
+    Action(ActionError),
+}
+
+/// A field parsed from the data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedField {
+    pub name: String,
+    pub kind: FieldKind,
+    pub endian: Endian,
+    /// The absolute offset of the field in the data.
+    pub offset: u64,
+    /// The amount of bytes the field occupies. For fields which couldn't be parsed, this is the
+    /// amount of bytes that were available.
+    pub length: u64,
+    pub value: Result<TemplateValue, FieldError>,
+}
+
+/// The result of parsing a [`StructTemplate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedStruct {
+    /// The absolute offset the structure was parsed at.
+    pub offset: u64,
+    pub fields: Vec<ParsedField>,
+}
+impl ParsedStruct {
+    pub fn get(&self, name: &str) -> Option<&ParsedField> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// The value of the field called `name`, if it exists and was parsed.
+    pub fn value(&self, name: &str) -> Option<&TemplateValue> {
+        self.get(name).and_then(|field| field.value.as_ref().ok())
+    }
+
+    /// The total amount of bytes occupied by the fields.
+    pub fn length(&self) -> u64 {
+        self.fields.iter().map(|field| field.length).sum()
+    }
+
+    /// Overwrites the field called `name` with `value`, encoded with the field's type and
+    /// endianness, as an [`EditAction`] added to `hex`. On success the parsed value is updated to
+    /// match.
+    /// A string is padded with NULs to the bytes the field currently occupies, so that the bytes
+    /// after it are left alone, though parsing again would find the string to be shorter. It may
+    /// only fill the field without a terminator if the field is its maximum length.
+    pub fn set_field<F, E>(
+        &mut self,
+        hex: &mut Hiex<F, E>,
+        name: &str,
+        value: TemplateValue,
+        other: E,
+    ) -> Result<(), SetFieldError>
+    where
+        F: Read + Seek + Write,
+    {
+        let field = self
+            .fields
+            .iter_mut()
+            .find(|field| field.name == name)
+            .ok_or(SetFieldError::UnknownField)?;
+        if let Err(err) = field.value {
+            return Err(SetFieldError::Unparsed(err));
+        }
+
+        let bytes = encode_value(field, &value)?;
+        hex.add_action(EditAction::new(field.offset, bytes), other)
+            .map_err(|(_, err)| SetFieldError::Action(err))?;
+        field.value = Ok(value);
+        Ok(())
+    }
+}
+
+/// Encode `value` to replace the bytes of `field`.
+fn encode_value(field: &ParsedField, value: &TemplateValue) -> Result<Vec<u8>, SetFieldError> {
+    let number = match (field.kind, value) {
+        (FieldKind::U8, TemplateValue::U8(value)) => u64::from(*value),
+        (FieldKind::U16, TemplateValue::U16(value)) => u64::from(*value),
+        (FieldKind::U32, TemplateValue::U32(value)) => u64::from(*value),
+        (FieldKind::U64, TemplateValue::U64(value)) => *value,
+        (FieldKind::I8, TemplateValue::I8(value)) => *value as u64,
+        (FieldKind::I16, TemplateValue::I16(value)) => *value as u64,
+        (FieldKind::I32, TemplateValue::I32(value)) => *value as u64,
+        (FieldKind::I64, TemplateValue::I64(value)) => *value as u64,
+        (FieldKind::F32, TemplateValue::F32(value)) => u64::from(value.to_bits()),
+        (FieldKind::F64, TemplateValue::F64(value)) => value.to_bits(),
+        (FieldKind::Bytes(size), TemplateValue::Bytes(bytes)) => {
+            return if bytes.len() == size {
+                Ok(bytes.clone())
+            } else {
+                Err(SetFieldError::Mismatch)
+            };
+        }
+        (FieldKind::CString { max_len }, TemplateValue::CString(string)) => {
+            if string.contains(&0) {
+                return Err(SetFieldError::Mismatch);
+            }
+            let length = clamp_usize(field.length, max_len);
+            let unterminated = string.len() == length && length == max_len;
+            if string.len() >= length && !unterminated {
+                return Err(SetFieldError::TooLong);
+            }
+            let mut bytes = string.clone();
+            bytes.resize(length, 0);
+            return Ok(bytes);
+        }
+        _ => return Err(SetFieldError::Mismatch),
+    };
+
+    let mut bytes = vec![0u8; field.kind.size().unwrap_or(0)];
+    field.endian.write_uint(number, &mut bytes);
+    Ok(bytes)
+}
+
+/// Decode a number of `kind` from exactly the right amount of `bytes`.
+pub(crate) fn decode_number(kind: FieldKind, endian: Endian, bytes: &[u8]) -> TemplateValue {
+    let value = endian.read_uint(bytes);
+    match kind {
+        FieldKind::U8 => TemplateValue::U8(value as u8),
+        FieldKind::U16 => TemplateValue::U16(value as u16),
+        FieldKind::U32 => TemplateValue::U32(value as u32),
+        FieldKind::U64 => TemplateValue::U64(value),
+        FieldKind::I8 => TemplateValue::I8(value as i8),
+        FieldKind::I16 => TemplateValue::I16(value as i16),
+        FieldKind::I32 => TemplateValue::I32(value as i32),
+        FieldKind::I64 => TemplateValue::I64(value as i64),
+        FieldKind::F32 => TemplateValue::F32(f32::from_bits(value as u32)),
+        FieldKind::F64 => TemplateValue::F64(f64::from_bits(value)),
+        FieldKind::Bytes(_) => TemplateValue::Bytes(bytes.to_vec()),
+        FieldKind::CString { .. } => TemplateValue::CString(bytes.to_vec()),
+    }
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Parse `template` with its first field at `offset`.
+    /// Fields which run past the end of the data have a `FieldError::UnexpectedEof` value, unless
+    /// the template is strict, in which case parsing fails with `ErrorKind::UnexpectedEof`.
+    pub fn parse_template_at(
+        &mut self,
+        offset: u64,
+        template: &StructTemplate,
+    ) -> std::io::Result<ParsedStruct> {
+        let length = self.length()?;
+        let mut fields = Vec::with_capacity(template.fields.len());
+        let mut position = offset;
+        for field in &template.fields {
+            let available = length.saturating_sub(position);
+            let (field_length, value) = match field.kind {
+                FieldKind::CString { max_len } => {
+                    let wanted = clamp_usize(available, max_len);
+                    let bytes = self.read_amount_at(position, wanted)?;
+                    match bytes.iter().position(|byte| *byte == 0) {
+                        Some(end) => (
+                            u64::from_usize(end + 1),
+                            Ok(TemplateValue::CString(bytes[..end].to_vec())),
+                        ),
+                        None if bytes.len() == max_len => {
+                            (u64::from_usize(max_len), Ok(TemplateValue::CString(bytes)))
+                        }
+                        None => (available, Err(FieldError::UnexpectedEof)),
+                    }
+                }
+                kind => {
+                    let size = kind.size().unwrap_or(0);
+                    if u64::from_usize(size) > available {
+                        (available, Err(FieldError::UnexpectedEof))
+                    } else {
+                        let bytes = self.read_amount_at(position, size)?;
+                        (
+                            u64::from_usize(size),
+                            Ok(decode_number(kind, field.endian, &bytes)),
+                        )
+                    }
+                }
+            };
+
+            if template.strict && value.is_err() {
+                return Err(std::io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    format!("field `{}` runs past the end of the data", field.name),
+                ));
+            }
+
+            fields.push(ParsedField {
+                name: field.name.clone(),
+                kind: field.kind,
+                endian: field.endian,
+                offset: position,
+                length: field_length,
+                value,
+            });
+            position += field_length;
+        }
+
+        Ok(ParsedStruct { offset, fields })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Field, FieldError, FieldKind, SetFieldError, StructTemplate, TemplateValue};
+    use crate::{Endian, Hiex};
+    use std::io::{Cursor, ErrorKind};
+
+    fn header() -> StructTemplate {
+        StructTemplate::new(vec![
+            Field::new("magic", FieldKind::Bytes(4), Endian::Little),
+            Field::new("version", FieldKind::U16, Endian::Little),
+            Field::new("flags", FieldKind::U16, Endian::Big),
+            Field::new("count", FieldKind::U32, Endian::Little),
+            Field::new("delta", FieldKind::I32, Endian::Big),
+            Field::new("ratio", FieldKind::F32, Endian::Little),
+        ])
+    }
+
+    #[test]
+    fn test_parse_template() {
+        let mut data = vec![0xEE, 0xEE];
+        data.extend_from_slice(b"HIEX");
+        data.extend_from_slice(&[0x02, 0x01]);
+        data.extend_from_slice(&[0x80, 0x01]);
+        data.extend_from_slice(&[0x78, 0x56, 0x34, 0x12]);
+        data.extend_from_slice(&(-5i32).to_be_bytes());
+        data.extend_from_slice(&1.5f32.to_le_bytes());
+        assert_eq!(data.len(), 22);
+        let mut hex = Hiex::<_, ()>::from_reader(Cursor::new(data)).unwrap();
+
+        let parsed = hex.parse_template_at(2, &header()).unwrap();
+        assert_eq!(parsed.length(), 20);
+        assert_eq!(
+            parsed.value("magic"),
+            Some(&TemplateValue::Bytes(b"HIEX".to_vec()))
+        );
+        assert_eq!(parsed.value("version"), Some(&TemplateValue::U16(0x0102)));
+        assert_eq!(parsed.value("flags"), Some(&TemplateValue::U16(0x8001)));
+        assert_eq!(
+            parsed.value("count"),
+            Some(&TemplateValue::U32(0x1234_5678))
+        );
+        assert_eq!(parsed.value("delta"), Some(&TemplateValue::I32(-5)));
+        assert_eq!(parsed.value("ratio"), Some(&TemplateValue::F32(1.5)));
+        let count = parsed.get("count").unwrap();
+        assert_eq!((count.offset, count.length), (10, 4));
+
+        // Starting later makes the last fields run past the end.
+        let parsed = hex.parse_template_at(5, &header()).unwrap();
+        assert!(parsed.get("delta").unwrap().value.is_ok());
+        let ratio = parsed.get("ratio").unwrap();
+        assert_eq!(ratio.value, Err(FieldError::UnexpectedEof));
+        assert_eq!((ratio.offset, ratio.length), (21, 1));
+
+        let err = hex
+            .parse_template_at(5, &header().strict(true))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_parse_cstring() {
+        let mut hex = Hiex::<_, ()>::from_reader(Cursor::new(b"ab\0cdefgh".to_vec())).unwrap();
+        let template = StructTemplate::new(vec![
+            Field::new("first", FieldKind::CString { max_len: 8 }, Endian::Little),
+            Field::new("second", FieldKind::CString { max_len: 4 }, Endian::Little),
+            Field::new("third", FieldKind::CString { max_len: 4 }, Endian::Little),
+        ]);
+        let parsed = hex.parse_template_at(0, &template).unwrap();
+        assert_eq!(
+            parsed.value("first"),
+            Some(&TemplateValue::CString(b"ab".to_vec()))
+        );
+        // Unterminated within its maximum length.
+        assert_eq!(
+            parsed.value("second"),
+            Some(&TemplateValue::CString(b"cdef".to_vec()))
+        );
+        assert_eq!(parsed.get("second").unwrap().offset, 3);
+        // Unterminated before the end of the data.
+        assert_eq!(
+            parsed.get("third").unwrap().value,
+            Err(FieldError::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn test_set_field() {
+        let mut data = b"HIEX".to_vec();
+        data.extend_from_slice(&[0; 16]);
+        let mut hex = Hiex::<_, ()>::from_reader(Cursor::new(data.clone())).unwrap();
+        let mut parsed = hex.parse_template_at(0, &header()).unwrap();
+
+        parsed
+            .set_field(&mut hex, "count", TemplateValue::U32(42), ())
+            .unwrap();
+        parsed
+            .set_field(&mut hex, "delta", TemplateValue::I32(-2), ())
+            .unwrap();
+        assert_eq!(hex.read_amount_at(8, 4).unwrap(), [42, 0, 0, 0]);
+        assert_eq!(hex.read_amount_at(12, 4).unwrap(), (-2i32).to_be_bytes());
+        assert_eq!(parsed.value("count"), Some(&TemplateValue::U32(42)));
+        assert_eq!(hex.parse_template_at(0, &header()).unwrap(), parsed);
+
+        assert!(matches!(
+            parsed.set_field(&mut hex, "count", TemplateValue::U16(1), ()),
+            Err(SetFieldError::Mismatch)
+        ));
+        assert!(matches!(
+            parsed.set_field(&mut hex, "magic", TemplateValue::Bytes(b"HI".to_vec()), ()),
+            Err(SetFieldError::Mismatch)
+        ));
+        assert!(matches!(
+            parsed.set_field(&mut hex, "missing", TemplateValue::U8(1), ()),
+            Err(SetFieldError::UnknownField)
+        ));
+
+        hex.undo_simple().unwrap();
+        hex.undo_simple().unwrap();
+        assert_eq!(hex.read_amount_at(0, 20).unwrap(), data);
+    }
+
+    #[test]
+    fn test_set_cstring_field() {
+        let mut hex = Hiex::<_, ()>::from_reader(Cursor::new(b"abcdef\0xyz".to_vec())).unwrap();
+        let template = StructTemplate::new(vec![
+            Field::new("name", FieldKind::CString { max_len: 8 }, Endian::Little),
+            Field::new("rest", FieldKind::Bytes(3), Endian::Little),
+        ]);
+        let mut parsed = hex.parse_template_at(0, &template).unwrap();
+
+        // Shorter strings are padded with NULs, so that the next field doesn't move.
+        parsed
+            .set_field(&mut hex, "name", TemplateValue::CString(b"hi".to_vec()), ())
+            .unwrap();
+        assert_eq!(hex.read_amount_at(0, 10).unwrap(), b"hi\0\0\0\0\0xyz");
+        assert_eq!(
+            parsed.value("name"),
+            Some(&TemplateValue::CString(b"hi".to_vec()))
+        );
+        assert_eq!(
+            hex.parse_template_at(0, &template).unwrap().value("name"),
+            parsed.value("name")
+        );
+
+        // The field is 7 bytes including its terminator.
+        assert!(matches!(
+            parsed.set_field(
+                &mut hex,
+                "name",
+                TemplateValue::CString(b"abcdefg".to_vec()),
+                ()
+            ),
+            Err(SetFieldError::TooLong)
+        ));
+        assert!(matches!(
+            parsed.set_field(&mut hex, "name", TemplateValue::U8(1), ()),
+            Err(SetFieldError::Mismatch)
+        ));
+
+        hex.undo_simple().unwrap();
+        assert_eq!(hex.read_amount_at(0, 10).unwrap(), b"abcdef\0xyz");
+    }
+}
@@ -0,0 +1,313 @@
+//! Guards which undo the actions added through them unless they are committed.
+use crate::{
+    action::{Action, ActionError, HistoryError},
+    Hiex,
+};
+use std::io::{Read, Seek, Write};
+
+/// What [`TransactionGuard::commit`] did with the actions added through the guard.
+#[must_use]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Commit {
+    /// They were collapsed into a single entry in the history.
+    Collapsed,
+    /// There were fewer than two, so they were left as they were.
+    Single,
+    /// They were left as separate entries, since at least one of them inserts or deletes bytes,
+    /// so undoing them all takes an undo for each.
+    Separate,
+}
+
+/// What [`TransactionGuard::rollback`] undid.
+#[must_use]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Rollback {
+    /// Every action added through the guard was undone.
+    Complete,
+    /// The oldest `discarded` actions added through the guard were discarded from the history to
+    /// fit the memory budget (see [`ActionList::set_memory_budget`]), so they are still applied.
+    /// The rest were undone.
+    ///
+    /// [`ActionList::set_memory_budget`]: crate::action::ActionList::set_memory_budget
+    Partial { discarded: usize },
+}
+
+/// Tracks the actions added to the history while it exists, see [`Hiex::transaction_guard`].
+/// [`TransactionGuard::commit`] keeps them as a single entry in the history. Otherwise, such as
+/// when returning early with `?`, dropping the guard undoes them. Any failure to undo them when
+/// it is dropped is only logged, so call [`TransactionGuard::rollback`] to see it.
+///
+/// The guard tracks the history after the point where it was created, so actions added through
+/// [`TransactionGuard::hex`] are tracked too. Guards can be nested this way, with the inner
+/// guard's actions becoming a single entry (or being undone) within the outer guard's.
+pub struct TransactionGuard<'a, F, E>
+where
+    F: 'static + Read + Seek + Write,
+    E: 'static + Clone,
+{
+    hex: &'a mut Hiex<F, E>,
+    other: Box<dyn FnMut() -> E + 'a>,
+    /// The amount of applied actions when the guard was created.
+    start: usize,
+    /// [`ActionList::evicted`](crate::action::ActionList::evicted) when the guard was created.
+    evicted: usize,
+    finished: bool,
+}
+impl<'a, F, E> TransactionGuard<'a, F, E>
+where
+    F: 'static + Read + Seek + Write,
+    E: 'static + Clone,
+{
+    /// Adds an action to the history, see [`Hiex::add_action`].
+    pub fn add_action<A>(&mut self, action: A) -> Result<(), (A, ActionError)>
+    where
+        A: 'static + Action<F, E>,
+    {
+        let other = (self.other)();
+        self.hex.add_action(action, other)
+    }
+
+    pub fn hex(&mut self) -> &mut Hiex<F, E> {
+        self.hex
+    }
+
+    /// The amount of actions added since the guard was created.
+    pub fn len(&self) -> usize {
+        self.hex.actions.past_len().saturating_sub(self.start())
+    }
+
+    /// The amount of applied actions when the guard was created, less those discarded since to
+    /// fit the memory budget (see [`ActionList::set_memory_budget`]).
+    ///
+    /// [`ActionList::set_memory_budget`]: crate::action::ActionList::set_memory_budget
+    fn start(&self) -> usize {
+        let evicted = self.hex.actions.evicted() - self.evicted;
+        self.start.saturating_sub(evicted)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The amount of actions added through the guard which were discarded since to fit the memory
+    /// budget, and so can't be undone.
+    fn discarded(&self) -> usize {
+        let evicted = self.hex.actions.evicted() - self.evicted;
+        evicted.saturating_sub(self.start)
+    }
+
+    /// Keeps the actions, collapsing them into a single entry in the history so that they are
+    /// undone together, which discards the undone actions after them.
+    /// They are left as separate entries if any of them insert or delete bytes, since the
+    /// bookmarks and tags they move can only be restored one action at a time, which is reported
+    /// as [`Commit::Separate`].
+    pub fn commit(mut self) -> Commit {
+        self.finished = true;
+        self.hex.collapse_history_from(self.start())
+    }
+
+    /// Undoes the actions, in reverse order, stopping at the first which fails to undo. The
+    /// actions before that one are left applied.
+    /// Actions which were discarded to fit the memory budget can't be undone, which is reported
+    /// as [`Rollback::Partial`].
+    pub fn rollback(mut self) -> Result<Rollback, HistoryError> {
+        self.finished = true;
+        self.undo_all()
+    }
+
+    fn undo_all(&mut self) -> Result<Rollback, HistoryError> {
+        while self.hex.actions.past_len() > self.start() {
+            let other = (self.other)();
+            if self.hex.undo(other)?.is_none() {
+                break;
+            }
+        }
+        Ok(match self.discarded() {
+            0 => Rollback::Complete,
+            discarded => Rollback::Partial { discarded },
+        })
+    }
+}
+impl<'a, F, E> Drop for TransactionGuard<'a, F, E>
+where
+    F: 'static + Read + Seek + Write,
+    E: 'static + Clone,
+{
+    fn drop(&mut self) {
+        if !self.finished {
+            match self.undo_all() {
+                Ok(Rollback::Complete) => {}
+                Ok(Rollback::Partial {
+                    discarded: _discarded,
+                }) => {
+                    debug!(discarded = _discarded; "rolled back a transaction partially");
+                }
+                Err(_err) => {
+                    debug!(index = _err.index; "failed to roll back a transaction");
+                }
+            }
+        }
+    }
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: 'static + Read + Seek + Write,
+    E: 'static + Clone,
+{
+    /// Starts tracking the actions added to the history, so that they can be kept as a single
+    /// entry, or are undone if the returned guard is dropped, see [`TransactionGuard`].
+    /// `other` is called for the extra data to pass to each action added, undone, or collapsed.
+    pub fn transaction_guard<'a, O>(&'a mut self, other: O) -> TransactionGuard<'a, F, E>
+    where
+        O: FnMut() -> E + 'a,
+    {
+        let start = self.actions.past_len();
+        // Actions added through the guard mustn't be merged into ones from before it.
+        self.actions.seal();
+        let evicted = self.actions.evicted();
+        TransactionGuard {
+            hex: self,
+            other: Box::new(other),
+            start,
+            evicted,
+            finished: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Commit, Rollback};
+    use crate::{action::ActionError, contents, EditAction, Hiex, InsertAction};
+    use std::io::Cursor;
+
+    /// Adds two edits, then fails before committing.
+    fn edit_then_fail(hex: &mut Hiex<Cursor<Vec<u8>>>) -> Result<(), ActionError> {
+        let mut guard = hex.transaction_guard(|| ());
+        guard
+            .add_action(EditAction::new(0, b"ab".to_vec()))
+            .map_err(|(_, err)| err)?;
+        guard
+            .add_action(EditAction::new(4, b"cd".to_vec()))
+            .map_err(|(_, err)| err)?;
+        guard
+            .add_action(EditAction::new(100, b"past the end".to_vec()))
+            .map_err(|(_, err)| err)?;
+        assert_eq!(guard.commit(), Commit::Collapsed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_commit() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"01234567".to_vec())).unwrap();
+        hex.add_action_simple(EditAction::new(7, b"!".to_vec()))
+            .unwrap();
+
+        let mut guard = hex.transaction_guard(|| ());
+        guard
+            .add_action(EditAction::new(0, b"ab".to_vec()))
+            .unwrap();
+        guard
+            .add_action(EditAction::new(4, b"cd".to_vec()))
+            .unwrap();
+        // Through the editor, and in a nested guard.
+        let mut inner = guard.hex().transaction_guard(|| ());
+        inner.add_action(EditAction::new(3, b"y".to_vec())).unwrap();
+        inner.add_action(EditAction::new(2, b"x".to_vec())).unwrap();
+        assert_eq!(inner.commit(), Commit::Collapsed);
+        assert_eq!(guard.len(), 3);
+        assert_eq!(guard.commit(), Commit::Collapsed);
+
+        assert_eq!(contents(&mut hex), b"abxycd6!");
+        assert_eq!(hex.actions.len(), 2);
+        hex.undo_simple().unwrap();
+        assert_eq!(contents(&mut hex), b"0123456!");
+        hex.redo_simple().unwrap();
+        assert_eq!(contents(&mut hex), b"abxycd6!");
+
+        // Inserting isn't collapsed.
+        let mut guard = hex.transaction_guard(|| ());
+        guard
+            .add_action(InsertAction::new(0, b"<".to_vec()))
+            .unwrap();
+        guard.add_action(EditAction::new(1, b"A".to_vec())).unwrap();
+        assert_eq!(guard.commit(), Commit::Separate);
+        assert_eq!(hex.actions.len(), 4);
+
+        let mut guard = hex.transaction_guard(|| ());
+        guard.add_action(EditAction::new(0, b"B".to_vec())).unwrap();
+        assert_eq!(guard.commit(), Commit::Single);
+        assert_eq!(hex.actions.len(), 5);
+    }
+
+    #[test]
+    fn test_transaction_rollback() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"01234567".to_vec())).unwrap();
+        hex.add_action_simple(EditAction::new(7, b"!".to_vec()))
+            .unwrap();
+        assert!(matches!(
+            edit_then_fail(&mut hex),
+            Err(ActionError::Invalid)
+        ));
+        assert_eq!(contents(&mut hex), b"0123456!");
+        assert_eq!(hex.actions.past_len(), 1);
+
+        // Undoing fails on a locked region, leaving the actions before it applied.
+        let mut guard = hex.transaction_guard(|| ());
+        guard
+            .add_action(EditAction::new(0, b"ab".to_vec()))
+            .unwrap();
+        guard
+            .add_action(EditAction::new(4, b"cd".to_vec()))
+            .unwrap();
+        guard.add_action(EditAction::new(6, b"e".to_vec())).unwrap();
+        guard.hex().lock_region(4..5);
+        let err = guard.rollback().unwrap_err();
+        assert_eq!(err.index, 2);
+        assert!(matches!(err.error, ActionError::RegionLocked { .. }));
+        assert_eq!(contents(&mut hex), b"ab23cd6!");
+        assert_eq!(hex.actions.past_len(), 3);
+
+        // Dropping ignores the failure in the same way.
+        let mut guard = hex.transaction_guard(|| ());
+        guard.add_action(EditAction::new(7, b"?".to_vec())).unwrap();
+        guard.add_action(EditAction::new(0, b"Z".to_vec())).unwrap();
+        guard.hex().lock_region(7..8);
+        drop(guard);
+        assert_eq!(contents(&mut hex), b"ab23cd6?");
+        assert_eq!(hex.actions.past_len(), 4);
+    }
+
+    #[test]
+    fn test_transaction_rollback_discarded() {
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(b"01234567".to_vec())).unwrap();
+        let mut guard = hex.transaction_guard(|| ());
+        guard
+            .add_action(EditAction::new(0, b"ab".to_vec()))
+            .unwrap();
+        guard
+            .add_action(EditAction::new(4, b"cd".to_vec()))
+            .unwrap();
+        assert_eq!(guard.rollback().unwrap(), Rollback::Complete);
+        assert_eq!(contents(&mut hex), b"01234567");
+
+        // The first edit is discarded to fit the budget, so only the second is undone.
+        let mut guard = hex.transaction_guard(|| ());
+        guard
+            .add_action(EditAction::new(0, b"ab".to_vec()))
+            .unwrap();
+        let budget = guard.hex().actions.memory_usage_per_action()[0].total();
+        guard.hex().set_memory_budget(Some(budget));
+        guard
+            .add_action(EditAction::new(4, b"cd".to_vec()))
+            .unwrap();
+        assert_eq!(guard.hex().actions.evicted(), 1);
+        assert_eq!(
+            guard.rollback().unwrap(),
+            Rollback::Partial { discarded: 1 }
+        );
+        assert_eq!(contents(&mut hex), b"ab234567");
+        assert_eq!(hex.actions.past_len(), 0);
+    }
+}
@@ -0,0 +1,2009 @@
+//! Actions which transform the bytes of a range in place.
+use crate::{
+    action::{
+        check_range, describe_bytes, plural_bytes, translate_offsets, Action, ActionError,
+        MemoryUsage,
+    },
+    checked_usize, clamp_usize, move_bytes, transform_range, write_at, Endian, CHUNK_SIZE,
+};
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+use usize_cast::FromUsize;
+
+/// How an arithmetic result that does not fit within the element width is handled.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ArithmeticMode {
+    /// Results wrap around modulo the width of the element.
+    Wrapping,
+    /// Results are clamped to the minimum/maximum value of the element.
+    Saturating,
+}
+
+/// An action which adds a signed `delta` to every element in a range.
+/// Elements are unsigned integers of `width` bytes (1, 2, 4, or 8), read with `endian`.
+/// `length` must be a multiple of `width`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ArithmeticAction {
+    pub position: u64,
+    pub length: u64,
+    pub width: usize,
+    pub endian: Endian,
+    pub delta: i64,
+    pub mode: ArithmeticMode,
+    /// Only stored for `ArithmeticMode::Saturating`, since wrapping arithmetic can be undone by
+    /// applying the negated delta.
+    previous_data: Vec<u8>,
+}
+impl ArithmeticAction {
+    pub fn new(position: u64, length: u64, width: usize, delta: i64, mode: ArithmeticMode) -> Self {
+        Self {
+            position,
+            length,
+            width,
+            endian: Endian::Little,
+            delta,
+            mode,
+            previous_data: Vec::new(),
+        }
+    }
+
+    pub fn with_endian(mut self, endian: Endian) -> Self {
+        self.endian = endian;
+        self
+    }
+
+    fn validate<F>(&self, data: &mut F) -> Result<(), ActionError>
+    where
+        F: Seek,
+    {
+        if ![1, 2, 4, 8].contains(&self.width) || self.length % u64::from_usize(self.width) != 0 {
+            return Err(ActionError::Invalid);
+        }
+        check_range(data, self.position, self.length)
+    }
+
+    /// Apply `delta` to every element of `chunk`.
+    /// `delta` is given as the twos-complement representation for wrapping.
+    fn apply_to_chunk(&self, chunk: &mut [u8], delta: i64) {
+        let bits = self.width * 8;
+        let max = u64::MAX >> (64 - bits);
+        for element in chunk.chunks_exact_mut(self.width) {
+            let value = self.endian.read_uint(element);
+            let value = match self.mode {
+                ArithmeticMode::Wrapping => value.wrapping_add(delta as u64) & max,
+                ArithmeticMode::Saturating => {
+                    (i128::from(value) + i128::from(delta)).clamp(0, i128::from(max)) as u64
+                }
+            };
+            self.endian.write_uint(value, element);
+        }
+    }
+}
+impl<F, E> Action<F, E> for ArithmeticAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        self.validate(data)?;
+
+        let save_previous = self.mode == ArithmeticMode::Saturating;
+        let mut previous = Vec::new();
+        transform_range(data, self.position, self.length, CHUNK_SIZE, |_, chunk| {
+            if save_previous {
+                previous.extend_from_slice(chunk);
+            }
+            self.apply_to_chunk(chunk, self.delta);
+        })?;
+        self.previous_data = previous;
+
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        match self.mode {
+            ArithmeticMode::Wrapping => {
+                // Wrapping addition is invertible, so we simply subtract what we added.
+                let delta = self.delta.wrapping_neg();
+                transform_range(data, self.position, self.length, CHUNK_SIZE, |_, chunk| {
+                    self.apply_to_chunk(chunk, delta)
+                })?;
+            }
+            ArithmeticMode::Saturating => write_at(data, self.position, &self.previous_data)?,
+        }
+        Ok(())
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.position..self.position.saturating_add(self.length))
+    }
+
+    fn description(&self) -> String {
+        describe_bytes("Adjust values in", self.position, self.length)
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        let action = ArithmeticAction::new(
+            self.position,
+            self.length,
+            self.width,
+            self.delta,
+            self.mode,
+        );
+        Some(Box::new(action.with_endian(self.endian)))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.position], delta)
+    }
+}
+impl MemoryUsage for ArithmeticAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.previous_data.len()
+    }
+}
+
+/// An action which reverses the order of the bytes within every `width` byte group of a range,
+/// converting between little and big endian. `width` must be 2, 4, or 8, and `length` a
+/// multiple of it. This is its own inverse, so no previous data is stored.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EndianSwapAction {
+    pub position: u64,
+    pub length: u64,
+    pub width: usize,
+}
+impl EndianSwapAction {
+    pub fn new(position: u64, length: u64, width: usize) -> Self {
+        Self {
+            position,
+            length,
+            width,
+        }
+    }
+}
+impl<F, E> Action<F, E> for EndianSwapAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        if ![2, 4, 8].contains(&self.width) || self.length % u64::from_usize(self.width) != 0 {
+            return Err(ActionError::Invalid);
+        }
+        check_range(data, self.position, self.length)?;
+
+        // The chunks are a multiple of every width, so groups are never split between them.
+        let width = self.width;
+        transform_range(data, self.position, self.length, CHUNK_SIZE, |_, chunk| {
+            chunk.chunks_exact_mut(width).for_each(<[u8]>::reverse)
+        })?;
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, other: E) -> Result<(), ActionError> {
+        self.apply(data, other)
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.position..self.position.saturating_add(self.length))
+    }
+
+    fn description(&self) -> String {
+        describe_bytes("Swap byte order of", self.position, self.length)
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(self.clone()))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.position], delta)
+    }
+}
+impl MemoryUsage for EndianSwapAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+    }
+}
+
+/// An action which flips a single bit, `bit` (`0` being the least significant) of the byte at
+/// `position`. This is its own inverse, so no previous data is stored.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BitFlipAction {
+    pub position: u64,
+    pub bit: u8,
+}
+impl BitFlipAction {
+    pub fn new(position: u64, bit: u8) -> Self {
+        Self { position, bit }
+    }
+}
+impl<F, E> Action<F, E> for BitFlipAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        if self.bit >= 8 {
+            return Err(ActionError::Invalid);
+        }
+        check_range(data, self.position, 1)?;
+
+        let mask = 1 << self.bit;
+        transform_range(data, self.position, 1, 1, |_, chunk| chunk[0] ^= mask)?;
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, other: E) -> Result<(), ActionError> {
+        self.apply(data, other)
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.position..self.position.saturating_add(1))
+    }
+
+    fn description(&self) -> String {
+        format!("Flip bit {} at {:#X}", self.bit, self.position)
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(self.clone()))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.position], delta)
+    }
+}
+impl MemoryUsage for BitFlipAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+    }
+}
+
+/// The conversion performed by a [`CaseConvertAction`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CaseMode {
+    Upper,
+    Lower,
+    /// Swap the case of every letter.
+    Toggle,
+}
+
+/// An action which converts the case of the ASCII letters in a range.
+/// Bytes which are not ASCII letters are left untouched.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CaseConvertAction {
+    pub position: u64,
+    pub length: u64,
+    pub mode: CaseMode,
+    /// Not stored for `CaseMode::Toggle`, since toggling is its own inverse.
+    previous_data: Vec<u8>,
+}
+impl CaseConvertAction {
+    pub fn new(position: u64, length: u64, mode: CaseMode) -> Self {
+        Self {
+            position,
+            length,
+            mode,
+            previous_data: Vec::new(),
+        }
+    }
+
+    fn convert(mode: CaseMode, chunk: &mut [u8]) {
+        match mode {
+            CaseMode::Upper => chunk.make_ascii_uppercase(),
+            CaseMode::Lower => chunk.make_ascii_lowercase(),
+            CaseMode::Toggle => {
+                for byte in chunk.iter_mut().filter(|byte| byte.is_ascii_alphabetic()) {
+                    // The case bit of ASCII letters.
+                    *byte ^= 0x20;
+                }
+            }
+        }
+    }
+}
+impl<F, E> Action<F, E> for CaseConvertAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        check_range(data, self.position, self.length)?;
+
+        let mode = self.mode;
+        let mut previous = Vec::new();
+        transform_range(data, self.position, self.length, CHUNK_SIZE, |_, chunk| {
+            if mode != CaseMode::Toggle {
+                previous.extend_from_slice(chunk);
+            }
+            Self::convert(mode, chunk);
+        })?;
+        self.previous_data = previous;
+
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        match self.mode {
+            CaseMode::Toggle => {
+                transform_range(data, self.position, self.length, CHUNK_SIZE, |_, chunk| {
+                    Self::convert(CaseMode::Toggle, chunk)
+                })?
+            }
+            CaseMode::Upper | CaseMode::Lower => {
+                write_at(data, self.position, &self.previous_data)?
+            }
+        }
+        Ok(())
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.position..self.position.saturating_add(self.length))
+    }
+
+    fn description(&self) -> String {
+        describe_bytes("Convert case of", self.position, self.length)
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(CaseConvertAction::new(
+            self.position,
+            self.length,
+            self.mode,
+        )))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.position], delta)
+    }
+}
+impl MemoryUsage for CaseConvertAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.previous_data.len()
+    }
+}
+
+/// Reverses the `length` bytes at `position` in place.
+/// Ranges larger than `chunk_size` are reversed by swapping chunks from both ends inwards, so that
+/// at most `2 * chunk_size` bytes are buffered at once.
+pub(crate) fn reverse_range<F>(
+    data: &mut F,
+    position: u64,
+    length: u64,
+    chunk_size: usize,
+) -> std::io::Result<()>
+where
+    F: Read + Write + Seek,
+{
+    let mut front_position = position;
+    let mut back_end = position + length;
+    let mut front = Vec::new();
+    let mut back = Vec::new();
+    loop {
+        let amount = clamp_usize((back_end - front_position) / 2, chunk_size);
+        if amount == 0 {
+            // Either nothing is left, or a single byte in the middle which stays where it is.
+            break;
+        }
+        let back_position = back_end - u64::from_usize(amount);
+
+        front.resize(amount, 0);
+        back.resize(amount, 0);
+        data.seek(SeekFrom::Start(front_position))?;
+        data.read_exact(&mut front)?;
+        data.seek(SeekFrom::Start(back_position))?;
+        data.read_exact(&mut back)?;
+
+        front.reverse();
+        back.reverse();
+        write_at(data, front_position, &back)?;
+        write_at(data, back_position, &front)?;
+
+        front_position += u64::from_usize(amount);
+        back_end = back_position;
+    }
+
+    Ok(())
+}
+
+/// An action which reverses the order of the bytes in a range.
+/// This is its own inverse, so no previous data is stored.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReverseAction {
+    pub position: u64,
+    pub length: u64,
+}
+impl ReverseAction {
+    pub fn new(position: u64, length: u64) -> Self {
+        Self { position, length }
+    }
+}
+impl<F, E> Action<F, E> for ReverseAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        check_range(data, self.position, self.length)?;
+        reverse_range(data, self.position, self.length, CHUNK_SIZE)?;
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, other: E) -> Result<(), ActionError> {
+        self.apply(data, other)
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.position..self.position.saturating_add(self.length))
+    }
+
+    fn description(&self) -> String {
+        describe_bytes("Reverse", self.position, self.length)
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(self.clone()))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.position], delta)
+    }
+}
+impl MemoryUsage for ReverseAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+    }
+}
+
+/// Rotates the `length` bytes at `position` left by `amount`, so that the byte at
+/// `position + amount` ends up at `position`. This is done by reversing both parts and then the
+/// whole range, so the range isn't buffered all at once.
+pub(crate) fn rotate_range_left<F>(
+    data: &mut F,
+    position: u64,
+    length: u64,
+    amount: u64,
+    chunk_size: usize,
+) -> std::io::Result<()>
+where
+    F: Read + Write + Seek,
+{
+    if length == 0 || amount % length == 0 {
+        return Ok(());
+    }
+    let amount = amount % length;
+    reverse_range(data, position, amount, chunk_size)?;
+    reverse_range(data, position + amount, length - amount, chunk_size)?;
+    reverse_range(data, position, length, chunk_size)
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RotateDirection {
+    /// Towards the start of the range, wrapping around to the end.
+    Left,
+    /// Towards the end of the range, wrapping around to the start.
+    Right,
+}
+
+/// An action which rotates the bytes in a range by `amount` positions, wrapping around within the
+/// range. Undoing rotates them back, so no previous data is stored.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RotateRangeAction {
+    pub position: u64,
+    pub length: u64,
+    pub amount: u64,
+    pub direction: RotateDirection,
+}
+impl RotateRangeAction {
+    pub fn new(position: u64, length: u64, amount: u64, direction: RotateDirection) -> Self {
+        Self {
+            position,
+            length,
+            amount,
+            direction,
+        }
+    }
+
+    /// The amount to rotate left by to rotate in `direction`.
+    fn left_amount(&self, direction: RotateDirection) -> u64 {
+        if self.length == 0 {
+            return 0;
+        }
+        let amount = self.amount % self.length;
+        match direction {
+            RotateDirection::Left => amount,
+            RotateDirection::Right => (self.length - amount) % self.length,
+        }
+    }
+
+    fn rotate<F>(&self, data: &mut F, direction: RotateDirection) -> Result<(), ActionError>
+    where
+        F: Read + Seek + Write,
+    {
+        check_range(data, self.position, self.length)?;
+        let amount = self.left_amount(direction);
+        rotate_range_left(data, self.position, self.length, amount, CHUNK_SIZE)?;
+        Ok(())
+    }
+}
+impl<F, E> Action<F, E> for RotateRangeAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        self.rotate(data, self.direction)
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        let reverse = match self.direction {
+            RotateDirection::Left => RotateDirection::Right,
+            RotateDirection::Right => RotateDirection::Left,
+        };
+        self.rotate(data, reverse)
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.position..self.position.saturating_add(self.length))
+    }
+
+    fn description(&self) -> String {
+        describe_bytes("Rotate", self.position, self.length)
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(self.clone()))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.position], delta)
+    }
+}
+impl MemoryUsage for RotateRangeAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+    }
+}
+
+/// Exchanges the `length` bytes at `a` with the `length` bytes at `b`, in chunks of at most
+/// `chunk_size` bytes. The ranges should not overlap.
+pub(crate) fn swap_ranges<F>(
+    data: &mut F,
+    a: u64,
+    b: u64,
+    length: u64,
+    chunk_size: usize,
+) -> std::io::Result<()>
+where
+    F: Read + Write + Seek,
+{
+    let mut first = Vec::new();
+    let mut second = Vec::new();
+    let mut offset = 0;
+    while offset < length {
+        let amount = clamp_usize(length - offset, chunk_size);
+        first.resize(amount, 0);
+        second.resize(amount, 0);
+
+        data.seek(SeekFrom::Start(a + offset))?;
+        data.read_exact(&mut first)?;
+        data.seek(SeekFrom::Start(b + offset))?;
+        data.read_exact(&mut second)?;
+        write_at(data, a + offset, &second)?;
+        write_at(data, b + offset, &first)?;
+
+        offset += u64::from_usize(amount);
+    }
+
+    Ok(())
+}
+
+/// An action which exchanges the contents of two equal-length, non-overlapping ranges.
+/// This is its own inverse, so no previous data is stored.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SwapRangesAction {
+    pub a: u64,
+    pub b: u64,
+    pub length: u64,
+}
+impl SwapRangesAction {
+    pub fn new(a: u64, b: u64, length: u64) -> Self {
+        Self { a, b, length }
+    }
+
+    /// Swaps records `i` and `j` of a table of `record_size` byte records starting at `base`.
+    pub fn records(base: u64, record_size: u64, i: u64, j: u64) -> Self {
+        let record = |index: u64| base.saturating_add(index.saturating_mul(record_size));
+        Self::new(record(i), record(j), record_size)
+    }
+}
+impl<F, E> Action<F, E> for SwapRangesAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        check_range(data, self.a, self.length)?;
+        check_range(data, self.b, self.length)?;
+        let (low, high) = (self.a.min(self.b), self.a.max(self.b));
+        if self.length != 0 && low + self.length > high {
+            return Err(ActionError::OverlappingRanges);
+        }
+
+        swap_ranges(data, self.a, self.b, self.length, CHUNK_SIZE)?;
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, other: E) -> Result<(), ActionError> {
+        self.apply(data, other)
+    }
+
+    /// Covers both ranges, and everything between them.
+    fn affected_range(&self) -> Option<Range<u64>> {
+        let (low, high) = (self.a.min(self.b), self.a.max(self.b));
+        Some(low..high.saturating_add(self.length))
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Swap {} at {:#X} and {:#X}",
+            plural_bytes(self.length),
+            self.a,
+            self.b
+        )
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(self.clone()))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.a, &mut self.b], delta)
+    }
+}
+impl MemoryUsage for SwapRangesAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+    }
+}
+
+/// The part of the `length` bytes at `a` which isn't covered by the `length` bytes at `b`.
+/// Since the ranges are the same length, this is always a single range.
+fn uncovered(a: u64, b: u64, length: u64) -> Range<u64> {
+    if a.abs_diff(b) >= length {
+        a..a + length
+    } else if a < b {
+        a..b
+    } else {
+        b + length..a + length
+    }
+}
+
+/// Reads the `length` bytes at `position`, which have to fit in memory.
+fn read_range<F>(data: &mut F, position: u64, length: u64) -> Result<Vec<u8>, ActionError>
+where
+    F: Read + Seek,
+{
+    let mut buffer = vec![0u8; checked_usize(length)?];
+    data.seek(SeekFrom::Start(position))?;
+    data.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// An action which moves the `length` bytes at `source` to `destination`, overwriting the bytes
+/// there. The bytes of the source which aren't overwritten by the destination are set to `fill`
+/// (`0` by default). The ranges may overlap.
+/// Only the bytes of the destination outside of the source are stored, since the rest are restored
+/// by moving the bytes back. These are held in memory, so moving a large range far away stores up
+/// to `length` bytes.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MoveRangeAction {
+    pub source: u64,
+    pub destination: u64,
+    pub length: u64,
+    pub fill: u8,
+    previous_data: Vec<u8>,
+}
+impl MoveRangeAction {
+    pub fn new(source: u64, destination: u64, length: u64) -> Self {
+        Self {
+            source,
+            destination,
+            length,
+            fill: 0,
+            previous_data: Vec::new(),
+        }
+    }
+
+    pub fn with_fill(mut self, fill: u8) -> Self {
+        self.fill = fill;
+        self
+    }
+}
+impl<F, E> Action<F, E> for MoveRangeAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        check_range(data, self.source, self.length)?;
+        check_range(data, self.destination, self.length)?;
+
+        // The part of the destination which overlaps the source is restored by moving back.
+        let overwritten = uncovered(self.destination, self.source, self.length);
+        let previous = read_range(data, overwritten.start, overwritten.end - overwritten.start)?;
+        move_bytes(
+            data,
+            self.source..self.source + self.length,
+            self.destination,
+            CHUNK_SIZE,
+        )?;
+        let left = uncovered(self.source, self.destination, self.length);
+        let fill = self.fill;
+        transform_range(
+            data,
+            left.start,
+            left.end - left.start,
+            CHUNK_SIZE,
+            |_, chunk| chunk.iter_mut().for_each(|byte| *byte = fill),
+        )?;
+        self.previous_data = previous;
+
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        move_bytes(
+            data,
+            self.destination..self.destination + self.length,
+            self.source,
+            CHUNK_SIZE,
+        )?;
+        let overwritten = uncovered(self.destination, self.source, self.length);
+        write_at(data, overwritten.start, &self.previous_data)?;
+        Ok(())
+    }
+
+    /// Covers both ranges, and everything between them.
+    fn affected_range(&self) -> Option<Range<u64>> {
+        let (low, high) = (
+            self.source.min(self.destination),
+            self.source.max(self.destination),
+        );
+        Some(low..high.saturating_add(self.length))
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Move {} from {:#X} to {:#X}",
+            plural_bytes(self.length),
+            self.source,
+            self.destination
+        )
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        let action = MoveRangeAction::new(self.source, self.destination, self.length);
+        Some(Box::new(action.with_fill(self.fill)))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.source, &mut self.destination], delta)
+    }
+}
+impl MemoryUsage for MoveRangeAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.previous_data.len()
+    }
+}
+
+/// An action which copies the `length` bytes at `source` over the bytes at `destination`, leaving
+/// the source as it was. The ranges may overlap, in which case the destination ends up with what
+/// the source held beforehand. Only the overwritten bytes of the destination are stored.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CopyRangeAction {
+    pub source: u64,
+    pub destination: u64,
+    pub length: u64,
+    previous_data: Vec<u8>,
+}
+impl CopyRangeAction {
+    pub fn new(source: u64, destination: u64, length: u64) -> Self {
+        Self {
+            source,
+            destination,
+            length,
+            previous_data: Vec::new(),
+        }
+    }
+}
+impl<F, E> Action<F, E> for CopyRangeAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        check_range(data, self.source, self.length)?;
+        check_range(data, self.destination, self.length)?;
+
+        let previous = read_range(data, self.destination, self.length)?;
+        move_bytes(
+            data,
+            self.source..self.source + self.length,
+            self.destination,
+            CHUNK_SIZE,
+        )?;
+        self.previous_data = previous;
+
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        write_at(data, self.destination, &self.previous_data)?;
+        Ok(())
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.destination..self.destination.saturating_add(self.length))
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Copy {} from {:#X} to {:#X}",
+            plural_bytes(self.length),
+            self.source,
+            self.destination
+        )
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(CopyRangeAction::new(
+            self.source,
+            self.destination,
+            self.length,
+        )))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.source, &mut self.destination], delta)
+    }
+}
+impl MemoryUsage for CopyRangeAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.previous_data.len()
+    }
+}
+
+/// Replaces each byte of `chunk` with `f(byte, key byte)`, where `key` is repeated from the start
+/// of the range and `offset` is where `chunk` is within it. `key` must not be empty.
+fn combine_repeated<T>(chunk: &mut [u8], offset: u64, key: &[u8], f: T)
+where
+    T: Fn(u8, u8) -> u8,
+{
+    let start = (offset % u64::from_usize(key.len())) as usize;
+    let repeated = key.iter().cycle().skip(start);
+    for (byte, &value) in chunk.iter_mut().zip(repeated) {
+        *byte = f(*byte, value);
+    }
+}
+
+/// An action which XORs every byte of a range with `key`, repeated from the start of the range.
+/// This is its own inverse, so no previous data is stored.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct XorRangeAction {
+    pub position: u64,
+    pub length: u64,
+    pub key: Vec<u8>,
+}
+impl XorRangeAction {
+    pub fn new(position: u64, length: u64, key: Vec<u8>) -> Self {
+        Self {
+            position,
+            length,
+            key,
+        }
+    }
+}
+impl<F, E> Action<F, E> for XorRangeAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        if self.key.is_empty() {
+            return Err(ActionError::Invalid);
+        }
+        check_range(data, self.position, self.length)?;
+
+        let key = &self.key;
+        transform_range(
+            data,
+            self.position,
+            self.length,
+            CHUNK_SIZE,
+            |offset, chunk| combine_repeated(chunk, offset, key, |byte, key| byte ^ key),
+        )?;
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, other: E) -> Result<(), ActionError> {
+        self.apply(data, other)
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.position..self.position.saturating_add(self.length))
+    }
+
+    fn description(&self) -> String {
+        describe_bytes("XOR", self.position, self.length)
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(self.clone()))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.position], delta)
+    }
+}
+impl MemoryUsage for XorRangeAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.key.len()
+    }
+}
+
+/// An action which overwrites a range several times, once with each of `passes` (each repeated
+/// as in [`FillAction`]), flushing the data after every pass so that each one reaches the
+/// backend. The range ends up holding the last pass.
+///
+/// NOTE: The original bytes are stored so that this can be undone, so they remain in memory for
+/// as long as the action is in the history. Whether overwriting actually destroys the previous
+/// bytes is up to the backend and the storage beneath it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ShredRangeAction {
+    pub position: u64,
+    pub length: u64,
+    pub passes: Vec<Vec<u8>>,
+    previous_data: Vec<u8>,
+}
+impl ShredRangeAction {
+    pub fn new(position: u64, length: u64, passes: Vec<Vec<u8>>) -> Self {
+        Self {
+            position,
+            length,
+            passes,
+            previous_data: Vec::new(),
+        }
+    }
+}
+impl<F, E> Action<F, E> for ShredRangeAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        if self.passes.is_empty() || self.passes.iter().any(Vec::is_empty) {
+            return Err(ActionError::Invalid);
+        }
+        check_range(data, self.position, self.length)?;
+
+        let mut previous = Vec::new();
+        for (index, pattern) in self.passes.iter().enumerate() {
+            let result = transform_range(
+                data,
+                self.position,
+                self.length,
+                CHUNK_SIZE,
+                |offset, chunk| {
+                    if index == 0 {
+                        previous.extend_from_slice(chunk);
+                    }
+                    combine_repeated(chunk, offset, pattern, |_, value| value);
+                },
+            )
+            .and_then(|()| data.flush());
+            if let Err(err) = result {
+                // Put back what a partial pass overwrote, so that failing doesn't lose the data.
+                if !previous.is_empty() {
+                    let _ = write_at(data, self.position, &previous);
+                }
+                return Err(err.into());
+            }
+        }
+        self.previous_data = previous;
+
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        write_at(data, self.position, &self.previous_data)?;
+        Ok(())
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.position..self.position.saturating_add(self.length))
+    }
+
+    fn description(&self) -> String {
+        describe_bytes("Shred", self.position, self.length)
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(ShredRangeAction::new(
+            self.position,
+            self.length,
+            self.passes.clone(),
+        )))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.position], delta)
+    }
+}
+impl MemoryUsage for ShredRangeAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.passes.iter().map(Vec::len).sum::<usize>()
+            + self.previous_data.len()
+    }
+}
+
+/// The output of SplitMix64 for the `index`th step from `seed`.
+fn split_mix64(seed: u64, index: u64) -> u64 {
+    let mut z = seed.wrapping_add(index.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// An action which fills a range with pseudo-random bytes generated from `seed`.
+/// The bytes are generated from their offset within the range, so the same seed and length
+/// always produce the same bytes, and redoing doesn't need to store them. They are not suitable
+/// for anything which needs to be unpredictable. The overwritten bytes are stored for undoing.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RandomFillAction {
+    pub position: u64,
+    pub length: u64,
+    pub seed: u64,
+    previous_data: Vec<u8>,
+}
+impl RandomFillAction {
+    pub fn new(position: u64, length: u64, seed: u64) -> Self {
+        Self {
+            position,
+            length,
+            seed,
+            previous_data: Vec::new(),
+        }
+    }
+
+    /// Fills `chunk`, which is at `offset` within the range.
+    fn generate(seed: u64, offset: u64, chunk: &mut [u8]) {
+        for (index, byte) in (offset..).zip(chunk.iter_mut()) {
+            *byte = split_mix64(seed, index / 8).to_le_bytes()[(index % 8) as usize];
+        }
+    }
+}
+impl<F, E> Action<F, E> for RandomFillAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        check_range(data, self.position, self.length)?;
+
+        let seed = self.seed;
+        let mut previous = Vec::new();
+        transform_range(
+            data,
+            self.position,
+            self.length,
+            CHUNK_SIZE,
+            |offset, chunk| {
+                previous.extend_from_slice(chunk);
+                Self::generate(seed, offset, chunk);
+            },
+        )?;
+        self.previous_data = previous;
+
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        write_at(data, self.position, &self.previous_data)?;
+        Ok(())
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.position..self.position.saturating_add(self.length))
+    }
+
+    fn description(&self) -> String {
+        describe_bytes("Randomize", self.position, self.length)
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(RandomFillAction::new(
+            self.position,
+            self.length,
+            self.seed,
+        )))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.position], delta)
+    }
+}
+impl MemoryUsage for RandomFillAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.previous_data.len()
+    }
+}
+
+/// The operation performed by a [`BitwiseRangeAction`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BitwiseOp {
+    And,
+    Or,
+    /// Inverts every bit. The mask is ignored.
+    Not,
+}
+
+/// An action which ANDs or ORs every byte of a range with `mask`, repeated from the start of the
+/// range, or inverts every byte.
+/// AND and OR lose information, so the previous bytes are stored as they are streamed through.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BitwiseRangeAction {
+    pub position: u64,
+    pub length: u64,
+    pub op: BitwiseOp,
+    pub mask: Vec<u8>,
+    /// Not stored for `BitwiseOp::Not`, since inverting is its own inverse.
+    previous_data: Vec<u8>,
+}
+impl BitwiseRangeAction {
+    pub fn new(position: u64, length: u64, op: BitwiseOp, mask: Vec<u8>) -> Self {
+        Self {
+            position,
+            length,
+            op,
+            mask,
+            previous_data: Vec::new(),
+        }
+    }
+
+    /// Inverts every byte within the range.
+    pub fn not(position: u64, length: u64) -> Self {
+        Self::new(position, length, BitwiseOp::Not, Vec::new())
+    }
+
+    fn invert<F>(&self, data: &mut F) -> std::io::Result<()>
+    where
+        F: Read + Seek + Write,
+    {
+        transform_range(data, self.position, self.length, CHUNK_SIZE, |_, chunk| {
+            chunk.iter_mut().for_each(|byte| *byte = !*byte)
+        })
+    }
+}
+impl<F, E> Action<F, E> for BitwiseRangeAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        if self.op != BitwiseOp::Not && self.mask.is_empty() {
+            return Err(ActionError::Invalid);
+        }
+        check_range(data, self.position, self.length)?;
+
+        let mask = &self.mask;
+        let mut previous = Vec::new();
+        match self.op {
+            BitwiseOp::Not => self.invert(data)?,
+            BitwiseOp::And | BitwiseOp::Or => {
+                let or = self.op == BitwiseOp::Or;
+                transform_range(
+                    data,
+                    self.position,
+                    self.length,
+                    CHUNK_SIZE,
+                    |offset, chunk| {
+                        previous.extend_from_slice(chunk);
+                        combine_repeated(chunk, offset, mask, |byte, mask| {
+                            if or {
+                                byte | mask
+                            } else {
+                                byte & mask
+                            }
+                        });
+                    },
+                )?
+            }
+        }
+        self.previous_data = previous;
+
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        match self.op {
+            BitwiseOp::Not => self.invert(data)?,
+            BitwiseOp::And | BitwiseOp::Or => write_at(data, self.position, &self.previous_data)?,
+        }
+        Ok(())
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.position..self.position.saturating_add(self.length))
+    }
+
+    fn description(&self) -> String {
+        let verb = match self.op {
+            BitwiseOp::And => "AND",
+            BitwiseOp::Or => "OR",
+            BitwiseOp::Not => "Invert",
+        };
+        describe_bytes(verb, self.position, self.length)
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(BitwiseRangeAction::new(
+            self.position,
+            self.length,
+            self.op,
+            self.mask.clone(),
+        )))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.position], delta)
+    }
+}
+impl MemoryUsage for BitwiseRangeAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.mask.len() + self.previous_data.len()
+    }
+}
+
+/// An action which fills a range with `pattern` repeated, starting from the beginning of the
+/// pattern at `position`. The last repetition is cut short if the length isn't a multiple of the
+/// pattern's length. The fill is written in chunks, so only the pattern and the overwritten bytes
+/// are kept in memory.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FillAction {
+    pub position: u64,
+    pub length: u64,
+    pub pattern: Vec<u8>,
+    previous_data: Vec<u8>,
+}
+impl FillAction {
+    pub fn new(position: u64, length: u64, pattern: Vec<u8>) -> Self {
+        Self {
+            position,
+            length,
+            pattern,
+            previous_data: Vec::new(),
+        }
+    }
+}
+impl<F, E> Action<F, E> for FillAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        if self.pattern.is_empty() {
+            return Err(ActionError::Invalid);
+        }
+        check_range(data, self.position, self.length)?;
+
+        let pattern = &self.pattern;
+        let mut previous = Vec::new();
+        transform_range(
+            data,
+            self.position,
+            self.length,
+            CHUNK_SIZE,
+            |offset, chunk| {
+                previous.extend_from_slice(chunk);
+                combine_repeated(chunk, offset, pattern, |_, value| value);
+            },
+        )?;
+        self.previous_data = previous;
+
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        write_at(data, self.position, &self.previous_data)?;
+        Ok(())
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.position..self.position.saturating_add(self.length))
+    }
+
+    fn description(&self) -> String {
+        describe_bytes("Fill", self.position, self.length)
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(FillAction::new(
+            self.position,
+            self.length,
+            self.pattern.clone(),
+        )))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.position], delta)
+    }
+}
+impl MemoryUsage for FillAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.pattern.len() + self.previous_data.len()
+    }
+}
+
+/// How a [`TransformAction`] is redone after being undone.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RedoMode {
+    /// Run the closure again on the restored bytes.
+    /// The closure must be deterministic for redo to reproduce the original result.
+    Rerun,
+    /// Store the output of the first application, and write it back when redoing.
+    /// This costs memory for the output, but allows non-deterministic closures.
+    ReplayOutput,
+}
+
+/// A user-provided transformation over a buffer of bytes.
+pub type TransformFn = Box<dyn FnMut(&mut [u8]) + Send>;
+
+/// An action which runs a user-provided closure over the bytes of a range.
+/// The entire range is read into memory and given to the closure at once.
+///
+/// NOTE: With `RedoMode::Rerun` (the default) redoing will run the closure again on the restored
+/// bytes, so it must be deterministic.
+/// If the closure panics, the panic is caught and the action fails with `ActionError::Custom`
+/// without the data being modified.
+pub struct TransformAction {
+    pub position: u64,
+    pub length: u64,
+    /// Used for the `Debug` implementation, since closures are not `Debug`, and as the verb of
+    /// the description, such as "decrypt 16 bytes at 0x10".
+    pub label: String,
+    pub redo_mode: RedoMode,
+    transform: TransformFn,
+    previous_data: Vec<u8>,
+    /// Only stored with `RedoMode::ReplayOutput`, after the first application.
+    output: Option<Vec<u8>>,
+}
+impl TransformAction {
+    pub fn new(position: u64, length: u64, transform: TransformFn) -> Self {
+        Self {
+            position,
+            length,
+            label: String::from("transform"),
+            redo_mode: RedoMode::Rerun,
+            transform,
+            previous_data: Vec::new(),
+            output: None,
+        }
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = label.into();
+        self
+    }
+
+    pub fn with_redo_mode(mut self, redo_mode: RedoMode) -> Self {
+        self.redo_mode = redo_mode;
+        self
+    }
+}
+impl std::fmt::Debug for TransformAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TransformAction")
+            .field("position", &self.position)
+            .field("length", &self.length)
+            .field("label", &self.label)
+            .field("redo_mode", &self.redo_mode)
+            .finish()
+    }
+}
+impl<F, E> Action<F, E> for TransformAction
+where
+    F: Read + Seek + Write,
+{
+    fn description(&self) -> String {
+        describe_bytes(&self.label, self.position, self.length)
+    }
+
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        check_range(data, self.position, self.length)?;
+
+        // The whole range is transformed at once, so it has to fit in memory.
+        let buffer = read_range(data, self.position, self.length)?;
+
+        let output = if let Some(output) = &self.output {
+            output.clone()
+        } else {
+            let mut output = buffer.clone();
+            let transform = &mut self.transform;
+            // The closure only ever sees our copy, so a panic can't leave the data half-written.
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| transform(&mut output)))
+                .map_err(|_| ActionError::Custom("transform closure panicked".into()))?;
+            if self.redo_mode == RedoMode::ReplayOutput {
+                self.output = Some(output.clone());
+            }
+            output
+        };
+
+        write_at(data, self.position, &output)?;
+        self.previous_data = buffer;
+
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        write_at(data, self.position, &self.previous_data)?;
+        Ok(())
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.position..self.position.saturating_add(self.length))
+    }
+}
+impl MemoryUsage for TransformAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.label.len()
+            + self.previous_data.len()
+            + self.output.as_ref().map_or(0, Vec::len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        reverse_range, rotate_range_left, swap_ranges, ArithmeticAction, ArithmeticMode, BitwiseOp,
+        BitwiseRangeAction, CaseMode, CopyRangeAction, FillAction, MoveRangeAction,
+        RandomFillAction, RedoMode, ReverseAction, RotateDirection, ShredRangeAction,
+        SwapRangesAction, TransformAction, XorRangeAction,
+    };
+    use crate::{
+        action::{Action, ActionError, MemoryUsage},
+        contents, Endian, Hiex, CHUNK_SIZE,
+    };
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+    /// Records the data each time it is flushed.
+    struct FlushLog {
+        inner: Cursor<Vec<u8>>,
+        flushed: Vec<Vec<u8>>,
+    }
+    impl Read for FlushLog {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+    impl Write for FlushLog {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.flushed.push(self.inner.get_ref().clone());
+            Ok(())
+        }
+    }
+    impl Seek for FlushLog {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    fn hiex(data: &[u8]) -> Hiex<Cursor<Vec<u8>>, ()> {
+        Hiex::from_reader(Cursor::new(data.to_vec())).unwrap()
+    }
+
+    #[test]
+    fn test_arithmetic_wrapping() {
+        let mut hex = hiex(&[0xFF, 0x00, 0x10, 0x7F]);
+        let action = ArithmeticAction::new(0, 3, 1, 1, ArithmeticMode::Wrapping);
+        hex.add_action(action, ()).unwrap();
+        assert_eq!(contents(&mut hex), [0x00, 0x01, 0x11, 0x7F]);
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), [0xFF, 0x00, 0x10, 0x7F]);
+        hex.redo(()).unwrap();
+        assert_eq!(contents(&mut hex), [0x00, 0x01, 0x11, 0x7F]);
+
+        // Width 2, big endian, with a negative delta that borrows across bytes.
+        let mut hex = hiex(&[0x01, 0x00, 0x00, 0x00]);
+        let action =
+            ArithmeticAction::new(0, 4, 2, -1, ArithmeticMode::Wrapping).with_endian(Endian::Big);
+        hex.add_action(action, ()).unwrap();
+        assert_eq!(contents(&mut hex), [0x00, 0xFF, 0xFF, 0xFF]);
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), [0x01, 0x00, 0x00, 0x00]);
+
+        // Wrapping doesn't need to store the previous data.
+        let mut action = ArithmeticAction::new(0, 4, 2, 7, ArithmeticMode::Wrapping);
+        let before = action.memory_usage();
+        let mut cursor = Cursor::new(vec![0u8; 4]);
+        Action::apply(&mut action, &mut cursor, ()).unwrap();
+        assert_eq!(cursor.into_inner(), [7, 0, 7, 0]);
+        assert_eq!(action.memory_usage(), before);
+
+        // Subtracting from 32-bit elements.
+        let mut hex = hiex(&[0x00, 0x00, 0x01, 0x00, 0x10, 0x00, 0x00, 0x00]);
+        hex.add_to_range(0..8, 4, Endian::Big, -2, ()).unwrap();
+        assert_eq!(
+            contents(&mut hex),
+            [0x00, 0x00, 0x00, 0xFE, 0x0F, 0xFF, 0xFF, 0xFE]
+        );
+        hex.undo(()).unwrap();
+        assert_eq!(
+            contents(&mut hex),
+            [0x00, 0x00, 0x01, 0x00, 0x10, 0x00, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_arithmetic_saturating() {
+        let original = [250u8, 5, 255, 0];
+        let mut hex = hiex(&original);
+        let action = ArithmeticAction::new(0, 4, 1, 10, ArithmeticMode::Saturating);
+        hex.add_action(action, ()).unwrap();
+        assert_eq!(contents(&mut hex), [255, 15, 255, 10]);
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), original);
+
+        // Width 2, little endian, saturating at zero.
+        let original = [0x05, 0x00, 0x34, 0x12];
+        let mut hex = hiex(&original);
+        let action = ArithmeticAction::new(0, 4, 2, -0x10, ArithmeticMode::Saturating);
+        hex.add_action(action, ()).unwrap();
+        assert_eq!(contents(&mut hex), [0x00, 0x00, 0x24, 0x12]);
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), original);
+    }
+
+    #[test]
+    fn test_arithmetic_invalid() {
+        let mut hex = hiex(&[0u8; 5]);
+        // Length is not a multiple of the width.
+        let action = ArithmeticAction::new(0, 5, 2, 1, ArithmeticMode::Wrapping);
+        assert!(hex.add_action(action, ()).is_err());
+        // Past the end of the data.
+        let action = ArithmeticAction::new(2, 4, 1, 1, ArithmeticMode::Wrapping);
+        assert!(hex.add_action(action, ()).is_err());
+        // Unsupported width.
+        let action = ArithmeticAction::new(0, 3, 3, 1, ArithmeticMode::Wrapping);
+        assert!(hex.add_action(action, ()).is_err());
+        assert_eq!(contents(&mut hex), [0u8; 5]);
+    }
+
+    #[test]
+    fn test_endian_swap() {
+        let original: Vec<u8> = (0..16).collect();
+        for (width, expected) in [
+            (2, [1, 0, 3, 2, 5, 4, 7, 6, 9, 8, 11, 10, 13, 12, 15, 14]),
+            (4, [3, 2, 1, 0, 7, 6, 5, 4, 11, 10, 9, 8, 15, 14, 13, 12]),
+            (8, [7, 6, 5, 4, 3, 2, 1, 0, 15, 14, 13, 12, 11, 10, 9, 8]),
+        ] {
+            let mut hex = hiex(&original);
+            hex.swap_endian(0..16, width, ()).unwrap();
+            assert_eq!(contents(&mut hex), expected);
+            hex.undo(()).unwrap();
+            assert_eq!(contents(&mut hex), original);
+            hex.redo(()).unwrap();
+            assert_eq!(contents(&mut hex), expected);
+        }
+
+        let mut hex = hiex(&original);
+        // Not a multiple of the width, an unsupported width, and past the end of the data.
+        assert!(hex.swap_endian(0..6, 4, ()).is_err());
+        assert!(hex.swap_endian(0..6, 3, ()).is_err());
+        assert!(hex.swap_endian(12..20, 4, ()).is_err());
+        assert_eq!(contents(&mut hex), original);
+    }
+
+    #[test]
+    fn test_bit_flip() {
+        let mut hex = hiex(&[0x00, 0xFF]);
+        hex.flip_bit(0, 0, ()).unwrap();
+        hex.flip_bit(1, 7, ()).unwrap();
+        assert_eq!(contents(&mut hex), [0x01, 0x7F]);
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), [0x01, 0xFF]);
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), [0x00, 0xFF]);
+        hex.redo(()).unwrap();
+        assert_eq!(contents(&mut hex), [0x01, 0xFF]);
+
+        assert!(hex.flip_bit(0, 8, ()).is_err());
+        assert!(hex.flip_bit(2, 0, ()).is_err());
+        assert_eq!(contents(&mut hex), [0x01, 0xFF]);
+    }
+
+    #[test]
+    fn test_case_convert() {
+        let original = b"aB3z\xE1\xC1-Qq\x00".to_vec();
+        for (mode, expected) in [
+            (CaseMode::Upper, b"AB3Z\xE1\xC1-QQ\x00"),
+            (CaseMode::Lower, b"ab3z\xE1\xC1-qq\x00"),
+            (CaseMode::Toggle, b"Ab3Z\xE1\xC1-qQ\x00"),
+        ] {
+            let mut hex = hiex(&original);
+            hex.convert_case(0..10, mode, ()).unwrap();
+            assert_eq!(&contents(&mut hex), expected);
+            hex.undo(()).unwrap();
+            assert_eq!(contents(&mut hex), original);
+            hex.redo(()).unwrap();
+            assert_eq!(&contents(&mut hex), expected);
+        }
+
+        // Only the given range is touched.
+        let mut hex = hiex(b"abcdef");
+        hex.convert_case(2..4, CaseMode::Upper, ()).unwrap();
+        assert_eq!(contents(&mut hex), b"abCDef");
+    }
+
+    #[test]
+    fn test_move_range() {
+        let original = b"0123456789";
+        for (source, destination, expected, stored) in [
+            (0, 6, b"\0\0\0\x00450123", 4),
+            // Overlapping, in either direction, which only stores the bytes outside the source.
+            (2, 4, b"01\0\x00234589", 2),
+            (4, 2, b"014567\0\x0089", 2),
+        ] {
+            let mut hex = hiex(original);
+            let action = MoveRangeAction::new(source, destination, 4);
+            hex.add_action(action, ()).unwrap();
+            assert_eq!(&contents(&mut hex), expected);
+            assert_eq!(
+                hex.actions.get(0).unwrap().memory_usage(),
+                std::mem::size_of::<MoveRangeAction>() + stored
+            );
+            hex.undo(()).unwrap();
+            assert_eq!(contents(&mut hex), original);
+            hex.redo(()).unwrap();
+            assert_eq!(&contents(&mut hex), expected);
+        }
+
+        let mut hex = hiex(original);
+        hex.move_range(1..3, 8, ()).unwrap();
+        assert_eq!(contents(&mut hex), b"0\0\x003456712");
+        let action = MoveRangeAction::new(0, 8, 3).with_fill(b'-');
+        assert!(hex.add_action(action, ()).is_err());
+        hex.add_action(MoveRangeAction::new(0, 1, 1).with_fill(b'-'), ())
+            .unwrap();
+        assert_eq!(contents(&mut hex), b"-0\x003456712");
+    }
+
+    #[test]
+    fn test_copy_range() {
+        let original = b"0123456789";
+        for (source, destination, expected) in [
+            (0, 6, b"0123450123"),
+            // Overlapping, in either direction.
+            (2, 4, b"0123234589"),
+            (4, 2, b"0145676789"),
+        ] {
+            let mut hex = hiex(original);
+            hex.copy_range(source..source + 4, destination, ()).unwrap();
+            assert_eq!(&contents(&mut hex), expected);
+            hex.undo(()).unwrap();
+            assert_eq!(contents(&mut hex), original);
+            hex.redo(()).unwrap();
+            assert_eq!(&contents(&mut hex), expected);
+        }
+
+        let mut hex = hiex(original);
+        let action = CopyRangeAction::new(0, 8, 3);
+        assert!(hex.add_action(action, ()).is_err());
+        assert_eq!(contents(&mut hex), original);
+    }
+
+    #[test]
+    fn test_xor_range() {
+        let original = b"\x00\x0F\xF0\xFF\x12\x34";
+        let mut hex = hiex(original);
+        hex.xor_range(1..6, vec![0xFF, 0x0F], ()).unwrap();
+        assert_eq!(contents(&mut hex), [0x00, 0xF0, 0xFF, 0x00, 0x1D, 0xCB]);
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), original);
+        hex.redo(()).unwrap();
+        assert_eq!(contents(&mut hex), [0x00, 0xF0, 0xFF, 0x00, 0x1D, 0xCB]);
+
+        // Nothing but the key is stored, however long the range.
+        let mut action = XorRangeAction::new(0, 6, vec![0xAA]);
+        let before = action.memory_usage();
+        let mut cursor = Cursor::new(original.to_vec());
+        Action::apply(&mut action, &mut cursor, ()).unwrap();
+        assert_eq!(action.memory_usage(), before);
+
+        assert!(hex
+            .add_action(XorRangeAction::new(0, 2, Vec::new()), ())
+            .is_err());
+    }
+
+    #[test]
+    fn test_bitwise_range() {
+        let original = b"\x00\x0F\xF0\xFF\x5A";
+        for (action, expected) in [
+            (
+                BitwiseRangeAction::new(0, 5, BitwiseOp::And, vec![0x3C]),
+                [0x00, 0x0C, 0x30, 0x3C, 0x18],
+            ),
+            (
+                BitwiseRangeAction::new(1, 4, BitwiseOp::Or, vec![0x80, 0x01]),
+                [0x00, 0x8F, 0xF1, 0xFF, 0x5B],
+            ),
+            (
+                BitwiseRangeAction::not(0, 4),
+                [0xFF, 0xF0, 0x0F, 0x00, 0x5A],
+            ),
+        ] {
+            let mut hex = hiex(original);
+            hex.add_action(action, ()).unwrap();
+            assert_eq!(contents(&mut hex), expected);
+            hex.undo(()).unwrap();
+            assert_eq!(contents(&mut hex), original);
+            hex.redo(()).unwrap();
+            assert_eq!(contents(&mut hex), expected);
+        }
+
+        let mut hex = hiex(original);
+        let action = BitwiseRangeAction::new(0, 2, BitwiseOp::And, Vec::new());
+        assert!(hex.add_action(action, ()).is_err());
+        assert!(hex.add_action(BitwiseRangeAction::not(3, 4), ()).is_err());
+        assert_eq!(contents(&mut hex), original);
+    }
+
+    #[test]
+    fn test_random_fill() {
+        let length = CHUNK_SIZE + 13;
+        let original = vec![0u8; length + 2];
+        let mut hex = hiex(&original);
+        hex.add_action(RandomFillAction::new(1, length as u64, 42), ())
+            .unwrap();
+        let filled = contents(&mut hex);
+        assert_eq!((filled[0], filled[length + 1]), (0, 0));
+        // Roughly evenly spread, rather than mostly one value.
+        let zeros = filled[1..=length].iter().filter(|&&byte| byte == 0).count();
+        assert!(zeros < length / 100);
+
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), original);
+        hex.redo(()).unwrap();
+        assert_eq!(contents(&mut hex), filled);
+
+        // Only the seed and length decide the bytes, not where they are or how they are chunked.
+        let mut hex = hiex(&[0xFF; 20]);
+        hex.add_action(RandomFillAction::new(4, 16, 42), ())
+            .unwrap();
+        assert_eq!(contents(&mut hex)[4..], filled[1..17]);
+        hex.add_action(RandomFillAction::new(4, 16, 43), ())
+            .unwrap();
+        assert_ne!(contents(&mut hex)[4..], filled[1..17]);
+    }
+
+    #[test]
+    fn test_shred_range() {
+        let original = b"secret data";
+        let mut hex = hiex(original);
+        let passes = vec![vec![0xFF], vec![0x00, 0x01], vec![b'-']];
+        hex.add_action(ShredRangeAction::new(0, 6, passes), ())
+            .unwrap();
+        assert_eq!(contents(&mut hex), b"------ data");
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), original);
+        hex.redo(()).unwrap();
+        assert_eq!(contents(&mut hex), b"------ data");
+
+        // Every pass reaches the data, and is flushed.
+        let mut action = ShredRangeAction::new(1, 2, vec![vec![1], vec![2]]);
+        let mut data = FlushLog {
+            inner: Cursor::new(vec![0; 4]),
+            flushed: Vec::new(),
+        };
+        Action::apply(&mut action, &mut data, ()).unwrap();
+        assert_eq!(data.flushed, [vec![0, 1, 1, 0], vec![0, 2, 2, 0]]);
+
+        let mut hex = hiex(original);
+        assert!(hex
+            .add_action(ShredRangeAction::new(0, 6, Vec::new()), ())
+            .is_err());
+        assert!(hex
+            .add_action(ShredRangeAction::new(0, 6, vec![vec![1], Vec::new()]), ())
+            .is_err());
+        assert_eq!(contents(&mut hex), original);
+    }
+
+    #[test]
+    fn test_fill() {
+        let mut hex = hiex(b"0123456789");
+        hex.fill(1..8, vec![0xDE, 0xAD, 0xBE, 0xEF], ()).unwrap();
+        assert_eq!(contents(&mut hex), b"0\xDE\xAD\xBE\xEF\xDE\xAD\xBE89");
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), b"0123456789");
+        hex.redo(()).unwrap();
+        assert_eq!(contents(&mut hex), b"0\xDE\xAD\xBE\xEF\xDE\xAD\xBE89");
+
+        // The pattern continues across chunks.
+        let length = CHUNK_SIZE * 2 + 5;
+        let mut hex = hiex(&vec![0u8; length]);
+        hex.add_action(FillAction::new(0, length as u64, b"abc".to_vec()), ())
+            .unwrap();
+        let expected: Vec<u8> = b"abc".iter().copied().cycle().take(length).collect();
+        assert_eq!(contents(&mut hex), expected);
+
+        let mut hex = hiex(b"0123");
+        assert!(hex
+            .add_action(FillAction::new(0, 2, Vec::new()), ())
+            .is_err());
+        assert!(hex.add_action(FillAction::new(2, 4, vec![0]), ()).is_err());
+        assert_eq!(contents(&mut hex), b"0123");
+    }
+
+    #[test]
+    fn test_reverse() {
+        for length in 0..12u8 {
+            let original: Vec<u8> = (0..length).collect();
+            let mut hex = hiex(&original);
+            hex.add_action(ReverseAction::new(0, u64::from(length)), ())
+                .unwrap();
+            let mut expected = original.clone();
+            expected.reverse();
+            assert_eq!(contents(&mut hex), expected);
+            hex.undo(()).unwrap();
+            assert_eq!(contents(&mut hex), original);
+
+            // Chunked path with a tiny chunk size, on odd and even lengths.
+            for chunk_size in 1..4 {
+                let mut cursor = Cursor::new(original.clone());
+                reverse_range(&mut cursor, 0, u64::from(length), chunk_size).unwrap();
+                assert_eq!(cursor.get_ref(), &expected);
+            }
+        }
+
+        // A range large enough to force the chunked path with the real chunk size.
+        let original: Vec<u8> = (0..200_001u32).map(|i| (i % 251) as u8).collect();
+        let mut hex = hiex(&original);
+        hex.add_action(ReverseAction::new(1, 200_000), ()).unwrap();
+        let mut expected = original.clone();
+        expected[1..].reverse();
+        assert_eq!(contents(&mut hex), expected);
+        // Applying it a second time returns the original.
+        hex.add_action(ReverseAction::new(1, 200_000), ()).unwrap();
+        assert_eq!(contents(&mut hex), original);
+
+        let mut hex = hiex(b"abc");
+        assert!(hex.add_action(ReverseAction::new(1, 3), ()).is_err());
+        hex.reverse_range(1..3, ()).unwrap();
+        assert_eq!(contents(&mut hex), b"acb");
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), b"abc");
+    }
+
+    #[test]
+    fn test_rotate_range() {
+        let original = b"abcdefgh".to_vec();
+        let mut hex = hiex(&original);
+        hex.rotate_range(1..7, 2, RotateDirection::Left, ())
+            .unwrap();
+        assert_eq!(contents(&mut hex), b"adefgbch");
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), original);
+
+        // Amounts past the length wrap around.
+        hex.rotate_range(0..8, 11, RotateDirection::Right, ())
+            .unwrap();
+        assert_eq!(contents(&mut hex), b"fghabcde");
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), original);
+        hex.rotate_range(0..8, 16, RotateDirection::Left, ())
+            .unwrap();
+        assert_eq!(contents(&mut hex), original);
+
+        let (_, err) = hex
+            .rotate_range(4..9, 1, RotateDirection::Left, ())
+            .unwrap_err();
+        assert!(matches!(err, ActionError::Invalid));
+
+        // Chunked.
+        let original: Vec<u8> = (0..100).collect();
+        let mut cursor = Cursor::new(original.clone());
+        rotate_range_left(&mut cursor, 10, 80, 33, 7).unwrap();
+        let mut expected = original;
+        expected[10..90].rotate_left(33);
+        assert_eq!(cursor.get_ref(), &expected);
+    }
+
+    #[test]
+    fn test_swap_ranges() {
+        // Adjacent, but not overlapping.
+        let mut hex = hiex(b"abcdEFGHij");
+        hex.swap_ranges(0, 4, 4, ()).unwrap();
+        assert_eq!(contents(&mut hex), b"EFGHabcdij");
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), b"abcdEFGHij");
+
+        // Ranges ending exactly at the end of the data.
+        hex.swap_ranges(8, 1, 2, ()).unwrap();
+        assert_eq!(contents(&mut hex), b"aijdEFGHbc");
+        hex.undo(()).unwrap();
+
+        // Past the end.
+        let (_, err) = hex.swap_ranges(0, 9, 2, ()).unwrap_err();
+        assert!(matches!(err, ActionError::Invalid));
+
+        // Overlapping.
+        let (_, err) = hex.swap_ranges(0, 3, 4, ()).unwrap_err();
+        assert!(matches!(err, ActionError::OverlappingRanges));
+        assert_eq!(contents(&mut hex), b"abcdEFGHij");
+
+        // Records of a table after a two byte header.
+        hex.add_action(SwapRangesAction::records(2, 2, 0, 3), ())
+            .unwrap();
+        assert_eq!(contents(&mut hex), b"abijEFGHcd");
+        hex.undo(()).unwrap();
+
+        // Chunked.
+        let mut cursor = Cursor::new(b"abcdEFGHij".to_vec());
+        swap_ranges(&mut cursor, 1, 6, 3, 2).unwrap();
+        assert_eq!(cursor.get_ref(), b"aGHiEFbcdj");
+    }
+
+    #[test]
+    fn test_transform() {
+        let original = b"\x00\x01\x02\x03\x04".to_vec();
+        let mut hex = hiex(&original);
+        let xor = Box::new(|data: &mut [u8]| data.iter_mut().for_each(|byte| *byte ^= 0xAA));
+        let action = TransformAction::new(1, 3, xor).with_label("xor 0xAA");
+        assert!(format!("{:?}", action).contains("xor 0xAA"));
+        hex.add_action(action, ()).unwrap();
+        assert_eq!(contents(&mut hex), [0x00, 0xAB, 0xA8, 0xA9, 0x04]);
+        hex.undo(()).unwrap();
+        assert_eq!(contents(&mut hex), original);
+        hex.redo(()).unwrap();
+        assert_eq!(contents(&mut hex), [0x00, 0xAB, 0xA8, 0xA9, 0x04]);
+
+        // A non-deterministic closure replays its first output.
+        let mut counter = 0u8;
+        let count = Box::new(move |data: &mut [u8]| {
+            counter += 1;
+            data.iter_mut().for_each(|byte| *byte = counter);
+        });
+        let action = TransformAction::new(0, 2, count).with_redo_mode(RedoMode::ReplayOutput);
+        let mut hex = hiex(&original);
+        hex.add_action(action, ()).unwrap();
+        assert_eq!(contents(&mut hex), [1, 1, 2, 3, 4]);
+        hex.undo(()).unwrap();
+        hex.redo(()).unwrap();
+        assert_eq!(contents(&mut hex), [1, 1, 2, 3, 4]);
+
+        // A panicking closure leaves both the data and the history untouched.
+        let panics = Box::new(|data: &mut [u8]| {
+            data[0] = 0xFF;
+            panic!("bad descrambler");
+        });
+        let mut hex = hiex(&original);
+        let (_, err) = hex
+            .add_action(TransformAction::new(0, 5, panics), ())
+            .unwrap_err();
+        assert!(matches!(err, ActionError::Custom(_)));
+        assert_eq!(contents(&mut hex), original);
+        assert!(hex.actions.is_empty());
+    }
+}
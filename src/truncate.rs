@@ -1,5 +1,8 @@
-use std::{fs::File, io::Cursor};
-use usize_cast::IntoUsize;
+use crate::checked_usize;
+use std::{
+    fs::File,
+    io::{Cursor, ErrorKind},
+};
 
 // TODO: tests
 /// A trait for objects which can be truncated
@@ -10,6 +13,23 @@ use usize_cast::IntoUsize;
 /// if the position is after the end then it should be set to the last valid position
 pub trait Truncate {
     fn truncate(&mut self, new_len: u64) -> std::io::Result<()>;
+
+    /// Opens `length` bytes at `position`, moving the bytes from there onwards forward, if this
+    /// can be done more efficiently than by copying them. The contents of the opened bytes are
+    /// unspecified.
+    /// Returns `false` if it isn't supported (the default), in which case the caller grows the
+    /// data and moves the bytes itself.
+    fn insert_space(&mut self, _position: u64, _length: u64) -> std::io::Result<bool> {
+        Ok(false)
+    }
+
+    /// Removes the bytes within `range`, moving the bytes after it back, if this can be done more
+    /// efficiently than by copying them.
+    /// Returns `false` if it isn't supported (the default), in which case the caller moves the
+    /// bytes and shrinks the data itself.
+    fn remove_space(&mut self, _range: std::ops::Range<u64>) -> std::io::Result<bool> {
+        Ok(false)
+    }
 }
 
 impl Truncate for File {
@@ -18,18 +38,27 @@ impl Truncate for File {
     }
 }
 
+/// Reserves enough space for `vec` to grow to `new_len`, failing with `ErrorKind::OutOfMemory`
+/// rather than aborting if it can't be allocated.
+fn reserve_to(vec: &mut Vec<u8>, new_len: usize) -> std::io::Result<()> {
+    let additional = new_len.saturating_sub(vec.len());
+    vec.try_reserve(additional)
+        .map_err(|err| std::io::Error::new(ErrorKind::OutOfMemory, err))
+}
+
 impl Truncate for Cursor<&mut Vec<u8>> {
     fn truncate(&mut self, new_len: u64) -> std::io::Result<()> {
         let position = self.position();
+        let new_len_usize = checked_usize(new_len)?;
+        reserve_to(self.get_mut(), new_len_usize)?;
         if position >= new_len {
             // TODO: check this. Is 0 a sensible value? also will this be good?
             self.set_position(new_len.saturating_sub(1));
         }
 
-        let new_len = new_len.into_usize();
         // SANITY: Since messing with the underlying vector as we do can mess with the position
         // we manually make sure the position is within bounds above.
-        self.get_mut().resize(new_len, 0);
+        self.get_mut().resize(new_len_usize, 0);
         Ok(())
     }
 }
@@ -37,15 +66,16 @@ impl Truncate for Cursor<&mut Vec<u8>> {
 impl Truncate for Cursor<Vec<u8>> {
     fn truncate(&mut self, new_len: u64) -> std::io::Result<()> {
         let position = self.position();
+        let new_len_usize = checked_usize(new_len)?;
+        reserve_to(self.get_mut(), new_len_usize)?;
         if position >= new_len {
             // TODO: check this. Is 0 a sensible value? also will this be good?
             self.set_position(new_len.saturating_sub(1));
         }
 
-        let new_len = new_len.into_usize();
         // SANITY: Since messing with the underlying vector as we do can mess with the position
         // we manually make sure the position is within bounds above.
-        self.get_mut().resize(new_len, 0);
+        self.get_mut().resize(new_len_usize, 0);
         Ok(())
     }
 }
@@ -0,0 +1,462 @@
+//! LEB128 variable-length integers, and writing them as actions.
+use crate::{
+    action::{translate_offsets, Action, ActionError, MemoryUsage, OffsetShift},
+    clamp_usize, resize_at, stream_len,
+    truncate::Truncate,
+    write_at, Hiex,
+};
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+use usize_cast::FromUsize;
+
+/// The most bytes a 64 bit value takes up as LEB128.
+pub const MAX_VARINT_LEN: usize = 10;
+
+/// A value to encode as LEB128.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Varint {
+    /// ULEB128
+    Unsigned(u64),
+    /// SLEB128
+    Signed(i64),
+}
+impl Varint {
+    /// The shortest encoding of the value.
+    pub fn encode(self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            Varint::Unsigned(mut value) => loop {
+                let byte = (value & 0x7F) as u8;
+                value >>= 7;
+                if value == 0 {
+                    bytes.push(byte);
+                    break;
+                }
+                bytes.push(byte | 0x80);
+            },
+            Varint::Signed(mut value) => loop {
+                let byte = (value & 0x7F) as u8;
+                value >>= 7;
+                // Done once the rest is only the sign extension of the last byte's sign bit.
+                let sign = byte & 0x40 != 0;
+                if (value == 0 && !sign) || (value == -1 && sign) {
+                    bytes.push(byte);
+                    break;
+                }
+                bytes.push(byte | 0x80);
+            },
+        }
+        bytes
+    }
+}
+
+/// The length of the LEB128 value at the start of `bytes`, whether signed or not, if it ends
+/// within them.
+pub fn varint_len(bytes: &[u8]) -> Option<usize> {
+    bytes
+        .iter()
+        .take(MAX_VARINT_LEN)
+        .position(|byte| byte & 0x80 == 0)
+        .map(|index| index + 1)
+}
+
+/// Decode the ULEB128 value at the start of `bytes`, and its length.
+/// Bits past the 64th are ignored.
+pub fn decode_uleb128(bytes: &[u8]) -> Option<(u64, usize)> {
+    let length = varint_len(bytes)?;
+    let value = bytes[..length]
+        .iter()
+        .enumerate()
+        .fold(0u64, |value, (i, byte)| {
+            value
+                | u64::from(byte & 0x7F)
+                    .checked_shl(7 * i as u32)
+                    .unwrap_or(0)
+        });
+    Some((value, length))
+}
+
+/// Decode the SLEB128 value at the start of `bytes`, and its length.
+/// Bits past the 64th are ignored.
+pub fn decode_sleb128(bytes: &[u8]) -> Option<(i64, usize)> {
+    let (value, length) = decode_uleb128(bytes)?;
+    let bits = 7 * length as u32;
+    let value = if bits < 64 && bytes[length - 1] & 0x40 != 0 {
+        value | (!0 << bits)
+    } else {
+        value
+    };
+    Some((value as i64, length))
+}
+
+/// Reads the LEB128 value at `position`, failing with `ActionError::Invalid` if it doesn't end
+/// within the data.
+fn read_varint_at<F>(data: &mut F, position: u64) -> Result<Vec<u8>, ActionError>
+where
+    F: Read + Seek,
+{
+    let length = stream_len(data)?;
+    if position >= length {
+        return Err(ActionError::Invalid);
+    }
+    let mut previous = vec![0u8; clamp_usize(length - position, MAX_VARINT_LEN)];
+    data.seek(SeekFrom::Start(position))?;
+    data.read_exact(&mut previous)?;
+    let previous_len = varint_len(&previous).ok_or(ActionError::Invalid)?;
+    previous.truncate(previous_len);
+    Ok(previous)
+}
+
+/// An action which overwrites the LEB128 value at a position with a new one.
+/// If the new value's encoding is a different length, then this fails with
+/// `ActionError::Invalid`, see [`VarintResizeAction`] to grow or shrink the data for it instead.
+/// If writing fails partway, the previous bytes are written back, and it fails with
+/// `ActionError::PartialWrite`.
+/// The previous encoding is stored for if the action is undone.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VarintWriteAction {
+    pub position: u64,
+    pub value: Varint,
+    previous_data: Vec<u8>,
+    new_data: Vec<u8>,
+}
+impl VarintWriteAction {
+    pub fn new(position: u64, value: Varint) -> Self {
+        Self {
+            position,
+            value,
+            previous_data: Vec::new(),
+            new_data: Vec::new(),
+        }
+    }
+}
+impl<F, E> Action<F, E> for VarintWriteAction
+where
+    F: Read + Seek + Write,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        let previous = read_varint_at(data, self.position)?;
+        let new_data = self.value.encode();
+        if new_data.len() != previous.len() {
+            return Err(ActionError::Invalid);
+        }
+
+        if let Err(error) = write_at(data, self.position, &new_data) {
+            let rolled_back = write_at(data, self.position, &previous).is_ok();
+            return Err(ActionError::PartialWrite { error, rolled_back });
+        }
+        self.previous_data = previous;
+        self.new_data = new_data;
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        write_at(data, self.position, &self.previous_data)?;
+        Ok(())
+    }
+
+    fn affected_range(&self) -> Option<Range<u64>> {
+        let length = u64::from_usize(self.value.encode().len());
+        Some(self.position..self.position.saturating_add(length))
+    }
+
+    fn description(&self) -> String {
+        format!("Write LEB128 value at {:#X}", self.position)
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(VarintWriteAction::new(self.position, self.value)))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.position], delta)
+    }
+}
+impl MemoryUsage for VarintWriteAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.previous_data.len() + self.new_data.len()
+    }
+}
+
+/// An action which overwrites the LEB128 value at a position with a new one, inserting or
+/// removing bytes after the previous encoding if the new one is a different length, moving the
+/// bytes after it.
+/// If writing the new encoding fails after resizing, the data is resized back and the previous
+/// bytes are written back, and it fails with `ActionError::PartialWrite`.
+/// The previous encoding is stored for if the action is undone.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VarintResizeAction {
+    pub position: u64,
+    pub value: Varint,
+    previous_data: Vec<u8>,
+    new_data: Vec<u8>,
+}
+impl VarintResizeAction {
+    pub fn new(position: u64, value: Varint) -> Self {
+        Self {
+            position,
+            value,
+            previous_data: Vec::new(),
+            new_data: Vec::new(),
+        }
+    }
+}
+impl<F, E> Action<F, E> for VarintResizeAction
+where
+    F: Read + Seek + Write + Truncate,
+{
+    fn apply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        let previous = read_varint_at(data, self.position)?;
+        let new_data = self.value.encode();
+        let previous_len = u64::from_usize(previous.len());
+        let new_len = u64::from_usize(new_data.len());
+
+        resize_at(data, self.position, previous_len, new_len)?;
+        if let Err(error) = write_at(data, self.position, &new_data) {
+            // Best-effort rollback of the resize as well, so that the bytes after it aren't left
+            // moved.
+            let rolled_back = resize_at(data, self.position, new_len, previous_len)
+                .and_then(|()| write_at(data, self.position, &previous))
+                .is_ok();
+            return Err(ActionError::PartialWrite { error, rolled_back });
+        }
+        self.previous_data = previous;
+        self.new_data = new_data;
+        Ok(())
+    }
+
+    fn unapply(&mut self, data: &mut F, _other: E) -> Result<(), ActionError> {
+        resize_at(
+            data,
+            self.position,
+            u64::from_usize(self.new_data.len()),
+            u64::from_usize(self.previous_data.len()),
+        )?;
+        write_at(data, self.position, &self.previous_data)?;
+        Ok(())
+    }
+
+    /// Everything from the position onwards.
+    fn affected_range(&self) -> Option<Range<u64>> {
+        Some(self.position..u64::MAX)
+    }
+
+    fn description(&self) -> String {
+        format!("Write LEB128 value at {:#X}", self.position)
+    }
+
+    fn clone_for_replay(&self) -> Option<Box<dyn Action<F, E>>> {
+        Some(Box::new(VarintResizeAction::new(self.position, self.value)))
+    }
+
+    fn translate(&mut self, delta: i64) -> bool {
+        translate_offsets(&mut [&mut self.position], delta)
+    }
+
+    fn offset_shift(&self) -> Option<OffsetShift> {
+        OffsetShift::resize(
+            self.position,
+            u64::from_usize(self.previous_data.len()),
+            u64::from_usize(self.new_data.len()),
+        )
+    }
+}
+impl MemoryUsage for VarintResizeAction {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.previous_data.len() + self.new_data.len()
+    }
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Overwrites the LEB128 value at `position` with `value` as a single action, failing if its
+    /// encoding is a different length. See [`VarintWriteAction`].
+    pub fn write_varint(
+        &mut self,
+        position: u64,
+        value: Varint,
+        other: E,
+    ) -> Result<(), (VarintWriteAction, ActionError)> {
+        self.add_action(VarintWriteAction::new(position, value), other)
+    }
+
+    /// Overwrites the LEB128 value at `position` with `value` as a single action, inserting or
+    /// removing bytes if its encoding is a different length. See [`VarintResizeAction`].
+    pub fn write_varint_resizing(
+        &mut self,
+        position: u64,
+        value: Varint,
+        other: E,
+    ) -> Result<(), (VarintResizeAction, ActionError)>
+    where
+        F: Truncate,
+    {
+        self.add_action(VarintResizeAction::new(position, value), other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_sleb128, decode_uleb128, Varint};
+    use crate::{action::ActionError, truncate::Truncate, Hiex};
+    use std::{
+        io::{Cursor, Read, Seek, SeekFrom, Write},
+        ops::Range,
+    };
+
+    #[test]
+    fn test_leb128() {
+        let unsigned: &[(u64, &[u8])] = &[
+            (0, &[0x00]),
+            (127, &[0x7F]),
+            (128, &[0x80, 0x01]),
+            (624_485, &[0xE5, 0x8E, 0x26]),
+        ];
+        for &(value, bytes) in unsigned {
+            assert_eq!(Varint::Unsigned(value).encode(), bytes);
+            assert_eq!(decode_uleb128(bytes), Some((value, bytes.len())));
+        }
+        let max = Varint::Unsigned(u64::MAX).encode();
+        assert_eq!(max.len(), 10);
+        assert_eq!(decode_uleb128(&max), Some((u64::MAX, 10)));
+
+        let signed: &[(i64, &[u8])] = &[
+            (0, &[0x00]),
+            (-1, &[0x7F]),
+            (63, &[0x3F]),
+            (64, &[0xC0, 0x00]),
+            (-64, &[0x40]),
+            (-123_456, &[0xC0, 0xBB, 0x78]),
+        ];
+        for &(value, bytes) in signed {
+            assert_eq!(Varint::Signed(value).encode(), bytes);
+            assert_eq!(decode_sleb128(bytes), Some((value, bytes.len())));
+        }
+        for &value in &[i64::MIN, i64::MAX] {
+            let bytes = Varint::Signed(value).encode();
+            assert_eq!(decode_sleb128(&bytes), Some((value, bytes.len())));
+        }
+
+        assert_eq!(decode_uleb128(&[0x80, 0x80]), None);
+    }
+
+    /// Splices its bytes directly when resizing, and fails the next write if `fail_next` is set.
+    struct FailOnceBackend {
+        inner: Cursor<Vec<u8>>,
+        fail_next: bool,
+    }
+    impl Read for FailOnceBackend {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+    impl Write for FailOnceBackend {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if std::mem::take(&mut self.fail_next) {
+                return Err(std::io::Error::other("failed once"));
+            }
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    impl Seek for FailOnceBackend {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+    impl Truncate for FailOnceBackend {
+        fn truncate(&mut self, new_len: u64) -> std::io::Result<()> {
+            self.inner.truncate(new_len)
+        }
+
+        fn insert_space(&mut self, position: u64, length: u64) -> std::io::Result<bool> {
+            let position = position as usize;
+            let space = std::iter::repeat(0).take(length as usize);
+            self.inner.get_mut().splice(position..position, space);
+            Ok(true)
+        }
+
+        fn remove_space(&mut self, range: Range<u64>) -> std::io::Result<bool> {
+            self.inner
+                .get_mut()
+                .drain(range.start as usize..range.end as usize);
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn test_write_varint() {
+        let original = b"a\xE5\x8E\x26z".to_vec();
+        let mut hex = Hiex::from_reader(Cursor::new(original.clone())).unwrap();
+        hex.write_varint(1, Varint::Unsigned(0x10_0000), ())
+            .unwrap();
+        assert_eq!(hex.read_amount_at(0, 5).unwrap(), b"a\x80\x80\x40z");
+
+        // A different length fails without resizing.
+        let (_, err) = hex.write_varint(1, Varint::Unsigned(1), ()).unwrap_err();
+        assert!(matches!(err, ActionError::Invalid));
+
+        hex.write_varint_resizing(1, Varint::Unsigned(1), ())
+            .unwrap();
+        assert_eq!(hex.length().unwrap(), 3);
+        assert_eq!(hex.read_amount_at(0, 3).unwrap(), b"a\x01z");
+        hex.write_varint_resizing(1, Varint::Signed(i64::MIN), ())
+            .unwrap();
+        assert_eq!(hex.length().unwrap(), 12);
+        assert_eq!(hex.read_amount_at(11, 1).unwrap(), b"z");
+
+        hex.undo(()).unwrap();
+        assert_eq!(hex.read_amount_at(0, 3).unwrap(), b"a\x01z");
+        hex.undo(()).unwrap();
+        hex.undo(()).unwrap();
+        assert_eq!(hex.length().unwrap(), 5);
+        assert_eq!(hex.read_amount_at(0, 5).unwrap(), original);
+
+        // Unterminated.
+        let mut hex = Hiex::from_reader(Cursor::new(vec![0x80, 0x80])).unwrap();
+        let (_, err) = hex
+            .write_varint_resizing(0, Varint::Unsigned(0), ())
+            .unwrap_err();
+        assert!(matches!(err, ActionError::Invalid));
+
+        // Without resizing, the data doesn't have to support it.
+        let mut bytes = original.clone();
+        let mut hex: Hiex<_> = Hiex::from_reader(Cursor::new(&mut bytes[..])).unwrap();
+        hex.write_varint(1, Varint::Unsigned(0x10_0000), ())
+            .unwrap();
+        hex.undo(()).unwrap();
+        drop(hex);
+        assert_eq!(bytes, original);
+    }
+
+    #[test]
+    fn test_varint_resize_rollback() {
+        let mut hex = Hiex::from_reader(FailOnceBackend {
+            inner: Cursor::new(b"a\x01z".to_vec()),
+            fail_next: true,
+        })
+        .unwrap();
+        let (_, err) = hex
+            .write_varint_resizing(1, Varint::Unsigned(0x10_0000), ())
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ActionError::PartialWrite {
+                rolled_back: true,
+                ..
+            }
+        ));
+        assert!(hex.history().is_empty());
+        assert_eq!(hex.into_inner().inner.get_ref(), b"a\x01z");
+    }
+}
@@ -0,0 +1,182 @@
+//! Noticing changes made to the backing file by other processes, such as a log being appended to.
+use crate::Hiex;
+use notify::{
+    event::{EventKind, ModifyKind},
+    RecommendedWatcher, RecursiveMode, Watcher,
+};
+use std::{
+    io::{Read, Seek, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, TryRecvError},
+};
+
+/// A change to the watched file, from [`WatchHandle::poll_events`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum WatchEvent {
+    /// The file was written to, and is now `length` bytes long, which is at least as long as it
+    /// was before.
+    Modified { length: u64 },
+    /// The file became shorter, and is now `length` bytes long.
+    Truncated { length: u64 },
+    /// The file was removed, or renamed away from the watched path.
+    Removed,
+}
+
+/// Watches a file for changes, from [`Hiex::watch_path`]. Events are delivered through a channel
+/// owned by the handle, so the editor isn't borrowed by the watcher; stop watching by dropping
+/// the handle.
+///
+/// Nothing is done to the editor's history by itself. If the file was changed where there are
+/// applied actions, see [`ActionList::mark_external_modification`](crate::action::ActionList::mark_external_modification).
+pub struct WatchHandle {
+    // Kept alive for as long as the handle is, as dropping it stops watching.
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    path: PathBuf,
+    /// The length of the file when it was last checked.
+    length: u64,
+}
+impl WatchHandle {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The length of the file when it was last checked, by [`WatchHandle::poll_events`] or
+    /// [`Hiex::refresh_on_growth`].
+    pub fn last_length(&self) -> u64 {
+        self.length
+    }
+
+    /// Returns the changes to the file since the last call, without blocking.
+    /// Several writes may be reported as a single `Modified`, or as one for each write, depending
+    /// on the platform.
+    pub fn poll_events(&mut self) -> std::io::Result<Vec<WatchEvent>> {
+        let mut events = Vec::new();
+        loop {
+            let event = match self.events.try_recv() {
+                Ok(event) => event.map_err(std::io::Error::other)?,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    return Err(std::io::Error::other("the file watcher stopped"))
+                }
+            };
+            match event.kind {
+                EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_)) => {
+                    events.push(WatchEvent::Removed);
+                }
+                EventKind::Modify(_) | EventKind::Any => {
+                    let length = match std::fs::metadata(&self.path) {
+                        Ok(metadata) => metadata.len(),
+                        // It was removed after the event, which is reported by its own event.
+                        Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                        Err(err) => return Err(err),
+                    };
+                    let event = if length < self.length {
+                        WatchEvent::Truncated { length }
+                    } else {
+                        WatchEvent::Modified { length }
+                    };
+                    self.length = length;
+                    // Writes in quick succession are often only seen once the file is checked.
+                    if events.last() != Some(&event) {
+                        events.push(event);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(events)
+    }
+}
+
+impl<F, E> Hiex<F, E>
+where
+    F: Read + Seek + Write,
+{
+    /// Starts watching the file at `path`, which should be the file the editor is over, for
+    /// changes made to it by other processes.
+    pub fn watch_path<P>(&mut self, path: P) -> std::io::Result<WatchHandle>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().to_path_buf();
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            // The handle being dropped is how watching stops, so there is nobody to tell.
+            let _ = sender.send(event);
+        })
+        .map_err(std::io::Error::other)?;
+        watcher
+            .watch(&path, RecursiveMode::NonRecursive)
+            .map_err(std::io::Error::other)?;
+
+        Ok(WatchHandle {
+            _watcher: watcher,
+            events,
+            path,
+            length: self.length()?,
+        })
+    }
+
+    /// Re-probes the length of the data, returning it if the file grew since `handle` last
+    /// checked. The length isn't cached by the editor, so this only needs the reader to report
+    /// its new length (as [`std::fs::File`] does), but it is a cheap way of polling a growing file
+    /// without going through the events.
+    pub fn refresh_on_growth(&mut self, handle: &mut WatchHandle) -> std::io::Result<Option<u64>> {
+        let length = self.length()?;
+        let grew = length > handle.length;
+        handle.length = length;
+        Ok(Some(length).filter(|_| grew))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WatchEvent;
+    use crate::Hiex;
+    use std::{
+        fs::OpenOptions,
+        io::Write,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn test_watch_path() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"0123").unwrap();
+        let path = file.path().to_path_buf();
+        let mut hex: Hiex<_> = Hiex::from_reader(file.reopen().unwrap()).unwrap();
+        let mut handle = hex.watch_path(&path).unwrap();
+        assert_eq!(handle.last_length(), 4);
+        assert!(handle.poll_events().unwrap().is_empty());
+
+        // Appending out-of-band, as another process would.
+        let mut appender = OpenOptions::new().append(true).open(&path).unwrap();
+        appender.write_all(b"4567").unwrap();
+        appender.sync_all().unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut events = Vec::new();
+        while !events.contains(&WatchEvent::Modified { length: 8 }) {
+            assert!(Instant::now() < deadline, "no event, got {:?}", events);
+            std::thread::sleep(Duration::from_millis(10));
+            events.extend(handle.poll_events().unwrap());
+        }
+        assert_eq!(hex.length().unwrap(), 8);
+        assert_eq!(hex.read_amount_at(4, 4).unwrap(), b"4567");
+
+        appender.write_all(b"89").unwrap();
+        assert_eq!(hex.refresh_on_growth(&mut handle).unwrap(), Some(10));
+        assert_eq!(hex.refresh_on_growth(&mut handle).unwrap(), None);
+
+        appender.set_len(2).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut events = Vec::new();
+        while !events.contains(&WatchEvent::Truncated { length: 2 }) {
+            assert!(Instant::now() < deadline, "no event, got {:?}", events);
+            std::thread::sleep(Duration::from_millis(10));
+            events.extend(handle.poll_events().unwrap());
+        }
+        assert_eq!(hex.length().unwrap(), 2);
+    }
+}